@@ -0,0 +1,57 @@
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use trust_dns_resolver::Name;
+use zeronsd::{addresses::Calculator, authority::ZTRecord};
+use zerotier_api::central_api::types::{Member, Network};
+
+const MEMBERS: u64 = 2000;
+
+fn network() -> Network {
+    serde_json::from_value(serde_json::json!({ "id": "1234567891011121" })).unwrap()
+}
+
+fn members() -> Vec<Member> {
+    (0..MEMBERS)
+        .map(|i| {
+            serde_json::from_value(serde_json::json!({
+                "networkId": "1234567891011121",
+                "nodeId": format!("{:010x}", i),
+                "name": format!("member-{}", i),
+                "config": { "ipAssignments": [format!("10.0.{}.{}", i / 256, i % 256)] },
+            }))
+            .unwrap()
+        })
+        .collect()
+}
+
+// the per-member work of a sync with 6PLANE and RFC4193 on, all of it borrowing the members.
+fn bench_addresses(c: &mut Criterion) {
+    let network = network();
+    let (sixplane, rfc4193) = (network.sixplane().ok(), network.rfc4193().ok());
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let members = members();
+    let mut group = c.benchmark_group("addresses of 2000 members");
+
+    group.bench_function("calculator", |b| {
+        b.iter(|| {
+            for member in &members {
+                member.sixplane().unwrap();
+                member.rfc4193().unwrap();
+            }
+        })
+    });
+
+    group.bench_function("records", |b| {
+        b.iter(|| {
+            for member in &members {
+                ZTRecord::new(member, sixplane, rfc4193, domain.clone(), false, &[]).unwrap();
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_addresses);
+criterion_main!(benches);
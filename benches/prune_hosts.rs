@@ -0,0 +1,106 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    net::{IpAddr, Ipv4Addr},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::runtime::Runtime;
+use trust_dns_resolver::{proto::rr::RData, Name};
+use trust_dns_server::client::rr::LowerName;
+use zeronsd::{authority::RecordAuthority, hosts::HostsFile};
+
+const HOSTS: u32 = 1500;
+
+// Counting tallies the bytes allocated, so the cost of a prune can be reported alongside its time.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn hosts() -> HostsFile {
+    (0..HOSTS)
+        .map(|i| {
+            (
+                IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i)),
+                vec![Name::from_str(&format!("host-{}.home.arpa.", i)).unwrap()],
+            )
+        })
+        .collect()
+}
+
+// a zone already holding the hosts file, as it is on every sync after the first.
+async fn authority(hosts: &HostsFile) -> RecordAuthority {
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-ffffffffff.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+
+    authority
+        .upsert_batch(
+            hosts
+                .iter()
+                .map(|(ip, names)| match ip {
+                    IpAddr::V4(ip) => (names[0].clone(), vec![RData::A(*ip)]),
+                    IpAddr::V6(ip) => (names[0].clone(), vec![RData::AAAA(*ip)]),
+                })
+                .collect(),
+        )
+        .await;
+    authority
+}
+
+fn bench_prune_hosts(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let hosts = hosts();
+    let authority = rt.block_on(authority(&hosts));
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    rt.block_on(authority.prune_hosts(&hosts)).unwrap();
+    println!(
+        "prune_hosts over {} hosts allocated {} bytes",
+        HOSTS,
+        ALLOCATED.load(Ordering::Relaxed) - before
+    );
+
+    let mut group = c.benchmark_group("prune 1500 hosts");
+    group.sample_size(10);
+
+    group.bench_function("unchanged", |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let authority = authority.clone();
+            let hosts = hosts.clone();
+            async move {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    authority.prune_hosts(&hosts).await.unwrap();
+                    total += start.elapsed();
+                }
+                total
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_prune_hosts);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+use std::{
+    net::Ipv4Addr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::runtime::Runtime;
+use trust_dns_resolver::{
+    proto::rr::{RData, RecordType},
+    Name,
+};
+use trust_dns_server::client::rr::{LowerName, RrKey};
+use zeronsd::authority::RecordAuthority;
+
+const MEMBERS: u32 = 5000;
+
+fn names() -> Vec<Name> {
+    (0..MEMBERS)
+        .map(|i| Name::from_str(&format!("zt-{:010x}.home.arpa.", i)).unwrap())
+        .collect()
+}
+
+async fn authority(names: &[Name]) -> RecordAuthority {
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-ffffffffff.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+
+    authority
+        .upsert_batch(
+            names
+                .iter()
+                .zip(0..)
+                .map(|(name, i)| {
+                    (
+                        name.clone(),
+                        vec![RData::A(Ipv4Addr::from(0x0a00_0000 + i))],
+                    )
+                })
+                .collect(),
+        )
+        .await;
+    authority
+}
+
+fn keys(names: &[Name]) -> Vec<RrKey> {
+    names
+        .iter()
+        .map(|name| RrKey::new(name.into(), RecordType::A))
+        .collect()
+}
+
+// removal only, leaving out the cost of building the zone each iteration.
+async fn time_removal(iters: u64, per_record: bool) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        let names = names();
+        let authority = authority(&names).await;
+        let keys = keys(&names);
+
+        let start = Instant::now();
+        if per_record {
+            for key in keys {
+                authority.bulk_delete(vec![key]).await;
+            }
+        } else {
+            authority.bulk_delete(keys).await;
+        }
+        total += start.elapsed();
+    }
+    total
+}
+
+fn bench_prune(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("remove 5000 members");
+    group.sample_size(10);
+
+    group.bench_function("lock per record", |b| {
+        b.to_async(&rt)
+            .iter_custom(|iters| time_removal(iters, true))
+    });
+
+    group.bench_function("bulk_delete", |b| {
+        b.to_async(&rt)
+            .iter_custom(|iters| time_removal(iters, false))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_prune);
+criterion_main!(benches);
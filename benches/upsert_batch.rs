@@ -0,0 +1,90 @@
+use std::{net::Ipv4Addr, str::FromStr};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use tokio::runtime::Runtime;
+use trust_dns_resolver::{proto::rr::RData, Name};
+use trust_dns_server::client::rr::LowerName;
+use zeronsd::authority::RecordAuthority;
+
+const MEMBERS: u32 = 1000;
+
+fn members() -> Vec<(Name, Vec<RData>)> {
+    named("zt")
+}
+
+fn named(prefix: &str) -> Vec<(Name, Vec<RData>)> {
+    (0..MEMBERS)
+        .map(|i| {
+            (
+                Name::from_str(&format!("{}-{:010x}.home.arpa.", prefix, i)).unwrap(),
+                vec![RData::A(Ipv4Addr::from(0x0a00_0000 + i))],
+            )
+        })
+        .collect()
+}
+
+async fn authority() -> RecordAuthority {
+    RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-ffffffffff.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+fn bench_upsert(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("upsert 1000 members");
+
+    group.bench_function("match_or_insert", |b| {
+        b.to_async(&rt).iter_batched(
+            members,
+            |members| async move {
+                let authority = authority().await;
+                for (name, rdatas) in members {
+                    let ips: Vec<_> = rdatas
+                        .iter()
+                        .filter_map(|rdata| rdata.to_ip_addr())
+                        .collect();
+                    authority.match_or_insert(name, &ips).await;
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("upsert_batch", |b| {
+        b.to_async(&rt).iter_batched(
+            members,
+            |members| async move { authority().await.upsert_batch(members).await },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // stale records to prune as well, as on a sync where every member was renamed.
+    group.bench_function("apply_batch", |b| {
+        b.to_async(&rt).iter_batched(
+            || (members(), named("renamed")),
+            |(members, renamed)| async move {
+                let authority = authority().await;
+                authority.upsert_batch(members).await;
+                authority
+                    .apply_batch(renamed, Vec::new(), usize::MAX)
+                    .await
+                    .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+
+    let held = zeronsd::metrics::ZONE_LOCK_HELD.clone();
+    println!(
+        "apply_batch held the zone lock for {:.0}µs on average",
+        held.get_sample_sum() / held.get_sample_count() as f64 * 1e6
+    );
+}
+
+criterion_group!(benches, bench_upsert);
+criterion_main!(benches);
@@ -1,39 +1,113 @@
 /// various IP calculation systems and some encode/decode functions
-use std::net::{IpAddr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use crate::errors;
 use error_stack::*;
-use hex::FromHexError;
 use ipnetwork::IpNetwork;
+use serde::Serialize;
 use zerotier_api::central_api::types::{Member, Network};
 
-fn digest_hex(code: String) -> Result<u64, FromHexError> {
-    Ok(hex::decode(code)?
+// digest_hex reads a ZeroTier ID of exactly len hex digits: 16 for a network, 10 for a node.
+// Anything else would be truncated or padded into an address that looks right but is not.
+fn digest_hex(kind: &str, id: &str, len: usize) -> Result<u64, errors::Error> {
+    let id = parse_id(kind, id, len)?;
+    Ok(hex::decode(id)
+        .change_context(errors::Error::Address)?
         .into_iter()
         .fold(0, |acc, x| acc << 8 | x as u64))
 }
 
-fn get_parts(member: Member) -> Result<(u64, u64), errors::Error> {
+// parse_id checks that id is a ZeroTier ID of len hex digits, returning it in lowercase.
+fn parse_id(kind: &str, id: &str, len: usize) -> Result<String, errors::Error> {
+    if id.len() != len || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(
+            Report::new(errors::Error::Address).attach_printable(format!(
+                "invalid {} ID {:?}: expected {} hex digits",
+                kind, id, len
+            )),
+        );
+    }
+
+    Ok(id.to_ascii_lowercase())
+}
+
+// missing_id is the error for an ID Central left out.
+fn missing_id(kind: &str) -> Report<errors::Error> {
+    Report::new(errors::Error::Address).attach_printable(format!("{} ID is missing", kind))
+}
+
+fn network_part(network: &Network) -> Result<u64, errors::Error> {
+    digest_hex(
+        "network",
+        network.id.as_deref().ok_or_else(|| missing_id("network"))?,
+        16,
+    )
+}
+
+fn get_parts(member: &Member) -> Result<(u64, u64), errors::Error> {
     Ok((
-        digest_hex(member.network_id.clone().unwrap_or(String::new()))
-            .change_context(errors::Error)?,
-        digest_hex(member.node_id.unwrap_or(String::new())).change_context(errors::Error)?,
+        digest_hex(
+            "network",
+            member
+                .network_id
+                .as_deref()
+                .ok_or_else(|| missing_id("network"))?,
+            16,
+        )?,
+        digest_hex(
+            "node",
+            member
+                .node_id
+                .as_deref()
+                .ok_or_else(|| missing_id("node"))?,
+            10,
+        )?,
     ))
 }
 
+// member_mac works out the Ethernet address of a member's interface on a network as ZeroTier does:
+// the node ID with the network ID folded into it, under a locally administered first octet taken
+// from the network ID.
+pub fn member_mac(network_id: &str, node_id: &str) -> Result<u64, errors::Error> {
+    let nwid = digest_hex("network", network_id, 16)?;
+    let node = digest_hex("node", node_id, 10)?;
+
+    let mut first = (nwid & 0xfe) as u8 | 0x02;
+    // 52: is what KVM and libvirt hand out, so ZeroTier stays clear of it.
+    if first == 0x52 {
+        first = 0x32;
+    }
+
+    let mut mac = (first as u64) << 40 | node;
+    mac ^= (nwid >> 8 & 0xff) << 32;
+    mac ^= (nwid >> 16 & 0xff) << 24;
+    mac ^= (nwid >> 24 & 0xff) << 16;
+    mac ^= (nwid >> 32 & 0xff) << 8;
+    mac ^= nwid >> 40 & 0xff;
+    Ok(mac)
+}
+
+// ipv6_to_ipv4_synthetic maps an IPv6 address to an IPv4 one for clients which only understand A
+// records, by taking its lower 32 bits as a NAT64 gateway using the well-known prefix (RFC 6052)
+// would. The address is only reachable through such a gateway.
+pub fn ipv6_to_ipv4_synthetic(ip: Ipv6Addr) -> Ipv4Addr {
+    Ipv4Addr::from(u128::from(ip) as u32)
+}
+
+// Calculator works out the addresses ZeroTier assigns from a network's or member's IDs. It
+// borrows, so syncing a large network does not copy every member to do so.
 pub trait Calculator {
-    fn sixplane(self) -> Result<IpNetwork, errors::Error>;
-    fn rfc4193(self) -> Result<IpNetwork, errors::Error>;
+    fn sixplane(&self) -> Result<IpNetwork, errors::Error>;
+    fn rfc4193(&self) -> Result<IpNetwork, errors::Error>;
 }
 
 impl Calculator for Network {
-    fn sixplane(self) -> Result<IpNetwork, errors::Error> {
-        let mut net_parts =
-            digest_hex(self.id.unwrap_or(String::new())).change_context(errors::Error)?;
+    fn sixplane(&self) -> Result<IpNetwork, errors::Error> {
+        let mut net_parts = network_part(self)?;
 
         net_parts ^= net_parts >> 32;
 
-        Ok(IpNetwork::new(
+        IpNetwork::new(
             IpAddr::V6(Ipv6Addr::new(
                 0xfc00 | (net_parts >> 24 & 0xff) as u16,
                 (net_parts >> 8) as u16,
@@ -46,13 +120,12 @@ impl Calculator for Network {
             )),
             40,
         )
-        .change_context(errors::Error)?)
+        .change_context(errors::Error::Address)
     }
 
-    fn rfc4193(self) -> Result<IpNetwork, errors::Error> {
-        let net_parts =
-            digest_hex(self.id.unwrap_or(String::new())).change_context(errors::Error)?;
-        Ok(IpNetwork::new(
+    fn rfc4193(&self) -> Result<IpNetwork, errors::Error> {
+        let net_parts = network_part(self)?;
+        IpNetwork::new(
             IpAddr::V6(Ipv6Addr::new(
                 0xfd00 | (net_parts >> 56 & 0xff) as u16,
                 (net_parts >> 40 & 0xffff) as u16,
@@ -65,17 +138,17 @@ impl Calculator for Network {
             )),
             88,
         )
-        .change_context(errors::Error)?)
+        .change_context(errors::Error::Address)
     }
 }
 
 impl Calculator for Member {
-    fn sixplane(self) -> Result<IpNetwork, errors::Error> {
-        let (mut net_parts, node_parts) = get_parts(self).change_context(errors::Error)?;
+    fn sixplane(&self) -> Result<IpNetwork, errors::Error> {
+        let (mut net_parts, node_parts) = get_parts(self)?;
 
         net_parts ^= net_parts >> 32;
 
-        Ok(IpNetwork::new(
+        IpNetwork::new(
             IpAddr::V6(Ipv6Addr::new(
                 0xfc00 | (net_parts >> 24 & 0xff) as u16,
                 (net_parts >> 8) as u16,
@@ -88,13 +161,13 @@ impl Calculator for Member {
             )),
             80,
         )
-        .change_context(errors::Error)?)
+        .change_context(errors::Error::Address)
     }
 
-    fn rfc4193(self) -> Result<IpNetwork, errors::Error> {
-        let (net_parts, node_parts) = get_parts(self).change_context(errors::Error)?;
+    fn rfc4193(&self) -> Result<IpNetwork, errors::Error> {
+        let (net_parts, node_parts) = get_parts(self)?;
 
-        Ok(IpNetwork::new(
+        IpNetwork::new(
             IpAddr::V6(Ipv6Addr::new(
                 0xfd00 | (net_parts >> 56 & 0xff) as u16,
                 (net_parts >> 40 & 0xffff) as u16,
@@ -107,6 +180,80 @@ impl Calculator for Member {
             )),
             128,
         )
-        .change_context(errors::Error)?)
+        .change_context(errors::Error::Address)
+    }
+}
+
+// AddressPlan is the addresses ZeroTier assigns in a network, worked out without asking Central:
+// the network's 6PLANE and RFC4193 prefixes, and a member's addresses in them when a node ID is
+// given. It is what the calc subcommand prints.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AddressPlan {
+    pub network_id: String,
+    pub node_id: Option<String>,
+    pub sixplane_prefix: String,
+    pub rfc4193_prefix: String,
+    // the member's 6PLANE address; the whole /80 it sits in is routed to the member
+    pub sixplane: Option<String>,
+    pub rfc4193: Option<String>,
+}
+
+impl AddressPlan {
+    pub fn new(network_id: &str, node_id: Option<&str>) -> Result<Self, errors::Error> {
+        let network_id = parse_id("network", network_id, 16)?;
+        let node_id = node_id.map(|id| parse_id("node", id, 10)).transpose()?;
+
+        let network: Network = serde_json::from_value(serde_json::json!({ "id": network_id }))
+            .change_context(errors::Error::Address)?;
+        let prefix = |net: IpNetwork| format!("{}/{}", net.network(), net.prefix());
+
+        let mut plan = Self {
+            sixplane_prefix: prefix(network.sixplane()?),
+            rfc4193_prefix: prefix(network.rfc4193()?),
+            network_id,
+            node_id: None,
+            sixplane: None,
+            rfc4193: None,
+        };
+
+        if let Some(node_id) = node_id {
+            let member: Member = serde_json::from_value(serde_json::json!({
+                "networkId": plan.network_id,
+                "nodeId": node_id,
+            }))
+            .change_context(errors::Error::Address)?;
+
+            let sixplane = member.sixplane()?;
+            plan.sixplane = Some(format!("{}/{}", sixplane.ip(), sixplane.prefix()));
+            plan.rfc4193 = Some(member.rfc4193()?.ip().to_string());
+            plan.node_id = Some(node_id);
+        }
+
+        Ok(plan)
+    }
+}
+
+impl std::fmt::Display for AddressPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rows = vec![
+            ("Network", self.network_id.as_str()),
+            ("6PLANE prefix", self.sixplane_prefix.as_str()),
+            ("RFC4193 prefix", self.rfc4193_prefix.as_str()),
+        ];
+
+        if let (Some(node_id), Some(sixplane), Some(rfc4193)) =
+            (&self.node_id, &self.sixplane, &self.rfc4193)
+        {
+            rows.push(("Node", node_id));
+            rows.push(("6PLANE address", sixplane));
+            rows.push(("RFC4193 address", rfc4193));
+        }
+
+        let width = rows.iter().map(|(k, _)| k.len()).max().unwrap_or_default();
+        for (key, value) in rows {
+            writeln!(f, "{:width$}  {}", key, value, width = width)?;
+        }
+
+        Ok(())
     }
 }
@@ -1,30 +1,48 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     net::IpAddr,
     path::PathBuf,
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
 use crate::{
-    addresses::Calculator,
-    errors,
-    hosts::{parse_hosts, HostsFile},
-    traits::{ToHostname, ToPointerSOA, ToWildcard},
-    utils::parse_member_name,
+    addresses::{ipv6_to_ipv4_synthetic, member_mac, Calculator},
+    collision::{hosts_winner, member_winner, Collision, Collisions, RecordSource},
+    ecs::{EcsMode, SubnetForwarder},
+    errors::{self, ApiResultExt},
+    geodnslite::LatencyAwareRecordAuthority,
+    hosts::{
+        hosts_file_list, is_hosts_file, parse_hosts_files, Cnames, HostsFile, HostsPrecedence,
+    },
+    serial::SoaSerialPolicy,
+    server::{QueryRateLimiter, RateLimitedAuthority},
+    snapshot::Snapshot,
+    traits::{CentralClient, ToHostname, ToPointerSOA, ToWildcard, UpstreamDiscovery},
+    utils::{parse_member_name, read_file_to_string, DEFAULT_MAX_MEMBER_PAGES},
 };
 use error_stack::{Result, ResultExt};
+use notify::{RecursiveMode, Watcher};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
 use async_trait::async_trait;
 use ipnetwork::IpNetwork;
 use trust_dns_resolver::{
-    config::NameServerConfigGroup,
-    proto::rr::{dnssec::SupportedAlgorithms, rdata::SOA, RData, Record, RecordSet, RecordType},
-    IntoName, Name,
+    config::{NameServerConfig, NameServerConfigGroup, Protocol},
+    proto::{
+        op::ResponseCode,
+        rr::{rdata::SOA, RData, Record, RecordSet, RecordType},
+    },
+    Name,
 };
 use trust_dns_server::{
-    authority::{AuthorityObject, Catalog},
+    authority::{AuthorityObject, Catalog, LookupError},
     client::rr::{LowerName, RrKey},
     store::{
         forwarder::{ForwardAuthority, ForwardConfig},
@@ -32,242 +50,1743 @@ use trust_dns_server::{
     },
 };
 
-use zerotier_api::central_api;
+use zerotier_api::{central_api, service_api};
 
-pub async fn find_members(mut zt: ZTAuthority) {
-    let mut timer = tokio::time::interval(zt.update_interval);
+// find_members keeps the authorities in sync with central, or offline, with just the hosts files.
+// It returns when zt.cancel is cancelled, or with an error not worth retrying, such as central
+// refusing the token.
+pub async fn find_members(zt: Arc<ZTAuthority>) -> Result<(), errors::Error> {
+    let mut interval = *zt.update_interval.read().await;
+    let mut timer = tokio::time::interval(interval);
+    let mut synced = false;
 
     loop {
-        match zt.configure_hosts().await {
+        // offline, the hosts files are all there is, so names dropped from them are removed here
+        // rather than along with departed members.
+        let hosts = if zt.client.is_some() {
+            zt.configure_hosts().await.map(|_| ())
+        } else {
+            zt.reload_hosts().await
+        };
+        match hosts {
             Ok(_) => {}
             Err(e) => tracing::error!("error refreshing hosts file: {}", e),
         }
 
-        match zt.get_members().await {
-            Ok((network, members)) => match zt.configure_members(network, members).await {
+        match zt.configure_peers().await {
+            Ok(_) => {}
+            Err(e) => tracing::error!("error refreshing peers: {}", e),
+        }
+
+        // offline, there are no members to sync.
+        if zt.client.is_some() {
+            match zt.get_members().await {
+                Ok((network, members)) => {
+                    synced = true;
+                    zt.save_snapshot(&network, &members);
+
+                    match zt.configure_members(network, members).await {
+                        Ok(_) => {}
+                        Err(e) => {
+                            tracing::error!("error configuring authority: {}", e)
+                        }
+                    }
+
+                    // bridged hosts are told apart from members by the members just synced.
+                    match zt.configure_bridged().await {
+                        Ok(_) => {}
+                        Err(e) => tracing::error!("error refreshing bridged hosts: {}", e),
+                    }
+                }
+                Err(e) => {
+                    if !e.current_context().is_retryable() {
+                        tracing::error!("giving up syncing members: {:?}", e);
+                        return Err(e);
+                    }
+
+                    tracing::error!("error syncing members: {}", e);
+
+                    // until we've heard from central at least once, serve whatever we saw last.
+                    if !synced {
+                        if let Some(snapshot) = zt.load_snapshot() {
+                            tracing::warn!(
+                                "Central is unreachable; serving cached records from a snapshot taken {} seconds ago",
+                                snapshot.age().as_secs()
+                            );
+
+                            match zt
+                                .configure_members(snapshot.network, snapshot.members)
+                                .await
+                            {
+                                Ok(_) => synced = true,
+                                Err(e) => {
+                                    tracing::error!(
+                                        "error configuring authority from snapshot: {}",
+                                        e
+                                    )
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "etcd-backend")]
+        if let Some(etcd) = &zt.etcd {
+            match etcd.mirror(zt.forward_authority.rrsets().await).await {
                 Ok(_) => {}
+                Err(e) => tracing::error!("error writing records to etcd: {}", e),
+            }
+        }
+
+        // pick up a new interval, starting the wait for the next sync over with it.
+        let current = *zt.update_interval.read().await;
+        if current != interval {
+            tracing::info!("Update interval changed to {:?}", current);
+            interval = current;
+            timer = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        }
+
+        tokio::select! {
+            biased;
+            _ = zt.cancel.cancelled() => {
+                tracing::info!("Stopped syncing");
+                return Ok(());
+            }
+            _ = timer.tick() => {}
+        }
+    }
+}
+
+// sync_members runs find_members, starting it over should it panic, so one bad sync can't stop DNS
+// updates for good. It returns what find_members does otherwise.
+pub async fn sync_members(zt: Arc<ZTAuthority>) -> Result<(), errors::Error> {
+    loop {
+        match tokio::spawn(find_members(zt.clone()).in_current_span()).await {
+            Ok(result) => return result,
+            Err(e) if e.is_panic() => {
+                let panic = e.into_panic();
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown cause");
+                tracing::error!("Sync panicked, restarting it: {}", message);
+                crate::metrics::SYNC_RESTARTS.inc();
+            }
+            Err(_) => return Ok(()),
+        }
+
+        // a panic that happens every sync shouldn't spin.
+        let interval = *zt.update_interval.read().await;
+        tokio::select! {
+            biased;
+            _ = zt.cancel.cancelled() => return Ok(()),
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+// initial_sync fills the zones before anything is served: the hosts files, peers and members are
+// synced once, retrying Central until timeout runs out. On failure the last snapshot, if any, is
+// served instead and the error returned, leaving it to the caller whether to start anyway.
+pub async fn initial_sync(zt: &ZTAuthority, timeout: Duration) -> Result<(), errors::Error> {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    let hosts = if zt.client.is_some() {
+        zt.configure_hosts().await.map(|_| ())
+    } else {
+        zt.reload_hosts().await
+    };
+    if let Err(e) = hosts {
+        tracing::error!("error reading hosts file: {}", e);
+    }
+
+    if let Err(e) = zt.configure_peers().await {
+        tracing::error!("error reading peers: {}", e);
+    }
+
+    if zt.client.is_none() {
+        return Ok(());
+    }
+
+    let mut last_error = None;
+    let fetched = tokio::time::timeout_at(deadline, async {
+        loop {
+            match zt.get_members().await {
+                Ok(fetched) => return Ok(fetched),
+                Err(e) if !e.current_context().is_retryable() => return Err(e),
                 Err(e) => {
-                    tracing::error!("error configuring authority: {}", e)
+                    tracing::warn!("error fetching members for the first sync, retrying: {}", e);
+                    last_error = Some(e);
                 }
-            },
-            Err(e) => {
-                tracing::error!("error syncing members: {}", e)
             }
+            tokio::time::sleep(INITIAL_SYNC_RETRY).await;
+        }
+    })
+    .await;
+
+    let err = match fetched {
+        Ok(Ok((network, members))) => {
+            zt.save_snapshot(&network, &members);
+            zt.configure_members(network, members).await?;
+            if let Err(e) = zt.configure_bridged().await {
+                tracing::error!("error reading bridged hosts: {}", e);
+            }
+            return Ok(());
+        }
+        Ok(Err(e)) => e,
+        Err(_) => last_error
+            .unwrap_or_else(|| {
+                error_stack::Report::new(errors::Error::CentralApi { status: None })
+                    .attach_printable("Central did not respond")
+            })
+            .attach_printable(format!("no members synced within {:?}", timeout)),
+    };
+
+    if let Some(snapshot) = zt.load_snapshot() {
+        tracing::warn!(
+            "Serving cached records from a snapshot taken {} seconds ago until Central can be reached",
+            snapshot.age().as_secs()
+        );
+        if let Err(e) = zt
+            .configure_members(snapshot.network, snapshot.members)
+            .await
+        {
+            tracing::error!("error configuring authority from snapshot: {}", e);
+        }
+    }
+
+    Err(err)
+}
+
+// how long startup waits on the first sync before giving up on it.
+pub const DEFAULT_INITIAL_SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+// how long to wait between attempts at the first sync.
+pub const INITIAL_SYNC_RETRY: Duration = Duration::from_secs(1);
+
+// default_address_filter keeps link-local addresses out of DNS; they are only reachable from the
+// same link, and ZeroTier's own are of no use to anyone else.
+pub fn default_address_filter() -> Vec<IpNetwork> {
+    ["169.254.0.0/16", "fe80::/10"]
+        .iter()
+        .map(|net| net.parse().unwrap())
+        .collect()
+}
+
+// how often members are fetched from Central and the zone refreshed.
+pub const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+// the most records a sync may leave in a zone unless max_records says otherwise; a guard against
+// a name template running away with memory.
+pub const DEFAULT_MAX_RECORDS: usize = 100_000;
+
+// the record types syncing writes, and so the only ones a sync removes once it no longer has them.
+// Records of any other type were put in the zone some other way, and are left for that to manage.
+pub const SYNCED_RECORD_TYPES: &[RecordType] = &[
+    RecordType::A,
+    RecordType::AAAA,
+    RecordType::PTR,
+    RecordType::CNAME,
+];
+
+// how long the hosts file must be left alone before a change to it is applied; editors often
+// write a file several times when saving it.
+pub const HOSTS_DEBOUNCE: Duration = Duration::from_millis(200);
+
+// watch_hosts applies changes to the hosts files as soon as they are made, rather than at the next
+// sync. Directories are watched instead of files, so editors which save by replacing a file don't
+// end the watch.
+pub async fn watch_hosts(zt: Arc<ZTAuthority>) -> Result<(), errors::Error> {
+    if zt.hosts_files.is_empty() {
+        return Ok(());
+    }
+
+    let mut dirs = Vec::new();
+    for path in &zt.hosts_files {
+        let dir = if path.is_dir() {
+            path.clone()
+        } else {
+            match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            }
+        };
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
         }
+    }
+
+    let hosts_files = zt.hosts_files.clone();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            // reading the files ourselves must not count as a change.
+            if !matches!(event.kind, notify::EventKind::Access(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| is_hosts_file(&hosts_files, path))
+            {
+                let _ = tx.send(());
+            }
+        }
+    })
+    .change_context(errors::Error::Io {
+        path: dirs[0].clone(),
+    })?;
+    for dir in &dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .change_context(errors::Error::Io { path: dir.clone() })
+            .attach_printable_lazy(|| format!("could not watch {}", dir.display()))?;
+    }
+
+    if let Err(e) = zt.configure_hosts().await {
+        tracing::error!("error refreshing hosts file: {}", e);
+    }
 
-        timer.tick().await;
+    while rx.recv().await.is_some() {
+        while let Ok(Some(())) = tokio::time::timeout(HOSTS_DEBOUNCE, rx.recv()).await {}
+
+        match zt.reload_hosts().await {
+            Ok(_) => tracing::info!("Reloaded hosts files"),
+            Err(e) => tracing::error!("error reloading hosts file: {}", e),
+        }
     }
+
+    Ok(())
 }
 
+// init_catalog is the catalog zeronsd serves: a forwarder at the root, and our own zones.
 pub async fn init_catalog(zt: ZTAuthority) -> Result<Catalog, errors::Error> {
     let mut catalog = Catalog::default();
 
-    let resolv =
-        trust_dns_resolver::system_conf::read_system_conf().change_context(errors::Error)?;
-    let mut nsconfig = NameServerConfigGroup::new();
-
-    for server in resolv.0.name_servers() {
-        nsconfig.push(server.clone());
+    // without a resolver configuration, our own zones are still worth serving.
+    match forwarder(&zt) {
+        Ok(forwarder) => {
+            zt.no_forwarder.store(false, Ordering::SeqCst);
+            catalog.upsert(Name::root().into(), zt.limit(forwarder));
+        }
+        Err(e) => {
+            zt.no_forwarder.store(true, Ordering::SeqCst);
+            tracing::warn!(
+                "Not forwarding queries outside our zones; they will be refused: {:?}",
+                e
+            );
+        }
     }
 
-    let options = Some(resolv.1);
-    let config = &ForwardConfig {
-        name_servers: nsconfig.clone(),
-        options,
+    zt.upsert_zones(&mut catalog)?;
+    Ok(catalog)
+}
+
+// forwarder answers queries outside our zones from the name servers of zt.resolv_conf, or of the
+// system's resolver configuration. On windows, that is the DNS servers of the network adapters.
+// Unless client subnets are stripped, queries carrying one are sent upstream by SubnetForwarder.
+fn forwarder(zt: &ZTAuthority) -> Result<Box<dyn AuthorityObject>, errors::Error> {
+    let config = match &zt.resolv_conf {
+        #[cfg(windows)]
+        None => ForwardConfig {
+            name_servers: discovered_name_servers(
+                &AdapterDnsServers,
+                &zt.listen_ips,
+                zt.randomize_udp_port,
+            )?,
+            options: None,
+        },
+        #[cfg(not(windows))]
+        None => {
+            let (config, options) = trust_dns_resolver::system_conf::read_system_conf()
+                .change_context(errors::Error::Config)
+                .attach_printable("could not read the system resolver configuration")?;
+            ForwardConfig {
+                name_servers: forward_name_servers(config.name_servers(), zt.randomize_udp_port),
+                options: Some(options),
+            }
+        }
+        Some(path) => {
+            let (config, options) = read_resolv_conf(path)?;
+            ForwardConfig {
+                name_servers: forward_name_servers(config.name_servers(), zt.randomize_udp_port),
+                options: Some(options),
+            }
+        }
     };
 
-    let forwarder = ForwardAuthority::try_from_config(
-        Name::root(),
-        trust_dns_server::authority::ZoneType::Primary,
-        config,
-    )
-    .expect("Could not initialize forwarder");
+    let forwarder: Box<dyn AuthorityObject> = Box::new(Arc::new(
+        ForwardAuthority::try_from_config(
+            Name::root(),
+            trust_dns_server::authority::ZoneType::Primary,
+            &config,
+        )
+        .map_err(|e| error_stack::Report::new(errors::Error::Server).attach_printable(e))?,
+    ));
 
-    catalog.upsert(Name::root().into(), Box::new(Arc::new(forwarder)));
+    Ok(match zt.ecs_mode {
+        EcsMode::Strip => forwarder,
+        _ => Box::new(SubnetForwarder::new(forwarder, &config)),
+    })
+}
 
-    catalog.upsert(
-        zt.forward_authority.domain_name.clone(),
-        zt.forward_authority.box_clone(),
-    );
+// discovered_name_servers forwards to the name servers discovery finds, save the addresses we
+// listen on. ZeroTier hands our own network's DNS settings to this host too, and forwarding to
+// ourselves would only loop. They are set up as forward_name_servers sets up any others.
+pub fn discovered_name_servers(
+    discovery: &dyn UpstreamDiscovery,
+    listen_ips: &[IpAddr],
+    randomize_udp_port: bool,
+) -> Result<NameServerConfigGroup, errors::Error> {
+    let mut servers = Vec::new();
+    for ip in discovery.name_servers()? {
+        if listen_ips.contains(&ip) {
+            tracing::debug!("Not forwarding to {}, which is us", ip);
+        } else if !ip.is_unspecified() && !servers.contains(&ip) {
+            servers.push(ip);
+        }
+    }
 
-    for (network, authority) in zt.reverse_authority_map {
-        catalog.upsert(
-            network.to_ptr_soa_name().change_context(errors::Error)?,
-            authority.box_clone(),
-        )
+    if servers.is_empty() {
+        return Err(errors::Error::Config)
+            .attach_printable("found no name servers to forward to, other than ourselves");
     }
 
-    Ok(catalog)
+    Ok(forward_name_servers(
+        &NameServerConfigGroup::from_ips_clear(&servers, 53, true),
+        randomize_udp_port,
+    ))
+}
+
+// AdapterDnsServers are the DNS servers of the network adapters that are up, as ipconfig lists
+// them.
+#[cfg(windows)]
+pub struct AdapterDnsServers;
+
+#[cfg(windows)]
+impl UpstreamDiscovery for AdapterDnsServers {
+    fn name_servers(&self) -> Result<Vec<IpAddr>, errors::Error> {
+        Ok(ipconfig::get_adapters()
+            .change_context(errors::Error::Config)
+            .attach_printable("could not list the network adapters")?
+            .iter()
+            .filter(|adapter| adapter.oper_status() == ipconfig::OperStatus::IfOperStatusUp)
+            .flat_map(|adapter| adapter.dns_servers().iter().copied())
+            .collect())
+    }
+}
+
+#[cfg(unix)]
+fn read_resolv_conf(
+    path: &std::path::Path,
+) -> Result<
+    (
+        trust_dns_resolver::config::ResolverConfig,
+        trust_dns_resolver::config::ResolverOpts,
+    ),
+    errors::Error,
+> {
+    let contents = std::fs::read(path)
+        .change_context(errors::Error::Io {
+            path: path.to_path_buf(),
+        })
+        .attach_printable_lazy(|| format!("could not read {}", path.display()))?;
+
+    trust_dns_resolver::system_conf::parse_resolv_conf(contents)
+        .change_context(errors::Error::Config)
+        .attach_printable_lazy(|| format!("invalid resolver configuration {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn read_resolv_conf(
+    path: &std::path::Path,
+) -> Result<
+    (
+        trust_dns_resolver::config::ResolverConfig,
+        trust_dns_resolver::config::ResolverOpts,
+    ),
+    errors::Error,
+> {
+    Err(errors::Error::Config).attach_printable(format!(
+        "resolv.conf files such as {} are only read on unix",
+        path.display()
+    ))
+}
+
+// forward_name_servers prepares the upstream resolvers queries are forwarded to. With
+// randomize_udp_port set, any fixed source port is cleared so that every UDP query goes out from a
+// new, randomly chosen port. Combined with the random query ID this is what makes forged upstream
+// responses hard to get accepted (cache poisoning; see RFC 5452): an off-path attacker has to guess
+// both instead of just the ID.
+pub fn forward_name_servers(
+    servers: &[NameServerConfig],
+    randomize_udp_port: bool,
+) -> NameServerConfigGroup {
+    let mut nsconfig = NameServerConfigGroup::new();
+
+    for server in servers {
+        let mut server = server.clone();
+
+        if server.protocol == Protocol::Udp {
+            if randomize_udp_port {
+                if let Some(bind_addr) = server.bind_addr.as_mut() {
+                    bind_addr.set_port(0);
+                }
+            } else {
+                tracing::warn!(
+                    "UDP source port randomization is disabled for upstream {}; forwarded queries are easier to spoof",
+                    server.socket_addr
+                );
+            }
+        }
+
+        nsconfig.push(server);
+    }
+
+    nsconfig
+}
+
+// the name and address of each peer given a record.
+pub type Peers = Vec<(Name, IpAddr)>;
+
+// HostsStamp identifies what configure_hosts last applied: the content of each hosts file, None
+// for one since deleted, and the member addresses they were resolved against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostsStamp {
+    files: Vec<(PathBuf, Option<[u8; 32]>)>,
+    members: BTreeMap<Name, Vec<IpAddr>>,
+}
+
+impl HostsStamp {
+    // new hashes each of files. Only one in previous may be missing; any other is an error, as
+    // reading it would be.
+    fn new(
+        files: Vec<PathBuf>,
+        members: BTreeMap<Name, Vec<IpAddr>>,
+        previous: Option<&HostsStamp>,
+    ) -> Result<Self, errors::Error> {
+        let mut hashed = Vec::with_capacity(files.len());
+        for file in files {
+            let hash = match std::fs::read(&file) {
+                Ok(content) => Some(openssl::sha::sha256(&content)),
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::NotFound
+                        && previous.is_some_and(|p| p.files.iter().any(|(f, _)| f == &file)) =>
+                {
+                    None
+                }
+                Err(e) => {
+                    return Err(error_stack::Report::new(e))
+                        .change_context(errors::Error::Io { path: file.clone() })
+                        .attach_printable_lazy(|| {
+                            format!("could not read hosts file {}", file.display())
+                        })
+                }
+            };
+            hashed.push((file, hash));
+        }
+
+        Ok(Self {
+            files: hashed,
+            members,
+        })
+    }
 }
 
 #[derive(Clone)]
 pub struct ZTAuthority {
     pub network_id: String,
-    pub hosts_file: Option<PathBuf>,
-    pub client: central_api::Client,
+    // hosts files, or directories of *.hosts files, merged in order.
+    pub hosts_files: Vec<PathBuf>,
+    // refuse hosts files with errors in them instead of using what can be read.
+    pub hosts_strict: bool,
+    // also read addresses given in a comment line above their names.
+    pub lenient_hosts: bool,
+    // None when offline, serving only the hosts files.
+    pub client: Option<Arc<dyn CentralClient>>,
     pub reverse_authority_map: HashMap<IpNetwork, RecordAuthority>,
     pub forward_authority: RecordAuthority,
+    // serves the hosts file when its names live under a different domain than the members.
+    pub hosts_authority: Option<RecordAuthority>,
     pub wildcard: bool,
-    pub update_interval: Duration,
-    pub hosts: Option<Box<HostsFile>>,
+    pub randomize_udp_port: bool,
+    // shared so it can be changed through the control socket while the sync loop runs.
+    pub update_interval: Arc<RwLock<Duration>>,
+    // the hosts files and their aliases as last read, shared by every copy so the sync loop, the
+    // hosts file watcher and the server see the same ones.
+    pub hosts: Arc<RwLock<Option<Box<HostsFile>>>>,
+    // aliases from cname directives in the hosts files.
+    pub cnames: Arc<RwLock<Option<Cnames>>>,
+    pub cache_dir: Option<PathBuf>,
+    // the network as of the last successful sync with central.
+    pub last_network: Arc<RwLock<Option<central_api::types::Network>>>,
+    // asks zerotier-one about its peers, for peer_dns and latency_aware.
+    pub peer_client: Option<service_api::Client>,
+    // when set, peers zerotier-one has a direct path to are given records as well.
+    pub peer_dns: bool,
+    // records for the peers found on the last refresh.
+    pub peers: Arc<RwLock<Option<Peers>>>,
+    // when set, hosts bridged onto the network are given records as well.
+    pub bridge_dns: bool,
+    // records for the bridged hosts found on the last refresh.
+    pub bridged: Arc<RwLock<Option<Peers>>>,
+    // when set, answers with several members' addresses are narrowed to the nearest member.
+    pub latency_aware: bool,
+    // the node ID of the member each address is assigned to, as of the last sync.
+    pub member_nodes: Arc<RwLock<HashMap<IpAddr, String>>>,
+    // latency in milliseconds to each peer with a direct path, by node ID.
+    pub peer_latencies: Arc<RwLock<HashMap<String, i64>>>,
+    // member lists longer than this are refused rather than turned into records.
+    pub max_members: Option<usize>,
+    // the most pages of members fetched from Central in one sync.
+    pub max_pages: Option<u32>,
+    // members neither seen nor authorized for this long get no records.
+    pub max_member_age: Option<Duration>,
+    // syncs which would leave a zone with more records than this are refused for that zone.
+    pub max_records: Option<usize>,
+    // give members with only IPv6 addresses A records as well, for NAT64.
+    pub ipv4_only: bool,
+    // reverse DNS is left to someone else; there are no reverse zones.
+    pub disable_ptr: bool,
+    // member addresses in these networks are left out of DNS.
+    pub address_filter: Vec<IpNetwork>,
+    // which of the hosts files and members decides the records of a name both give.
+    pub hosts_precedence: HostsPrecedence,
+    // the addresses of each member name as of the last sync.
+    pub member_addresses: Arc<RwLock<BTreeMap<Name, Vec<IpAddr>>>>,
+    // the names claimed by more than one source of records as of the last sync.
+    pub collisions: Arc<RwLock<Collisions>>,
+    // when set, clients over the query rate limit are refused.
+    pub query_rate_limiter: Option<QueryRateLimiter>,
+    // names blocked or redirected in every zone, forwarded ones included.
+    pub rpz: Option<Arc<Rpz>>,
+    // the resolv.conf whose name servers queries outside our zones are forwarded to; the system's
+    // resolver configuration when None.
+    pub resolv_conf: Option<PathBuf>,
+    // the addresses we answer on, which are never forwarded to.
+    pub listen_ips: Vec<IpAddr>,
+    // what becomes of the client subnet of forwarded queries.
+    pub ecs_mode: EcsMode,
+    // set when queries outside our zones are refused, for want of a usable resolver configuration.
+    pub no_forwarder: Arc<AtomicBool>,
+    // while set, queries for our zones are answered with SERVFAIL; see pause.
+    pub paused: Arc<AtomicBool>,
+    // counts pauses and resumes, so an auto-resume only ends the pause it was set for.
+    pub pauses: Arc<AtomicU64>,
+    // cancelled on shutdown; find_members returns once the sync under way, if any, is done.
+    pub cancel: CancellationToken,
+    // what configure_hosts last applied; while it holds, the hosts files are not parsed again.
+    pub hosts_stamp: Arc<RwLock<Option<HostsStamp>>>,
+    // how many times configure_hosts has parsed the hosts files.
+    pub hosts_parses: Arc<AtomicU64>,
+    // set while RFC4193 addresses are on but have no reverse zones, as of the last sync.
+    pub rfc4193_unserved: Arc<AtomicBool>,
+    // when set, the members zone is served from etcd, and each sync writes its records there.
+    #[cfg(feature = "etcd-backend")]
+    pub etcd: Option<crate::backends::etcd::EtcdRecordAuthority>,
+}
+
+// hosts_by_name turns the ip -> names mapping of hosts files around.
+fn hosts_by_name(hosts: &HostsFile) -> BTreeMap<Name, Vec<IpAddr>> {
+    let mut names: BTreeMap<Name, Vec<IpAddr>> = BTreeMap::new();
+    for (ip, hostnames) in hosts {
+        for name in hostnames {
+            names.entry(name.clone()).or_default().push(*ip);
+        }
+    }
+    for ips in names.values_mut() {
+        ips.sort();
+    }
+    names
+}
+
+fn ip_rdata(ip: &IpAddr) -> RData {
+    match ip {
+        IpAddr::V4(ip) => RData::A(*ip),
+        IpAddr::V6(ip) => RData::AAAA(*ip),
+    }
+}
+
+fn rdata_ip(rdata: &RData) -> Option<IpAddr> {
+    match rdata {
+        RData::A(ip) => Some(IpAddr::V4(*ip)),
+        RData::AAAA(ip) => Some(IpAddr::V6(*ip)),
+        _ => None,
+    }
 }
 
 impl ZTAuthority {
-    pub async fn configure_hosts(&mut self) -> Result<(), errors::Error> {
-        self.hosts = Some(Box::new(
-            parse_hosts(
-                self.hosts_file.clone(),
-                self.forward_authority.domain_name.clone().into(),
+    // new builds a ZTAuthority serving forward_authority and reverse_authority_map for
+    // network_id, with everything else as `zeronsd start` has it by default; callers set the rest
+    // with struct update syntax.
+    pub fn new(
+        network_id: String,
+        client: Option<Arc<dyn CentralClient>>,
+        forward_authority: RecordAuthority,
+        reverse_authority_map: HashMap<IpNetwork, RecordAuthority>,
+        update_interval: Duration,
+    ) -> Self {
+        Self {
+            network_id,
+            hosts_files: Vec::new(),
+            hosts_strict: false,
+            lenient_hosts: false,
+            client,
+            reverse_authority_map,
+            forward_authority,
+            hosts_authority: None,
+            wildcard: false,
+            randomize_udp_port: true,
+            update_interval: Arc::new(RwLock::new(update_interval)),
+            hosts: Default::default(),
+            cnames: Default::default(),
+            cache_dir: None,
+            last_network: Default::default(),
+            peer_client: None,
+            peer_dns: false,
+            peers: Default::default(),
+            bridge_dns: false,
+            bridged: Default::default(),
+            latency_aware: false,
+            member_nodes: Default::default(),
+            peer_latencies: Default::default(),
+            max_members: None,
+            max_pages: None,
+            max_member_age: None,
+            max_records: None,
+            ipv4_only: false,
+            disable_ptr: false,
+            address_filter: default_address_filter(),
+            hosts_precedence: Default::default(),
+            member_addresses: Default::default(),
+            collisions: Default::default(),
+            query_rate_limiter: None,
+            rpz: None,
+            resolv_conf: None,
+            listen_ips: Vec::new(),
+            ecs_mode: Default::default(),
+            no_forwarder: Default::default(),
+            paused: Default::default(),
+            pauses: Default::default(),
+            cancel: Default::default(),
+            hosts_stamp: Default::default(),
+            hosts_parses: Default::default(),
+            rfc4193_unserved: Default::default(),
+            #[cfg(feature = "etcd-backend")]
+            etcd: None,
+        }
+    }
+
+    // hosts_authority is the authority names from the hosts file are written to.
+    pub fn hosts_authority(&self) -> &RecordAuthority {
+        self.hosts_authority
+            .as_ref()
+            .unwrap_or(&self.forward_authority)
+    }
+
+    #[tracing::instrument(
+        skip_all,
+        fields(addresses = tracing::field::Empty, changed = tracing::field::Empty)
+    )]
+    pub async fn configure_hosts(&self) -> Result<bool, errors::Error> {
+        let members = self.member_addresses.read().await.clone();
+        let previous = self.hosts_stamp.read().await.clone();
+        let stamp = HostsStamp::new(
+            hosts_file_list(&self.hosts_files)?,
+            members.clone(),
+            previous.as_ref(),
+        )?;
+        if previous.as_ref() == Some(&stamp) {
+            return Ok(false);
+        }
+
+        // a file deleted since it was read is as good as empty, so its names are pruned.
+        let mut files = Vec::new();
+        for (file, hash) in &stamp.files {
+            match hash {
+                Some(_) => files.push(file.clone()),
+                None => tracing::warn!(
+                    "Hosts file {} is gone; serving none of its names",
+                    file.display()
+                ),
+            }
+        }
+
+        let (hosts, cnames) = parse_hosts_files(
+            &files,
+            self.hosts_authority().domain_name.clone().into(),
+            self.hosts_strict,
+            self.lenient_hosts,
+        )?;
+        self.hosts_parses.fetch_add(1, Ordering::Relaxed);
+        let (resolved, _) = self.resolve_hosts(&hosts, &members);
+        tracing::Span::current().record("addresses", hosts.len() as i64);
+        *self.hosts.write().await = Some(Box::new(hosts));
+
+        let mut changed = 0;
+        for (hostname, ips) in hosts_by_name(&resolved) {
+            if self.hosts_authority().match_or_insert(hostname, &ips).await {
+                changed += 1;
+            }
+        }
+        tracing::Span::current().record("changed", changed);
+
+        self.hosts_authority()
+            .upsert_batch(
+                cnames
+                    .iter()
+                    .filter(|(alias, _)| self.alias_served(alias, &members))
+                    .map(|(alias, target)| (alias.clone(), vec![RData::CNAME(target.clone())]))
+                    .collect(),
+            )
+            .await;
+        *self.cnames.write().await = Some(cnames);
+        *self.hosts_stamp.write().await = Some(stamp);
+
+        Ok(true)
+    }
+
+    // resolve_hosts works out the addresses each name in the hosts files is written with, given
+    // the addresses members had at the last sync, according to hosts_precedence. It also returns
+    // the names claimed by both. Hosts files served from their own zone never conflict.
+    fn resolve_hosts(
+        &self,
+        hosts: &HostsFile,
+        members: &BTreeMap<Name, Vec<IpAddr>>,
+    ) -> (HostsFile, BTreeSet<Name>) {
+        let mut resolved: HostsFile = HashMap::new();
+        let mut conflicts = BTreeSet::new();
+
+        for (name, mut ips) in hosts_by_name(hosts) {
+            let member_ips = match members.get(&name) {
+                Some(member_ips) if self.hosts_authority.is_none() => member_ips,
+                _ => {
+                    for ip in ips {
+                        resolved.entry(ip).or_default().push(name.clone());
+                    }
+                    continue;
+                }
+            };
+            conflicts.insert(name.clone());
+
+            match self.hosts_precedence {
+                HostsPrecedence::HostsWins => {}
+                HostsPrecedence::MemberWins => continue,
+                HostsPrecedence::Merge => {
+                    for ip in member_ips {
+                        if !ips.contains(ip) {
+                            ips.push(*ip);
+                        }
+                    }
+                }
+            }
+
+            for ip in ips {
+                resolved.entry(ip).or_default().push(name.clone());
+            }
+        }
+
+        (resolved, conflicts)
+    }
+
+    // alias_served reports whether the cname directive for alias is written, given the addresses
+    // members had at the last sync. Only a member winning the name keeps it from being written.
+    fn alias_served(&self, alias: &Name, members: &BTreeMap<Name, Vec<IpAddr>>) -> bool {
+        self.hosts_authority.is_some()
+            || !members.contains_key(alias)
+            || self.hosts_precedence != HostsPrecedence::MemberWins
+    }
+
+    // resolve_members leaves every name claimed by several members with just one of them; see
+    // member_winner. The others lose their custom name, keeping their zt-<node id> name.
+    fn resolve_members(records: &mut [(String, ZTRecord)], collisions: &mut Collisions) {
+        let mut claims: BTreeMap<Name, Vec<String>> = BTreeMap::new();
+        for (node_id, record) in records.iter() {
+            for name in std::iter::once(&record.fqdn).chain(&record.custom_name) {
+                let claimants = claims.entry(name.clone()).or_default();
+                if !claimants.contains(node_id) {
+                    claimants.push(node_id.clone());
+                }
+            }
+        }
+
+        for (name, mut node_ids) in claims {
+            if node_ids.len() < 2 {
+                continue;
+            }
+            node_ids.sort();
+
+            let winner = member_winner(&name, &node_ids).cloned();
+            for (node_id, record) in records.iter_mut() {
+                if record.custom_name.as_ref() == Some(&name) && Some(&*node_id) != winner.as_ref()
+                {
+                    record.custom_name = None;
+                    record.ptr_name = record.fqdn.clone();
+                }
+            }
+
+            collisions.insert(
+                name.clone(),
+                Collision {
+                    name,
+                    sources: node_ids.into_iter().map(RecordSource::Member).collect(),
+                    winner: winner.map(RecordSource::Member),
+                },
+            );
+        }
+    }
+
+    // report_collisions logs each collision once, when it appears or how it is resolved changes,
+    // rather than on every sync.
+    async fn report_collisions(&self, collisions: Collisions) {
+        let mut reported = self.collisions.write().await;
+        for (name, collision) in &collisions {
+            if reported.get(name) != Some(collision) {
+                tracing::warn!(
+                    name = %name,
+                    sources = %collision
+                        .sources
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    winner = %collision
+                        .winner
+                        .as_ref()
+                        .map_or("all".to_string(), ToString::to_string),
+                    "{}",
+                    collision
+                );
+            }
+        }
+        for name in reported.keys() {
+            if !collisions.contains_key(name) {
+                tracing::info!(name = %name, "Name is no longer claimed by more than one source");
+            }
+        }
+        *reported = collisions;
+    }
+
+    // authorities lists every zone served.
+    fn authorities(&self) -> Vec<&RecordAuthority> {
+        let mut authorities = vec![&self.forward_authority];
+        authorities.extend(self.hosts_authority.as_ref());
+        authorities.extend(self.reverse_authority_map.values());
+        authorities
+    }
+
+    // health_check checks every zone served, logging each one failing it.
+    pub async fn health_check(&self) -> Result<(), errors::Error> {
+        let mut result = Ok(());
+        for authority in self.authorities() {
+            if let Err(e) = authority.health_check().await {
+                tracing::error!(zone = %authority.domain_name, "Zone failed its health check: {:?}", e);
+                result = Err(e);
+            }
+        }
+
+        result
+    }
+
+    // health_report is health_check zone by zone, with the records each zone holds, for the
+    // detailed health check.
+    pub async fn health_report(&self) -> serde_json::Value {
+        let mut healthy = true;
+        let mut zones = Vec::new();
+        for authority in self.authorities() {
+            let error = match authority.health_check().await {
+                Ok(()) => None,
+                Err(e) => {
+                    tracing::error!(zone = %authority.domain_name, "Zone failed its health check: {:?}", e);
+                    healthy = false;
+                    Some(format!("{:?}", e))
+                }
+            };
+            zones.push(serde_json::json!({
+                "zone": authority.domain_name.to_string(),
+                "healthy": error.is_none(),
+                "error": error,
+                "records": authority.export_json().await,
+            }));
+        }
+
+        serde_json::json!({
+            "healthy": healthy,
+            "network": self.network_summary().await,
+            "zones": zones,
+        })
+    }
+
+    // pause answers every query for our zones with SERVFAIL rather than possibly stale records,
+    // for maintenance such as editing the hosts files, until resume is called. With a timeout,
+    // queries are answered again once it passes. Forwarded queries are unaffected.
+    pub fn pause(&self, timeout: Option<Duration>) {
+        let pause = self.pauses.fetch_add(1, Ordering::SeqCst) + 1;
+        self.set_paused(true);
+
+        match timeout {
+            Some(timeout) => {
+                tracing::warn!(
+                    "Paused for {}s; queries are answered with SERVFAIL",
+                    timeout.as_secs()
+                );
+
+                let zt = self.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(timeout).await;
+                    // a resume, or another pause, has taken over in the meantime.
+                    if zt.pauses.load(Ordering::SeqCst) == pause {
+                        zt.resume();
+                    }
+                });
+            }
+            None => tracing::warn!("Paused; queries are answered with SERVFAIL"),
+        }
+    }
+
+    // resume ends a pause.
+    pub fn resume(&self) {
+        self.pauses.fetch_add(1, Ordering::SeqCst);
+        if self.paused.swap(false, Ordering::SeqCst) {
+            self.set_paused(false);
+            tracing::info!("Resumed answering queries");
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        for authority in self.authorities() {
+            authority.paused.store(paused, Ordering::SeqCst);
+        }
+        #[cfg(feature = "etcd-backend")]
+        if let Some(etcd) = &self.etcd {
+            etcd.set_paused(paused);
+        }
+    }
+
+    // upsert_zones puts our zones (members, hosts and reverse) in catalog, wrapped just as
+    // init_catalog serves them, so a program embedding zeronsd can serve them next to its own.
+    pub fn upsert_zones(&self, catalog: &mut Catalog) -> Result<(), errors::Error> {
+        let forward: Box<dyn AuthorityObject> = if self.latency_aware {
+            Box::new(LatencyAwareRecordAuthority::new(
+                self.forward_authority.clone(),
+                self.member_nodes.clone(),
+                self.peer_latencies.clone(),
+            ))
+        } else {
+            self.forward_authority.box_clone()
+        };
+        #[cfg(feature = "etcd-backend")]
+        let forward: Box<dyn AuthorityObject> = match &self.etcd {
+            Some(etcd) => Box::new(etcd.clone()),
+            None => forward,
+        };
+        catalog.upsert(
+            self.forward_authority.domain_name.clone(),
+            self.limit(forward),
+        );
+
+        if let Some(hosts_authority) = &self.hosts_authority {
+            catalog.upsert(
+                hosts_authority.domain_name.clone(),
+                self.limit(hosts_authority.box_clone()),
+            );
+        }
+
+        for (network, authority) in &self.reverse_authority_map {
+            catalog.upsert(
+                network
+                    .to_ptr_soa_name()
+                    .change_context(errors::Error::DnsName)?,
+                self.limit(authority.box_clone()),
             )
-            .change_context(errors::Error)?,
-        ));
+        }
+
+        Ok(())
+    }
+
+    // limit wraps a zone so it counts against the same per-client limit and answers by the same
+    // response policy as every other zone we serve, forwarded ones included.
+    fn limit(&self, authority: Box<dyn AuthorityObject>) -> Box<dyn AuthorityObject> {
+        let authority: Box<dyn AuthorityObject> = match &self.rpz {
+            Some(rpz) => Box::new(RpzAuthority::new(authority, rpz.clone())),
+            None => authority,
+        };
+        match &self.query_rate_limiter {
+            Some(limiter) => Box::new(RateLimitedAuthority::new(authority, limiter.clone())),
+            None => authority,
+        }
+    }
 
-        for (ip, hostnames) in self.hosts.clone().unwrap().iter() {
-            for hostname in hostnames {
-                self.forward_authority
-                    .match_or_insert(hostname.clone(), &[*ip])
-                    .await;
+    // collisions lists the names claimed by more than one source as of the last sync.
+    pub async fn collisions(&self) -> Vec<Collision> {
+        self.collisions.read().await.values().cloned().collect()
+    }
+
+    // dangling_cnames lists the aliases whose target is in one of our zones but has no records
+    // there. Targets outside our zones are not checked.
+    pub async fn dangling_cnames(&self) -> Vec<(Name, Name)> {
+        let mut dangling = Vec::new();
+
+        let cnames = self.cnames.read().await.clone();
+        for (alias, target) in cnames.iter().flatten() {
+            let lower = LowerName::from(target);
+            let authority = [Some(&self.forward_authority), self.hosts_authority.as_ref()]
+                .into_iter()
+                .flatten()
+                .filter(|authority| authority.domain_name.zone_of(&lower))
+                .max_by_key(|authority| authority.domain_name.num_labels());
+
+            if let Some(authority) = authority {
+                if !authority.has_name(target).await {
+                    dangling.push((alias.clone(), target.clone()));
+                }
             }
         }
 
+        dangling
+    }
+
+    async fn warn_dangling_cnames(&self) {
+        for (alias, target) in self.dangling_cnames().await {
+            tracing::warn!(record = %alias, target = %target, "Alias target has no records");
+        }
+    }
+
+    // reload_hosts re-reads the hosts file and applies it straight away, removing the names that
+    // were dropped from it since it was last read.
+    pub async fn reload_hosts(&self) -> Result<(), errors::Error> {
+        let previous = self.hosts.read().await.clone();
+        let previous_cnames = self.cnames.read().await.clone();
+        if !self.configure_hosts().await? {
+            return Ok(());
+        }
+
+        let hosts = self.hosts.read().await.clone().unwrap_or_default();
+        self.hosts_authority().prune_hosts(&hosts).await?;
+
+        if let Some(previous) = previous {
+            let current: HashSet<&Name> = hosts.values().flatten().collect();
+            let removed = previous
+                .values()
+                .flatten()
+                .filter(|name| !current.contains(name))
+                .flat_map(|name| {
+                    [RecordType::A, RecordType::AAAA].map(|rt| RrKey::new(name.into(), rt))
+                })
+                .collect();
+            self.hosts_authority().bulk_delete(removed).await;
+        }
+
+        if let Some(previous) = previous_cnames {
+            let current = self.cnames.read().await.clone().unwrap_or_default();
+            let removed = previous
+                .keys()
+                .filter(|alias| !current.contains_key(alias))
+                .map(|alias| RrKey::new(alias.into(), RecordType::CNAME))
+                .collect();
+            self.hosts_authority().bulk_delete(removed).await;
+        }
+
+        self.warn_dangling_cnames().await;
+
+        Ok(())
+    }
+
+    // configure_peers adds records for the peers zerotier-one knows a direct path to, if enabled.
+    // They are kept until the next call, when those no longer reachable are pruned along with any
+    // departed members. The latencies to peers are refreshed too.
+    pub async fn configure_peers(&self) -> Result<(), errors::Error> {
+        let client = match &self.peer_client {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+
+        let peers = client.get_peers().await.local_context()?;
+        *self.peer_latencies.write().await = peers
+            .iter()
+            .filter_map(|peer| Some((peer.address.clone()?, peer.latency?)))
+            .collect();
+
+        if !self.peer_dns {
+            return Ok(());
+        }
+
+        let records = peer_records(&peers, self.forward_authority.domain_name.clone().into());
+        // a member with the same name keeps it.
+        let members = self.member_addresses.read().await.clone();
+
+        self.forward_authority
+            .upsert_batch(
+                records
+                    .iter()
+                    .filter(|(name, _)| !members.contains_key(name))
+                    .map(|(name, ip)| {
+                        let rdata = match ip {
+                            IpAddr::V4(ip) => RData::A(*ip),
+                            IpAddr::V6(ip) => RData::AAAA(*ip),
+                        };
+                        (name.clone(), vec![rdata])
+                    })
+                    .collect(),
+            )
+            .await;
+
+        *self.peers.write().await = Some(records);
+        Ok(())
+    }
+
+    // configure_bridged adds records for hosts bridged onto the network, if enabled: those in the
+    // ARP table on the network's interface which are not members. As with peers, they are kept
+    // until the next call, and those gone by then pruned along with any departed members.
+    pub async fn configure_bridged(&self) -> Result<(), errors::Error> {
+        let client = match &self.peer_client {
+            Some(client) if self.bridge_dns => client,
+            _ => return Ok(()),
+        };
+
+        let device = client
+            .get_network(&self.network_id)
+            .await
+            .local_context()?
+            .into_inner()
+            .port_device_name
+            .ok_or_else(|| {
+                error_stack::Report::new(errors::Error::LocalApi)
+                    .attach_printable("zerotier-one did not name the network's interface")
+            })?;
+        let arp = read_file_to_string(std::path::Path::new(ARP_TABLE), "ARP table")?;
+
+        let member_macs: HashSet<u64> = self
+            .member_nodes
+            .read()
+            .await
+            .values()
+            .filter_map(|node_id| member_mac(&self.network_id, node_id).ok())
+            .collect();
+        self.serve_bridged(bridged_records(
+            &arp,
+            &device,
+            &member_macs,
+            self.forward_authority.domain_name.clone().into(),
+        ))
+        .await;
         Ok(())
     }
 
+    // serve_bridged writes records for bridged hosts, replacing those of the last call at the next
+    // sync.
+    pub async fn serve_bridged(&self, records: Peers) {
+        // a member with the same name keeps it.
+        let members = self.member_addresses.read().await.clone();
+
+        self.forward_authority
+            .upsert_batch(
+                records
+                    .iter()
+                    .filter(|(name, _)| !members.contains_key(name))
+                    .map(|(name, ip)| (name.clone(), vec![ip_rdata(ip)]))
+                    .collect(),
+            )
+            .await;
+
+        *self.bridged.write().await = Some(records);
+    }
+
+    #[tracing::instrument(skip_all, fields(members = members.len() as i64))]
     pub async fn configure_members(
         &self,
         network: central_api::types::Network,
         members: Vec<central_api::types::Member>,
     ) -> Result<(), errors::Error> {
-        let mut forward_records = vec![self.forward_authority.domain_name.clone()];
+        crate::metrics::MEMBERS.set(members.len() as i64);
+
+        // the records we already have are kept rather than risking running out of memory.
+        let max_members = self.max_members.unwrap_or(usize::MAX);
+        if members.len() > max_members {
+            tracing::error!(
+                "Network has {} members, more than max_members ({}); not updating records",
+                members.len(),
+                max_members
+            );
+            return Ok(());
+        }
+
+        // members long gone from the network are left out, so their names go with them.
+        let members = match self.max_member_age {
+            Some(max_age) => active_members(members, max_age, std::time::SystemTime::now()),
+            None => members,
+        };
+
+        let mut forward_records =
+            vec![(self.forward_authority.domain_name.clone(), RecordType::NS)];
         let mut reverse_records = HashMap::new();
 
         self.reverse_authority_map
             .iter()
             .for_each(|(network, authority)| {
-                reverse_records.insert(network, vec![authority.domain_name.clone()]);
+                reverse_records.insert(
+                    network,
+                    vec![(authority.domain_name.clone(), RecordType::NS)],
+                );
             });
 
-        if let Some(hosts) = self.hosts.clone() {
-            self.forward_authority
-                .prune_hosts(hosts.clone())
-                .await
-                .change_context(errors::Error)?;
-            forward_records.append(&mut hosts.values().flatten().map(|v| v.into()).collect());
-        }
-
         let (mut sixplane, mut rfc4193) = (None, None);
-
-        let v6assign = network.config.clone().unwrap().v6_assign_mode;
+        // the RFC4193 network, when it has addresses but no reverse zones to put them in.
+        let mut unserved = None;
+
+        // central briefly sends networks without a config while the controller restarts; members
+        // still get their assigned IPs, just none of the v6 modes.
+        let v6assign = match network.config.as_ref() {
+            Some(config) => config.v6_assign_mode.clone(),
+            None => {
+                tracing::warn!(
+                    "Network {} has no config; syncing without 6PLANE or RFC4193 records",
+                    network.id.as_deref().unwrap_or_default()
+                );
+                None
+            }
+        };
         if let Some(v6assign) = v6assign {
             if v6assign._6plane.unwrap_or(false) {
-                let s = network.clone().sixplane().change_context(errors::Error)?;
-                sixplane = Some(s);
+                match network.sixplane() {
+                    Ok(s) => sixplane = Some(s),
+                    Err(e) => tracing::warn!("Skipping 6PLANE records: {:?}", e),
+                }
             }
 
             if v6assign.rfc4193.unwrap_or(false) {
-                let s = network.clone().rfc4193().change_context(errors::Error)?;
-                rfc4193 = Some(s);
-                reverse_records
-                    .get_mut(&s)
-                    .unwrap()
-                    .push(s.to_ptr_soa_name().change_context(errors::Error)?)
-            }
-        }
-
-        for member in members {
-            let record = ZTRecord::new(
-                &member,
-                sixplane,
-                rfc4193,
-                self.forward_authority.domain_name.clone().into(),
-                self.wildcard,
-            )
-            .change_context(errors::Error)?;
-
-            self.forward_authority
-                .insert_member(&mut forward_records, record.clone())
-                .await
-                .change_context(errors::Error)?;
+                match network.rfc4193() {
+                    Ok(s) => rfc4193 = Some(s),
+                    Err(e) => tracing::warn!("Skipping RFC4193 records: {:?}", e),
+                }
+            }
 
-            if let Some(ips) = member.clone().config.and_then(|c| {
-                c.ip_assignments.map(|v| {
-                    v.iter()
-                        .filter_map(|ip| IpAddr::from_str(ip).ok())
-                        .collect::<Vec<IpAddr>>()
-                })
-            }) {
-                for (network, authority) in self.reverse_authority_map.clone() {
-                    for ip in ips.clone() {
-                        if network.contains(ip) {
-                            authority
-                                .insert_member_ptr(
-                                    reverse_records.get_mut(&network).unwrap(),
-                                    record.clone(),
-                                )
-                                .await
-                                .change_context(errors::Error)?;
-                        }
+            if let Some(s) = rfc4193 {
+                // reverse zones are only set up at startup, so one turned on since has none until
+                // a restart. Turned off, its zone is left up but emptied, as the addresses are gone.
+                for zone in s.to_ptr_zones() {
+                    match reverse_records.get_mut(&zone) {
+                        Some(records) => records.push((
+                            zone.to_ptr_soa_name()
+                                .change_context(errors::Error::DnsName)?,
+                            RecordType::NS,
+                        )),
+                        None if !self.disable_ptr => unserved = Some(s),
+                        None => {}
                     }
                 }
             }
+        }
+
+        // warned about when it starts rather than at every sync until the restart.
+        if self
+            .rfc4193_unserved
+            .swap(unserved.is_some(), Ordering::SeqCst)
+            != unserved.is_some()
+        {
+            if let Some(s) = unserved {
+                tracing::warn!(
+                    "RFC4193 addresses were turned on after startup; restart to serve PTR records for {}",
+                    s
+                );
+            }
+        }
+
+        let mut forward_batch = Vec::new();
+        let mut reverse_batch: HashMap<IpNetwork, Vec<(Name, Vec<RData>)>> = HashMap::new();
+
+        // every name is settled on a single source before anything is written.
+        let mut records = member_records_parallel(
+            members,
+            sixplane,
+            rfc4193,
+            self.forward_authority.domain_name.clone().into(),
+            self.wildcard,
+            &self.address_filter,
+        )
+        .await?;
+
+        let mut collisions = Collisions::new();
+        Self::resolve_members(&mut records, &mut collisions);
+
+        *self.member_nodes.write().await = records
+            .iter()
+            .flat_map(|(node_id, record)| record.ips.iter().map(move |ip| (*ip, node_id.clone())))
+            .collect();
 
-            if let Some(ptr) = rfc4193 {
-                if let Some(authority) = self.reverse_authority_map.get(&ptr) {
-                    if let Some(records) = reverse_records.get_mut(&ptr) {
-                        let ptr = member
-                            .rfc4193()
-                            .change_context(errors::Error)?
-                            .ip()
-                            .into_name()
-                            .change_context(errors::Error)?;
-                        authority
-                            .configure_ptr(ptr.clone(), record.ptr_name.clone())
-                            .await
-                            .change_context(errors::Error)?;
-                        records.push(ptr.into());
+        // every name a member writes, wildcards included, since a hosts file may claim those too.
+        let owners: BTreeMap<Name, String> = records
+            .iter()
+            .flat_map(|(node_id, record)| {
+                record
+                    .names()
+                    .into_iter()
+                    .map(move |name| (name, node_id.clone()))
+            })
+            .collect();
+
+        for (_, record) in records {
+            // synthetic addresses are only ever served forward; they have no place in our reverse
+            // zones.
+            if self.ipv4_only {
+                RecordAuthority::batch_member(
+                    &mut forward_records,
+                    &mut forward_batch,
+                    &record.clone().with_synthetic_ipv4(),
+                );
+            } else {
+                RecordAuthority::batch_member(&mut forward_records, &mut forward_batch, &record);
+            }
+
+            // the map is keyed by the zones' networks, so this is where the PTR is answered. The
+            // member's RFC4193 address is among its addresses, and answered the same way.
+            for (network, authority) in &self.reverse_authority_map {
+                if record.ips.iter().any(|ip| network.contains(*ip)) {
+                    RecordAuthority::batch_member_ptr(
+                        reverse_records.get_mut(network).unwrap(),
+                        reverse_batch.entry(*network).or_default(),
+                        &record,
+                        network,
+                        &authority.domain_name,
+                    )?;
+                }
+            }
+        }
+
+        // peers and bridged hosts never take a name from a member; configure_peers and
+        // configure_bridged leave them out next time.
+        let (peers, bridged) = (self.peers.read().await, self.bridged.read().await);
+        for (records, source) in [
+            (&*peers, RecordSource::Peer),
+            (&*bridged, RecordSource::Bridge),
+        ] {
+            for (name, ip) in records.iter().flatten() {
+                match owners.get(name) {
+                    Some(node_id) => {
+                        collisions.insert(
+                            name.clone(),
+                            Collision {
+                                name: name.clone(),
+                                sources: vec![
+                                    RecordSource::Member(node_id.clone()),
+                                    source.clone(),
+                                ],
+                                winner: Some(RecordSource::Member(node_id.clone())),
+                            },
+                        );
                     }
+                    None => forward_records.push((name.into(), ip_record_type(ip))),
                 }
             }
         }
+        drop((peers, bridged));
 
-        self.forward_authority
-            .prune_records(forward_records.clone())
-            .await
-            .change_context(errors::Error)?;
+        // with the hosts files and members known, settle the names both claim before writing.
+        let members: BTreeMap<Name, Vec<IpAddr>> = forward_batch
+            .iter()
+            .map(|(name, rdatas)| (name.clone(), rdatas.iter().filter_map(rdata_ip).collect()))
+            .collect();
+        *self.member_addresses.write().await = members.clone();
+
+        let max_records = self.max_records.unwrap_or(DEFAULT_MAX_RECORDS);
+        let mut quota = None;
+
+        let hosts = self.hosts.read().await.clone();
+        let cnames = self.cnames.read().await.clone();
+        if let Some(hosts) = hosts {
+            let (resolved, mut conflicts) = self.resolve_hosts(&hosts, &members);
+            for name in &conflicts {
+                let Some(node_id) = owners.get(name) else {
+                    continue;
+                };
+                let member = RecordSource::Member(node_id.clone());
+                collisions.insert(
+                    name.clone(),
+                    Collision {
+                        name: name.clone(),
+                        sources: vec![member.clone(), RecordSource::Hosts],
+                        winner: hosts_winner(self.hosts_precedence, RecordSource::Hosts, member),
+                    },
+                );
+            }
+
+            // aliases only conflict with members in the same zone, like addresses.
+            let aliases: Vec<&Name> = cnames
+                .iter()
+                .flatten()
+                .map(|(alias, _)| alias)
+                .filter(|alias| self.hosts_authority.is_none() && owners.contains_key(alias))
+                .collect();
+            for alias in &aliases {
+                let member = RecordSource::Member(owners[*alias].clone());
+                collisions.insert(
+                    (*alias).clone(),
+                    Collision {
+                        name: (*alias).clone(),
+                        sources: vec![member.clone(), RecordSource::Cname],
+                        winner: hosts_winner(self.hosts_precedence, RecordSource::Cname, member),
+                    },
+                );
+                if self.alias_served(alias, &members) {
+                    conflicts.insert((*alias).clone());
+                }
+            }
 
-        for (network, authority) in self.reverse_authority_map.clone() {
-            authority
-                .prune_records(reverse_records.get(&network).unwrap().clone())
+            // whichever side did not win a conflict does not write the name at all. Merged
+            // addresses are written by the hosts side.
+            if self.hosts_precedence != HostsPrecedence::MemberWins {
+                forward_batch.retain(|(name, _)| !conflicts.contains(name));
+                forward_records.retain(|(name, _)| !conflicts.contains(&Name::from(name)));
+            }
+
+            let mut hosts_batch = hosts_by_name(&resolved)
+                .into_iter()
+                .map(|(name, ips)| (name, ips.iter().map(ip_rdata).collect()))
+                .collect();
+
+            let mut hosts_records = resolved
+                .iter()
+                .flat_map(|(ip, names)| names.iter().map(|name| (name.into(), ip_record_type(ip))))
+                .chain(
+                    cnames
+                        .iter()
+                        .flatten()
+                        .filter(|(alias, _)| self.alias_served(alias, &members))
+                        .map(|(alias, _)| (alias.into(), RecordType::CNAME)),
+                )
+                .collect();
+            // the hosts files and members never write the same name in a zone; whichever lost a
+            // conflict was left out above. So sharing a zone, they can be written together.
+            match &self.hosts_authority {
+                Some(authority) => {
+                    let mut records = vec![(authority.domain_name.clone(), RecordType::NS)];
+                    records.append(&mut hosts_records);
+                    quota = quota.or(authority
+                        .apply_batch(hosts_batch, records, max_records)
+                        .await
+                        .err());
+                }
+                None => {
+                    forward_batch.append(&mut hosts_batch);
+                    forward_records.append(&mut hosts_records);
+                }
+            }
+        }
+
+        self.report_collisions(collisions).await;
+
+        quota = quota.or(self
+            .forward_authority
+            .apply_batch(forward_batch, forward_records, max_records)
+            .await
+            .err());
+
+        for (network, authority) in &self.reverse_authority_map {
+            quota = quota.or(authority
+                .apply_batch(
+                    reverse_batch.remove(network).unwrap_or_default(),
+                    reverse_records.remove(network).unwrap_or_default(),
+                    max_records,
+                )
                 .await
-                .change_context(errors::Error)?;
+                .err());
         }
 
-        Ok(())
+        self.warn_dangling_cnames().await;
+
+        // a zone over quota was left as it was; the others are up to date.
+        match quota {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // save_snapshot persists the network and member list to the cache directory, if configured.
+    fn save_snapshot(
+        &self,
+        network: &central_api::types::Network,
+        members: &[central_api::types::Member],
+    ) {
+        if let Some(cache_dir) = &self.cache_dir {
+            let snapshot =
+                Snapshot::new(self.network_id.clone(), network.clone(), members.to_vec());
+            if let Err(e) = snapshot.save(cache_dir) {
+                tracing::error!("error writing snapshot: {:?}", e);
+            }
+        }
     }
 
+    // load_snapshot reads the last persisted network and member list, if any.
+    fn load_snapshot(&self) -> Option<Snapshot> {
+        self.cache_dir
+            .as_deref()
+            .and_then(|cache_dir| Snapshot::load(cache_dir, &self.network_id))
+    }
+
+    #[tracing::instrument(skip_all, fields(members = tracing::field::Empty))]
     pub async fn get_members(
         &self,
     ) -> Result<(central_api::types::Network, Vec<central_api::types::Member>), errors::Error> {
-        let client = self.client.clone();
-        let network_id = self.network_id.clone();
+        let client = self
+            .client
+            .as_ref()
+            .ok_or(errors::Error::Config)
+            .attach_printable("offline; there is no Central to fetch members from")?;
+
+        // the two requests are independent, so don't pay for both round trips.
+        let (members, network) = tokio::try_join!(
+            async {
+                client
+                    .get_network_member_list(
+                        &self.network_id,
+                        self.max_pages.unwrap_or(DEFAULT_MAX_MEMBER_PAGES),
+                    )
+                    .await
+                    .attach_printable("could not fetch the member list")
+            },
+            async {
+                client
+                    .get_network_by_id(&self.network_id)
+                    .await
+                    .attach_printable("could not fetch the network")
+            },
+        )?;
 
-        let members = client
-            .get_network_member_list(&network_id)
-            .await
-            .change_context(errors::Error)?;
-        let network = client
-            .get_network_by_id(&network_id)
+        *self.last_network.write().await = Some(network.clone());
+        tracing::Span::current().record("members", members.len() as i64);
+
+        Ok((network, members))
+    }
+
+    // network_info returns the network as of the last successful sync with central.
+    pub async fn network_info(&self) -> Option<central_api::types::Network> {
+        self.last_network.read().await.clone()
+    }
+
+    // network_summary is the ID of the network served, with its name in central once a sync has
+    // brought it, for the health check and zeronsd status.
+    pub async fn network_summary(&self) -> serde_json::Value {
+        let name = self
+            .network_info()
             .await
-            .change_context(errors::Error)?;
+            .and_then(|network| network.config?.name);
+        serde_json::json!({
+            "id": self.network_id,
+            "name": name,
+        })
+    }
+}
+
+// where Linux lists the neighbours it has resolved addresses of, bridged hosts among them.
+pub const ARP_TABLE: &str = "/proc/net/arp";
+
+// bridged_records names each host arp, in the format of /proc/net/arp, has a complete entry for
+// on device, bar members, bridge-<mac>. ZeroTier's bridges answer ARP for the hosts behind them,
+// so those hosts show up here once this host has talked to them.
+pub fn bridged_records(
+    arp: &str,
+    device: &str,
+    member_macs: &HashSet<u64>,
+    domain_name: Name,
+) -> Vec<(Name, IpAddr)> {
+    // ATF_COM: the hardware address is known.
+    const COMPLETE: u32 = 0x2;
+
+    let mut records = Vec::new();
+    for line in arp.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (ip, flags, mac, dev) = match fields.as_slice() {
+            [ip, _, flags, mac, _, dev] => (ip, flags, mac, dev),
+            _ => continue,
+        };
+        if dev != &device {
+            continue;
+        }
+
+        let complete = u32::from_str_radix(flags.trim_start_matches("0x"), 16)
+            .is_ok_and(|flags| flags & COMPLETE != 0);
+        let (ip, mac) = match (
+            std::net::Ipv4Addr::from_str(ip),
+            u64::from_str_radix(&mac.replace(':', ""), 16),
+        ) {
+            (Ok(ip), Ok(mac)) if complete => (ip, mac),
+            _ => continue,
+        };
+        if member_macs.contains(&mac) {
+            continue;
+        }
+
+        match format!("bridge-{:012x}", mac).to_fqdn(domain_name.clone()) {
+            Ok(name) => records.push((name, IpAddr::V4(ip))),
+            Err(e) => tracing::warn!("Skipping bridged host {}: {:?}", ip, e),
+        }
+    }
+
+    records.sort();
+    records
+}
+
+// peer_records names the peers we have a direct path to, as zt-<nodeid>-peer.<domain>, pointing at
+// the physical address of the path in use. Peers without a known latency are only reachable via
+// relay and are skipped.
+pub fn peer_records(peers: &[service_api::types::Peer], domain_name: Name) -> Vec<(Name, IpAddr)> {
+    let mut records = Vec::new();
+
+    for peer in peers {
+        if peer.latency.is_none_or(|latency| latency < 0) {
+            continue;
+        }
 
-        Ok((network.to_owned(), members.to_owned()))
+        let address = match &peer.address {
+            Some(address) => address,
+            None => continue,
+        };
+
+        let usable = |path: &&service_api::types::PeerPathsItem| {
+            path.active.unwrap_or(false) && !path.expired.unwrap_or(false)
+        };
+        let path = peer
+            .paths
+            .iter()
+            .filter(usable)
+            .find(|path| path.preferred.unwrap_or(false))
+            .or_else(|| peer.paths.iter().find(usable));
+
+        // paths are reported as ip/port.
+        let ip = match path
+            .and_then(|path| path.address.as_deref())
+            .and_then(|addr| addr.rsplit_once('/'))
+            .and_then(|(ip, _)| IpAddr::from_str(ip).ok())
+        {
+            Some(ip) => ip,
+            None => continue,
+        };
+
+        match format!("zt-{}-peer", address).to_fqdn(domain_name.clone()) {
+            Ok(name) => records.push((name, ip)),
+            Err(e) => tracing::warn!(node_id = %address, error = ?e, "Invalid peer address"),
+        }
     }
+
+    records
 }
 
 #[derive(Clone)]
 pub struct RecordAuthority {
     domain_name: LowerName,
     authority: Arc<InMemoryAuthority>,
+    serial_policy: SoaSerialPolicy,
+    serial_increment: u32,
+    // the record types apply_batch may remove.
+    managed_types: &'static [RecordType],
+    // shared by every clone, so the copies in the catalog see ZTAuthority::pause.
+    paused: Arc<AtomicBool>,
 }
 
 impl RecordAuthority {
@@ -277,14 +1796,102 @@ impl RecordAuthority {
     ) -> Result<Self, errors::Error> {
         Ok(Self {
             authority: Arc::new(
-                Self::configure_authority(domain_name.clone().into(), member_name.into())
-                    .await
-                    .change_context(errors::Error)?,
+                Self::configure_authority(domain_name.clone().into(), member_name.into()).await?,
             ),
             domain_name,
+            serial_policy: SoaSerialPolicy::default(),
+            serial_increment: 1,
+            managed_types: SYNCED_RECORD_TYPES,
+            paused: Default::default(),
         })
     }
 
+    // with_managed_types sets the record types syncs write to the zone, and so may remove from it.
+    // The SOA and NS records at the apex are never removed, whatever is given.
+    pub fn with_managed_types(mut self, types: &'static [RecordType]) -> Self {
+        self.managed_types = types;
+        self
+    }
+
+    // with_serial_policy sets how the SOA serial moves forward when the zone changes.
+    pub fn with_serial_policy(mut self, policy: SoaSerialPolicy, increment: u32) -> Self {
+        self.serial_policy = policy;
+        self.serial_increment = increment;
+        self
+    }
+
+    // clone_with_domain creates an empty zone for new_domain, served by the same nameserver and
+    // with the same serial policy. None of its records or its pause flag are shared with this one.
+    pub async fn clone_with_domain(
+        &self,
+        new_domain: LowerName,
+    ) -> Result<RecordAuthority, errors::Error> {
+        let member_name = self
+            .authority
+            .records()
+            .await
+            .get(&RrKey::new(self.domain_name.clone(), RecordType::NS))
+            .and_then(|rrset| rrset.records_without_rrsigs().next())
+            .and_then(|record| match record.data() {
+                Some(RData::NS(name)) => Some(LowerName::from(name)),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                error_stack::Report::new(errors::Error::DnsName)
+                    .attach_printable(format!("{} has no NS record to copy", self.domain_name))
+            })?;
+
+        Ok(RecordAuthority::new(new_domain, member_name)
+            .await?
+            .with_serial_policy(self.serial_policy, self.serial_increment)
+            .with_managed_types(self.managed_types))
+    }
+
+    // serial is the current SOA serial of the zone.
+    pub async fn serial(&self) -> u32 {
+        self.authority.serial().await
+    }
+
+    // bump_serial moves the SOA serial forward after a change to the zone. It works on the record
+    // map directly as callers already hold the lock on it.
+    fn bump_serial(&self, rr: &mut BTreeMap<RrKey, Arc<RecordSet>>) {
+        let rrkey = RrKey::new(self.domain_name.clone(), RecordType::SOA);
+        let record = match rr
+            .get(&rrkey)
+            .and_then(|rrset| rrset.records_without_rrsigs().next().cloned())
+        {
+            Some(record) => record,
+            None => return,
+        };
+
+        if let Some(RData::SOA(soa)) = record.data() {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let serial = self
+                .serial_policy
+                .next_serial(soa.serial(), self.serial_increment, now);
+
+            let soa = SOA::new(
+                soa.mname().clone(),
+                soa.rname().clone(),
+                serial,
+                soa.refresh(),
+                soa.retry(),
+                soa.expire(),
+                soa.minimum(),
+            );
+
+            let mut rrset = RecordSet::new(record.name(), RecordType::SOA, serial);
+            rrset.insert(
+                Record::from_rdata(record.name().clone(), record.ttl(), RData::SOA(soa)),
+                serial,
+            );
+            rr.insert(rrkey, Arc::new(rrset));
+        }
+    }
+
     async fn configure_authority(
         domain_name: Name,
         member_name: Name,
@@ -295,9 +1902,9 @@ impl RecordAuthority {
         soa.set_data(Some(RData::SOA(SOA::new(
             domain_name.clone(),
             Name::from_str("administrator")
-                .change_context(errors::Error)?
+                .change_context(errors::Error::DnsName)?
                 .append_domain(&domain_name)
-                .change_context(errors::Error)?,
+                .change_context(errors::Error::DnsName)?,
             1,
             30,
             0,
@@ -333,236 +1940,545 @@ impl RecordAuthority {
         Ok(authority)
     }
 
-    async fn replace_ip_record(&self, fqdn: Name, rdatas: Vec<RData>) {
+    // replace_ip_record swaps the rt record set of fqdn for one holding just rdatas. Data of any
+    // other type is left out, so an A set never picks up IPv6 addresses.
+    async fn replace_ip_record(&self, fqdn: Name, rt: RecordType, rdatas: Vec<RData>) {
         let serial = self.authority.serial().await;
+        let mut rrset = RecordSet::with_ttl(fqdn.clone(), rt, 60);
         for rdata in rdatas {
-            let mut address = Record::with(fqdn.clone(), rdata.to_record_type(), 60);
-            address.set_data(Some(rdata.clone()));
-            tracing::info!("Adding new record {}: ({})", fqdn.clone(), rdata);
-            self.authority.upsert(address, serial).await;
+            if rdata.to_record_type() != rt {
+                tracing::warn!(
+                    record = %fqdn,
+                    rdata = %rdata,
+                    "Leaving out data that is not {}",
+                    rt
+                );
+                continue;
+            }
+            tracing::info!(record = %fqdn, rdata = %rdata, "Adding new record");
+            rrset.insert(Record::from_rdata(fqdn.clone(), 60, rdata), serial);
         }
+
+        let mut rr = self.authority.records_mut().await;
+        rr.insert(RrKey::new(fqdn.into(), rt), Arc::new(rrset));
+        self.bump_serial(&mut rr);
     }
 
-    async fn prune_hosts(&self, hosts: Box<HostsFile>) -> Result<(), errors::Error> {
+    // prune_hosts brings the A and AAAA record sets of names in hosts back in line with the file,
+    // each type on its own: a set is replaced only when its addresses differ from the file's, and
+    // removed when the file has none of that type for the name. Other record types are not
+    // touched. The zone is only looked at while finding those, and the changes are made after.
+    pub async fn prune_hosts(&self, hosts: &HostsFile) -> Result<(), errors::Error> {
         let serial = self.authority.serial().await;
+
+        let mut hosts_map: HashMap<LowerName, Vec<IpAddr>> = HashMap::new();
+        for (ip, hosts) in hosts {
+            for host in hosts {
+                hosts_map.entry(host.into()).or_default().push(*ip);
+            }
+        }
+
         let mut rr = self.authority.records_mut().await;
+        let mut replacements = Vec::new();
 
-        let mut hosts_map = HashMap::new();
+        for (name, ips) in &hosts_map {
+            // names the zone has no addresses for are left to configure_hosts, which knows which
+            // of them belong in it.
+            let types = [RecordType::A, RecordType::AAAA];
+            if !types
+                .iter()
+                .any(|rt| rr.contains_key(&RrKey::new(name.clone(), *rt)))
+            {
+                continue;
+            }
 
-        for (ip, hosts) in hosts.into_iter() {
-            for host in hosts {
-                if !hosts_map.contains_key(&host) {
-                    hosts_map.insert(host.clone(), vec![]);
+            for rt in types {
+                let mut wanted: Vec<IpAddr> = ips
+                    .iter()
+                    .filter(|ip| match rt {
+                        RecordType::A => ip.is_ipv4(),
+                        _ => ip.is_ipv6(),
+                    })
+                    .copied()
+                    .collect();
+                wanted.sort();
+                wanted.dedup();
+
+                let rrkey = RrKey::new(name.clone(), rt);
+                let mut existing: Vec<IpAddr> = rr
+                    .get(&rrkey)
+                    .map(|rset| {
+                        rset.records_without_rrsigs()
+                            .filter_map(|r| r.data().and_then(RData::to_ip_addr))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                existing.sort();
+                existing.dedup();
+
+                if existing == wanted {
+                    continue;
                 }
 
-                hosts_map.get_mut(&host).unwrap().push(ip);
+                let key = Name::from(name);
+                let new_rset = (!wanted.is_empty()).then(|| {
+                    let mut rset = RecordSet::new(&key, rt, serial);
+                    for ip in &wanted {
+                        rset.add_rdata(match ip {
+                            IpAddr::V4(ip) => RData::A(*ip),
+                            IpAddr::V6(ip) => RData::AAAA(*ip),
+                        });
+                    }
+                    rset
+                });
+
+                tracing::warn!(record = %key, r#type = %rt, ips = ?wanted, "Replacing host record");
+                replacements.push((rrkey, new_rset));
+            }
+        }
+
+        if replacements.is_empty() {
+            return Ok(());
+        }
+
+        for (rrkey, rset) in replacements {
+            match rset {
+                Some(rset) => rr.insert(rrkey, Arc::new(rset)),
+                None => rr.remove(&rrkey),
+            };
+        }
+        self.bump_serial(&mut rr);
+
+        Ok(())
+    }
+
+    // apply_batch writes a sync's changes to the zone in one go: the record sets in records are
+    // upserted as with upsert_batch, and every record set of a managed type named neither there
+    // nor in keep is removed. Other types, and the SOA and NS at the apex, are always left in
+    // place. Record types are kept separately, so a name losing its only IPv4 address keeps its
+    // AAAA records. The zone is locked once, so queries never see it half
+    // updated; how long that took is recorded on the span and in the zone_lock_held_seconds
+    // metric. Changes which would leave more than max_records records are refused, leaving the
+    // zone untouched.
+    #[tracing::instrument(
+        name = "prune",
+        skip_all,
+        fields(
+            zone = %self.domain_name,
+            removed = tracing::field::Empty,
+            lock_held_us = tracing::field::Empty
+        )
+    )]
+    pub async fn apply_batch(
+        &self,
+        records: Vec<(Name, Vec<RData>)>,
+        keep: Vec<(LowerName, RecordType)>,
+        max_records: usize,
+    ) -> Result<(), errors::Error> {
+        let rrsets = Self::record_sets(records, self.authority.serial().await);
+        let mut keep: HashSet<(LowerName, RecordType)> = keep.into_iter().collect();
+        keep.extend(
+            rrsets
+                .keys()
+                .map(|rrkey| (rrkey.name().clone(), rrkey.record_type)),
+        );
+
+        let kept = |rrkey: &RrKey| {
+            let rtype = rrkey.record_type;
+            !self.managed_types.contains(&rtype)
+                || (rrkey.name() == &self.domain_name
+                    && matches!(rtype, RecordType::SOA | RecordType::NS))
+                || keep.contains(&(rrkey.name().clone(), rtype))
+        };
+
+        let mut rr = self.authority.records_mut().await;
+        let locked = std::time::Instant::now();
+
+        let total = rrsets
+            .values()
+            .map(|rs| rs.records_without_rrsigs().count())
+            .sum::<usize>()
+            + rr.iter()
+                .filter(|(rrkey, _)| !rrsets.contains_key(rrkey) && kept(rrkey))
+                .map(|(_, rs)| rs.records_without_rrsigs().count())
+                .sum::<usize>();
+        if total > max_records {
+            drop(rr);
+            crate::metrics::RECORD_QUOTA_EXCEEDED.inc();
+            tracing::error!(
+                records = total,
+                max_records,
+                "Refusing to update zone {}: the sync would leave {} records, more than max_records ({}); still serving the previous records",
+                self.domain_name,
+                total,
+                max_records
+            );
+            return Err(errors::Error::RecordQuota).attach_printable_lazy(|| {
+                format!(
+                    "zone {} would have {} records, more than {}",
+                    self.domain_name, total, max_records
+                )
+            });
+        }
+
+        let mut changed = self.write_record_sets(&mut rr, rrsets);
+        let mut removed = 0;
+        rr.retain(|rrkey, _| {
+            if kept(rrkey) {
+                return true;
+            }
+            tracing::warn!(record = %rrkey.name(), r#type = %rrkey.record_type, "Removing expired record");
+            removed += 1;
+            false
+        });
+        changed |= removed > 0;
+
+        if changed {
+            self.bump_serial(&mut rr);
+        }
+        drop(rr);
+
+        let held = locked.elapsed();
+        crate::metrics::ZONE_LOCK_HELD.observe(held.as_secs_f64());
+        tracing::Span::current()
+            .record("removed", removed as i64)
+            .record("lock_held_us", held.as_micros() as i64);
+        Ok(())
+    }
+
+    // has_name reports whether the zone holds records of any type for name, directly or through
+    // a wildcard.
+    pub async fn has_name(&self, name: &Name) -> bool {
+        let exact = LowerName::from(name);
+        let found = self
+            .authority
+            .records()
+            .await
+            .keys()
+            .any(|rrkey| rrkey.name() == &exact);
+
+        found || self.closest_wildcard(&exact).await.is_some()
+    }
+
+    // closest_wildcard finds the wildcard a name the zone doesn't hold is answered from, as RFC
+    // 4592 has it: the one directly under the nearest ancestor of name that exists, which is to say
+    // holds records or has names below it. So *.zt-abc answers a.b.zt-abc too, unless there are
+    // names under b.zt-abc.
+    pub async fn closest_wildcard(&self, name: &LowerName) -> Option<LowerName> {
+        let records = self.authority.records().await;
+        // records are kept in canonical order (RFC 4034), where a name comes before everything
+        // under it and nothing else sorts in between, so the first key from name on says whether
+        // name or anything below it has records.
+        let first_from = |name: &LowerName| {
+            records
+                .range(RrKey::new(name.clone(), RecordType::ZERO)..)
+                .next()
+                .map(|(rrkey, _)| rrkey.name().clone())
+        };
+
+        let mut ancestor = name.base_name();
+        while self.domain_name.zone_of(&ancestor) {
+            if first_from(&ancestor).is_some_and(|first| ancestor.zone_of(&first)) {
+                let wildcard = ancestor.to_wildcard();
+                return (first_from(&wildcard).as_ref() == Some(&wildcard)).then_some(wildcard);
             }
+            if ancestor.is_root() {
+                break;
+            }
+            ancestor = ancestor.base_name();
         }
 
-        for (host, ips) in hosts_map.into_iter() {
-            for (rrkey, rset) in rr.clone() {
-                let key = &rrkey.name().into_name().expect("could not parse name");
-                let records = rset.records(false, SupportedAlgorithms::all());
+        None
+    }
 
-                let rt = rset.record_type();
-                let rdatas: Vec<RData> = ips
-                    .clone()
-                    .into_iter()
-                    .filter_map(|i| match i {
-                        IpAddr::V4(ip) => {
-                            if rt == RecordType::A {
-                                Some(RData::A(ip))
-                            } else {
-                                None
-                            }
-                        }
-                        IpAddr::V6(ip) => {
-                            if rt == RecordType::AAAA {
-                                Some(RData::AAAA(ip))
-                            } else {
-                                None
-                            }
-                        }
+    // contains reports whether the zone holds records of rtype for name, without copying the zone.
+    pub async fn contains(&self, name: &Name, rtype: RecordType) -> bool {
+        self.authority
+            .records()
+            .await
+            .contains_key(&RrKey::new(name.into(), rtype))
+    }
+
+    // rrsets copies out every record set in the zone.
+    pub async fn rrsets(&self) -> Vec<Arc<RecordSet>> {
+        self.authority.records().await.values().cloned().collect()
+    }
+
+    // export_json lists every record in the zone as objects with name, type, ttl and rdata fields,
+    // the rdata formatted as it would appear in a zone file.
+    pub async fn export_json(&self) -> serde_json::Value {
+        let records = self.authority.records().await;
+
+        serde_json::Value::Array(
+            records
+                .values()
+                .flat_map(|rrset| rrset.records_without_rrsigs())
+                .map(|record| {
+                    serde_json::json!({
+                        "name": record.name().to_string(),
+                        "type": record.record_type().to_string(),
+                        "ttl": record.ttl(),
+                        "rdata": record.data().map(ToString::to_string),
                     })
-                    .collect();
+                })
+                .collect(),
+        )
+    }
 
-                if key.eq(&host)
-                    && (records.is_empty()
-                        || !records
-                            .map(|r| r.data().unwrap())
-                            .all(|rd| rdatas.contains(rd)))
-                {
-                    let mut new_rset = RecordSet::new(key, rt, serial);
-                    for rdata in rdatas.clone() {
-                        new_rset.add_rdata(rdata);
-                    }
+    // ttl_scan lists the records in the zone with a TTL below warn_threshold, for finding what
+    // clients may cache for less time than expected.
+    pub async fn ttl_scan(&self, warn_threshold: Duration) -> Vec<(Name, RecordType, u32)> {
+        let records = self.authority.records().await;
 
-                    tracing::warn!("Replacing host record for {} with {:#?}", key, ips);
-                    rr.remove(&rrkey);
-                    rr.insert(rrkey.clone(), Arc::new(new_rset));
-                }
-            }
+        let mut found: Vec<_> = records
+            .values()
+            .flat_map(|rrset| rrset.records_without_rrsigs())
+            .filter(|record| u64::from(record.ttl()) < warn_threshold.as_secs())
+            .map(|record| (record.name().clone(), record.record_type(), record.ttl()))
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    // health_check verifies the zone still has what every answer from it depends on: an SOA
+    // record with SOA data, and an NS record.
+    pub async fn health_check(&self) -> Result<(), errors::Error> {
+        let records = self.authority.records().await;
+        let rdata = |rtype| {
+            records
+                .get(&RrKey::new(self.domain_name.clone(), rtype))
+                .and_then(|rrset| rrset.records_without_rrsigs().next())
+                .and_then(|record| record.data())
+        };
+
+        if !matches!(rdata(RecordType::SOA), Some(RData::SOA(_))) {
+            return Err(errors::Error::Server)
+                .attach_printable(format!("{} has no valid SOA record", self.domain_name));
         }
 
-        Ok(())
+        match rdata(RecordType::NS) {
+            Some(RData::NS(_)) => Ok(()),
+            _ => Err(errors::Error::Server)
+                .attach_printable(format!("{} has no NS record", self.domain_name)),
+        }
     }
 
-    async fn prune_records(&self, written: Vec<LowerName>) -> Result<(), errors::Error> {
-        let mut rrkey_list = Vec::new();
+    // bulk_delete removes all of to_remove from the zone while taking the lock on it only once.
+    pub async fn bulk_delete(&self, to_remove: Vec<RrKey>) {
+        if to_remove.is_empty() {
+            return;
+        }
 
         let mut rr = self.authority.records_mut().await;
+        let mut changed = false;
 
-        for (rrkey, rs) in rr.clone() {
-            let key = &rrkey
-                .name()
-                .into_name()
-                .change_context(errors::Error)?
-                .into();
-            if !written.contains(key) && rs.record_type() != RecordType::SOA {
-                rrkey_list.push(rrkey);
+        for rrkey in to_remove {
+            if rr.remove(&rrkey).is_some() {
+                tracing::warn!(record = %rrkey.name(), "Removing expired record");
+                changed = true;
             }
         }
 
-        for rrkey in rrkey_list {
-            tracing::warn!("Removing expired record {}", rrkey.name());
-            rr.remove(&rrkey);
+        if changed {
+            self.bump_serial(&mut rr);
         }
-
-        Ok(())
     }
 
-    pub async fn match_or_insert(&self, name: Name, ips: &[IpAddr]) {
-        let rdatas: Vec<RData> = ips
-            .iter()
-            .map(|&ip| match ip {
-                IpAddr::V4(ip) => RData::A(ip),
-                IpAddr::V6(ip) => RData::AAAA(ip),
-            })
-            .collect();
+    // match_or_insert makes the A and AAAA records of name hold exactly the addresses in ips,
+    // replacing a record set only when its addresses differ as a set from those given. A type
+    // ips has no addresses of is left alone. It reports whether anything was replaced.
+    pub async fn match_or_insert(&self, name: Name, ips: &[IpAddr]) -> bool {
+        let key = LowerName::from(&name);
+        let mut changed = false;
 
         for rt in [RecordType::A, RecordType::AAAA] {
-            let type_records = self.authority.records().await.clone();
-            let name_records = type_records.get(&RrKey::new(name.clone().into(), rt));
+            let mut desired: Vec<RData> = Vec::new();
+            for ip in ips {
+                let rdata = match (ip, rt) {
+                    (IpAddr::V4(ip), RecordType::A) => RData::A(*ip),
+                    (IpAddr::V6(ip), RecordType::AAAA) => RData::AAAA(*ip),
+                    _ => continue,
+                };
+                if !desired.contains(&rdata) {
+                    desired.push(rdata);
+                }
+            }
 
-            let type_ips: Vec<IpAddr> = ips
-                .iter()
-                .copied()
-                .filter(|ip| {
-                    matches!(
-                        (ip, rt),
-                        (IpAddr::V4(_), RecordType::A) | (IpAddr::V6(_), RecordType::AAAA)
-                    )
-                })
-                .collect();
+            if desired.is_empty() {
+                continue;
+            }
 
-            match name_records {
-                Some(name_records) => {
-                    if name_records.is_empty()
-                        || !name_records
+            // a name without records of this type needs them written; only a name holding some
+            // has its record set looked at.
+            let same = self.contains(&name, rt).await
+                && self
+                    .authority
+                    .records()
+                    .await
+                    .get(&RrKey::new(key.clone(), rt))
+                    .is_some_and(|rrset| {
+                        let stored: Vec<&RData> = rrset
                             .records_without_rrsigs()
-                            .all(|r| rdatas.clone().contains(r.data().unwrap()))
-                            && !type_ips.is_empty()
-                    {
-                        self.replace_ip_record(name.clone(), rdatas.clone()).await;
-                    }
-                }
-                None => {
-                    if !type_ips.is_empty() {
-                        self.replace_ip_record(name.clone(), rdatas.clone()).await;
-                    }
-                }
+                            .filter_map(Record::data)
+                            .collect();
+                        stored.len() == desired.len()
+                            && desired.iter().all(|rd| stored.contains(&rd))
+                    });
+
+            if !same {
+                self.replace_ip_record(name.clone(), rt, desired).await;
+                changed = true;
             }
         }
+
+        changed
     }
 
-    async fn insert_member(
-        &self,
-        records: &mut Vec<LowerName>,
-        record: ZTRecord,
-    ) -> Result<(), errors::Error> {
-        self.match_or_insert(record.fqdn.clone(), &record.ips).await;
-        records.push(record.fqdn.clone().into());
+    // upsert_batch replaces the record sets for every name in the batch, taking the records lock
+    // once for the whole batch instead of once per record. Data supplied more than once for the
+    // same name and record type is merged into a single record set; record sets which already
+    // hold exactly the supplied data are left alone.
+    pub async fn upsert_batch(&self, records: Vec<(Name, Vec<RData>)>) {
+        let rrsets = Self::record_sets(records, self.authority.serial().await);
 
-        if record.wildcard {
-            self.match_or_insert(record.fqdn.clone().to_wildcard(), &record.ips)
-                .await;
-            records.push(record.fqdn.clone().to_wildcard().into());
+        let mut rr = self.authority.records_mut().await;
+        if self.write_record_sets(&mut rr, rrsets) {
+            self.bump_serial(&mut rr);
         }
+    }
 
-        if let Some(name) = &record.custom_name {
-            self.match_or_insert(name.clone(), &record.ips).await;
-            records.push(name.clone().into());
-
-            if record.wildcard {
-                self.match_or_insert(record.get_custom_wildcard().unwrap(), &record.ips)
-                    .await;
-                records.push(record.get_custom_wildcard().unwrap().into());
+    // record_sets gathers a batch into one record set per name and record type.
+    fn record_sets(records: Vec<(Name, Vec<RData>)>, serial: u32) -> BTreeMap<RrKey, RecordSet> {
+        let mut rrsets: BTreeMap<RrKey, RecordSet> = BTreeMap::new();
+
+        for (name, rdatas) in records {
+            for rdata in rdatas {
+                let rt = rdata.to_record_type();
+                let rrset = rrsets
+                    .entry(RrKey::new(name.clone().into(), rt))
+                    .or_insert_with(|| RecordSet::with_ttl(name.clone(), rt, 60));
+                rrset.insert(Record::from_rdata(name.clone(), 60, rdata), serial);
             }
         }
 
-        Ok(())
+        rrsets
     }
 
-    // insert_member_ptr is a lot like insert_authority, but for PTRs.
-    async fn insert_member_ptr(
+    // write_record_sets puts rrsets in the locked zone, skipping those it already holds, and
+    // reports whether anything changed. The serial is left to the caller.
+    fn write_record_sets(
         &self,
-        records: &mut Vec<LowerName>,
-        record: ZTRecord,
-    ) -> Result<(), errors::Error> {
-        for ip in record.ips.clone() {
-            let ip = ip.into_name().change_context(errors::Error)?;
-            self.configure_ptr(ip.clone(), record.ptr_name.clone())
-                .await
-                .change_context(errors::Error)?;
-            records.push(ip.into());
+        rr: &mut BTreeMap<RrKey, Arc<RecordSet>>,
+        rrsets: BTreeMap<RrKey, RecordSet>,
+    ) -> bool {
+        let mut changed = false;
+
+        for (rrkey, rrset) in rrsets {
+            if let Some(existing) = rr.get(&rrkey) {
+                if same_rdata(existing, &rrset) {
+                    continue;
+                }
+            }
+
+            changed = true;
+
+            for record in rrset.records_without_rrsigs() {
+                tracing::info!(
+                    record = %rrset.name(),
+                    rdata = %record.data().unwrap(),
+                    "Adding new record"
+                );
+            }
+
+            rr.insert(rrkey, Arc::new(rrset));
         }
 
-        Ok(())
+        changed
     }
 
-    async fn configure_ptr(&self, ptr: Name, fqdn: Name) -> Result<(), errors::Error> {
-        let records = self.authority.records().await.clone();
+    // batch_member queues the forward records for a member to be written with upsert_batch.
+    fn batch_member(
+        records: &mut Vec<(LowerName, RecordType)>,
+        batch: &mut Vec<(Name, Vec<RData>)>,
+        record: &ZTRecord,
+    ) {
+        let rdatas: Vec<RData> = record
+            .ips
+            .iter()
+            .map(|&ip| match ip {
+                IpAddr::V4(ip) => RData::A(ip),
+                IpAddr::V6(ip) => RData::AAAA(ip),
+            })
+            .collect();
+
+        for name in record.names() {
+            for ip in &record.ips {
+                records.push((name.clone().into(), ip_record_type(ip)));
+            }
+            batch.push((name, rdatas.clone()));
+        }
+    }
 
-        match records.get(&RrKey::new(ptr.clone().into(), RecordType::PTR)) {
-            Some(records) => {
-                if !records
-                    .records_without_rrsigs()
-                    .any(|rec| rec.data().unwrap().eq(&RData::PTR(fqdn.clone())))
-                {
-                    self.set_ptr_record(ptr.clone(), fqdn.clone()).await;
-                }
+    // batch_member_ptr is a lot like batch_member, but for PTRs. origin is the name of the zone
+    // network is served from; a PTR name outside it could never be found through the catalog.
+    fn batch_member_ptr(
+        records: &mut Vec<(LowerName, RecordType)>,
+        batch: &mut Vec<(Name, Vec<RData>)>,
+        record: &ZTRecord,
+        network: &IpNetwork,
+        origin: &LowerName,
+    ) -> Result<(), errors::Error> {
+        // only the addresses in the zone; the others are answered by their own.
+        for ip in record
+            .ips
+            .iter()
+            .copied()
+            .filter(|ip| network.contains(*ip))
+        {
+            let ip = ip.to_hostname()?;
+            if !origin.zone_of(&LowerName::from(&ip)) {
+                tracing::warn!(
+                    record = %ip,
+                    "Not serving PTR record outside of its reverse zone {}",
+                    origin
+                );
+                continue;
             }
-            None => self.set_ptr_record(ptr.clone(), fqdn.clone()).await,
+            batch.push((ip.clone(), vec![RData::PTR(record.ptr_name.clone())]));
+            records.push((ip.into(), RecordType::PTR));
         }
 
         Ok(())
     }
+}
 
-    async fn set_ptr_record(&self, ptr: Name, fqdn: Name) {
-        tracing::info!("Adding/Replacing record {}: ({})", ptr, fqdn);
-
-        let mut records = self.authority.records_mut().await;
-        records.remove(&RrKey::new(
-            ptr.clone()
-                .into_name()
-                .expect("Could not coerce IP address into DNS name")
-                .into(),
-            RecordType::PTR,
-        ));
-        drop(records);
-
-        let serial = self.authority.serial().await;
-        let mut address = Record::with(ptr.clone(), RecordType::PTR, 60);
-        address.set_data(Some(RData::PTR(fqdn.clone())));
-
-        self.authority.upsert(address, serial).await;
+// ip_record_type is the type of record an address is served with.
+fn ip_record_type(ip: &IpAddr) -> RecordType {
+    match ip {
+        IpAddr::V4(_) => RecordType::A,
+        IpAddr::V6(_) => RecordType::AAAA,
     }
 }
 
+// same_rdata reports whether two record sets hold the same data, ignoring order.
+fn same_rdata(a: &RecordSet, b: &RecordSet) -> bool {
+    let a: Vec<&RData> = a
+        .records_without_rrsigs()
+        .filter_map(Record::data)
+        .collect();
+    let b: Vec<&RData> = b
+        .records_without_rrsigs()
+        .filter_map(Record::data)
+        .collect();
+
+    a.len() == b.len() && a.iter().all(|rd| b.contains(rd))
+}
+
 #[async_trait]
 impl AuthorityObject for RecordAuthority {
     fn box_clone(&self) -> Box<dyn AuthorityObject> {
-        Box::new(self.authority.clone())
+        Box::new(self.clone())
     }
 
     fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
@@ -604,7 +2520,50 @@ impl AuthorityObject for RecordAuthority {
         Box<dyn trust_dns_server::authority::LookupObject>,
         trust_dns_server::authority::LookupError,
     > {
-        self.authority.search(request_info, lookup_options).await
+        if self.paused.load(Ordering::SeqCst) {
+            return Err(LookupError::from(ResponseCode::ServFail));
+        }
+
+        // at debug, so following every query is a matter of --log-filter zeronsd::query=debug.
+        let span = tracing::debug_span!(
+            target: "zeronsd::query",
+            "query",
+            name = %request_info.query.name(),
+            rtype = %request_info.query.query_type(),
+            src = %request_info.src.ip(),
+        );
+        let name = request_info.query.name().clone();
+        let rtype = request_info.query.query_type();
+        async {
+            match self.authority.search(request_info, lookup_options).await {
+                // trust-dns only tries the wildcard one label up; deeper names get theirs here.
+                Err(e) if e.is_nx_domain() => match self.closest_wildcard(&name).await {
+                    Some(wildcard) => {
+                        let lookup = self
+                            .authority
+                            .lookup(&wildcard, rtype, lookup_options)
+                            .await?;
+                        Ok(Box::new(SynthesizedLookup {
+                            records: lookup
+                                .iter()
+                                .cloned()
+                                .map(|mut record| {
+                                    if *record.name() == Name::from(&wildcard) {
+                                        record.set_name(Name::from(&name));
+                                    }
+                                    record
+                                })
+                                .collect(),
+                        })
+                            as Box<dyn trust_dns_server::authority::LookupObject>)
+                    }
+                    None => Err(e),
+                },
+                result => result,
+            }
+        }
+        .instrument(span)
+        .await
     }
 
     async fn get_nsec_records(
@@ -619,8 +2578,303 @@ impl AuthorityObject for RecordAuthority {
     }
 }
 
-#[derive(Debug, Clone)]
-struct ZTRecord {
+// RpzAction is what a response policy does with a query for a name it lists.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpzAction {
+    // answer NXDOMAIN, as if the name did not exist.
+    Block,
+    // answer with a CNAME to the target, such as a walled garden.
+    Redirect(Name),
+}
+
+// Rpz is a response policy: names listed in it are blocked or redirected rather than answered.
+// An entry starting with "*." covers the names under it, but not the name itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rpz {
+    entries: HashMap<LowerName, RpzAction>,
+}
+
+impl Rpz {
+    // parse reads one entry per line: a name to block, or "name CNAME target" to redirect it.
+    // "CNAME ." blocks, as in RPZ zone files. Blank lines and #-comments are skipped.
+    pub fn parse(s: &str) -> Result<Self, errors::Error> {
+        let mut entries = HashMap::new();
+
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || {
+                error_stack::Report::new(errors::Error::Config).attach_printable(format!(
+                    "invalid response policy on line {}: {:?}",
+                    number + 1,
+                    line
+                ))
+            };
+            let fqdn = |s: &str| -> Result<Name, errors::Error> {
+                let mut name = Name::from_str(s).map_err(|_| invalid())?;
+                name.set_fqdn(true);
+                Ok(name)
+            };
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (name, action) = match fields.as_slice() {
+                [name] => (fqdn(name)?, RpzAction::Block),
+                [name, rtype, "."] if rtype.eq_ignore_ascii_case("CNAME") => {
+                    (fqdn(name)?, RpzAction::Block)
+                }
+                [name, rtype, target] if rtype.eq_ignore_ascii_case("CNAME") => {
+                    (fqdn(name)?, RpzAction::Redirect(fqdn(target)?))
+                }
+                _ => return Err(invalid()),
+            };
+
+            entries.insert(LowerName::from(name), action);
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn from_file(path: &std::path::Path) -> Result<Self, errors::Error> {
+        Self::parse(&crate::utils::read_file_to_string(
+            path,
+            "response policy file",
+        )?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // action finds what the policy does with name: an entry for name itself, or else a wildcard
+    // entry for the nearest enclosing name.
+    pub fn action(&self, name: &LowerName) -> Option<&RpzAction> {
+        if let Some(action) = self.entries.get(name) {
+            return Some(action);
+        }
+
+        let mut parent = name.clone();
+        while !parent.is_root() {
+            parent = parent.base_name();
+            let wildcard = LowerName::from(Name::from(&parent).into_wildcard());
+            if let Some(action) = self.entries.get(&wildcard) {
+                return Some(action);
+            }
+        }
+
+        None
+    }
+}
+
+// RpzAuthority applies a response policy to the queries of the authority it serves, answering
+// listed names itself instead of passing them on.
+pub struct RpzAuthority {
+    authority: Box<dyn AuthorityObject>,
+    rpz: Arc<Rpz>,
+}
+
+impl RpzAuthority {
+    pub fn new(authority: Box<dyn AuthorityObject>, rpz: Arc<Rpz>) -> Self {
+        Self { authority, rpz }
+    }
+}
+
+// SynthesizedLookup holds records made up for a query rather than found under its name: the CNAME
+// a redirected name is answered with, or the records of a wildcard under the name asked for.
+struct SynthesizedLookup {
+    records: Vec<Record>,
+}
+
+impl trust_dns_server::authority::LookupObject for SynthesizedLookup {
+    fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Record> + Send + 'a> {
+        Box::new(self.records.iter())
+    }
+
+    fn take_additionals(&mut self) -> Option<Box<dyn trust_dns_server::authority::LookupObject>> {
+        None
+    }
+}
+
+#[async_trait]
+impl AuthorityObject for RpzAuthority {
+    fn box_clone(&self) -> Box<dyn AuthorityObject> {
+        Box::new(Self {
+            authority: self.authority.box_clone(),
+            rpz: self.rpz.clone(),
+        })
+    }
+
+    fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
+        self.authority.zone_type()
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        self.authority.is_axfr_allowed()
+    }
+
+    async fn update(
+        &self,
+        update: &trust_dns_server::authority::MessageRequest,
+    ) -> trust_dns_server::authority::UpdateResult<bool> {
+        self.authority.update(update).await
+    }
+
+    fn origin(&self) -> &LowerName {
+        self.authority.origin()
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: trust_dns_server::authority::LookupOptions,
+    ) -> core::result::Result<Box<dyn trust_dns_server::authority::LookupObject>, LookupError> {
+        self.authority.lookup(name, rtype, lookup_options).await
+    }
+
+    async fn search(
+        &self,
+        request_info: trust_dns_server::server::RequestInfo<'_>,
+        lookup_options: trust_dns_server::authority::LookupOptions,
+    ) -> core::result::Result<Box<dyn trust_dns_server::authority::LookupObject>, LookupError> {
+        let name = request_info.query.name();
+        match self.rpz.action(name) {
+            None => self.authority.search(request_info, lookup_options).await,
+            Some(RpzAction::Block) => {
+                tracing::info!(
+                    name = %name,
+                    client = %request_info.src.ip(),
+                    "Blocking query by response policy"
+                );
+                Err(LookupError::from(ResponseCode::NXDomain))
+            }
+            Some(RpzAction::Redirect(target)) => {
+                tracing::info!(
+                    name = %name,
+                    target = %target,
+                    client = %request_info.src.ip(),
+                    "Redirecting query by response policy"
+                );
+                Ok(Box::new(SynthesizedLookup {
+                    records: vec![Record::from_rdata(
+                        name.into(),
+                        60,
+                        RData::CNAME(target.clone()),
+                    )],
+                }))
+            }
+        }
+    }
+
+    async fn get_nsec_records(
+        &self,
+        name: &LowerName,
+        lookup_options: trust_dns_server::authority::LookupOptions,
+    ) -> core::result::Result<Box<dyn trust_dns_server::authority::LookupObject>, LookupError> {
+        self.authority.get_nsec_records(name, lookup_options).await
+    }
+}
+
+// last_active is when Central last saw member or authorized it, in milliseconds since the epoch.
+// Central may reset either to 0, which counts as not knowing.
+fn last_active(member: &central_api::types::Member) -> Option<i64> {
+    [
+        member.last_seen,
+        member
+            .config
+            .as_ref()
+            .and_then(|config| config.last_authorized_time),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|time| *time > 0)
+    .max()
+}
+
+// active_members leaves out the members Central has neither seen nor authorized within max_age of
+// now. Members with neither time are kept, since those may only have been reset.
+pub fn active_members(
+    members: Vec<central_api::types::Member>,
+    max_age: Duration,
+    now: std::time::SystemTime,
+) -> Vec<central_api::types::Member> {
+    let now = now
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let cutoff = now.saturating_sub(i64::try_from(max_age.as_millis()).unwrap_or(i64::MAX));
+
+    let total = members.len();
+    let active: Vec<_> = members
+        .into_iter()
+        .filter(|member| match last_active(member) {
+            Some(time) if time < cutoff => {
+                tracing::debug!(
+                    node_id = ?member.node_id,
+                    name = ?member.name,
+                    hours = (now - time) / 3_600_000,
+                    "Skipping member not seen recently"
+                );
+                false
+            }
+            _ => true,
+        })
+        .collect();
+
+    if active.len() < total {
+        tracing::info!(
+            "Skipping {} members not seen in the last {}h",
+            total - active.len(),
+            max_age.as_secs() / 3600
+        );
+    }
+    active
+}
+
+// ip_assignments is the one place a member's IP assignments from Central are read. Central has
+// been seen to hand out junk such as "null" for one, so those are skipped with a warning rather than
+// failing the sync.
+fn ip_assignments(
+    member: &central_api::types::Member,
+    member_name: &str,
+) -> Result<Vec<IpAddr>, errors::Error> {
+    let config = member
+        .config
+        .as_ref()
+        .ok_or(errors::Error::Member)
+        .attach_printable_lazy(|| format!("{} has no config", member_name))?;
+
+    Ok(config
+        .ip_assignments
+        .iter()
+        .flatten()
+        .filter_map(|s| match IpAddr::from_str(s) {
+            Ok(ip) => Some(ip),
+            Err(e) => {
+                tracing::warn!(
+                    member = %member_name,
+                    assignment = ?s,
+                    error = %e,
+                    "Ignoring invalid IP assignment"
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZTRecord {
     fqdn: Name,
     custom_name: Option<Name>,
     ptr_name: Name,
@@ -635,18 +2889,18 @@ impl ZTRecord {
         rfc4193: Option<IpNetwork>,
         domain_name: Name,
         wildcard: bool,
+        address_filter: &[IpNetwork],
     ) -> Result<Self, errors::Error> {
         let member_name = format!(
             "zt-{}",
             member
-                .clone()
                 .node_id
-                .expect("Node ID for member does not exist")
+                .as_deref()
+                .ok_or(errors::Error::Member)
+                .attach_printable("member has no node ID")?
         );
 
-        let fqdn = member_name
-            .to_fqdn(domain_name.clone())
-            .change_context(errors::Error)?;
+        let fqdn = member_name.to_fqdn(domain_name.clone())?;
 
         // this is default the zt-<member id> but can switch to a named name if
         // tweaked in central. see below.
@@ -658,31 +2912,31 @@ impl ZTRecord {
             ptr_name = name;
         }
 
-        let mut ips = member
-            .clone()
-            .config
-            .expect("Member config does not exist")
-            .ip_assignments
-            .map_or(Vec::new(), |v| {
-                v.iter()
-                    .map(|s| IpAddr::from_str(s).expect("Could not parse IP address"))
-                    .collect()
-            });
+        let mut ips = ip_assignments(member, &member_name)?;
 
         if sixplane.is_some() {
-            ips.push(
-                member
-                    .clone()
-                    .sixplane()
-                    .change_context(errors::Error)?
-                    .ip(),
-            );
+            ips.push(member.sixplane()?.ip());
         }
 
         if rfc4193.is_some() {
-            ips.push(member.clone().rfc4193().change_context(errors::Error)?.ip());
+            ips.push(member.rfc4193()?.ip());
         }
 
+        ips.retain(
+            |ip| match address_filter.iter().find(|net| net.contains(*ip)) {
+                Some(net) => {
+                    tracing::debug!(
+                        member = %member_name,
+                        %ip,
+                        filter = %net,
+                        "Not registering filtered address"
+                    );
+                    false
+                }
+                None => true,
+            },
+        );
+
         Ok(Self {
             wildcard,
             fqdn,
@@ -692,7 +2946,209 @@ impl ZTRecord {
         })
     }
 
+    // with_synthetic_ipv4 gives a member without any IPv4 address one made from each of its IPv6
+    // addresses.
+    pub fn with_synthetic_ipv4(mut self) -> Self {
+        if !self.ips.iter().any(IpAddr::is_ipv4) {
+            let synthetic: Vec<IpAddr> = self
+                .ips
+                .iter()
+                .filter_map(|ip| match ip {
+                    IpAddr::V6(ip) => Some(IpAddr::V4(ipv6_to_ipv4_synthetic(*ip))),
+                    IpAddr::V4(_) => None,
+                })
+                .collect();
+            self.ips.extend(synthetic);
+        }
+        self
+    }
+
     pub fn get_custom_wildcard(&self) -> Option<Name> {
         self.custom_name.as_ref().map(ToWildcard::to_wildcard)
     }
+
+    // names are all the names the member's addresses are written under: its fqdn and custom name,
+    // and with wildcard on, the wildcards under them.
+    pub fn names(&self) -> Vec<Name> {
+        let mut names = vec![self.fqdn.clone()];
+
+        if self.wildcard {
+            names.push(self.fqdn.to_wildcard());
+        }
+
+        if let Some(name) = &self.custom_name {
+            names.push(name.clone());
+
+            if self.wildcard {
+                names.push(name.to_wildcard());
+            }
+        }
+
+        names
+    }
+}
+
+// MEMBER_CHUNK is the fewest members handed to one blocking task; below it, spawning costs more
+// than the records do.
+const MEMBER_CHUNK: usize = 256;
+
+type MemberRecords = Vec<(String, ZTRecord)>;
+
+// member_records builds each member's record in order, skipping (with a warning) any member whose
+// addresses cannot be worked out. Each record comes with the node ID of its member.
+pub fn member_records(
+    members: Vec<central_api::types::Member>,
+    sixplane: Option<IpNetwork>,
+    rfc4193: Option<IpNetwork>,
+    domain_name: Name,
+    wildcard: bool,
+    address_filter: &[IpNetwork],
+) -> Result<MemberRecords, errors::Error> {
+    let mut records = Vec::new();
+    for member in members {
+        match ZTRecord::new(
+            &member,
+            sixplane,
+            rfc4193,
+            domain_name.clone(),
+            wildcard,
+            address_filter,
+        ) {
+            Ok(record) => records.push((member.node_id.clone().unwrap_or_default(), record)),
+            // an address worked out from a malformed ID would only point somewhere else.
+            Err(e) if e.current_context() == &errors::Error::Address => tracing::warn!(
+                node_id = ?member.node_id,
+                "Skipping member whose addresses cannot be worked out: {:?}",
+                e
+            ),
+            // Central sometimes still lists members it has deleted, with nothing filled in.
+            Err(e) if e.current_context() == &errors::Error::Member => tracing::warn!(
+                node_id = ?member.node_id,
+                "Skipping malformed member: {:?}",
+                e
+            ),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(records)
+}
+
+// member_records_parallel is member_records spread over blocking tasks. Chunks are put back in the
+// order they were cut, so the result (and which error is returned) matches the sequential path no
+// matter which chunk finishes first.
+pub async fn member_records_parallel(
+    mut members: Vec<central_api::types::Member>,
+    sixplane: Option<IpNetwork>,
+    rfc4193: Option<IpNetwork>,
+    domain_name: Name,
+    wildcard: bool,
+    address_filter: &[IpNetwork],
+) -> Result<MemberRecords, errors::Error> {
+    use futures_util::stream::{FuturesUnordered, StreamExt};
+
+    let tasks = std::thread::available_parallelism().map_or(1, usize::from);
+    let chunk = members.len().div_ceil(tasks).max(MEMBER_CHUNK);
+    if members.len() <= chunk {
+        return member_records(
+            members,
+            sixplane,
+            rfc4193,
+            domain_name,
+            wildcard,
+            address_filter,
+        );
+    }
+    let address_filter: Arc<[IpNetwork]> = address_filter.into();
+
+    let mut chunks = Vec::new();
+    while !members.is_empty() {
+        let rest = members.split_off(chunk.min(members.len()));
+        chunks.push(std::mem::replace(&mut members, rest));
+    }
+
+    let mut pending: FuturesUnordered<_> = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, members)| {
+            let (domain_name, address_filter) = (domain_name.clone(), address_filter.clone());
+            let task = tokio::task::spawn_blocking(move || {
+                member_records(
+                    members,
+                    sixplane,
+                    rfc4193,
+                    domain_name,
+                    wildcard,
+                    &address_filter,
+                )
+            });
+            async move { (i, task.await) }
+        })
+        .collect();
+
+    let mut done = Vec::with_capacity(pending.len());
+    while let Some((i, result)) = pending.next().await {
+        // a member that panics the sequential path would panic it here too.
+        match result {
+            Ok(result) => done.push((i, result)),
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => return Err(error_stack::Report::new(e).change_context(errors::Error::Server)),
+        }
+    }
+    done.sort_unstable_by_key(|(i, _)| *i);
+
+    let mut records = Vec::new();
+    for (_, result) in done {
+        records.extend(result?);
+    }
+    Ok(records)
+}
+
+impl std::fmt::Display for ZTRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} [{}] (ptr: {})",
+            self.fqdn,
+            self.ips
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.ptr_name
+        )
+    }
+}
+
+// ZTRecord parses from "<fqdn> <ip>[,<ip>...] <ptr_name>". A ptr_name other than the fqdn is taken
+// to be the member's custom name, as it is for records made from Central.
+impl FromStr for ZTRecord {
+    type Err = errors::ErrorReport;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [fqdn, ips, ptr_name] = fields[..] else {
+            return Err(errors::Error::Config).attach_printable_lazy(|| {
+                format!("invalid record {:?}: want fqdn ips ptr_name", s)
+            });
+        };
+
+        let fqdn = Name::from_str(fqdn).change_context(errors::Error::DnsName)?;
+        let ptr_name = Name::from_str(ptr_name).change_context(errors::Error::DnsName)?;
+        let ips = ips
+            .split(',')
+            .map(|ip| {
+                IpAddr::from_str(ip)
+                    .change_context(errors::Error::Address)
+                    .attach_printable_lazy(|| format!("invalid IP {:?}", ip))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            custom_name: (ptr_name != fqdn).then(|| ptr_name.clone()),
+            fqdn,
+            ptr_name,
+            ips,
+            wildcard: false,
+        })
+    }
 }
@@ -0,0 +1,448 @@
+/// A record authority kept in etcd, so that several zeronsd instances on different nodes of a
+/// network serve the same zone. Records live under `/zeronsd/{network_id}/records/{name}/{type}`
+/// as DNS wire format; lookups are cached locally for a short while to keep etcd off the hot path.
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use error_stack::{Result, ResultExt};
+use etcd_client::{Client, GetOptions, KvClient};
+use tokio::sync::RwLock;
+use trust_dns_proto::{
+    op::ResponseCode,
+    rr::{rdata::SOA, RData, Record, RecordSet, RecordType},
+    serialize::binary::{BinDecodable, BinDecoder, BinEncodable, BinEncoder},
+};
+use trust_dns_server::{
+    authority::{
+        AuthLookup, AuthorityObject, LookupError, LookupObject, LookupOptions, LookupRecords,
+        MessageRequest, UpdateResult, ZoneType,
+    },
+    client::rr::{LowerName, Name},
+    server::RequestInfo,
+};
+
+use crate::errors;
+
+// how long a lookup answered from etcd is served from the local cache.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(5);
+
+const RECORD_TTL: u32 = 60;
+
+#[derive(Clone)]
+enum Cached {
+    Records(Vec<Arc<RecordSet>>),
+    NameExists,
+    NxDomain,
+}
+
+type Cache = HashMap<(LowerName, RecordType), (Instant, Cached)>;
+
+#[derive(Clone)]
+pub struct EtcdRecordAuthority {
+    client: KvClient,
+    network_id: String,
+    domain_name: LowerName,
+    cache: Arc<RwLock<Cache>>,
+    cache_ttl: Duration,
+    // shared by every clone, so the copy in the catalog sees ZTAuthority::pause.
+    paused: Arc<AtomicBool>,
+}
+
+impl EtcdRecordAuthority {
+    // connect joins the etcd cluster at endpoints and makes sure the zone has a SOA record and
+    // lists this member as a name server.
+    pub async fn connect(
+        endpoints: &[String],
+        network_id: String,
+        domain_name: Name,
+        member_name: Name,
+    ) -> Result<Self, errors::Error> {
+        let client = Client::connect(endpoints, None)
+            .await
+            .change_context(errors::Error::Server)
+            .attach_printable_lazy(|| format!("could not connect to etcd at {:?}", endpoints))?;
+
+        let authority = Self {
+            client: client.kv_client(),
+            network_id,
+            domain_name: domain_name.clone().into(),
+            cache: Default::default(),
+            cache_ttl: DEFAULT_CACHE_TTL,
+            paused: Default::default(),
+        };
+
+        if authority
+            .get(&domain_name, RecordType::SOA)
+            .await?
+            .is_none()
+        {
+            authority
+                .upsert(
+                    domain_name.clone(),
+                    vec![RData::SOA(SOA::new(
+                        domain_name.clone(),
+                        Name::from_str("administrator")
+                            .change_context(errors::Error::DnsName)?
+                            .append_domain(&domain_name)
+                            .change_context(errors::Error::DnsName)?,
+                        1,
+                        30,
+                        0,
+                        -1,
+                        0,
+                    ))],
+                )
+                .await?;
+        }
+
+        let mut ns: Vec<RData> = authority
+            .get(&domain_name, RecordType::NS)
+            .await?
+            .map(|rs| {
+                rs.records_without_rrsigs()
+                    .filter_map(Record::data)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !ns.contains(&RData::NS(member_name.clone())) {
+            ns.push(RData::NS(member_name));
+            authority.upsert(domain_name, ns).await?;
+        }
+
+        Ok(authority)
+    }
+
+    // with_cache_ttl sets how long answers are served from the local cache before etcd is asked
+    // again.
+    pub fn with_cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    // set_paused makes queries for the zone answer SERVFAIL while paused is set.
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    // record_key is where the records of one type for a name are kept.
+    pub fn record_key(network_id: &str, name: &LowerName, rtype: RecordType) -> String {
+        format!("{}{}", Self::name_prefix(network_id, name), rtype)
+    }
+
+    fn name_prefix(network_id: &str, name: &LowerName) -> String {
+        format!("/zeronsd/{}/records/{}/", network_id, name)
+    }
+
+    // upsert replaces the records for a name with rdatas, one key per record type.
+    pub async fn upsert(&self, name: Name, rdatas: Vec<RData>) -> Result<(), errors::Error> {
+        let mut sets: BTreeMap<RecordType, Vec<Record>> = BTreeMap::new();
+        for rdata in rdatas {
+            sets.entry(rdata.to_record_type())
+                .or_default()
+                .push(Record::from_rdata(name.clone(), RECORD_TTL, rdata));
+        }
+
+        let lower: LowerName = name.into();
+        let mut client = self.client.clone();
+        for (rtype, records) in sets {
+            let key = Self::record_key(&self.network_id, &lower, rtype);
+            client
+                .put(key.clone(), encode_records(&records)?, None)
+                .await
+                .change_context(errors::Error::Server)
+                .attach_printable_lazy(|| format!("could not write {} to etcd", key))?;
+        }
+
+        self.invalidate(&lower).await;
+        Ok(())
+    }
+
+    // remove deletes the records of one type for a name.
+    pub async fn remove(&self, name: Name, rtype: RecordType) -> Result<(), errors::Error> {
+        let lower: LowerName = name.into();
+        let key = Self::record_key(&self.network_id, &lower, rtype);
+        self.client
+            .clone()
+            .delete(key.clone(), None)
+            .await
+            .change_context(errors::Error::Server)
+            .attach_printable_lazy(|| format!("could not delete {} from etcd", key))?;
+
+        self.invalidate(&lower).await;
+        Ok(())
+    }
+
+    // mirror makes the zone in etcd hold rrsets and nothing else, writing only what changed since.
+    // The SOA and NS records at the apex are left alone, as every member serving the zone lists
+    // itself there.
+    pub async fn mirror(&self, rrsets: Vec<Arc<RecordSet>>) -> Result<(), errors::Error> {
+        let mut client = self.client.clone();
+        let prefix = format!("/zeronsd/{}/records/", self.network_id);
+        let resp = client
+            .get(prefix.clone(), Some(GetOptions::new().with_prefix()))
+            .await
+            .change_context(errors::Error::Server)
+            .attach_printable_lazy(|| format!("could not read {} from etcd", prefix))?;
+
+        let mut stored: HashMap<String, Vec<u8>> = resp
+            .kvs()
+            .iter()
+            .filter_map(|kv| Some((kv.key_str().ok()?.to_string(), kv.value().to_vec())))
+            .collect();
+        for rtype in [RecordType::SOA, RecordType::NS] {
+            stored.remove(&Self::record_key(
+                &self.network_id,
+                &self.domain_name,
+                rtype,
+            ));
+        }
+
+        let mut changed = false;
+        for rrset in rrsets {
+            let name = LowerName::from(rrset.name());
+            let rtype = rrset.record_type();
+            if name == self.domain_name && matches!(rtype, RecordType::SOA | RecordType::NS) {
+                continue;
+            }
+
+            let key = Self::record_key(&self.network_id, &name, rtype);
+            let records: Vec<Record> = rrset.records_without_rrsigs().cloned().collect();
+            let value = encode_records(&records)?;
+            if stored.remove(&key).as_ref() != Some(&value) {
+                client
+                    .put(key.clone(), value, None)
+                    .await
+                    .change_context(errors::Error::Server)
+                    .attach_printable_lazy(|| format!("could not write {} to etcd", key))?;
+                changed = true;
+            }
+        }
+
+        // what is left is gone from the zone.
+        for key in stored.into_keys() {
+            client
+                .delete(key.clone(), None)
+                .await
+                .change_context(errors::Error::Server)
+                .attach_printable_lazy(|| format!("could not delete {} from etcd", key))?;
+            changed = true;
+        }
+
+        if changed {
+            self.cache.write().await.clear();
+        }
+        Ok(())
+    }
+
+    async fn invalidate(&self, name: &LowerName) {
+        self.cache.write().await.retain(|(n, _), _| n != name);
+    }
+
+    async fn get(
+        &self,
+        name: &Name,
+        rtype: RecordType,
+    ) -> Result<Option<RecordSet>, errors::Error> {
+        let key = Self::record_key(&self.network_id, &name.clone().into(), rtype);
+        let resp = self
+            .client
+            .clone()
+            .get(key.clone(), None)
+            .await
+            .change_context(errors::Error::Server)
+            .attach_printable_lazy(|| format!("could not read {} from etcd", key))?;
+
+        match resp.kvs().first() {
+            Some(kv) => Ok(Some(record_set(name, rtype, decode_records(kv.value())?))),
+            None => Ok(None),
+        }
+    }
+
+    // fetch answers a lookup from etcd, bypassing the cache.
+    async fn fetch(&self, name: &LowerName, rtype: RecordType) -> Result<Cached, errors::Error> {
+        let mut client = self.client.clone();
+        let prefix = Self::name_prefix(&self.network_id, name);
+        let fqdn: Name = name.into();
+
+        if rtype == RecordType::ANY {
+            let resp = client
+                .get(prefix.clone(), Some(GetOptions::new().with_prefix()))
+                .await
+                .change_context(errors::Error::Server)
+                .attach_printable_lazy(|| format!("could not read {} from etcd", prefix))?;
+
+            let mut sets = Vec::new();
+            for kv in resp.kvs() {
+                let records = decode_records(kv.value())?;
+                if let Some(rtype) = records.first().map(Record::record_type) {
+                    sets.push(Arc::new(record_set(&fqdn, rtype, records)));
+                }
+            }
+
+            return Ok(if sets.is_empty() {
+                Cached::NxDomain
+            } else {
+                Cached::Records(sets)
+            });
+        }
+
+        if let Some(rs) = self.get(&fqdn, rtype).await? {
+            return Ok(Cached::Records(vec![Arc::new(rs)]));
+        }
+
+        // tell a name without records of this type apart from a name that does not exist.
+        let resp = client
+            .get(
+                prefix.clone(),
+                Some(
+                    GetOptions::new()
+                        .with_prefix()
+                        .with_keys_only()
+                        .with_limit(1),
+                ),
+            )
+            .await
+            .change_context(errors::Error::Server)
+            .attach_printable_lazy(|| format!("could not read {} from etcd", prefix))?;
+
+        Ok(if resp.kvs().is_empty() {
+            Cached::NxDomain
+        } else {
+            Cached::NameExists
+        })
+    }
+}
+
+fn record_set(name: &Name, rtype: RecordType, records: Vec<Record>) -> RecordSet {
+    let mut rs = RecordSet::with_ttl(name.clone(), rtype, RECORD_TTL);
+    for record in records {
+        rs.insert(record, 0);
+    }
+    rs
+}
+
+// encode_records writes records back to back in DNS wire format.
+pub fn encode_records(records: &[Record]) -> Result<Vec<u8>, errors::Error> {
+    let mut buf = Vec::new();
+    let mut encoder = BinEncoder::new(&mut buf);
+    for record in records {
+        record
+            .emit(&mut encoder)
+            .change_context(errors::Error::DnsName)?;
+    }
+    Ok(buf)
+}
+
+// decode_records reads records written by encode_records.
+pub fn decode_records(buf: &[u8]) -> Result<Vec<Record>, errors::Error> {
+    let mut decoder = BinDecoder::new(buf);
+    let mut records = Vec::new();
+    while !decoder.is_empty() {
+        records.push(Record::read(&mut decoder).change_context(errors::Error::DnsName)?);
+    }
+    Ok(records)
+}
+
+#[async_trait]
+impl AuthorityObject for EtcdRecordAuthority {
+    fn box_clone(&self) -> Box<dyn AuthorityObject> {
+        Box::new(self.clone())
+    }
+
+    fn zone_type(&self) -> ZoneType {
+        ZoneType::Primary
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        false
+    }
+
+    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+        Err(ResponseCode::NotImp)
+    }
+
+    fn origin(&self) -> &LowerName {
+        &self.domain_name
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        let key = (name.clone(), rtype);
+        let cached = self.cache.read().await.get(&key).cloned();
+
+        let answer = match cached {
+            Some((fetched, answer)) if fetched.elapsed() < self.cache_ttl => answer,
+            stale => match self.fetch(name, rtype).await {
+                Ok(answer) => {
+                    self.cache
+                        .write()
+                        .await
+                        .insert(key, (Instant::now(), answer.clone()));
+                    answer
+                }
+                Err(e) => match stale {
+                    // etcd being away is no reason to stop answering with what we knew.
+                    Some((_, answer)) => {
+                        tracing::warn!("serving {} from cache: {:?}", name, e);
+                        answer
+                    }
+                    None => {
+                        tracing::error!("lookup of {} failed: {:?}", name, e);
+                        return Err(LookupError::from(ResponseCode::ServFail));
+                    }
+                },
+            },
+        };
+
+        match answer {
+            Cached::Records(mut sets) => {
+                let records = if sets.len() == 1 {
+                    LookupRecords::new(lookup_options, sets.remove(0))
+                } else {
+                    LookupRecords::many(lookup_options, sets)
+                };
+                Ok(Box::new(AuthLookup::answers(records, None)))
+            }
+            Cached::NameExists => Err(LookupError::NameExists),
+            Cached::NxDomain => Err(LookupError::from(ResponseCode::NXDomain)),
+        }
+    }
+
+    async fn search(
+        &self,
+        request_info: RequestInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        if self.paused.load(Ordering::SeqCst) {
+            return Err(LookupError::from(ResponseCode::ServFail));
+        }
+
+        self.lookup(
+            request_info.query.name(),
+            request_info.query.query_type(),
+            lookup_options,
+        )
+        .await
+    }
+
+    async fn get_nsec_records(
+        &self,
+        _name: &LowerName,
+        _lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        Ok(Box::<AuthLookup>::default())
+    }
+}
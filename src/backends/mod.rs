@@ -0,0 +1,2 @@
+#[cfg(feature = "etcd-backend")]
+pub mod etcd;
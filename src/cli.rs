@@ -1,13 +1,27 @@
 use crate::{
+    addresses::AddressPlan,
+    authority::DEFAULT_UPDATE_INTERVAL,
+    dnssec::{Rollover, RolloverTiming},
+    ecs::EcsMode,
     errors,
-    init::{ConfigFormat, Launcher},
-    supervise::Properties,
-    utils::ZEROTIER_LOCAL_URL,
+    hosts::{check_hosts_files, HostsPrecedence},
+    info::get_network_info,
+    init::{ConfigFormat, HumanDuration, Launcher},
+    log::{LevelFilter, LogFormat, LogTarget},
+    serial::SoaSerialPolicy,
+    server::QueryRateLimitConfig,
+    supervise::{Foreground, Properties},
+    utils::{
+        authtoken_path, central_client_with_url, central_token, domain_or_default, get_listen_ips,
+        parse_ips_from_cidrs, read_network_ids_from_dir, validate_token, HttpOptions, TokenStatus,
+        CENTRAL_BASEURL, DEFAULT_SERVICE_API_TIMEOUT, ZEROTIER_LOCAL_URL,
+    },
 };
+use clap_complete::{generate, Shell};
 use error_stack::*;
-use std::{path::PathBuf, time::Duration};
+use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 
 #[derive(Parser)]
 #[clap(version, author = "ZeroTier, Inc. <https://zerotier.com>")]
@@ -25,25 +39,81 @@ pub enum Command {
     /// Start the nameserver
     Start(StartArgs),
 
-    /// Configure supervision of the nameserver for a single network
-    Supervise(StartArgs),
+    /// Configure supervision of the nameserver for a single network, or supervise it from here
+    Supervise(SuperviseArgs),
 
     /// Remove supervision of the nameserver for a network
     Unsupervise(UnsuperviseArgs),
+
+    /// Check that a ZeroTier Central token is usable
+    TokenValidate(TokenValidateArgs),
+
+    /// Check hosts files for problems, exiting non-zero if any are errors
+    CheckHosts(CheckHostsArgs),
+
+    /// Show a network's DNS settings and member counts from ZeroTier Central
+    NetworkInfo(NetworkInfoArgs),
+
+    /// Compute a network's 6PLANE and RFC4193 addresses, and a member's, without contacting Central
+    Calc(CalcArgs),
+
+    /// List the records a running nameserver serves, through its control socket
+    #[cfg(unix)]
+    Status(StatusArgs),
+
+    /// Change the log filter of a running nameserver, through its control socket
+    #[cfg(unix)]
+    LogLevel(LogLevelArgs),
+
+    /// Roll the DNSSEC zone signing key over, signing with both keys until the old one can go
+    RotateKey(RotateKeyArgs),
+
+    /// Print a shell completion script to stdout
+    Completions(CompletionsArgs),
 }
 
 #[derive(Args, Clone)]
 pub struct StartArgs {
     /// Network ID to query
-    pub network_id: String,
+    #[clap(required_unless_present = "auto-network-id")]
+    pub network_id: Option<String>,
+
+    /// Use the network zerotier-one on this host has joined, from its networks.d directory
+    #[clap(long = "auto-network-id", conflicts_with = "network-id")]
+    pub auto_network_id: bool,
 
     /// TLD to use for hostnames
     #[clap(short, long)]
     pub domain: Option<String>,
 
-    /// An additional list of hosts in /etc/hosts format
+    /// Use the network's name in Central as the domain, such as home.arpa for a network named "home"
+    #[clap(long = "network-name-as-domain", conflicts_with = "domain")]
+    pub network_name_as_domain: bool,
+
+    /// An additional list of hosts in /etc/hosts format, or a directory of *.hosts files. May be
+    /// given more than once; names in later files override earlier ones
     #[clap(short = 'f', long = "file", value_name = "PATH")]
-    pub hosts: Option<PathBuf>,
+    pub hosts: Vec<PathBuf>,
+
+    /// Domain appended to names in the hosts file (defaults to the TLD)
+    #[clap(long = "hosts-domain", value_name = "DOMAIN")]
+    pub hosts_domain: Option<String>,
+
+    /// Refuse hosts files with errors in them rather than skipping the bad lines
+    #[clap(long = "hosts-strict")]
+    pub hosts_strict: bool,
+
+    /// Also accept an address in a comment line (`# 10.0.0.1`) for the names on the next line
+    #[clap(long = "lenient-hosts")]
+    pub lenient_hosts: bool,
+
+    /// Which records a name in both the hosts file and Central gets [hosts-wins, member-wins, merge]
+    #[clap(
+        long = "hosts-precedence",
+        value_name = "PRECEDENCE",
+        default_value = "hosts-wins"
+    )]
+    pub hosts_precedence: HostsPrecedence,
 
     /// Path to authtoken.secret (usually detected)
     #[clap(short, long, value_name = "PATH")]
@@ -65,6 +135,10 @@ pub struct StartArgs {
     #[clap(long = "config-type", default_value = "yaml")]
     pub config_type: ConfigFormat,
 
+    /// File of ZERONSD_*=value lines to read the configuration from; the environment overrides it
+    #[clap(long = "env-file", value_name = "PATH", conflicts_with = "config")]
+    pub env_file: Option<PathBuf>,
+
     #[clap(long = "tls-cert", value_name = "PATH")]
     pub tls_cert: Option<PathBuf>,
 
@@ -74,6 +148,10 @@ pub struct StartArgs {
     #[clap(long = "tls-key", value_name = "PATH")]
     pub tls_key: Option<PathBuf>,
 
+    /// Only answer DoT clients presenting a certificate signed by a CA in this PEM file
+    #[clap(long = "dot-client-ca", value_name = "PATH")]
+    pub dot_client_ca: Option<PathBuf>,
+
     /// Provide a different URL for contacting the local zerotier-one service. Default:
     #[clap(long = "local-url", value_name = "LOCAL_URL", default_value = ZEROTIER_LOCAL_URL)]
     pub local_url: String,
@@ -81,15 +159,189 @@ pub struct StartArgs {
     /// Log Level to print [off, trace, debug, error, warn, info]
     #[clap(short = 'l', long = "log-level", value_name = "LEVEL")]
     pub log_level: Option<crate::log::LevelFilter>,
+
+    /// Per-module log levels on top of the log level, e.g. "zeronsd=debug,trust_dns_server=warn"
+    #[clap(long = "log-filter", value_name = "DIRECTIVES")]
+    pub log_filter: Option<String>,
+
+    /// How log lines are written [text, json]
+    #[clap(long = "log-format", value_name = "FORMAT", default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Where logs go; journald and syslog fall back to stdout when unavailable [stdout, journald, syslog]
+    #[clap(long = "log-target", value_name = "TARGET", default_value = "stdout")]
+    pub log_target: LogTarget,
+
+    /// Export spans to this OTLP/HTTP traces endpoint, e.g. http://localhost:4318/v1/traces (needs the otlp feature)
+    #[clap(long = "otlp-endpoint", value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Directory to keep a snapshot of the member list in, used when Central is unreachable at startup
+    #[clap(long = "cache-dir", value_name = "PATH")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Maximum number of DNS servers to configure in Central, including other instances'
+    #[clap(long = "dns-servers-limit", value_name = "COUNT")]
+    pub dns_servers_limit: Option<usize>,
+
+    /// Remove this instance's IPs from the DNS servers in Central when shutting down
+    #[clap(long = "remove-dns-on-shutdown")]
+    pub remove_dns_on_shutdown: bool,
+
+    /// Do not push DNS settings to Central; serve DNS only
+    #[clap(long = "no-update-dns")]
+    pub no_update_dns: bool,
+
+    /// Do not force a random source port for each forwarded UDP query (not recommended)
+    #[clap(long = "no-randomize-udp-port")]
+    pub no_randomize_udp_port: bool,
+
+    /// Serve records for peers with a direct path, as zt-<nodeid>-peer.<domain>
+    #[clap(long = "peer-dns")]
+    pub peer_dns: bool,
+
+    /// Serve records for hosts bridged onto the network, as bridge-<mac>.<domain> (Linux only)
+    #[clap(long = "bridge-dns")]
+    pub bridge_dns: bool,
+
+    /// Answer with the addresses of the nearest member, by path latency, when a name has several
+    #[clap(long = "latency-aware")]
+    pub latency_aware: bool,
+
+    /// How the SOA serial moves forward when a zone changes [increment, timestamp]
+    #[clap(
+        long = "soa-serial-policy",
+        value_name = "POLICY",
+        default_value = "increment"
+    )]
+    pub soa_serial_policy: SoaSerialPolicy,
+
+    /// Amount the SOA serial is bumped by on each change with the increment policy
+    #[clap(long = "serial-increment", value_name = "COUNT", default_value = "1")]
+    pub serial_increment: u32,
+
+    /// How long to wait when connecting to Central, such as "10s" [default: 10s]
+    #[clap(long = "http-connect-timeout", value_name = "DURATION")]
+    pub http_connect_timeout: Option<HumanDuration>,
+
+    /// How long to wait for a request to Central to complete, such as "30s" [default: 30s]
+    #[clap(long = "http-timeout", value_name = "DURATION")]
+    pub http_timeout: Option<HumanDuration>,
+
+    /// How long to wait for a request to zerotier-one to complete, such as "10s" [default: 10s]
+    #[clap(long = "service-api-timeout", value_name = "DURATION")]
+    pub service_api_timeout: Option<HumanDuration>,
+
+    /// How often to fetch members from Central, such as "30s" or "1m30s" [default: 30s]
+    #[clap(long = "update-interval", value_name = "DURATION")]
+    pub update_interval: Option<HumanDuration>,
+
+    /// How long to wait on the first sync before serving, such as "10s" [default: 30s]
+    #[clap(long = "initial-sync-timeout", value_name = "DURATION")]
+    pub initial_sync_timeout: Option<HumanDuration>,
+
+    /// Fail to start if the first sync does not complete, rather than serving with a warning
+    #[clap(long = "require-initial-sync")]
+    pub require_initial_sync: bool,
+
+    /// Text appended to the User-Agent sent to Central
+    #[clap(long = "user-agent-suffix", value_name = "TEXT")]
+    pub user_agent_suffix: Option<String>,
+
+    /// Only trust Central presenting the certificate with this SHA-256 fingerprint (hex)
+    #[clap(long = "cert-fingerprint", value_name = "SHA256")]
+    pub cert_fingerprint: Option<String>,
+
+    /// Refuse to update records from a member list longer than this
+    #[clap(long = "max-members", value_name = "COUNT")]
+    pub max_members: Option<usize>,
+
+    /// Most pages of members to fetch from Central in one sync (default 100)
+    #[clap(long = "max-pages", value_name = "COUNT")]
+    pub max_pages: Option<u32>,
+
+    /// Leave out members Central has neither seen nor authorized for this long, such as "30d"
+    #[clap(long = "max-member-age", value_name = "DURATION")]
+    pub max_member_age: Option<HumanDuration>,
+
+    /// The same as --max-member-age, in hours
+    #[clap(
+        long = "max-member-age-hours",
+        value_name = "HOURS",
+        conflicts_with = "max-member-age"
+    )]
+    pub max_member_age_hours: Option<u64>,
+
+    /// Refuse syncs which would leave a zone with more records than this (default 100000)
+    #[clap(long = "max-records", value_name = "COUNT")]
+    pub max_records: Option<usize>,
+
+    /// Address to serve Prometheus metrics on, e.g. 127.0.0.1:9100
+    #[clap(long = "metrics-listen", value_name = "ADDR")]
+    pub metrics_listen: Option<std::net::SocketAddr>,
+
+    /// Give members with only IPv6 addresses A records too, mapped for a NAT64 gateway
+    #[clap(long = "ipv4-only")]
+    pub ipv4_only: bool,
+
+    /// Leave member addresses in this network out of DNS; may be given more than once
+    /// [default: 169.254.0.0/16 and fe80::/10]
+    #[clap(long = "address-filter", value_name = "CIDR")]
+    pub address_filter: Vec<ipnetwork::IpNetwork>,
+
+    /// Unix socket to accept runtime commands on, such as changing the update interval
+    #[clap(long = "control-socket", value_name = "PATH")]
+    pub control_socket: Option<PathBuf>,
+
+    /// Serve only the hosts files, without a Central token; requires at least one -f
+    #[clap(long = "offline")]
+    pub offline: bool,
+
+    /// Serve no reverse zones, leaving PTR records to another server
+    #[clap(long = "disable-ptr")]
+    pub disable_ptr: bool,
+
+    /// File of names to answer NXDOMAIN for, or redirect with "name CNAME target"
+    #[clap(long = "rpz-file", value_name = "PATH")]
+    pub rpz_file: Option<PathBuf>,
+
+    /// What to do with the client subnet (ECS) of forwarded queries: strip, pass-through or
+    /// anonymize-prefix:<bits>
+    #[clap(long = "ecs-mode", value_name = "MODE")]
+    pub ecs_mode: Option<EcsMode>,
+
+    /// Keep the members zone in this etcd cluster and serve it from there, so several instances answer alike; may be repeated (needs the etcd-backend feature)
+    #[clap(long = "etcd-endpoint", value_name = "URL")]
+    pub etcd_endpoints: Vec<String>,
+
+    /// Refuse queries from a client beyond this many a second
+    #[clap(long = "query-rate-limit", value_name = "QPS")]
+    pub query_rate_limit: Option<u32>,
+
+    /// Queries a client may make at once before the rate limit applies (defaults to the limit)
+    #[clap(
+        long = "query-rate-burst",
+        value_name = "COUNT",
+        requires = "query-rate-limit"
+    )]
+    pub query_rate_burst: Option<u32>,
 }
 
-impl Into<Launcher> for StartArgs {
-    fn into(self) -> Launcher {
-        if let Some(config) = self.config {
-            let res = Launcher::new_from_config(config.to_str().unwrap(), self.config_type);
+impl From<StartArgs> for Launcher {
+    fn from(args: StartArgs) -> Self {
+        let res = if let Some(config) = args.config {
+            Some(Launcher::new_from_config(
+                config.to_str().unwrap(),
+                args.config_type,
+            ))
+        } else {
+            args.env_file.as_deref().map(Launcher::from_env_file)
+        };
+
+        if let Some(res) = res {
             match res {
                 Ok(mut res) => {
-                    res.network_id = Some(self.network_id.clone());
+                    res.network_id = args.network_id.clone();
                     res
                 }
                 Err(e) => {
@@ -99,63 +351,558 @@ impl Into<Launcher> for StartArgs {
             }
         } else {
             Launcher {
-                domain: self.domain,
-                hosts: self.hosts,
-                secret: self.secret,
-                token: self.token,
-                wildcard: self.wildcard,
-                chain_cert: self.chain_cert,
-                tls_cert: self.tls_cert,
-                tls_key: self.tls_key,
-                log_level: self.log_level,
-                network_id: Some(self.network_id),
-                local_url: Some(self.local_url),
+                domain: args.domain,
+                auto_domain: args.network_name_as_domain,
+                hosts: args.hosts,
+                hosts_domain: args.hosts_domain,
+                hosts_strict: args.hosts_strict,
+                lenient_hosts: args.lenient_hosts,
+                hosts_precedence: args.hosts_precedence,
+                secret: args.secret,
+                token: args.token,
+                wildcard: args.wildcard,
+                chain_cert: args.chain_cert,
+                tls_cert: args.tls_cert,
+                tls_key: args.tls_key,
+                dot_client_ca: args.dot_client_ca,
+                log_level: args.log_level,
+                log_filter: args.log_filter,
+                log_format: args.log_format,
+                log_target: args.log_target,
+                otlp_endpoint: args.otlp_endpoint,
+                network_id: args.network_id,
+                local_url: Some(args.local_url),
+                cache_dir: args.cache_dir,
+                dns_servers_limit: args.dns_servers_limit,
+                remove_dns_on_shutdown: args.remove_dns_on_shutdown,
+                no_update_dns: args.no_update_dns,
+                randomize_udp_port: !args.no_randomize_udp_port,
+                peer_dns: args.peer_dns,
+                bridge_dns: args.bridge_dns,
+                latency_aware: args.latency_aware,
+                soa_serial_policy: args.soa_serial_policy,
+                serial_increment: args.serial_increment,
+                http_connect_timeout: args.http_connect_timeout,
+                http_timeout: args.http_timeout,
+                service_api_timeout: args.service_api_timeout,
+                update_interval: args.update_interval,
+                initial_sync_timeout: args.initial_sync_timeout,
+                require_initial_sync: args.require_initial_sync,
+                user_agent_suffix: args.user_agent_suffix,
+                central_tls_fingerprint: args.cert_fingerprint,
+                max_members: args.max_members,
+                max_pages: args.max_pages,
+                max_member_age: args.max_member_age,
+                max_member_age_hours: args.max_member_age_hours,
+                max_records: args.max_records,
+                metrics_listen: args.metrics_listen,
+                ipv4_only: args.ipv4_only,
+                address_filter: (!args.address_filter.is_empty()).then_some(args.address_filter),
+                control_socket: args.control_socket,
+                offline: args.offline,
+                disable_ptr: args.disable_ptr,
+                rpz_file: args.rpz_file,
+                ecs_mode: args.ecs_mode,
+                etcd_endpoints: args.etcd_endpoints,
+                query_rate_limit: args.query_rate_limit.map(|requests_per_second| {
+                    QueryRateLimitConfig {
+                        requests_per_second,
+                        burst: args.query_rate_burst.unwrap_or(requests_per_second),
+                    }
+                }),
             }
         }
     }
 }
 
+#[derive(Args, Clone)]
+pub struct SuperviseArgs {
+    /// Run the nameserver and restart it when it fails, instead of installing a service for it
+    #[clap(long)]
+    pub foreground: bool,
+
+    /// With --foreground, exit when the nameserver does rather than restarting it
+    #[clap(long, requires = "foreground")]
+    pub once: bool,
+
+    /// With --foreground, append the nameserver's output to this file
+    #[clap(long = "log-file", value_name = "PATH", requires = "foreground")]
+    pub log_file: Option<PathBuf>,
+
+    /// With --foreground, write the supervisor's PID to this file while it runs
+    #[clap(long = "pid-file", value_name = "PATH", requires = "foreground")]
+    pub pid_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub start: StartArgs,
+}
+
 #[derive(Args)]
 pub struct UnsuperviseArgs {
     /// Network ID to remove
     pub network_id: String,
 }
 
+#[derive(Args)]
+pub struct TokenValidateArgs {
+    /// Path to a file containing the ZeroTier Central token
+    #[clap(short, long, value_name = "PATH")]
+    pub token: Option<PathBuf>,
+
+    /// Provide a different URL for contacting ZeroTier Central. Default:
+    #[clap(long = "central-url", value_name = "URL", default_value = CENTRAL_BASEURL)]
+    pub central_url: String,
+}
+
+#[derive(Args)]
+pub struct NetworkInfoArgs {
+    /// Network ID to query
+    pub network_id: String,
+
+    /// Path to a file containing the ZeroTier Central token
+    #[clap(short, long, value_name = "PATH")]
+    pub token: Option<PathBuf>,
+
+    /// Path to authtoken.secret, used to tell whether this host serves DNS for the network (usually detected)
+    #[clap(short, long, value_name = "PATH")]
+    pub secret: Option<PathBuf>,
+
+    /// Provide a different URL for contacting ZeroTier Central. Default:
+    #[clap(long = "central-url", value_name = "URL", default_value = CENTRAL_BASEURL)]
+    pub central_url: String,
+
+    /// Provide a different URL for contacting the local zerotier-one service. Default:
+    #[clap(long = "local-url", value_name = "LOCAL_URL", default_value = ZEROTIER_LOCAL_URL)]
+    pub local_url: String,
+
+    /// Print JSON instead of a table
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct CalcArgs {
+    /// Network ID, 16 hex digits
+    pub network_id: String,
+
+    /// Node ID of a member, 10 hex digits
+    pub node_id: Option<String>,
+
+    /// Print JSON instead of a table
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct RotateKeyArgs {
+    /// How long zones are signed with both keys before the old one is removed, such as "2h"; at
+    /// least as long as caches may hold the old key, which is also the default
+    #[clap(long, value_name = "DURATION")]
+    pub overlap: Option<HumanDuration>,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to complete for
+    #[clap(value_name = "SHELL", value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Args)]
+pub struct CheckHostsArgs {
+    /// Hosts files, or directories of *.hosts files, to check
+    #[clap(required = true, value_name = "PATH")]
+    pub files: Vec<PathBuf>,
+
+    /// Domain appended to names in the hosts files
+    #[clap(short, long)]
+    pub domain: Option<String>,
+
+    /// Also accept an address in a comment line (`# 10.0.0.1`) for the names on the next line
+    #[clap(long = "lenient-hosts")]
+    pub lenient_hosts: bool,
+}
+
+#[cfg(unix)]
+#[derive(Args)]
+pub struct StatusArgs {
+    /// Path to the control socket of the running nameserver
+    #[clap(long = "control-socket", value_name = "PATH")]
+    pub control_socket: PathBuf,
+
+    /// Print JSON instead of a table
+    #[clap(long)]
+    pub json: bool,
+
+    /// Warn about records with a TTL below this, such as "30s" [default: 30s]
+    #[clap(long = "ttl-threshold", value_name = "DURATION")]
+    pub ttl_threshold: Option<HumanDuration>,
+}
+
+#[cfg(unix)]
+#[derive(Args)]
+pub struct LogLevelArgs {
+    /// A level, or per-module directives such as "zeronsd=debug,warn"
+    #[clap(value_name = "FILTER")]
+    pub filter: String,
+
+    /// Path to the control socket of the running nameserver
+    #[clap(long = "control-socket", value_name = "PATH")]
+    pub control_socket: PathBuf,
+}
+
 pub async fn init() -> Result<(), errors::Error> {
     let cli = Cli::parse();
 
     let result = match cli.command {
-        Command::Start(args) => {
-            start(args).await.change_context(errors::Error)?;
+        Command::Start(args) => start(args).await,
+        Command::Supervise(args) => supervise(args).await,
+        Command::Unsupervise(args) => unsupervise(args),
+        Command::TokenValidate(args) => token_validate(args).await,
+        Command::NetworkInfo(args) => network_info(args).await,
+        Command::Calc(args) => calc(args),
+        Command::CheckHosts(args) => check_hosts(args),
+        #[cfg(unix)]
+        Command::Status(args) => status(args).await,
+        #[cfg(unix)]
+        Command::LogLevel(args) => log_level(args).await,
+        Command::RotateKey(args) => rotate_key(args),
+        Command::Completions(args) => {
+            completions(args);
+            Ok(())
+        }
+    };
 
-            loop {
-                tokio::time::sleep(Duration::MAX).await
-            }
+    if let Err(e) = result {
+        // in journald or syslog, the report is kept to one entry rather than a line each.
+        match crate::utils::log_target() {
+            Some(LogTarget::Journald | LogTarget::Syslog) => tracing::error!("{:?}", e),
+            _ => eprintln!("{:?}", e),
         }
-        Command::Supervise(args) => supervise(args),
-        Command::Unsupervise(args) => unsupervise(args),
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+// auto_network_id picks the network to serve from the networks.d directory beside authtoken.secret.
+// With several joined, the first is used; -s points at another zerotier-one home.
+fn auto_network_id(args: &mut StartArgs) -> Result<(), errors::Error> {
+    if !args.auto_network_id {
+        return Ok(());
+    }
+
+    let authtoken = authtoken_path(args.secret.as_deref())?;
+    let dir = authtoken
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new("."))
+        .join("networks.d");
+    let ids = read_network_ids_from_dir(&dir)?;
+
+    let Some(id) = ids.first() else {
+        return Err(errors::Error::Config).attach_printable(format!(
+            "no networks found in {}; join one or pass a network ID",
+            dir.display()
+        ));
     };
 
-    if result.is_err() {
-        eprintln!("{}", result.unwrap_err())
+    if ids.len() > 1 {
+        eprintln!(
+            "warning: {} networks found in {} ({}); using {}",
+            ids.len(),
+            dir.display(),
+            ids.join(", "),
+            id
+        );
     }
 
+    args.network_id = Some(id.clone());
     Ok(())
 }
 
-async fn start(args: StartArgs) -> Result<(), errors::Error> {
+async fn start(mut args: StartArgs) -> Result<(), errors::Error> {
+    auto_network_id(&mut args)?;
     let launcher: Launcher = args.into();
 
-    launcher.start().await.change_context(errors::Error)?;
+    let ztauthority = launcher.start().await?;
+    wait_for_shutdown().await;
+    launcher.shutdown(&ztauthority).await
+}
+
+// wait_for_shutdown resolves once the process has been asked to terminate.
+pub(crate) async fn wait_for_shutdown() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = terminate.recv() => {},
+            }
+            return;
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn token_validate(args: TokenValidateArgs) -> Result<(), errors::Error> {
+    // a missing or malformed token is as much an auth failure as a rejected one.
+    let client = match central_token(args.token.as_deref()).and_then(|token| {
+        central_client_with_url(&args.central_url, token, &HttpOptions::default())
+    }) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(TokenStatus::Invalid.exit_code());
+        }
+    };
+
+    let status = validate_token(&client).await;
+    match status {
+        TokenStatus::Valid(_) => println!("{}", status),
+        _ => {
+            eprintln!("{}", status);
+            std::process::exit(status.exit_code());
+        }
+    }
+
+    Ok(())
+}
+
+async fn network_info(args: NetworkInfoArgs) -> Result<(), errors::Error> {
+    let client = central_client_with_url(
+        &args.central_url,
+        central_token(args.token.as_deref())?,
+        &HttpOptions::default(),
+    )?;
+
+    let mut info = get_network_info(&client, &args.network_id).await?;
+
+    // a host that is not joined, or not running zerotier-one, simply does not manage the network.
+    if let Ok(authtoken) = authtoken_path(args.secret.as_deref()) {
+        if let Ok(ips) = get_listen_ips(
+            &authtoken,
+            &args.network_id,
+            args.local_url,
+            DEFAULT_SERVICE_API_TIMEOUT,
+        )
+        .await
+        {
+            info = info.managed_by(&parse_ips_from_cidrs(&ips), DEFAULT_UPDATE_INTERVAL);
+        }
+    }
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&info).change_context(errors::Error::Config)?
+        );
+    } else {
+        print!("{}", info);
+    }
+
+    Ok(())
+}
+
+// rotate_key checks the rollover asked for, but has no key to roll over: zones are served unsigned
+// until zeronsd signs them.
+pub fn rotate_key(args: RotateKeyArgs) -> Result<(), errors::Error> {
+    Rollover::new(
+        std::time::SystemTime::now(),
+        RolloverTiming::default(),
+        args.overlap.map(Into::into),
+    )?;
+
+    Err(errors::Error::Unsigned)
+        .attach_printable("zeronsd serves its zones unsigned, so there is no DNSSEC key to rotate")
+}
+
+fn completions(args: CompletionsArgs) {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    generate(args.shell, &mut cmd, name, &mut std::io::stdout());
+}
+
+fn calc(args: CalcArgs) -> Result<(), errors::Error> {
+    let plan = AddressPlan::new(&args.network_id, args.node_id.as_deref())?;
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&plan).change_context(errors::Error::Config)?
+        );
+    } else {
+        print!("{}", plan);
+    }
+
+    Ok(())
+}
+
+// control sends command to the control socket at path, returning the data it answers with.
+#[cfg(unix)]
+async fn control(
+    path: &std::path::Path,
+    command: serde_json::Value,
+) -> Result<serde_json::Value, errors::Error> {
+    let response = crate::control::request(path, command).await?;
+
+    if let Some(error) = response.get("error").and_then(|e| e.as_str()) {
+        return Err(Report::new(errors::Error::Server).attach_printable(error.to_string()));
+    }
+
+    Ok(response
+        .get("data")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null))
+}
+
+// records with a TTL below this are warned about by status.
+#[cfg(unix)]
+const DEFAULT_TTL_THRESHOLD: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[cfg(unix)]
+async fn status(args: StatusArgs) -> Result<(), errors::Error> {
+    let log_filter = control(
+        &args.control_socket,
+        serde_json::json!({"cmd": "log_filter"}),
+    )
+    .await?;
+    let forwarding = control(
+        &args.control_socket,
+        serde_json::json!({"cmd": "forwarding"}),
+    )
+    .await?;
+    let network = control(&args.control_socket, serde_json::json!({"cmd": "network"})).await?;
+    let records = match control(
+        &args.control_socket,
+        serde_json::json!({"cmd": "list_records"}),
+    )
+    .await?
+    {
+        serde_json::Value::Null => serde_json::Value::Array(vec![]),
+        records => records,
+    };
+
+    let threshold = args
+        .ttl_threshold
+        .map_or(DEFAULT_TTL_THRESHOLD, std::time::Duration::from);
+    let short_ttls = match control(
+        &args.control_socket,
+        serde_json::json!({"cmd": "ttl_scan", "threshold": threshold.as_secs()}),
+    )
+    .await?
+    {
+        serde_json::Value::Null => serde_json::Value::Array(vec![]),
+        records => records,
+    };
+
+    if args.json {
+        let status = serde_json::json!({
+            "network": network,
+            "log_filter": log_filter,
+            "forwarding": forwarding,
+            "records": records,
+            "short_ttls": short_ttls,
+        });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&status).change_context(errors::Error::Config)?
+        );
+    } else {
+        // a comment, as zone files have them, so the records below still read as a zone.
+        println!("; {}", crate::metrics::describe_network(&network));
+        if let Some(log_filter) = log_filter.as_str() {
+            println!("; log filter: {}", log_filter);
+        }
+        if forwarding == serde_json::Value::Bool(false) {
+            println!("; forwarding: off, no usable resolver configuration");
+        }
+        for record in records.as_array().into_iter().flatten() {
+            println!(
+                "{}\t{}\t{}\t{}",
+                record["name"].as_str().unwrap_or_default(),
+                record["ttl"],
+                record["type"].as_str().unwrap_or_default(),
+                record["rdata"].as_str().unwrap_or_default(),
+            );
+        }
+    }
+
+    // the nameserver logs these too; they go to stderr so the table above is still a zone.
+    for record in short_ttls.as_array().into_iter().flatten() {
+        eprintln!(
+            "warning: {} {} has a TTL of {}s, below {}s",
+            record["name"].as_str().unwrap_or_default(),
+            record["type"].as_str().unwrap_or_default(),
+            record["ttl"],
+            threshold.as_secs()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn log_level(args: LogLevelArgs) -> Result<(), errors::Error> {
+    let filter = control(
+        &args.control_socket,
+        serde_json::json!({"cmd": "set_log_filter", "filter": args.filter}),
+    )
+    .await?;
+
+    println!("Log filter set to {}", filter.as_str().unwrap_or_default());
+    Ok(())
+}
+
+fn check_hosts(args: CheckHostsArgs) -> Result<(), errors::Error> {
+    let domain = domain_or_default(args.domain.as_deref())?;
+    let diagnostics = check_hosts_files(&args.files, domain, args.lenient_hosts)?;
+
+    for diagnostic in &diagnostics {
+        let severity = if diagnostic.is_error() {
+            "error"
+        } else {
+            "warning"
+        };
+        println!("{}: {}", severity, diagnostic);
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    if errors > 0 {
+        eprintln!("{} error(s) found", errors);
+        std::process::exit(1);
+    }
+
     Ok(())
 }
 
 fn unsupervise(args: UnsuperviseArgs) -> Result<(), errors::Error> {
-    crate::utils::init_logger(Some(tracing::Level::INFO));
+    crate::utils::init_logger(
+        crate::log::env_filter(LevelFilter::Info, None)?,
+        LogFormat::Text,
+        LogTarget::Stdout,
+        None,
+    );
     Properties::from(args).uninstall_supervisor()
 }
 
-fn supervise(args: StartArgs) -> Result<(), errors::Error> {
-    crate::utils::init_logger(Some(tracing::Level::INFO));
+async fn supervise(args: SuperviseArgs) -> Result<(), errors::Error> {
+    crate::utils::init_logger(
+        crate::log::env_filter(LevelFilter::Info, None)?,
+        LogFormat::Text,
+        LogTarget::Stdout,
+        None,
+    );
+
+    if args.foreground {
+        return Foreground::from_env(args.once, args.log_file, args.pid_file)?
+            .run()
+            .await;
+    }
+
+    let mut args = args.start;
+    auto_network_id(&mut args)?;
     Properties::from(args).install_supervisor()
 }
@@ -0,0 +1,111 @@
+/// names claimed by more than one source of records, and which of them gets to serve it
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+use trust_dns_resolver::Name;
+
+use crate::hosts::HostsPrecedence;
+
+// RecordSource is where the records asked for under a name come from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RecordSource {
+    // a member, by node ID
+    Member(String),
+    // an address in the hosts files
+    Hosts,
+    // a cname directive in the hosts files
+    Cname,
+    // a peer with a direct path, with --peer-dns
+    Peer,
+    // a host bridged onto the network, with --bridge-dns
+    Bridge,
+}
+
+impl std::fmt::Display for RecordSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordSource::Member(node_id) => write!(f, "member {}", node_id),
+            RecordSource::Hosts => f.write_str("hosts file"),
+            RecordSource::Cname => f.write_str("hosts file cname"),
+            RecordSource::Peer => f.write_str("peer"),
+            RecordSource::Bridge => f.write_str("bridged host"),
+        }
+    }
+}
+
+impl Serialize for RecordSource {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Collision is a name more than one source asked for records under. The winner is the source
+// whose records are served; without one, the addresses of all of them are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Collision {
+    #[serde(serialize_with = "serialize_name")]
+    pub name: Name,
+    pub sources: Vec<RecordSource>,
+    pub winner: Option<RecordSource>,
+}
+
+fn serialize_name<S: Serializer>(name: &Name, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(name)
+}
+
+impl std::fmt::Display for Collision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sources = self
+            .sources
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        match &self.winner {
+            Some(winner) => write!(
+                f,
+                "{} is claimed by {}; using {}",
+                self.name, sources, winner
+            ),
+            None => write!(
+                f,
+                "{} is claimed by {}; serving the addresses of all of them",
+                self.name, sources
+            ),
+        }
+    }
+}
+
+pub type Collisions = BTreeMap<Name, Collision>;
+
+// member_winner picks which of several members claiming name keeps it: the member it is the
+// zt-<node id> name of, or otherwise the one with the lowest node ID. The rest are left with only
+// their zt-<node id> names.
+pub fn member_winner<'a>(name: &Name, node_ids: &'a [String]) -> Option<&'a String> {
+    let own = name
+        .iter()
+        .next()
+        .and_then(|label| std::str::from_utf8(label).ok())
+        .and_then(|label| label.strip_prefix("zt-"))
+        .map(str::to_lowercase);
+
+    node_ids
+        .iter()
+        .find(|node_id| own.as_deref() == Some(node_id.to_lowercase().as_str()))
+        .or_else(|| node_ids.iter().min())
+}
+
+// hosts_winner picks between the hosts files and the member for a name both claim. A cname cannot
+// share its name with addresses, so merging gives the name to the alias.
+pub fn hosts_winner(
+    precedence: HostsPrecedence,
+    hosts: RecordSource,
+    member: RecordSource,
+) -> Option<RecordSource> {
+    match precedence {
+        HostsPrecedence::HostsWins => Some(hosts),
+        HostsPrecedence::MemberWins => Some(member),
+        HostsPrecedence::Merge if hosts == RecordSource::Cname => Some(hosts),
+        HostsPrecedence::Merge => None,
+    }
+}
@@ -0,0 +1,318 @@
+/// a unix socket for adjusting a running instance. Each line written to it is a JSON command,
+/// such as `{"cmd":"set_interval","secs":60}`, and is answered with a line of JSON.
+use std::{path::Path, time::Duration};
+
+use error_stack::{Report, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{
+    authority::{RecordAuthority, ZTAuthority},
+    errors,
+};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    // change how often members are fetched from central
+    SetInterval { secs: u64 },
+    // list the names claimed by more than one source of records
+    Collisions,
+    // list every record served, forward zones first, then the reverse zones
+    ListRecords,
+    // list the records with a TTL below threshold seconds, logging a warning for each
+    TtlScan { threshold: u64 },
+    // report the log filter in effect
+    LogFilter,
+    // report whether queries outside our zones are forwarded
+    Forwarding,
+    // report the ID of the network served and its name in central
+    Network,
+    // replace the log filter, with directives such as "debug" or "zeronsd=debug,warn"
+    SetLogFilter { filter: String },
+    // answer queries for our zones with SERVFAIL, until resumed or for timeout seconds
+    Pause { timeout: Option<u64> },
+    // answer queries again
+    Resume,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            data: None,
+        }
+    }
+
+    fn data(data: serde_json::Value) -> Self {
+        Self {
+            ok: true,
+            error: None,
+            data: Some(data),
+        }
+    }
+
+    fn error(error: String) -> Self {
+        Self {
+            ok: false,
+            error: Some(error),
+            data: None,
+        }
+    }
+}
+
+// bind_control listens on path, replacing a socket left behind by an earlier run. Only the user
+// zeronsd runs as may connect. Anything other than a socket at path is left alone.
+pub fn bind_control(path: &Path) -> Result<UnixListener, errors::Error> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)
+            .change_context(errors::Error::Io {
+                path: path.to_path_buf(),
+            })
+            .attach_printable_lazy(|| {
+                format!("could not remove old control socket {}", path.display())
+            })?,
+        Ok(_) => {
+            return Err(errors::Error::Io {
+                path: path.to_path_buf(),
+            })
+            .attach_printable(format!(
+                "{} exists and is not a socket; not replacing it",
+                path.display()
+            ));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(e).change_context(errors::Error::Io {
+                path: path.to_path_buf(),
+            });
+        }
+    }
+
+    // the socket is created with the mode the umask allows, so no one else can connect to it in
+    // the moment before a chmod would take effect.
+    let umask = Umask::set(0o177);
+    let listener = UnixListener::bind(path)
+        .change_context(errors::Error::Io {
+            path: path.to_path_buf(),
+        })
+        .attach_printable_lazy(|| format!("could not listen on {}", path.display()));
+    drop(umask);
+
+    listener
+}
+
+// Umask sets the process umask until dropped, then puts the one before back.
+struct Umask(libc::mode_t);
+
+impl Umask {
+    fn set(mask: libc::mode_t) -> Self {
+        // SAFETY: umask only swaps the process's file mode creation mask and cannot fail.
+        Self(unsafe { libc::umask(mask) })
+    }
+}
+
+impl Drop for Umask {
+    fn drop(&mut self) {
+        // SAFETY: as in set.
+        unsafe {
+            libc::umask(self.0);
+        }
+    }
+}
+
+// serve_control answers commands on listener until the process exits.
+pub async fn serve_control(listener: UnixListener, zt: ZTAuthority) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let zt = zt.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_connection(stream, &zt).await {
+                        tracing::debug!("error serving control connection: {:?}", e);
+                    }
+                });
+            }
+            Err(e) => tracing::error!("error accepting control connection: {}", e),
+        }
+    }
+}
+
+async fn serve_connection(stream: UnixStream, zt: &ZTAuthority) -> Result<(), errors::Error> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .change_context(errors::Error::Server)?
+    {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str(&line) {
+            Ok(command) => handle(zt, command).await,
+            Err(e) => Response::error(format!("invalid command: {}", e)),
+        };
+
+        let mut out = serde_json::to_string(&response).change_context(errors::Error::Server)?;
+        out.push('\n');
+        write
+            .write_all(out.as_bytes())
+            .await
+            .change_context(errors::Error::Server)?;
+    }
+
+    Ok(())
+}
+
+// handle carries out a single command.
+pub async fn handle(zt: &ZTAuthority, command: Command) -> Response {
+    match command {
+        Command::SetInterval { secs } => {
+            if secs == 0 {
+                return Response::error("interval must be at least one second".to_string());
+            }
+
+            *zt.update_interval.write().await = Duration::from_secs(secs);
+            tracing::info!(
+                "Update interval set to {}s through the control socket",
+                secs
+            );
+            Response::ok()
+        }
+        Command::Collisions => match serde_json::to_value(zt.collisions().await) {
+            Ok(data) => Response::data(data),
+            Err(e) => Response::error(format!("could not list collisions: {}", e)),
+        },
+        Command::ListRecords => {
+            let mut records = Vec::new();
+            for authority in authorities(zt) {
+                if let serde_json::Value::Array(zone) = authority.export_json().await {
+                    records.extend(zone);
+                }
+            }
+
+            Response::data(serde_json::Value::Array(records))
+        }
+        Command::TtlScan { threshold } => {
+            let mut records = Vec::new();
+            for authority in authorities(zt) {
+                for (name, rtype, ttl) in authority.ttl_scan(Duration::from_secs(threshold)).await {
+                    tracing::warn!(
+                        record = %name,
+                        rtype = %rtype,
+                        ttl,
+                        "Record TTL is below {}s; clients will soon ask for it again",
+                        threshold
+                    );
+                    records.push(serde_json::json!({
+                        "name": name.to_string(),
+                        "type": rtype.to_string(),
+                        "ttl": ttl,
+                    }));
+                }
+            }
+
+            Response::data(serde_json::Value::Array(records))
+        }
+        Command::LogFilter => Response::data(serde_json::json!(crate::utils::log_filter())),
+        Command::Forwarding => Response::data(serde_json::json!(!zt
+            .no_forwarder
+            .load(std::sync::atomic::Ordering::SeqCst))),
+        Command::Network => Response::data(zt.network_summary().await),
+        Command::SetLogFilter { filter } => {
+            match crate::log::parse_filter(&filter).and_then(crate::utils::set_log_filter) {
+                Ok(filter) => Response::data(serde_json::json!(filter)),
+                Err(e) => Response::error(reason(&e)),
+            }
+        }
+        Command::Pause { timeout } => {
+            if timeout == Some(0) {
+                return Response::error("timeout must be at least one second".to_string());
+            }
+
+            zt.pause(timeout.map(Duration::from_secs));
+            Response::ok()
+        }
+        Command::Resume => {
+            zt.resume();
+            Response::ok()
+        }
+    }
+}
+
+// authorities lists every zone served, forward zones first, then the reverse zones in order.
+fn authorities(zt: &ZTAuthority) -> Vec<&RecordAuthority> {
+    let mut authorities = vec![&zt.forward_authority];
+    authorities.extend(zt.hosts_authority.as_ref());
+
+    let mut reverse: Vec<_> = zt.reverse_authority_map.iter().collect();
+    reverse.sort_by_key(|(network, _)| network.to_string());
+    authorities.extend(reverse.into_iter().map(|(_, authority)| authority));
+    authorities
+}
+
+// reason is what a report says went wrong, on one line.
+fn reason(report: &Report<errors::Error>) -> String {
+    use error_stack::{AttachmentKind, FrameKind};
+
+    std::iter::once(report.current_context().to_string())
+        .chain(report.frames().filter_map(|frame| match frame.kind() {
+            FrameKind::Attachment(AttachmentKind::Printable(printable)) => {
+                Some(printable.to_string())
+            }
+            _ => None,
+        }))
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+// request sends command to the control socket at path and returns its response.
+pub async fn request(
+    path: &Path,
+    command: serde_json::Value,
+) -> Result<serde_json::Value, errors::Error> {
+    let stream = UnixStream::connect(path)
+        .await
+        .change_context(errors::Error::Io {
+            path: path.to_path_buf(),
+        })
+        .attach_printable_lazy(|| format!("could not connect to {}", path.display()))?;
+    let (read, mut write) = stream.into_split();
+
+    let mut out = serde_json::to_string(&command).change_context(errors::Error::Server)?;
+    out.push('\n');
+    write
+        .write_all(out.as_bytes())
+        .await
+        .change_context(errors::Error::Server)?;
+
+    let line = BufReader::new(read)
+        .lines()
+        .next_line()
+        .await
+        .change_context(errors::Error::Server)?
+        .ok_or_else(|| {
+            Report::new(errors::Error::Server)
+                .attach_printable("control socket closed without answering")
+        })?;
+
+    serde_json::from_str(&line).change_context(errors::Error::Server)
+}
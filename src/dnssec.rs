@@ -0,0 +1,150 @@
+/// the timing of DNSSEC zone signing key rollovers, by the double-signature method of RFC 7583:
+/// the new key is published and signs alongside the old one, and the old key and its signatures
+/// go once nothing can still be relying on them. zeronsd does not sign its zones yet, so nothing
+/// drives a rollover; this is the state machine a signer will.
+use std::time::{Duration, SystemTime};
+
+use error_stack::*;
+
+use crate::errors;
+
+// RolloverTiming holds the intervals a rollover has to wait out, named as in RFC 7583 section 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RolloverTiming {
+    // Dprp: how long a change to the zone takes to reach every server answering for it.
+    pub propagation_delay: Duration,
+    // TTLkey: the TTL of the DNSKEY RRset.
+    pub key_ttl: Duration,
+    // TTLsig: the longest TTL of any signature in the zone.
+    pub signature_ttl: Duration,
+    // a margin on top, for resolvers that hold on to records a little longer than they should.
+    pub safety_margin: Duration,
+}
+
+impl Default for RolloverTiming {
+    // zeronsd is the only server for its zones, so changes reach them at once.
+    fn default() -> Self {
+        Self {
+            propagation_delay: Duration::ZERO,
+            key_ttl: Duration::from_secs(3600),
+            signature_ttl: Duration::from_secs(3600),
+            safety_margin: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl RolloverTiming {
+    // min_overlap is the least time the zone must be signed with both keys: until every server has
+    // the new key and its signatures, and every cached copy of the DNSKEY RRset or a signature from
+    // before has expired.
+    pub fn min_overlap(&self) -> Duration {
+        self.propagation_delay + self.key_ttl.max(self.signature_ttl) + self.safety_margin
+    }
+}
+
+// KeyState is where a key is in its life, in the terms of RFC 7583 section 3.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    // created, but not yet in the zone.
+    Generated,
+    // in the DNSKEY RRset and signing, but resolvers may still hold the RRset from before it.
+    Published,
+    // signing, and known to every resolver that has the DNSKEY RRset.
+    Active,
+    // out of the zone, along with its signatures.
+    Removed,
+}
+
+// Phase is how far a rollover has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    // the old key alone signs; the rollover has yet to start.
+    Pending,
+    // both keys are published and every RRset is signed with each.
+    DoubleSigned,
+    // the old key and its signatures are gone; the new key signs alone.
+    Complete,
+}
+
+// Rollover is one double-signature rollover of a zone signing key, started at started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rollover {
+    started: SystemTime,
+    timing: RolloverTiming,
+    overlap: Duration,
+}
+
+impl Rollover {
+    // new plans a rollover keeping the zone double-signed for overlap, or the least timing allows
+    // without one. An overlap shorter than that would leave resolvers with signatures they can't
+    // check, so it is refused.
+    pub fn new(
+        started: SystemTime,
+        timing: RolloverTiming,
+        overlap: Option<Duration>,
+    ) -> Result<Self, errors::Error> {
+        let min_overlap = timing.min_overlap();
+        let overlap = overlap.unwrap_or(min_overlap);
+        if overlap < min_overlap {
+            return Err(errors::Error::Config).attach_printable(format!(
+                "a key rollover overlap of {}s is shorter than the {}s caches may hold the old key",
+                overlap.as_secs(),
+                min_overlap.as_secs()
+            ));
+        }
+
+        Ok(Self {
+            started,
+            timing,
+            overlap,
+        })
+    }
+
+    // phase is where the rollover stands at now.
+    pub fn phase(&self, now: SystemTime) -> Phase {
+        if now < self.started {
+            Phase::Pending
+        } else if now < self.completes_at() {
+            Phase::DoubleSigned
+        } else {
+            Phase::Complete
+        }
+    }
+
+    // completes_at is when the old key and its signatures can be removed.
+    pub fn completes_at(&self) -> SystemTime {
+        self.started + self.overlap
+    }
+
+    // new_key_active_at is when every resolver that has the DNSKEY RRset knows the new key:
+    // Trdy in RFC 7583, which for this method is also when the key can be said to be active.
+    pub fn new_key_active_at(&self) -> SystemTime {
+        self.started + self.timing.propagation_delay + self.timing.key_ttl
+    }
+
+    // old_key is the state of the key being replaced at now.
+    pub fn old_key(&self, now: SystemTime) -> KeyState {
+        match self.phase(now) {
+            Phase::Pending | Phase::DoubleSigned => KeyState::Active,
+            Phase::Complete => KeyState::Removed,
+        }
+    }
+
+    // new_key is the state of the replacement key at now.
+    pub fn new_key(&self, now: SystemTime) -> KeyState {
+        match self.phase(now) {
+            Phase::Pending => KeyState::Generated,
+            _ if now < self.new_key_active_at() => KeyState::Published,
+            _ => KeyState::Active,
+        }
+    }
+
+    // signs_with_old and signs_with_new say which keys the zone is signed with at now.
+    pub fn signs_with_old(&self, now: SystemTime) -> bool {
+        self.old_key(now) != KeyState::Removed
+    }
+
+    pub fn signs_with_new(&self, now: SystemTime) -> bool {
+        self.new_key(now) != KeyState::Generated
+    }
+}
@@ -0,0 +1,397 @@
+/// the client subnet (ECS, RFC 7871) of forwarded queries: stripped, passed on as the client sent
+/// it, or cut down to a shorter prefix. trust-dns's forwarder asks upstream with queries of its
+/// own, so queries with a subnet to pass on are sent upstream from here instead.
+use std::{str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use error_stack::ResultExt;
+use tokio::net::{TcpStream, UdpSocket};
+use trust_dns_resolver::{
+    config::{NameServerConfig, Protocol},
+    proto::{
+        error::ProtoError,
+        iocompat::AsyncIoTokioAsStd,
+        op::{Edns, Message, MessageType, NoopMessageFinalizer, OpCode, Query, ResponseCode},
+        rr::{
+            rdata::opt::{EdnsCode, EdnsOption},
+            Record, RecordType,
+        },
+        tcp::TcpClientStream,
+        udp::UdpClientStream,
+        xfer::{
+            DnsExchange, DnsHandle, DnsMultiplexer, DnsRequest, DnsRequestOptions, DnsResponse,
+            FirstAnswer,
+        },
+        TokioTime,
+    },
+};
+use trust_dns_server::{
+    authority::{AuthorityObject, LookupError, LookupObject, LookupOptions, MessageRequest},
+    client::rr::LowerName,
+    server::{Request, RequestHandler, RequestInfo, ResponseHandler, ResponseInfo},
+    store::forwarder::ForwardConfig,
+};
+
+use crate::errors;
+
+// the EDNS option code of a client subnet (RFC 7871).
+const ECS_OPTION_CODE: u16 = 8;
+
+// the EDNS payload size asked for upstream, small enough not to be fragmented on most paths.
+const UPSTREAM_MAX_PAYLOAD: u16 = 1232;
+
+tokio::task_local! {
+    // the client subnet option to send upstream with the query being answered, with the mode
+    // already applied; see ClientSubnetHandler.
+    static CLIENT_SUBNET: Option<Vec<u8>>;
+}
+
+// EcsMode is what becomes of the client subnet (ECS) option of a query that is forwarded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EcsMode {
+    // the option is removed, so upstream learns nothing of where the client is.
+    #[default]
+    Strip,
+    // the option is left as the client sent it, for answers tuned to the client's network.
+    PassThrough,
+    // the option is kept to at most this many leading bits of the client's address.
+    AnonymizePrefix(u8),
+}
+
+impl EcsMode {
+    // preprocess applies the mode to the EDNS of a query about to be forwarded. A client subnet
+    // option that can't be read is removed whatever the mode, rather than passed on as it is.
+    pub fn preprocess(&self, edns: &mut Edns) {
+        let code = EdnsCode::from(ECS_OPTION_CODE);
+        let data = match edns.option(code) {
+            Some(EdnsOption::Unknown(_, data)) => data.clone(),
+            _ => return,
+        };
+
+        match self {
+            Self::PassThrough if parse_client_subnet(&data).is_some() => {}
+            Self::AnonymizePrefix(prefix) => match anonymize_client_subnet(&data, *prefix) {
+                Some(data) => edns
+                    .options_mut()
+                    .insert(EdnsOption::Unknown(ECS_OPTION_CODE, data)),
+                None => edns.options_mut().remove(code),
+            },
+            _ => edns.options_mut().remove(code),
+        }
+    }
+
+    // client_subnet is the client subnet option of request to send upstream, if the mode leaves
+    // one.
+    pub fn client_subnet(&self, request: &MessageRequest) -> Option<Vec<u8>> {
+        let mut edns = request.edns()?.clone();
+        self.preprocess(&mut edns);
+        match edns.option(EdnsCode::from(ECS_OPTION_CODE)) {
+            Some(EdnsOption::Unknown(_, data)) => Some(data.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for EcsMode {
+    type Err = errors::ErrorReport;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "strip" => Ok(Self::Strip),
+            "pass-through" => Ok(Self::PassThrough),
+            _ => match s.strip_prefix("anonymize-prefix:").map(u8::from_str) {
+                Some(Ok(prefix)) if prefix <= 128 => Ok(Self::AnonymizePrefix(prefix)),
+                _ => Err(errors::Error::Config).attach_printable(format!(
+                    "invalid ECS mode {:?}: allowed values: [strip, pass-through, anonymize-prefix:<0-128>]",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for EcsMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strip => f.write_str("strip"),
+            Self::PassThrough => f.write_str("pass-through"),
+            Self::AnonymizePrefix(prefix) => write!(f, "anonymize-prefix:{}", prefix),
+        }
+    }
+}
+
+impl serde::Serialize for EcsMode {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for EcsMode {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|e: errors::ErrorReport| serde::de::Error::custom(format!("{:?}", e)))
+    }
+}
+
+// parse_client_subnet reads the family, source prefix length and address bytes of a client
+// subnet option, checking they agree with each other as RFC 7871 requires.
+fn parse_client_subnet(data: &[u8]) -> Option<(u16, u8, &[u8])> {
+    let (family, source, address) = match data {
+        [f0, f1, source, _scope, address @ ..] => {
+            (u16::from_be_bytes([*f0, *f1]), *source, address)
+        }
+        _ => return None,
+    };
+    let max = match family {
+        1 => 32,
+        2 => 128,
+        _ => return None,
+    };
+    (source <= max && address.len() == usize::from(source).div_ceil(8))
+        .then_some((family, source, address))
+}
+
+// anonymize_client_subnet shortens a client subnet option to at most prefix bits of address, with
+// the bits past the prefix cleared. The scope, which is for answers, goes back to 0.
+fn anonymize_client_subnet(data: &[u8], prefix: u8) -> Option<Vec<u8>> {
+    let (family, source, address) = parse_client_subnet(data)?;
+    let source = source.min(prefix);
+    let len = usize::from(source).div_ceil(8);
+
+    let mut address = address[..len].to_vec();
+    if source % 8 != 0 {
+        address[len - 1] &= 0xff << (8 - source % 8);
+    }
+
+    let mut data = family.to_be_bytes().to_vec();
+    data.extend([source, 0]);
+    data.extend(address);
+    Some(data)
+}
+
+// ClientSubnetHandler hands requests to handler with the client subnet the mode leaves them, for
+// SubnetForwarder to send upstream.
+pub struct ClientSubnetHandler<H> {
+    handler: H,
+    mode: EcsMode,
+}
+
+impl<H: RequestHandler> ClientSubnetHandler<H> {
+    pub fn new(handler: H, mode: EcsMode) -> Self {
+        Self { handler, mode }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for ClientSubnetHandler<H> {
+    async fn handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        CLIENT_SUBNET
+            .scope(
+                self.mode.client_subnet(request),
+                self.handler.handle_request(request, response_handle),
+            )
+            .await
+    }
+}
+
+// SubnetForwarder forwards queries with a client subnet to pass on to the name servers itself,
+// with the subnet, and leaves the rest to forwarder. Answers tuned to one subnet are no good to
+// another, so the former are not cached.
+pub struct SubnetForwarder {
+    forwarder: Box<dyn AuthorityObject>,
+    name_servers: Vec<NameServerConfig>,
+    timeout: Duration,
+}
+
+impl SubnetForwarder {
+    pub fn new(forwarder: Box<dyn AuthorityObject>, config: &ForwardConfig) -> Self {
+        Self {
+            forwarder,
+            name_servers: config.name_servers.to_vec(),
+            timeout: config.options.unwrap_or_default().timeout,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthorityObject for SubnetForwarder {
+    fn box_clone(&self) -> Box<dyn AuthorityObject> {
+        Box::new(Self {
+            forwarder: self.forwarder.box_clone(),
+            name_servers: self.name_servers.clone(),
+            timeout: self.timeout,
+        })
+    }
+
+    fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
+        self.forwarder.zone_type()
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        self.forwarder.is_axfr_allowed()
+    }
+
+    async fn update(
+        &self,
+        update: &MessageRequest,
+    ) -> trust_dns_server::authority::UpdateResult<bool> {
+        self.forwarder.update(update).await
+    }
+
+    fn origin(&self) -> &LowerName {
+        self.forwarder.origin()
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        self.forwarder.lookup(name, rtype, lookup_options).await
+    }
+
+    async fn search(
+        &self,
+        request_info: RequestInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        match CLIENT_SUBNET.try_with(Clone::clone).ok().flatten() {
+            Some(subnet) => forward_with_subnet(
+                &self.name_servers,
+                self.timeout,
+                request_info.query.original().clone(),
+                subnet,
+            )
+            .await
+            .map(|lookup| Box::new(lookup) as Box<dyn LookupObject>),
+            None => self.forwarder.search(request_info, lookup_options).await,
+        }
+    }
+
+    async fn get_nsec_records(
+        &self,
+        name: &LowerName,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        self.forwarder.get_nsec_records(name, lookup_options).await
+    }
+}
+
+// SubnetLookup is the answer to a query forwarded with a client subnet.
+pub struct SubnetLookup(Vec<Record>);
+
+impl LookupObject for SubnetLookup {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Record> + Send + 'a> {
+        Box::new(self.0.iter())
+    }
+
+    fn take_additionals(&mut self) -> Option<Box<dyn LookupObject>> {
+        None
+    }
+}
+
+// forward_with_subnet asks name_servers for query in turn, with the client subnet option subnet,
+// until one answers. A truncated answer moves on as well, which reaches the TCP name server after
+// the UDP one for the same address.
+async fn forward_with_subnet(
+    name_servers: &[NameServerConfig],
+    timeout: Duration,
+    query: Query,
+    subnet: Vec<u8>,
+) -> core::result::Result<SubnetLookup, LookupError> {
+    let mut edns = Edns::new();
+    edns.set_max_payload(UPSTREAM_MAX_PAYLOAD);
+    edns.options_mut()
+        .insert(EdnsOption::Unknown(ECS_OPTION_CODE, subnet));
+
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(query)
+        .set_edns(edns);
+
+    let mut error = LookupError::from(ResponseCode::ServFail);
+    for server in name_servers {
+        match exchange(server, timeout, message.clone()).await {
+            Ok(response) if response.truncated() => {
+                tracing::debug!("Truncated answer from {}", server.socket_addr);
+            }
+            Ok(response) => match response.response_code() {
+                ResponseCode::NoError => return Ok(SubnetLookup(response.answers().to_vec())),
+                ResponseCode::NXDomain => return Err(LookupError::from(ResponseCode::NXDomain)),
+                code => {
+                    tracing::debug!("{} answered {}", server.socket_addr, code);
+                    error = LookupError::from(code);
+                }
+            },
+            Err(e) => {
+                tracing::debug!("error forwarding to {}: {}", server.socket_addr, e);
+                error = LookupError::from(std::io::Error::other(e));
+            }
+        }
+    }
+
+    Err(error)
+}
+
+// exchange sends message to server and waits for the answer.
+async fn exchange(
+    server: &NameServerConfig,
+    timeout: Duration,
+    message: Message,
+) -> core::result::Result<DnsResponse, ProtoError> {
+    let request = DnsRequest::new(message, DnsRequestOptions::default());
+
+    match server.protocol {
+        Protocol::Udp => {
+            let (mut exchange, background) = DnsExchange::connect::<_, _, TokioTime>(
+                UdpClientStream::<UdpSocket>::with_bind_addr_and_timeout(
+                    server.socket_addr,
+                    server.bind_addr,
+                    timeout,
+                ),
+            )
+            .await?;
+            tokio::spawn(background);
+            exchange.send(request).first_answer().await
+        }
+        Protocol::Tcp => {
+            let (stream, handle) =
+                TcpClientStream::<AsyncIoTokioAsStd<TcpStream>>::with_bind_addr_and_timeout(
+                    server.socket_addr,
+                    server.bind_addr,
+                    timeout,
+                );
+            let (mut exchange, background) = DnsExchange::connect::<_, _, TokioTime>(
+                DnsMultiplexer::<_, NoopMessageFinalizer>::with_timeout(
+                    stream, handle, timeout, None,
+                ),
+            )
+            .await?;
+            tokio::spawn(background);
+            exchange.send(request).first_answer().await
+        }
+        protocol => Err(ProtoError::from(format!(
+            "forwarding with a client subnet over {} is not supported",
+            protocol
+        ))),
+    }
+}
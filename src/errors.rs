@@ -1,7 +1,106 @@
+use std::path::PathBuf;
+
 pub use error_stack::{Report, ResultExt};
-#[derive(Debug, thiserror::Error)]
-#[error("An error occurred")]
-pub struct Error;
+
+// Error classifies what went wrong; the report it is the context of carries the details.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("invalid configuration")]
+    Config,
+    #[error("request to ZeroTier Central failed{}", status.map(|s| format!(" (HTTP {})", s)).unwrap_or_default())]
+    CentralApi { status: Option<u16> },
+    #[error("request to zerotier-one failed")]
+    LocalApi,
+    #[error("I/O error on {}", path.display())]
+    Io { path: PathBuf },
+    #[error("invalid DNS name")]
+    DnsName,
+    #[error("invalid address")]
+    Address,
+    #[error("malformed member")]
+    Member,
+    #[error("TLS error")]
+    Tls,
+    #[error("DNS server error")]
+    Server,
+    #[error("could not configure supervision")]
+    Supervise,
+    #[error("zone would exceed its record quota")]
+    RecordQuota,
+    #[error("zones are not signed with DNSSEC")]
+    Unsigned,
+}
+
+impl Error {
+    // is_retryable reports whether trying the same thing again later could succeed. Bad
+    // configuration and credentials Central refuses will not fix themselves.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            Error::Config
+                | Error::CentralApi {
+                    status: Some(401 | 403)
+                }
+        )
+    }
+}
 
 pub type ErrorReport = Report<Error>;
 pub type Result<T, E = error_stack::Report<Error>> = core::result::Result<T, E>;
+
+// ApiResultExt attaches the right context to errors from the Central and zerotier-one clients.
+pub trait ApiResultExt<T> {
+    // central_context classifies the error as a failed Central request, keeping the HTTP status.
+    fn central_context(self) -> Result<T>;
+    // local_context classifies the error as a failed zerotier-one request.
+    fn local_context(self) -> Result<T>;
+}
+
+impl<T, E> ApiResultExt<T> for core::result::Result<T, zerotier_api::central_api::Error<E>>
+where
+    E: std::fmt::Debug + Send + Sync + 'static,
+{
+    fn central_context(self) -> Result<T> {
+        self.map_err(|e| {
+            let status = e.status().map(|s| s.as_u16());
+            Report::new(ApiError::new(e)).change_context(Error::CentralApi { status })
+        })
+    }
+
+    fn local_context(self) -> Result<T> {
+        self.map_err(|e| Report::new(ApiError::new(e)).change_context(Error::LocalApi))
+    }
+}
+
+// ApiError describes a failed API request without the headers of the response, which a proxy may
+// have copied the credentials of the request into.
+#[derive(Debug)]
+pub struct ApiError(String);
+
+impl ApiError {
+    fn new<E: std::fmt::Debug>(e: zerotier_api::central_api::Error<E>) -> Self {
+        use zerotier_api::central_api::Error as ClientError;
+
+        Self(match &e {
+            ClientError::ErrorResponse(response) => format!(
+                "Error Response: status: {}; value: {:?}",
+                response.status(),
+                response.as_ref()
+            ),
+            ClientError::UnexpectedResponse(response) => format!(
+                "Unexpected Response: status: {}; url: {}",
+                response.status(),
+                response.url()
+            ),
+            e => e.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ApiError {}
@@ -0,0 +1,180 @@
+/// latency-aware answers: of several addresses for a name, answer with those of the member nearest
+/// the querier, judged by the path latencies zerotier-one reports for its peers
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use trust_dns_resolver::proto::rr::{Record, RecordType};
+use trust_dns_server::{
+    authority::{AuthorityObject, LookupError, LookupObject, LookupOptions, MessageRequest},
+    client::rr::LowerName,
+    server::RequestInfo,
+};
+
+use crate::authority::RecordAuthority;
+
+// LatencyAwareRecordAuthority serves a RecordAuthority, narrowing address answers which span
+// several members down to the nearest of them.
+//
+// zerotier-one only knows the latency of its own paths, so "nearest" is measured from this host:
+// a querier asking for a name one of its own addresses is under gets that address, and otherwise
+// the member with the lowest latency from here is picked. Members without a direct path, and
+// queries where no candidate has a known latency, get every address as usual.
+#[derive(Clone)]
+pub struct LatencyAwareRecordAuthority {
+    authority: RecordAuthority,
+    // node ID of the member each address is assigned to, the same mapping PTR records are made
+    // from.
+    member_nodes: Arc<RwLock<HashMap<IpAddr, String>>>,
+    // latency in milliseconds to each peer with a direct path, by node ID.
+    peer_latencies: Arc<RwLock<HashMap<String, i64>>>,
+}
+
+impl LatencyAwareRecordAuthority {
+    pub fn new(
+        authority: RecordAuthority,
+        member_nodes: Arc<RwLock<HashMap<IpAddr, String>>>,
+        peer_latencies: Arc<RwLock<HashMap<String, i64>>>,
+    ) -> Self {
+        Self {
+            authority,
+            member_nodes,
+            peer_latencies,
+        }
+    }
+}
+
+// nearest keeps the address records belonging to the member nearest the querier; see
+// LatencyAwareRecordAuthority. Records are returned untouched when there is nothing to choose
+// between.
+pub fn nearest(
+    records: Vec<Record>,
+    querier: IpAddr,
+    member_nodes: &HashMap<IpAddr, String>,
+    peer_latencies: &HashMap<String, i64>,
+) -> Vec<Record> {
+    let querier = member_nodes.get(&querier);
+    let node = |record: &Record| {
+        record
+            .data()
+            .and_then(|rdata| rdata.to_ip_addr())
+            .and_then(|ip| member_nodes.get(&ip))
+    };
+    let distance = |node: &String| {
+        if Some(node) == querier {
+            Some(0)
+        } else {
+            peer_latencies
+                .get(node)
+                .copied()
+                .filter(|latency| *latency >= 0)
+        }
+    };
+
+    let best = records
+        .iter()
+        .filter_map(node)
+        .filter_map(|node| distance(node).map(|distance| (distance, node)))
+        .min()
+        .map(|(_, node)| node.clone());
+
+    match best {
+        Some(best) if records.iter().any(|record| node(record) != Some(&best)) => records
+            .into_iter()
+            .filter(|record| node(record) == Some(&best))
+            .collect(),
+        _ => records,
+    }
+}
+
+// NearestLookup is the narrowed answer, keeping whatever additional records came with it.
+struct NearestLookup {
+    records: Vec<Record>,
+    additionals: Option<Box<dyn LookupObject>>,
+}
+
+impl LookupObject for NearestLookup {
+    fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Record> + Send + 'a> {
+        Box::new(self.records.iter())
+    }
+
+    fn take_additionals(&mut self) -> Option<Box<dyn LookupObject>> {
+        self.additionals.take()
+    }
+}
+
+#[async_trait]
+impl AuthorityObject for LatencyAwareRecordAuthority {
+    fn box_clone(&self) -> Box<dyn AuthorityObject> {
+        Box::new(self.clone())
+    }
+
+    fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
+        self.authority.zone_type()
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        self.authority.is_axfr_allowed()
+    }
+
+    async fn update(
+        &self,
+        update: &MessageRequest,
+    ) -> trust_dns_server::authority::UpdateResult<bool> {
+        self.authority.update(update).await
+    }
+
+    fn origin(&self) -> &LowerName {
+        self.authority.origin()
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        self.authority.lookup(name, rtype, lookup_options).await
+    }
+
+    async fn search(
+        &self,
+        request_info: RequestInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        let querier = request_info.src.ip();
+        let rtype = request_info.query.query_type();
+        let mut lookup = self.authority.search(request_info, lookup_options).await?;
+
+        if !matches!(rtype, RecordType::A | RecordType::AAAA) {
+            return Ok(lookup);
+        }
+
+        let records: Vec<Record> = lookup.iter().cloned().collect();
+        if records.len() < 2 {
+            return Ok(lookup);
+        }
+
+        Ok(Box::new(NearestLookup {
+            records: nearest(
+                records,
+                querier,
+                &*self.member_nodes.read().await,
+                &*self.peer_latencies.read().await,
+            ),
+            additionals: lookup.take_additionals(),
+        }))
+    }
+
+    async fn get_nsec_records(
+        &self,
+        name: &LowerName,
+        lookup_options: LookupOptions,
+    ) -> Result<Box<dyn LookupObject>, LookupError> {
+        self.authority.get_nsec_records(name, lookup_options).await
+    }
+}
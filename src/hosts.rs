@@ -1,89 +1,512 @@
+use error_stack::{AttachmentKind, FrameKind, Report, ResultExt};
+use serde::{Deserialize, Serialize};
 /// functionality to deal with the handling of /etc/hosts formatted files
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     net::IpAddr,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
 };
-use tracing::warn;
+use tracing::{info, warn};
 use trust_dns_server::client::rr::Name;
 
-use crate::traits::ToHostname;
+use crate::{
+    errors,
+    traits::{ToHostname, ToWildcard},
+};
 
 pub type HostsFile = HashMap<IpAddr, Vec<Name>>;
+/// Aliases from `cname` directives, as alias -> target.
+pub type Cnames = BTreeMap<Name, Name>;
+
+const CNAME_DIRECTIVE: &str = "cname";
+
+/// Which records a name gets when it is both in the hosts files and the name of a member.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostsPrecedence {
+    /// the addresses from the hosts files
+    #[default]
+    HostsWins,
+    /// the addresses of the member
+    MemberWins,
+    /// the addresses from both
+    Merge,
+}
+
+impl FromStr for HostsPrecedence {
+    type Err = errors::ErrorReport;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "hosts-wins" => Ok(Self::HostsWins),
+            "member-wins" => Ok(Self::MemberWins),
+            "merge" => Ok(Self::Merge),
+            _ => Err(errors::Error::Config).attach_printable(
+                "invalid hosts precedence: allowed values: [hosts-wins, member-wins, merge]",
+            ),
+        }
+    }
+}
 
 const WHITESPACE_SPLIT: &str = r"\s+";
 const COMMENT_MATCH: &str = r"^\s*#";
 
 /// Parses an /etc/hosts-formatted file into a mapping of ip -> [name]. Used to populate the
-/// authority.
+/// authority. `cname` directives are skipped; see `parse_hosts_entries`.
 pub fn parse_hosts(
     hosts_file: Option<PathBuf>,
     domain_name: Name,
 ) -> Result<HostsFile, std::io::Error> {
-    let mut input: HostsFile = HashMap::new();
+    match hosts_file {
+        Some(hosts_file) => Ok(parse_hosts_entries(&hosts_file, domain_name, false)?.0),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// Parses an /etc/hosts-formatted file into its addresses and its aliases, logging any problems
+/// with it as warnings. See `check_hosts_file` for the format.
+pub fn parse_hosts_entries(
+    hosts_file: &Path,
+    domain_name: Name,
+    lenient: bool,
+) -> Result<(HostsFile, Cnames), std::io::Error> {
+    let (input, cnames, diagnostics) = check_hosts_file(hosts_file, domain_name, lenient)?;
+    for diagnostic in diagnostics {
+        warn!("{}", diagnostic);
+    }
+
+    Ok((input, cnames))
+}
+
+/// What is wrong with a line of a hosts file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    BadAddress,
+    BadHostname,
+    BadDirective,
+    Duplicate,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Reason::BadAddress => "bad address",
+            Reason::BadHostname => "bad hostname",
+            Reason::BadDirective => "bad cname directive",
+            Reason::Duplicate => "duplicate",
+        })
+    }
+}
+
+/// A problem found on one line of a hosts file. The rest of the line, and of the file, is still
+/// used where possible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    // 1-based, as editors count them
+    pub line: usize,
+    pub content: String,
+    pub reason: Reason,
+    pub message: String,
+}
 
-    if hosts_file.is_none() {
-        return Ok(input);
+impl Diagnostic {
+    /// Duplicates are harmless; everything else means part of the file was ignored.
+    pub fn is_error(&self) -> bool {
+        self.reason != Reason::Duplicate
     }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {} ({})",
+            self.path.display(),
+            self.line,
+            self.reason,
+            self.message,
+            self.content
+        )
+    }
+}
+
+/// Parses an /etc/hosts-formatted file into its addresses, its aliases, and the problems found
+/// along the way. Aliases are given on lines of the form `cname <alias> <target>`; the alias is
+/// qualified with the domain like any other name, as is the target unless it ends in a `.`,
+/// which marks a name outside the zone. A name beginning with `*.` is a wildcard for everything
+/// under it without records of its own.
+///
+/// With lenient set, the format some VPN tools write is accepted as well: a comment holding
+/// nothing but an address, followed by a line of only names, is read as an entry for that
+/// address.
+pub fn check_hosts_file(
+    hosts_file: &Path,
+    domain_name: Name,
+    lenient: bool,
+) -> Result<(HostsFile, Cnames, Vec<Diagnostic>), std::io::Error> {
+    let mut input: HostsFile = HashMap::new();
+    let mut cnames: Cnames = BTreeMap::new();
+    let mut diagnostics = Vec::new();
+
+    // where each address, name and alias was first seen, to report repeats against.
+    let mut seen: HashMap<(IpAddr, Name), usize> = HashMap::new();
+    let mut alias_lines: HashMap<Name, usize> = HashMap::new();
 
     let whitespace = regex::Regex::new(WHITESPACE_SPLIT).unwrap();
     let comment = regex::Regex::new(COMMENT_MATCH).unwrap();
-    let content = std::fs::read_to_string(hosts_file.unwrap())?;
+    let content = std::fs::read_to_string(hosts_file)?;
+
+    // with lenient set, the address in a comment line like `# 10.0.0.1`, for the next line.
+    let mut pending_ip: Option<IpAddr> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let pending = pending_ip.take();
+        let mut report = |reason, message: String| {
+            diagnostics.push(Diagnostic {
+                path: hosts_file.to_path_buf(),
+                line: index + 1,
+                content: line.trim().to_string(),
+                reason,
+                message,
+            })
+        };
 
-    for line in content.lines() {
         if line.trim().is_empty() {
             continue;
         }
 
         // after whitespace is ruled out as the only thing on the line, the line is split by ..
         // whitespace and the parts iterated.
-        let mut ary = whitespace.split(line);
+        let mut ary = whitespace.split(line.trim());
 
         // the first item will be the ip
         if let Some(ip) = ary.next() {
             // technically we're still matching the head of the line at this point. if it's a
-            // comment, bail.
+            // comment, bail, unless it is the address for the names on the next line.
             if comment.is_match(ip) {
+                if lenient {
+                    pending_ip = commented_ip(line);
+                }
                 continue;
             }
 
-            // ensure we have an IP, again, this is still the first field.
-            match IpAddr::from_str(ip) {
-                Ok(parsed_ip) => {
-                    // now that we have the ip, it's all names now.
-                    let mut v: Vec<Name> = Vec::new();
-
-                    // continue to iterate over the hosts. If we encounter a comment, stop
-                    // processing.
-                    for host in ary.take_while(|h| !comment.is_match(h)) {
-                        let fqdn = match host.to_fqdn(domain_name.clone()) {
-                            Ok(fqdn) => Some(fqdn),
-                            Err(e) => {
-                                warn!("Invalid host {}: {:?}", host, e);
-                                None
-                            }
-                        };
-
-                        if let Some(fqdn) = fqdn {
-                            v.push(fqdn)
+            if ip.eq_ignore_ascii_case(CNAME_DIRECTIVE) {
+                let args: Vec<&str> = ary.take_while(|h| !comment.is_match(h)).collect();
+                match parse_cname(&args, domain_name.clone()) {
+                    Ok((alias, target)) => {
+                        if let Some(earlier) = alias_lines.insert(alias.clone(), index + 1) {
+                            report(
+                                Reason::Duplicate,
+                                format!(
+                                    "{} was already aliased on line {}; this one is used",
+                                    alias, earlier
+                                ),
+                            );
                         }
+                        cnames.insert(alias, target);
                     }
+                    Err(e) => report(Reason::BadDirective, describe(&e)),
+                }
+                continue;
+            }
 
-                    // if we have a valid ip in the collection already, append, don't clobber
-                    // it.
-                    if let Entry::Vacant(e) = input.entry(parsed_ip) {
-                        e.insert(v);
-                    } else {
-                        input.get_mut(&parsed_ip).unwrap().append(&mut v);
-                    }
+            // link-local addresses may carry a zone index (fe80::1%lo0) which has no meaning in
+            // DNS and which IpAddr does not accept, so it is dropped.
+            let addr = ip.split_once('%').map_or(ip, |(addr, _)| addr);
+
+            // ensure we have an IP, again, this is still the first field. If we encounter a
+            // comment among the names that follow, stop processing.
+            let (parsed_ip, hosts): (IpAddr, Vec<&str>) = match (IpAddr::from_str(addr), pending) {
+                (Ok(parsed_ip), _) => (
+                    parsed_ip,
+                    ary.take_while(|h| !comment.is_match(h)).collect(),
+                ),
+                // a line of only names, following a commented address, is an entry for it.
+                (Err(_), Some(pending)) => (
+                    pending,
+                    std::iter::once(ip)
+                        .chain(ary)
+                        .take_while(|h| !comment.is_match(h))
+                        .collect(),
+                ),
+                (Err(e), None) => {
+                    report(Reason::BadAddress, format!("{}: {}", ip, e));
+                    continue;
                 }
-                Err(e) => {
-                    warn!("Couldn't parse {}: {}", ip, e);
+            };
+
+            // now that we have the ip, it's all names now.
+            let mut v: Vec<Name> = Vec::new();
+
+            for host in hosts {
+                match host_fqdn(host, domain_name.clone()) {
+                    Ok(fqdn) => match seen.entry((parsed_ip, fqdn.clone())) {
+                        Entry::Occupied(first) => report(
+                            Reason::Duplicate,
+                            format!(
+                                "{} {} was already given on line {}",
+                                parsed_ip,
+                                host,
+                                first.get()
+                            ),
+                        ),
+                        Entry::Vacant(e) => {
+                            e.insert(index + 1);
+                            v.push(fqdn);
+                        }
+                    },
+                    Err(e) => report(Reason::BadHostname, format!("{}: {}", host, describe(&e))),
                 }
             }
+
+            // if we have a valid ip in the collection already, append, don't clobber it.
+            if let Entry::Vacant(e) = input.entry(parsed_ip) {
+                e.insert(v);
+            } else {
+                input.get_mut(&parsed_ip).unwrap().append(&mut v);
+            }
         }
     }
 
-    Ok(input)
+    // a name with addresses cannot also be an alias.
+    let addressed: HashSet<&Name> = input.values().flatten().collect();
+    cnames.retain(|alias, _| {
+        let conflict = addressed.contains(alias);
+        if conflict {
+            let line = alias_lines[alias];
+            diagnostics.push(Diagnostic {
+                path: hosts_file.to_path_buf(),
+                line,
+                content: content
+                    .lines()
+                    .nth(line - 1)
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                reason: Reason::BadDirective,
+                message: format!("{} has addresses, so cannot be an alias", alias),
+            });
+        }
+        !conflict
+    });
+    diagnostics.sort_by_key(|d| d.line);
+
+    Ok((input, cnames, diagnostics))
+}
+
+// commented_ip reads the address out of a comment line holding nothing else, such as
+// `# 10.0.0.1`.
+fn commented_ip(line: &str) -> Option<IpAddr> {
+    let addr = line.trim().trim_start_matches('#').trim();
+    let addr = addr.split_once('%').map_or(addr, |(addr, _)| addr);
+    IpAddr::from_str(addr).ok()
+}
+
+// describe picks the most specific explanation out of an error report.
+fn describe(report: &errors::ErrorReport) -> String {
+    report
+        .frames()
+        .find_map(|frame| match frame.kind() {
+            FrameKind::Attachment(AttachmentKind::Printable(printable)) => {
+                Some(printable.to_string())
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| report.to_string())
+}
+
+// host_fqdn qualifies a name from a hosts file. A leading `*.` makes it a wildcard for the names
+// under it that have no records of their own.
+fn host_fqdn(host: &str, domain_name: Name) -> errors::Result<Name> {
+    match host.strip_prefix("*.") {
+        Some(rest) if !rest.contains('*') => Ok(rest.to_fqdn(domain_name)?.to_wildcard()),
+        _ if host.contains('*') => Err(Report::new(errors::Error::DnsName))
+            .attach_printable("'*' is only allowed as the first label"),
+        _ => host.to_fqdn(domain_name),
+    }
+}
+
+// parse_cname reads the arguments of a cname directive into the alias and its target.
+fn parse_cname(args: &[&str], domain_name: Name) -> errors::Result<(Name, Name)> {
+    let (alias, target) = match args {
+        [alias, target] => (alias, target),
+        _ => {
+            return Err(Report::new(errors::Error::DnsName)).attach_printable(format!(
+                "expected 'cname <alias> <target>', got {} argument(s)",
+                args.len()
+            ))
+        }
+    };
+
+    let alias = alias.to_fqdn(domain_name.clone())?;
+    let target = if target.ends_with('.') {
+        Name::from_str(target)
+            .change_context(errors::Error::DnsName)
+            .attach_printable_lazy(|| format!("invalid cname target {}", target))?
+    } else {
+        target.to_fqdn(domain_name)?
+    };
+
+    if alias == target {
+        return Err(Report::new(errors::Error::DnsName))
+            .attach_printable(format!("{} is aliased to itself", alias));
+    }
+
+    Ok((alias, target))
+}
+
+/// The extension of the files read from a hosts directory.
+pub const HOSTS_DIR_EXTENSION: &str = "hosts";
+
+/// Expands hosts paths into the files to read, in order. A directory stands for the `*.hosts`
+/// files in it, sorted by name.
+pub fn hosts_file_list(paths: &[PathBuf]) -> errors::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(path)
+            .change_context(errors::Error::Io { path: path.clone() })
+            .attach_printable_lazy(|| {
+                format!("could not list hosts directory {}", path.display())
+            })?
+        {
+            let entry = entry.change_context(errors::Error::Io { path: path.clone() })?;
+            let file = entry.path();
+            if file.is_file()
+                && file
+                    .extension()
+                    .is_some_and(|ext| ext == HOSTS_DIR_EXTENSION)
+            {
+                entries.push(file);
+            }
+        }
+
+        entries.sort();
+        files.append(&mut entries);
+    }
+
+    Ok(files)
+}
+
+/// Parses several hosts files (or directories of them) into one mapping of addresses and one of
+/// aliases. A name given in a later file takes the addresses or alias from that file, replacing
+/// whatever any earlier file gave it. Problems with the files are logged, unless strict is set, in
+/// which case any that are errors fail the whole parse. See `check_hosts_file` for lenient.
+pub fn parse_hosts_files(
+    paths: &[PathBuf],
+    domain_name: Name,
+    strict: bool,
+    lenient: bool,
+) -> errors::Result<(HostsFile, Cnames)> {
+    enum Definition {
+        Addresses(Vec<IpAddr>),
+        Alias(Name),
+    }
+
+    let mut names: BTreeMap<Name, (Definition, PathBuf)> = BTreeMap::new();
+
+    for file in hosts_file_list(paths)? {
+        let (addresses, cnames, diagnostics) =
+            check_hosts_file(&file, domain_name.clone(), lenient)
+                .change_context(errors::Error::Io { path: file.clone() })
+                .attach_printable_lazy(|| {
+                    format!("could not read hosts file {}", file.display())
+                })?;
+
+        if strict && diagnostics.iter().any(Diagnostic::is_error) {
+            let mut report = Report::new(errors::Error::Config)
+                .attach_printable(format!("hosts file {} has errors", file.display()));
+            for diagnostic in diagnostics.iter().filter(|d| d.is_error()) {
+                report = report.attach_printable(diagnostic.to_string());
+            }
+            return Err(report);
+        }
+
+        for diagnostic in diagnostics {
+            warn!("{}", diagnostic);
+        }
+
+        let mut file_names: BTreeMap<Name, Definition> = cnames
+            .into_iter()
+            .map(|(alias, target)| (alias, Definition::Alias(target)))
+            .collect();
+        for (ip, hosts) in addresses {
+            for host in hosts {
+                match file_names
+                    .entry(host)
+                    .or_insert_with(|| Definition::Addresses(Vec::new()))
+                {
+                    Definition::Addresses(ips) => ips.push(ip),
+                    Definition::Alias(_) => unreachable!("aliases with addresses are dropped"),
+                }
+            }
+        }
+
+        for (name, entry) in file_names {
+            if let Some((_, earlier)) = names.get(&name) {
+                info!(
+                    "{} in hosts file {} overrides {}",
+                    name,
+                    file.display(),
+                    earlier.display()
+                );
+            }
+            names.insert(name, (entry, file.clone()));
+        }
+    }
+
+    let mut merged: HostsFile = HashMap::new();
+    let mut cnames: Cnames = BTreeMap::new();
+    for (name, (entry, _)) in names {
+        match entry {
+            Definition::Addresses(ips) => {
+                for ip in ips {
+                    merged.entry(ip).or_default().push(name.clone());
+                }
+            }
+            Definition::Alias(target) => {
+                cnames.insert(name, target);
+            }
+        }
+    }
+
+    Ok((merged, cnames))
+}
+
+/// Checks several hosts files (or directories of them), returning every problem found in them.
+pub fn check_hosts_files(
+    paths: &[PathBuf],
+    domain_name: Name,
+    lenient: bool,
+) -> errors::Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+
+    for file in hosts_file_list(paths)? {
+        let (_, _, mut found) = check_hosts_file(&file, domain_name.clone(), lenient)
+            .change_context(errors::Error::Io { path: file.clone() })
+            .attach_printable_lazy(|| format!("could not read hosts file {}", file.display()))?;
+        diagnostics.append(&mut found);
+    }
+
+    Ok(diagnostics)
+}
+
+/// Reports whether a change to path could affect the hosts files configured as paths.
+pub fn is_hosts_file(paths: &[PathBuf], path: &Path) -> bool {
+    paths.iter().any(|configured| {
+        if configured.is_dir() {
+            path.extension()
+                .is_some_and(|ext| ext == HOSTS_DIR_EXTENSION)
+        } else {
+            path.file_name() == configured.file_name()
+        }
+    })
 }
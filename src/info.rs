@@ -0,0 +1,195 @@
+/// a summary of a network's DNS-relevant settings in Central, for the network-info subcommand
+use std::{net::IpAddr, time::Duration};
+
+use error_stack::Result;
+use serde::Serialize;
+use zerotier_api::central_api::{
+    self,
+    types::{Member, Network},
+};
+
+use crate::{
+    errors::{self, ApiResultExt},
+    utils::{get_network_members, parse_ip_from_cidr, DEFAULT_MAX_MEMBER_PAGES},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NetworkInfo {
+    pub id: String,
+    pub name: Option<String>,
+    // milliseconds since the epoch, as Central reports it
+    pub creation_time: Option<i64>,
+    pub dns_domain: Option<String>,
+    pub dns_servers: Vec<String>,
+    pub members: usize,
+    pub authorized: usize,
+    pub unauthorized: usize,
+    pub v4_assign_mode: Vec<&'static str>,
+    pub v6_assign_mode: Vec<&'static str>,
+    // seconds between syncs, present only when this host is one of the network's DNS servers
+    pub update_interval: Option<u64>,
+}
+
+impl NetworkInfo {
+    // new summarizes a network and its members.
+    pub fn new(network: Network, members: &[Member]) -> Self {
+        let config = network.config;
+        let dns = config.as_ref().and_then(|c| c.dns.clone());
+        let authorized = members
+            .iter()
+            .filter(|m| m.config.as_ref().and_then(|c| c.authorized) == Some(true))
+            .count();
+
+        let mut v4_assign_mode = Vec::new();
+        if let Some(mode) = config.as_ref().and_then(|c| c.v4_assign_mode.clone()) {
+            if mode.zt == Some(true) {
+                v4_assign_mode.push("zt");
+            }
+        }
+
+        let mut v6_assign_mode = Vec::new();
+        if let Some(mode) = config.as_ref().and_then(|c| c.v6_assign_mode.clone()) {
+            for (name, enabled) in [
+                ("zt", mode.zt),
+                ("rfc4193", mode.rfc4193),
+                ("6plane", mode._6plane),
+            ] {
+                if enabled == Some(true) {
+                    v6_assign_mode.push(name);
+                }
+            }
+        }
+
+        Self {
+            id: network.id.unwrap_or_default(),
+            name: config
+                .as_ref()
+                .and_then(|c| c.name.clone())
+                .filter(|n| !n.is_empty()),
+            creation_time: config.as_ref().and_then(|c| c.creation_time),
+            dns_domain: dns
+                .as_ref()
+                .and_then(|d| d.domain.clone())
+                .filter(|d| !d.is_empty()),
+            dns_servers: dns.and_then(|d| d.servers).unwrap_or_default(),
+            members: members.len(),
+            authorized,
+            unauthorized: members.len() - authorized,
+            v4_assign_mode,
+            v6_assign_mode,
+            update_interval: None,
+        }
+    }
+
+    // managed_by records the update interval if any of listen_ips is one of the network's DNS
+    // servers, which is as close as we can get to knowing that this host's zeronsd manages it.
+    pub fn managed_by(mut self, listen_ips: &[IpAddr], update_interval: Duration) -> Self {
+        let managed = self
+            .dns_servers
+            .iter()
+            .filter_map(|s| parse_ip_from_cidr(s.clone()).ok())
+            .any(|ip| listen_ips.contains(&ip));
+
+        if managed {
+            self.update_interval = Some(update_interval.as_secs());
+        }
+        self
+    }
+}
+
+// get_network_info fetches the network and its members from Central.
+pub async fn get_network_info(
+    client: &central_api::Client,
+    network_id: &str,
+) -> Result<NetworkInfo, errors::Error> {
+    let (network, members) = tokio::try_join!(
+        async { client.get_network_by_id(network_id).await.central_context() },
+        get_network_members(client, network_id, DEFAULT_MAX_MEMBER_PAGES),
+    )?;
+
+    Ok(NetworkInfo::new(network.into_inner(), &members))
+}
+
+impl std::fmt::Display for NetworkInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn list(items: &[&str]) -> String {
+            if items.is_empty() {
+                "none".to_string()
+            } else {
+                items.join(", ")
+            }
+        }
+
+        let rows = [
+            ("Network", self.id.clone()),
+            ("Name", self.name.clone().unwrap_or_else(|| "-".to_string())),
+            (
+                "Created",
+                self.creation_time
+                    .map(format_timestamp)
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            (
+                "DNS domain",
+                self.dns_domain
+                    .clone()
+                    .unwrap_or_else(|| "not configured".to_string()),
+            ),
+            (
+                "DNS servers",
+                list(
+                    &self
+                        .dns_servers
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+            ("Members", self.members.to_string()),
+            ("Authorized", self.authorized.to_string()),
+            ("Unauthorized", self.unauthorized.to_string()),
+            ("IPv4 assignment", list(&self.v4_assign_mode)),
+            ("IPv6 assignment", list(&self.v6_assign_mode)),
+            (
+                "Update interval",
+                self.update_interval
+                    .map(|secs| format!("{}s", secs))
+                    .unwrap_or_else(|| "not managed by this host".to_string()),
+            ),
+        ];
+
+        let width = rows.iter().map(|(k, _)| k.len()).max().unwrap_or_default();
+        for (key, value) in rows {
+            writeln!(f, "{:width$}  {}", key, value, width = width)?;
+        }
+
+        Ok(())
+    }
+}
+
+// format_timestamp renders milliseconds since the epoch as a UTC date and time.
+pub fn format_timestamp(millis: i64) -> String {
+    let secs = millis.div_euclid(1000);
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // civil_from_days, from Howard Hinnant's date algorithms.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
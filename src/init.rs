@@ -1,38 +1,133 @@
-use crate::errors;
+use crate::errors::{self, ApiResultExt};
 use std::{
     collections::{hash_map::Entry, HashMap},
-    path::PathBuf,
+    ffi::OsString,
+    net::SocketAddr,
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
     time::Duration,
 };
 
 use error_stack::{Result, ResultExt};
 use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
-use tracing::{info, warn};
+use tracing::{info, info_span, warn, Instrument};
+use trust_dns_resolver::Name;
+use zerotier_api::central_api;
 
-use openssl::{pkey::PKey, stack::Stack, x509::X509};
+use openssl::x509::X509;
 
 use crate::{
     addresses::*,
-    authority::{find_members, RecordAuthority, ZTAuthority},
+    authority::{
+        default_address_filter, initial_sync, sync_members, watch_hosts, RecordAuthority, Rpz,
+        ZTAuthority, ARP_TABLE, DEFAULT_INITIAL_SYNC_TIMEOUT, DEFAULT_UPDATE_INTERVAL,
+    },
+    ecs::EcsMode,
+    hosts::HostsPrecedence,
+    serial::SoaSerialPolicy,
     server::*,
-    traits::ToPointerSOA,
+    traits::{CentralClient, ToPointerSOA},
     utils::*,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Launcher {
     pub domain: Option<String>,
-    pub hosts: Option<PathBuf>,
+    // name the domain after the network in Central instead, such as home.arpa for "home".
+    #[serde(default)]
+    pub auto_domain: bool,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub hosts: Vec<PathBuf>,
+    pub hosts_domain: Option<String>,
+    #[serde(default)]
+    pub hosts_strict: bool,
+    #[serde(default)]
+    pub lenient_hosts: bool,
+    #[serde(default)]
+    pub hosts_precedence: HostsPrecedence,
     pub secret: Option<PathBuf>,
     pub token: Option<PathBuf>,
     pub chain_cert: Option<PathBuf>,
     pub tls_cert: Option<PathBuf>,
     pub tls_key: Option<PathBuf>,
+    // CA certificates DoT clients must present a certificate signed by.
+    pub dot_client_ca: Option<PathBuf>,
+    #[serde(default)]
     pub wildcard: bool,
     pub log_level: Option<crate::log::LevelFilter>,
+    // EnvFilter directives applied on top of log_level, such as "trust_dns_server=warn".
+    pub log_filter: Option<String>,
+    #[serde(default)]
+    pub log_format: crate::log::LogFormat,
+    #[serde(default)]
+    pub log_target: crate::log::LogTarget,
+    // OTLP/HTTP traces endpoint spans are exported to, with the otlp feature.
+    pub otlp_endpoint: Option<String>,
     pub local_url: Option<String>,
+    pub cache_dir: Option<PathBuf>,
+    pub dns_servers_limit: Option<usize>,
+    #[serde(default)]
+    pub remove_dns_on_shutdown: bool,
+    #[serde(default)]
+    pub no_update_dns: bool,
+    #[serde(default = "default_true")]
+    pub randomize_udp_port: bool,
+    #[serde(default)]
+    pub peer_dns: bool,
+    // give hosts bridged onto the network records from the ARP table; Linux only.
+    #[serde(default)]
+    pub bridge_dns: bool,
+    #[serde(default)]
+    pub latency_aware: bool,
+    #[serde(default)]
+    pub soa_serial_policy: SoaSerialPolicy,
+    #[serde(default = "default_serial_increment")]
+    pub serial_increment: u32,
+    // how long to wait connecting to Central, such as "10s"; a bare number is seconds.
+    pub http_connect_timeout: Option<HumanDuration>,
+    // how long to wait for a request to Central to complete, such as "30s".
+    pub http_timeout: Option<HumanDuration>,
+    // how long to wait on a request to zerotier-one, such as "10s"; a bare number is seconds.
+    pub service_api_timeout: Option<HumanDuration>,
+    // how often members are fetched from Central, such as "30s" or "1m30s".
+    pub update_interval: Option<HumanDuration>,
+    // how long startup waits on the first sync before serving, such as "30s".
+    pub initial_sync_timeout: Option<HumanDuration>,
+    // fail to start, rather than serve with a warning, if the first sync doesn't complete.
+    #[serde(default)]
+    pub require_initial_sync: bool,
+    pub user_agent_suffix: Option<String>,
+    // SHA-256 fingerprint, in hex, of the only certificate Central is trusted with.
+    pub central_tls_fingerprint: Option<String>,
+    pub max_members: Option<usize>,
+    // the most pages of members fetched from Central in one sync.
+    pub max_pages: Option<u32>,
+    // members neither seen nor authorized by Central for this long get no records, such as "30d".
+    pub max_member_age: Option<HumanDuration>,
+    // max_member_age in hours, as it was first given.
+    pub max_member_age_hours: Option<u64>,
+    // the most records a sync may leave in any one zone.
+    pub max_records: Option<usize>,
+    pub metrics_listen: Option<SocketAddr>,
+    #[serde(default)]
+    pub ipv4_only: bool,
+    // member addresses in these networks get no records; link-local ones unless set.
+    pub address_filter: Option<Vec<IpNetwork>>,
+    pub control_socket: Option<PathBuf>,
+    #[serde(default)]
+    pub offline: bool,
+    #[serde(default)]
+    pub disable_ptr: bool,
+    pub query_rate_limit: Option<QueryRateLimitConfig>,
+    // names to block, or redirect with "name CNAME target", one per line.
+    pub rpz_file: Option<PathBuf>,
+    // what becomes of the client subnet of forwarded queries; stripped unless set.
+    pub ecs_mode: Option<EcsMode>,
+    // etcd cluster the members zone is kept in and served from, with the etcd-backend feature.
+    #[serde(default)]
+    pub etcd_endpoints: Vec<String>,
     #[serde(skip_deserializing)]
     pub network_id: Option<String>,
 }
@@ -52,68 +147,413 @@ impl FromStr for ConfigFormat {
             "json" | "JSON" => Ok(ConfigFormat::JSON),
             "yaml" | "YAML" => Ok(ConfigFormat::YAML),
             "toml" | "TOML" => Ok(ConfigFormat::TOML),
-            _ => Err(errors::Error)
+            _ => Err(errors::Error::Config)
                 .attach_printable("invalid format: allowed values: [json, yaml, toml]"),
         }
     }
 }
 
+// ENV_PREFIX starts the names of the environment variables Launcher::from_env reads.
+pub const ENV_PREFIX: &str = "ZERONSD_";
+
+// MANY_PTR_ZONES is how many reverse zones one network can be split into before startup warns;
+// a /12 is sixteen /16s, a /9 is already 128.
+const MANY_PTR_ZONES: usize = 16;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_serial_increment() -> u32 {
+    1
+}
+
+// prefixed_vars keeps the ZERONSD_* variables of vars. Others are skipped whatever they hold, so
+// a variable that isn't UTF-8 only stops us if it is one of ours.
+pub fn prefixed_vars(
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> Result<Vec<(String, String)>, errors::Error> {
+    let mut prefixed = Vec::new();
+    for (key, value) in vars {
+        if !key.to_string_lossy().starts_with(ENV_PREFIX) {
+            continue;
+        }
+
+        match (key.into_string(), value.into_string()) {
+            (Ok(key), Ok(value)) => prefixed.push((key, value)),
+            (Ok(key), Err(_)) => {
+                return Err(errors::Error::Config)
+                    .attach_printable(format!("{} is not valid UTF-8", key));
+            }
+            (Err(key), _) => {
+                return Err(errors::Error::Config)
+                    .attach_printable(format!("{} is not valid UTF-8", key.to_string_lossy()));
+            }
+        }
+    }
+    Ok(prefixed)
+}
+
+// one_or_many accepts either a single path or a list of them, so configurations written before
+// several hosts files were supported keep working.
+fn one_or_many<'de, D>(deserializer: D) -> core::result::Result<Vec<PathBuf>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(path)) => vec![path],
+        Some(OneOrMany::Many(paths)) => paths,
+        None => Vec::new(),
+    })
+}
+
+// HumanDuration is a duration written the way people do, such as "30s" or "1m30s". A bare number
+// is taken as seconds, as durations were before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HumanDuration(pub Duration);
+
+impl FromStr for HumanDuration {
+    type Err = errors::ErrorReport;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        parse_duration(s)
+            .change_context(errors::Error::Config)
+            .attach_printable_lazy(|| format!("invalid duration {:?}", s))
+    }
+}
+
+fn parse_duration(s: &str) -> core::result::Result<HumanDuration, humantime::DurationError> {
+    match s.trim().parse() {
+        Ok(secs) => Ok(HumanDuration(Duration::from_secs(secs))),
+        Err(_) => humantime::parse_duration(s).map(HumanDuration),
+    }
+}
+
+impl std::fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        humantime::format_duration(self.0).fmt(f)
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(duration: HumanDuration) -> Self {
+        duration.0
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = HumanDuration;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a duration such as \"1m30s\", or a number of seconds")
+            }
+
+            fn visit_u64<E: serde::de::Error>(
+                self,
+                secs: u64,
+            ) -> core::result::Result<Self::Value, E> {
+                Ok(HumanDuration(Duration::from_secs(secs)))
+            }
+
+            fn visit_i64<E: serde::de::Error>(
+                self,
+                secs: i64,
+            ) -> core::result::Result<Self::Value, E> {
+                u64::try_from(secs)
+                    .map(|secs| HumanDuration(Duration::from_secs(secs)))
+                    .map_err(|_| E::custom(format!("negative duration {}", secs)))
+            }
+
+            fn visit_str<E: serde::de::Error>(
+                self,
+                s: &str,
+            ) -> core::result::Result<Self::Value, E> {
+                parse_duration(s).map_err(|e| E::custom(format!("invalid duration {:?}: {}", s, e)))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
 impl Default for Launcher {
     fn default() -> Self {
         Launcher {
             domain: None,
-            hosts: None,
+            hosts: Vec::new(),
+            hosts_domain: None,
+            hosts_strict: false,
+            lenient_hosts: false,
+            hosts_precedence: HostsPrecedence::default(),
             secret: None,
             token: None,
             chain_cert: None,
             tls_cert: None,
             tls_key: None,
+            dot_client_ca: None,
+            auto_domain: false,
             wildcard: false,
             network_id: None,
             log_level: None,
+            log_filter: None,
+            log_format: crate::log::LogFormat::default(),
+            log_target: crate::log::LogTarget::default(),
+            otlp_endpoint: None,
             local_url: Some(ZEROTIER_LOCAL_URL.to_string()),
+            cache_dir: None,
+            dns_servers_limit: None,
+            remove_dns_on_shutdown: false,
+            no_update_dns: false,
+            randomize_udp_port: true,
+            peer_dns: false,
+            bridge_dns: false,
+            latency_aware: false,
+            soa_serial_policy: SoaSerialPolicy::default(),
+            serial_increment: default_serial_increment(),
+            http_connect_timeout: None,
+            http_timeout: None,
+            service_api_timeout: None,
+            update_interval: None,
+            initial_sync_timeout: None,
+            require_initial_sync: false,
+            user_agent_suffix: None,
+            central_tls_fingerprint: None,
+            max_members: None,
+            max_pages: None,
+            max_member_age: None,
+            max_member_age_hours: None,
+            max_records: None,
+            metrics_listen: None,
+            ipv4_only: false,
+            address_filter: None,
+            control_socket: None,
+            offline: false,
+            disable_ptr: false,
+            query_rate_limit: None,
+            rpz_file: None,
+            ecs_mode: None,
+            etcd_endpoints: Vec::new(),
         }
     }
 }
 
 impl Launcher {
     pub fn new_from_config(filename: &str, format: ConfigFormat) -> Result<Self, errors::Error> {
-        let res = std::fs::read_to_string(filename).change_context(errors::Error)?;
+        let res = read_file_to_string(Path::new(filename), "config file")?;
         Self::parse_format(&res, format)
     }
 
     pub fn parse_format(s: &str, format: ConfigFormat) -> Result<Self, errors::Error> {
         Ok(match format {
-            ConfigFormat::JSON => serde_json::from_str(s).change_context(errors::Error)?,
-            ConfigFormat::YAML => serde_yml::from_str(s).change_context(errors::Error)?,
-            ConfigFormat::TOML => toml::from_str(s).change_context(errors::Error)?,
+            ConfigFormat::JSON => serde_json::from_str(s).change_context(errors::Error::Config)?,
+            ConfigFormat::YAML => serde_yml::from_str(s).change_context(errors::Error::Config)?,
+            ConfigFormat::TOML => toml::from_str(s).change_context(errors::Error::Config)?,
+        })
+    }
+
+    pub fn to_config_string(&self, format: ConfigFormat) -> Result<String, errors::Error> {
+        Ok(match format {
+            ConfigFormat::JSON => {
+                serde_json::to_string_pretty(self).change_context(errors::Error::Config)?
+            }
+            ConfigFormat::YAML => {
+                serde_yml::to_string(self).change_context(errors::Error::Config)?
+            }
+            ConfigFormat::TOML => toml::to_string(self).change_context(errors::Error::Config)?,
+        })
+    }
+
+    // http_options collects the settings for the HTTP clients, filling in defaults.
+    pub fn http_options(&self) -> HttpOptions {
+        HttpOptions {
+            connect_timeout: self
+                .http_connect_timeout
+                .map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from),
+            request_timeout: self
+                .http_timeout
+                .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from),
+            user_agent_suffix: self.user_agent_suffix.clone(),
+            central_tls_fingerprint: self.central_tls_fingerprint.clone(),
+        }
+    }
+
+    pub fn service_api_timeout(&self) -> Duration {
+        self.service_api_timeout
+            .map_or(DEFAULT_SERVICE_API_TIMEOUT, Duration::from)
+    }
+
+    // max_member_age is how long a member may go unseen and unauthorized before it gets no
+    // records. An age past what a Duration holds is as good as no limit, and is kept as the most.
+    pub fn max_member_age(&self) -> Option<Duration> {
+        self.max_member_age.map(Duration::from).or_else(|| {
+            self.max_member_age_hours
+                .map(|hours| Duration::from_secs(hours.saturating_mul(3600)))
         })
     }
 
     pub fn parse(s: &str, network_id: String, format: ConfigFormat) -> Result<Self, errors::Error> {
-        let mut l: Launcher = Self::parse_format(s, format).change_context(errors::Error)?;
+        let mut l: Launcher = Self::parse_format(s, format)?;
         l.network_id = Some(network_id);
         Ok(l)
     }
 
+    // from_env reads the settings from ZERONSD_* variables named after the configuration keys,
+    // such as ZERONSD_DOMAIN=home.arpa. Where a variable is given more than once the last one
+    // wins.
+    pub fn from_env(
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, errors::Error> {
+        let vars: HashMap<String, String> = vars
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+            .collect();
+        envy::prefixed(ENV_PREFIX)
+            .from_iter(vars)
+            .change_context(errors::Error::Config)
+    }
+
+    // from_env_file reads ZERONSD_* variables from a file of KEY=VALUE lines, as systemd's
+    // EnvironmentFile= does. Variables set in the environment take precedence over the file.
+    pub fn from_env_file(path: &Path) -> Result<Self, errors::Error> {
+        let contents = read_file_to_string(path, "env file")?;
+        let vars = dotenvy::from_read_iter(contents.as_bytes())
+            .collect::<core::result::Result<Vec<_>, _>>()
+            .change_context(errors::Error::Config)
+            .attach_printable_lazy(|| format!("invalid env file {}", path.display()))?;
+        Self::from_env(vars.into_iter().chain(prefixed_vars(std::env::vars_os())?))
+    }
+
     pub async fn start(&self) -> Result<ZTAuthority, errors::Error> {
+        #[cfg(feature = "otlp")]
+        let telemetry = self
+            .otlp_endpoint
+            .as_deref()
+            .map(crate::telemetry::otlp_layer)
+            .transpose()?;
+        #[cfg(not(feature = "otlp"))]
+        let telemetry = None;
+
         crate::utils::init_logger(
-            self.log_level
-                .clone()
-                .unwrap_or(crate::log::LevelFilter::Info)
-                .to_log(),
+            crate::log::env_filter(
+                self.log_level
+                    .clone()
+                    .unwrap_or(crate::log::LevelFilter::Info),
+                self.log_filter.as_deref(),
+            )?,
+            self.log_format,
+            self.log_target,
+            telemetry,
         );
 
+        #[cfg(not(feature = "otlp"))]
+        if self.otlp_endpoint.is_some() {
+            warn!("Not exporting traces; zeronsd was built without the otlp feature");
+        }
+
         if self.network_id.is_none() {
-            return Err(errors::Error).attach_printable("network ID is invalid; cannot continue");
+            return Err(errors::Error::Config)
+                .attach_printable("network ID is invalid; cannot continue");
         }
 
-        let domain_name =
-            domain_or_default(self.domain.as_deref()).change_context(errors::Error)?;
-        let authtoken = authtoken_path(self.secret.as_deref());
-        let client =
-            central_client(central_token(self.token.as_deref()).change_context(errors::Error)?)
-                .change_context(errors::Error)?;
+        let http = self.http_options();
+        if http.connect_timeout.is_zero() {
+            return Err(errors::Error::Config)
+                .attach_printable("http_connect_timeout must not be zero");
+        }
+        if http.request_timeout.is_zero() {
+            return Err(errors::Error::Config).attach_printable("http_timeout must not be zero");
+        }
+
+        let authtoken = authtoken_path(self.secret.as_deref())?;
+        check_authtoken(&authtoken)?;
+        let client = self.central_client()?;
+        let domain_name = match (self.auto_domain, &client) {
+            (false, _) => domain_or_default(self.domain.as_deref())?,
+            (true, _) if self.domain.is_some() => {
+                return Err(errors::Error::Config)
+                    .attach_printable("auto_domain cannot be used with domain");
+            }
+            (true, None) => {
+                return Err(errors::Error::Config).attach_printable(
+                    "auto_domain needs the network name from Central; it cannot be used offline",
+                );
+            }
+            (true, Some(client)) => {
+                let domain_name =
+                    derive_domain_from_network(client, &self.network_id.clone().unwrap()).await?;
+                info!(
+                    "Using {} as the domain, after the network name",
+                    domain_name
+                );
+                domain_name
+            }
+        };
+        let query_rate_limiter = self
+            .query_rate_limit
+            .map(QueryRateLimiter::new)
+            .transpose()?;
+        let rpz = match &self.rpz_file {
+            Some(path) => {
+                let rpz = Rpz::from_file(path)?;
+                info!(
+                    "Applying {} response policy entries from {}",
+                    rpz.len(),
+                    path.display()
+                );
+                Some(Arc::new(rpz))
+            }
+            None => None,
+        };
+        let update_interval = self
+            .update_interval
+            .map_or(DEFAULT_UPDATE_INTERVAL, Duration::from);
+        if update_interval.is_zero() {
+            return Err(errors::Error::Config).attach_printable("update_interval must not be zero");
+        }
+        let initial_sync_timeout = self
+            .initial_sync_timeout
+            .map_or(DEFAULT_INITIAL_SYNC_TIMEOUT, Duration::from);
+        if self.service_api_timeout() == Duration::ZERO {
+            return Err(errors::Error::Config)
+                .attach_printable("service_api_timeout must not be zero");
+        }
+        if self.max_member_age.is_some() && self.max_member_age_hours.is_some() {
+            return Err(errors::Error::Config).attach_printable(
+                "max_member_age_hours is the old name of max_member_age; set only one",
+            );
+        }
+        if self.max_member_age() == Some(Duration::ZERO) {
+            return Err(errors::Error::Config).attach_printable("max_member_age must not be zero");
+        }
+        if self.max_pages == Some(0) {
+            return Err(errors::Error::Config).attach_printable("max_pages must not be zero");
+        }
+        if self.max_records == Some(0) {
+            return Err(errors::Error::Config).attach_printable("max_records must not be zero");
+        }
 
         info!("Welcome to ZeroNS!");
         let ips = get_listen_ips(
@@ -122,143 +562,464 @@ impl Launcher {
             self.local_url
                 .clone()
                 .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+            self.service_api_timeout(),
         )
-        .await
-        .change_context(errors::Error)?;
+        .await?;
 
-        // more or less the setup for the "main loop"
-        if !ips.is_empty() {
-            update_central_dns(
-                domain_name.clone(),
-                ips.iter()
-                    .map(|i| parse_ip_from_cidr(i.clone()).to_string())
-                    .collect(),
-                client.clone(),
-                self.network_id.clone().unwrap(),
-            )
-            .await
-            .change_context(errors::Error)?;
+        let (listen_ips, networks) = normalize_listen_ips(&ips);
 
-            let mut listen_ips = Vec::new();
-            let mut ipmap = HashMap::new();
+        // more or less the setup for the "main loop"
+        if !listen_ips.is_empty() {
             let mut authority_map = HashMap::new();
 
-            for cidr in ips.clone() {
-                let listen_ip = parse_ip_from_cidr(cidr.clone());
-                listen_ips.push(listen_ip);
-                let cidr = IpNetwork::from_str(&cidr.clone()).change_context(errors::Error)?;
-                ipmap.entry(listen_ip).or_insert_with(|| cidr.network());
-
-                if let Entry::Vacant(e) = authority_map.entry(cidr) {
-                    tracing::debug!("{}", cidr.to_ptr_soa_name().change_context(errors::Error)?);
-                    let ptr_authority = RecordAuthority::new(
-                        cidr.to_ptr_soa_name().change_context(errors::Error)?,
-                        cidr.to_ptr_soa_name().change_context(errors::Error)?,
-                    )
-                    .await
-                    .change_context(errors::Error)?;
-                    e.insert(ptr_authority);
+            // with disable_ptr, reverse DNS is left entirely to someone else.
+            let networks = if self.disable_ptr {
+                Vec::new()
+            } else {
+                networks
+            };
+
+            // reverse zones are keyed by the octet-aligned network they cover, so networks off a
+            // boundary such as a /22 are served as the zones that make them up, and networks
+            // sharing a zone share its authority.
+            let mut zones = Vec::new();
+            for cidr in networks {
+                let cidr_zones = cidr.to_ptr_zones();
+                if cidr_zones.len() > MANY_PTR_ZONES {
+                    warn!(
+                        "Network {} is served as {} reverse zones; consider a prefix on an octet (IPv4) or nibble (IPv6) boundary",
+                        cidr,
+                        cidr_zones.len()
+                    );
                 }
+                zones.extend(cidr_zones);
             }
 
             let member_name = get_member_name(
-                authtoken,
+                &authtoken,
                 domain_name.clone(),
                 self.local_url
                     .clone()
                     .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+                self.service_api_timeout(),
             )
-            .await
-            .change_context(errors::Error)?;
+            .await?;
 
-            let network = client
-                .get_network_by_id(&self.network_id.clone().unwrap())
-                .await
-                .change_context(errors::Error)?;
+            // offline, there is no network configuration to find the RFC4193 prefix in.
+            let network = match &client {
+                Some(client) => Some(
+                    client
+                        .get_network_by_id(&self.network_id.clone().unwrap())
+                        .await
+                        .central_context()?,
+                ),
+                None => None,
+            };
 
-            if let Some(v6assign) = network.config.clone().unwrap().v6_assign_mode {
-                if v6assign._6plane.unwrap_or(false) {
+            // a network without a config is served as one without v6 modes; the sync picks the
+            // config back up once central sends it.
+            if let Some((network, v6assign)) = network.and_then(|network| {
+                let v6assign = match network.config.as_ref() {
+                    Some(config) => config.v6_assign_mode.clone()?,
+                    None => {
+                        warn!(
+                            "Network {} has no config; starting without RFC4193 PTR records",
+                            self.network_id.as_deref().unwrap_or_default()
+                        );
+                        return None;
+                    }
+                };
+                Some((network, v6assign))
+            }) {
+                if v6assign._6plane.unwrap_or(false) && !self.disable_ptr {
                     warn!("6PLANE PTR records are not yet supported");
                 }
 
-                if v6assign.rfc4193.unwrap_or(false) {
-                    let cidr = network.clone().rfc4193().unwrap();
-                    if let Entry::Vacant(e) = authority_map.entry(cidr) {
-                        tracing::debug!(
-                            "{}",
-                            cidr.to_ptr_soa_name().change_context(errors::Error)?
-                        );
-                        let ptr_authority = RecordAuthority::new(
-                            cidr.to_ptr_soa_name().change_context(errors::Error)?,
-                            cidr.to_ptr_soa_name().change_context(errors::Error)?,
-                        )
-                        .await
-                        .change_context(errors::Error)?;
-                        e.insert(ptr_authority);
-                    }
+                let rfc4193 = if v6assign.rfc4193.unwrap_or(false) && !self.disable_ptr {
+                    network
+                        .rfc4193()
+                        .map_err(|e| warn!("Not serving RFC4193 PTR records: {:?}", e))
+                        .ok()
+                } else {
+                    None
+                };
+
+                if let Some(rfc4193) = rfc4193 {
+                    zones.extend(rfc4193.to_ptr_zones());
+                }
+            }
+
+            for zone in zones {
+                if let Entry::Vacant(e) = authority_map.entry(zone) {
+                    let name = zone
+                        .to_ptr_soa_name()
+                        .change_context(errors::Error::DnsName)?;
+                    tracing::debug!("{}", name);
+                    let ptr_authority = RecordAuthority::new(name.clone(), name)
+                        .await?
+                        .with_serial_policy(self.soa_serial_policy, self.serial_increment);
+                    e.insert(ptr_authority);
                 }
             }
 
             let authority = RecordAuthority::new(domain_name.clone().into(), member_name.clone())
-                .await
-                .change_context(errors::Error)?;
+                .await?
+                .with_serial_policy(self.soa_serial_policy, self.serial_increment);
+
+            let hosts_authority = match self.hosts_domain.as_deref() {
+                Some(hosts_domain) => {
+                    let hosts_domain = domain_or_default(Some(hosts_domain))?;
+                    if hosts_domain != domain_name {
+                        Some(authority.clone_with_domain(hosts_domain.into()).await?)
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            };
+
+            if self.bridge_dns && !Path::new(ARP_TABLE).exists() {
+                return Err(errors::Error::Config).attach_printable(format!(
+                    "bridge_dns reads the ARP table from {}, which this system does not have",
+                    ARP_TABLE
+                ));
+            }
+
+            // every instance serves the zone as kept in etcd, so none can narrow it to its nearest
+            // members.
+            if !self.etcd_endpoints.is_empty() && self.latency_aware {
+                return Err(errors::Error::Config)
+                    .attach_printable("latency_aware can't be used with etcd_endpoints");
+            }
+
+            #[cfg(feature = "etcd-backend")]
+            let etcd = if self.etcd_endpoints.is_empty() {
+                None
+            } else {
+                Some(
+                    crate::backends::etcd::EtcdRecordAuthority::connect(
+                        &self.etcd_endpoints,
+                        self.network_id.clone().unwrap(),
+                        domain_name.clone(),
+                        member_name.clone().into(),
+                    )
+                    .await?,
+                )
+            };
+            #[cfg(not(feature = "etcd-backend"))]
+            if !self.etcd_endpoints.is_empty() {
+                return Err(errors::Error::Config).attach_printable(
+                    "etcd_endpoints needs zeronsd built with the etcd-backend feature",
+                );
+            }
+
+            let peer_client = if self.peer_dns || self.latency_aware || self.bridge_dns {
+                Some(local_client_from_file(
+                    &authtoken,
+                    self.local_url
+                        .clone()
+                        .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+                    self.service_api_timeout(),
+                )?)
+            } else {
+                None
+            };
 
             let ztauthority = ZTAuthority {
-                client,
-                network_id: self.network_id.clone().unwrap(),
-                hosts: None, // this will be parsed later.
-                hosts_file: self.hosts.clone(),
-                reverse_authority_map: authority_map,
-                forward_authority: authority,
+                hosts_files: self.hosts.clone(),
+                hosts_strict: self.hosts_strict,
+                lenient_hosts: self.lenient_hosts,
+                hosts_authority,
                 wildcard: self.wildcard,
-                update_interval: Duration::new(30, 0),
+                randomize_udp_port: self.randomize_udp_port,
+                cache_dir: self.cache_dir.clone(),
+                peer_client,
+                peer_dns: self.peer_dns,
+                bridge_dns: self.bridge_dns,
+                latency_aware: self.latency_aware,
+                max_members: self.max_members,
+                max_pages: self.max_pages,
+                max_member_age: self.max_member_age(),
+                max_records: self.max_records,
+                ipv4_only: self.ipv4_only,
+                disable_ptr: self.disable_ptr,
+                address_filter: self
+                    .address_filter
+                    .clone()
+                    .unwrap_or_else(default_address_filter),
+                hosts_precedence: self.hosts_precedence,
+                query_rate_limiter: query_rate_limiter.clone(),
+                rpz,
+                listen_ips: listen_ips.clone(),
+                ecs_mode: self.ecs_mode.unwrap_or_default(),
+                #[cfg(feature = "etcd-backend")]
+                etcd,
+                ..ZTAuthority::new(
+                    self.network_id.clone().unwrap(),
+                    client
+                        .clone()
+                        .map(|client| Arc::new(client) as Arc<dyn CentralClient>),
+                    authority,
+                    authority_map,
+                    update_interval,
+                )
             };
 
-            tokio::spawn(find_members(ztauthority.clone()));
+            // the sync loop and the hosts file watcher share one copy; what they change is shared
+            // with the server's and the control socket's copies as well.
+            let sync = Arc::new(ztauthority.clone());
 
-            let server = Server::new(ztauthority.to_owned());
-            for ip in listen_ips {
-                info!("Your IP for this network: {}", ip);
+            // with strict hosts files, one with errors is a configuration error like any other.
+            if self.hosts_strict {
+                sync.configure_hosts().await?;
+            }
 
-                let tls_cert = if let Some(tls_cert) = self.tls_cert.clone() {
-                    let pem = std::fs::read(tls_cert).change_context(errors::Error)?;
-                    Some(X509::from_pem(&pem).change_context(errors::Error)?)
-                } else {
-                    None
-                };
+            // the zones are filled before anything is served, so the first queries don't miss.
+            if ztauthority.client.is_none() {
+                info!("Offline; serving the hosts files without contacting Central");
+            }
+            if let Err(e) = initial_sync(&sync, initial_sync_timeout).await {
+                if self.require_initial_sync {
+                    return Err(e).attach_printable("the first sync did not complete");
+                }
+                warn!(
+                    "The first sync did not complete; serving anyway and syncing in the background: {:?}",
+                    e
+                );
+            }
 
-                let chain = if let Some(chain_cert) = self.chain_cert.clone() {
-                    let pem = std::fs::read(chain_cert).change_context(errors::Error)?;
-                    let chain = X509::stack_from_pem(&pem).change_context(errors::Error)?;
+            // what the background tasks log is tagged with the network it concerns.
+            let span = info_span!("network", network_id = %ztauthority.network_id);
 
-                    let mut stack = Stack::new().change_context(errors::Error)?;
-                    for cert in chain {
-                        stack.push(cert).change_context(errors::Error)?;
+            if !ztauthority.hosts_files.is_empty() {
+                let hosts = sync.clone();
+                tokio::spawn(
+                    async move {
+                        if let Err(e) = watch_hosts(hosts).await {
+                            warn!(
+                                "Not watching the hosts file; changes apply at the next sync: {:?}",
+                                e
+                            );
+                        }
                     }
-                    Some(stack)
-                } else {
-                    None
-                };
+                    .instrument(span.clone()),
+                );
+            }
 
-                let key = if let Some(key_path) = self.tls_key.clone() {
-                    let pem = std::fs::read(key_path).change_context(errors::Error)?;
-                    Some(PKey::private_key_from_pem(&pem).change_context(errors::Error)?)
-                } else {
-                    None
-                };
+            if let Some(metrics_listen) = self.metrics_listen {
+                let listener = tokio::net::TcpListener::bind(metrics_listen)
+                    .await
+                    .change_context(errors::Error::Server)
+                    .attach_printable_lazy(|| {
+                        format!("could not listen for metrics on {}", metrics_listen)
+                    })?;
+                info!("Serving metrics on http://{}/metrics", metrics_listen);
+                tokio::spawn(crate::metrics::serve_metrics(listener, ztauthority.clone()));
+            }
 
-                tokio::spawn(
-                    server
+            if let Some(limiter) = query_rate_limiter {
+                tokio::spawn(limiter.prune_stale(RATE_LIMIT_PRUNE_INTERVAL));
+            }
+
+            #[cfg(unix)]
+            tokio::spawn(crate::utils::debug_on_sigusr2());
+
+            #[cfg(unix)]
+            if let Some(control_socket) = self.control_socket.as_deref() {
+                let listener = crate::control::bind_control(control_socket)?;
+                info!("Accepting control commands on {}", control_socket.display());
+                tokio::spawn(crate::control::serve_control(listener, ztauthority.clone()));
+            }
+
+            tokio::spawn(
+                async move {
+                    if sync_members(sync).await.is_err() {
+                        std::process::exit(1);
+                    }
+                }
+                .instrument(span),
+            );
+
+            // the interface is only needed to listen on link-local addresses, so failing to find it
+            // costs just those.
+            let interface = if needs_interface(&listen_ips) {
+                match get_listen_interface(
+                    &authtoken,
+                    &self.network_id.clone().unwrap(),
+                    self.local_url
                         .clone()
-                        .listen(ip, Duration::new(1, 0), tls_cert, chain, key),
-                );
+                        .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+                    self.service_api_timeout(),
+                )
+                .await
+                {
+                    Ok(interface) => interface,
+                    Err(e) => {
+                        warn!(
+                            "Could not find the network's interface; not listening on link-local addresses: {:?}",
+                            e
+                        );
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            let client_ca = match self.dot_client_ca.as_deref() {
+                Some(_) if self.tls_cert.is_none() || self.tls_key.is_none() => {
+                    return Err(errors::Error::Config)
+                        .attach_printable("dot_client_ca needs tls_cert and tls_key for DoT");
+                }
+                Some(path) => {
+                    let pem = read_file(path, "DoT client CA (dot_client_ca)")?;
+                    let client_ca =
+                        X509::stack_from_pem(&pem).change_context(errors::Error::Tls)?;
+                    if client_ca.is_empty() {
+                        return Err(errors::Error::Tls)
+                            .attach_printable(format!("no certificates in {}", path.display()));
+                    }
+                    Some(client_ca)
+                }
+                None => None,
+            };
+
+            // one certificate serves every address, and is reloaded when renewed.
+            let tls = match (self.tls_cert.clone(), self.tls_key.clone()) {
+                (Some(cert), Some(key)) => {
+                    let tls = CertReloader::new(
+                        TlsFiles {
+                            cert,
+                            chain: self.chain_cert.clone(),
+                            key,
+                        },
+                        client_ca,
+                    )?;
+                    let watched = tls.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = watched.watch().await {
+                            warn!(
+                                "Not watching the TLS certificate; restart to apply a renewal: {:?}",
+                                e
+                            );
+                        }
+                    });
+                    Some(tls)
+                }
+                _ => None,
+            };
+
+            let server = Server::new(ztauthority.to_owned())
+                .with_interface(interface)
+                .with_activated_sockets(ActivatedSockets::from_env());
+            for ip in listen_ips.iter().cloned() {
+                info!("Your IP for this network: {}", ip);
+
+                tokio::spawn(server.clone().listen(ip, Duration::new(1, 0), tls.clone()));
+            }
+
+            // Central is only pointed at us once there is something to answer with.
+            if let Some(client) = client {
+                self.push_central_dns(
+                    domain_name.clone(),
+                    listen_ips.iter().map(ToString::to_string).collect(),
+                    client,
+                )
+                .await?;
             }
 
             return Ok(ztauthority);
         }
 
-        return Err(errors::Error).attach_printable(
+        Err(errors::Error::LocalApi).attach_printable(
             "No listening IPs for your interface; assign one in ZeroTier Central.",
-        );
+        )
+    }
+
+    // central_client connects to Central with the configured token. Offline, there is no client;
+    // only the hosts files are served, so at least one must be given.
+    pub fn central_client(&self) -> Result<Option<central_api::Client>, errors::Error> {
+        if self.offline {
+            if self.hosts.is_empty() {
+                return Err(errors::Error::Config).attach_printable(
+                    "offline mode serves only hosts files; pass at least one with -f",
+                );
+            }
+            if self.token.is_some() {
+                warn!("Ignoring the Central token; offline is set");
+            }
+            return Ok(None);
+        }
+
+        let token = central_token(self.token.as_deref()).attach_printable(
+            "to serve only hosts files without a Central token, set offline and pass a hosts file",
+        )?;
+        Ok(Some(central_client_with_options(
+            token,
+            &self.http_options(),
+        )?))
+    }
+
+    // push_central_dns points Central's DNS settings at us, unless configured not to.
+    pub async fn push_central_dns(
+        &self,
+        domain_name: Name,
+        ips: Vec<String>,
+        client: central_api::Client,
+    ) -> Result<(), errors::Error> {
+        if self.no_update_dns {
+            info!("Not updating DNS settings in Central; no_update_dns is set");
+            return Ok(());
+        }
+
+        update_central_dns(
+            domain_name,
+            ips,
+            client,
+            self.network_id.clone().unwrap_or_default(),
+            self.dns_servers_limit,
+        )
+        .await
+    }
+
+    // shutdown stops the sync loop and undoes the changes made to Central by start, if configured
+    // to.
+    pub async fn shutdown(&self, ztauthority: &ZTAuthority) -> Result<(), errors::Error> {
+        ztauthority.cancel.cancel();
+
+        #[cfg(feature = "otlp")]
+        crate::telemetry::shutdown().await;
+
+        if !self.remove_dns_on_shutdown || self.no_update_dns {
+            return Ok(());
+        }
+
+        // the authority only holds what syncing needs, so the DNS settings go through a client
+        // of our own.
+        if let Some(client) = self.central_client()? {
+            let ips = get_listen_ips(
+                &authtoken_path(self.secret.as_deref())?,
+                &ztauthority.network_id,
+                self.local_url
+                    .clone()
+                    .unwrap_or(ZEROTIER_LOCAL_URL.to_string()),
+                self.service_api_timeout(),
+            )
+            .await?;
+
+            info!("Removing our DNS servers from Central");
+            remove_central_dns(
+                normalize_listen_ips(&ips)
+                    .0
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                client,
+                ztauthority.network_id.clone(),
+            )
+            .await?;
+        }
+
+        Ok(())
     }
 }
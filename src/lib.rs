@@ -1,11 +1,24 @@
 pub mod addresses;
 pub mod authority;
+pub mod backends;
 pub mod cli;
+pub mod collision;
+#[cfg(unix)]
+pub mod control;
+pub mod dnssec;
+pub mod ecs;
 pub mod errors;
+pub mod geodnslite;
 pub mod hosts;
+pub mod info;
 pub mod log;
+pub mod metrics;
+pub mod serial;
 pub mod server;
+pub mod snapshot;
 pub mod supervise;
+#[cfg(feature = "otlp")]
+pub mod telemetry;
 pub mod traits;
 pub mod utils;
 
@@ -3,20 +3,21 @@ use std::str::FromStr;
 use crate::errors;
 use error_stack::*;
 use serde::{Deserialize, Serialize};
+use tracing_subscriber::filter::{Directive, EnvFilter};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum LevelFilter {
-    #[serde(rename(deserialize = "off"))]
+    #[serde(rename = "off")]
     Off,
-    #[serde(rename(deserialize = "error"))]
+    #[serde(rename = "error")]
     Error,
-    #[serde(rename(deserialize = "warn"))]
+    #[serde(rename = "warn")]
     Warn,
-    #[serde(rename(deserialize = "info"))]
+    #[serde(rename = "info")]
     Info,
-    #[serde(rename(deserialize = "trace"))]
+    #[serde(rename = "trace")]
     Trace,
-    #[serde(rename(deserialize = "debug"))]
+    #[serde(rename = "debug")]
     Debug,
 }
 
@@ -33,17 +34,32 @@ impl LevelFilter {
     }
 }
 
-impl ToString for LevelFilter {
-    fn to_string(&self) -> String {
-        match self {
+impl From<LevelFilter> for tracing_subscriber::filter::Directive {
+    fn from(level: LevelFilter) -> Self {
+        use tracing_subscriber::filter::LevelFilter as Level;
+
+        match level {
+            LevelFilter::Off => Level::OFF,
+            LevelFilter::Error => Level::ERROR,
+            LevelFilter::Warn => Level::WARN,
+            LevelFilter::Info => Level::INFO,
+            LevelFilter::Trace => Level::TRACE,
+            LevelFilter::Debug => Level::DEBUG,
+        }
+        .into()
+    }
+}
+
+impl std::fmt::Display for LevelFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             LevelFilter::Off => "off",
             LevelFilter::Error => "error",
             LevelFilter::Warn => "warn",
             LevelFilter::Info => "info",
             LevelFilter::Trace => "trace",
             LevelFilter::Debug => "debug",
-        }
-        .to_string()
+        })
     }
 }
 
@@ -58,21 +74,146 @@ impl FromStr for LevelFilter {
             "info" => Ok(Self::Info),
             "trace" => Ok(Self::Trace),
             "debug" => Ok(Self::Debug),
-            _ => Err(errors::Error).attach_printable(
+            _ => Err(errors::Error::Config).attach_printable(
                 "invalid format: allowed values: [off, error, warn, info, debug, trace]",
             ),
         }
     }
 }
 
+// parse_directives reads a comma-separated list of EnvFilter directives, such as
+// "zeronsd=debug,trust_dns_server=warn". A bare level like "debug" is a directive too, applying to
+// everything. Every component that does not parse is named in the error.
+pub fn parse_directives(s: &str) -> Result<Vec<Directive>, errors::Error> {
+    let mut directives = Vec::new();
+    let mut invalid = Vec::new();
+
+    for component in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        match Directive::from_str(component) {
+            Ok(directive) => directives.push(directive),
+            Err(e) => invalid.push(format!("{} ({})", component, e)),
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(errors::Error::Config).attach_printable(format!(
+            "invalid log filter {:?}: bad components: {}",
+            s,
+            invalid.join(", ")
+        ));
+    }
+
+    Ok(directives)
+}
+
+// env_filter builds the filter logs are written through: level for everything, narrowed or widened
+// per module by filter. ZERONSD_LOG, or failing that RUST_LOG, replaces both when set.
+pub fn env_filter(level: LevelFilter, filter: Option<&str>) -> Result<EnvFilter, errors::Error> {
+    let directives = match std::env::var("ZERONSD_LOG").or_else(|_| std::env::var("RUST_LOG")) {
+        Ok(env) => parse_directives(&env)?,
+        Err(_) => {
+            let mut directives = vec![Directive::from(level)];
+            if let Some(filter) = filter {
+                directives.append(&mut parse_directives(filter)?);
+            }
+            directives
+        }
+    };
+
+    Ok(filter_from(directives))
+}
+
+// parse_filter reads a filter for a running instance, which environment variables no longer
+// have a say in.
+pub fn parse_filter(s: &str) -> Result<EnvFilter, errors::Error> {
+    let directives = parse_directives(s)?;
+    if directives.is_empty() {
+        return Err(errors::Error::Config).attach_printable("log filter is empty");
+    }
+
+    Ok(filter_from(directives))
+}
+
+fn filter_from(directives: Vec<Directive>) -> EnvFilter {
+    directives
+        .into_iter()
+        .fold(EnvFilter::default(), EnvFilter::add_directive)
+}
+
+// LogFormat is how log lines are written: plain text for people, or JSON lines for log pipelines.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        })
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = errors::ErrorReport;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(errors::Error::Config)
+                .attach_printable("invalid log format: allowed values: [text, json]"),
+        }
+    }
+}
+
+// LogTarget is where logs go. journald and syslog keep each event, multi-line error reports
+// included, as a single entry with its severity; they are only available on unix.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    #[default]
+    Stdout,
+    Journald,
+    Syslog,
+}
+
+impl std::fmt::Display for LogTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LogTarget::Stdout => "stdout",
+            LogTarget::Journald => "journald",
+            LogTarget::Syslog => "syslog",
+        })
+    }
+}
+
+impl FromStr for LogTarget {
+    type Err = errors::ErrorReport;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(Self::Stdout),
+            "journald" => Ok(Self::Journald),
+            "syslog" => Ok(Self::Syslog),
+            _ => Err(errors::Error::Config)
+                .attach_printable("invalid log target: allowed values: [stdout, journald, syslog]"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::LevelFilter;
+    use super::{parse_directives, parse_filter, LevelFilter, LogFormat, LogTarget};
     use std::str::FromStr;
 
     #[test]
     fn test_level_from_str() {
-        for item in vec![
+        for item in [
             ("off", LevelFilter::Off),
             ("error", LevelFilter::Error),
             ("warn", LevelFilter::Warn),
@@ -88,7 +229,7 @@ mod tests {
 
     #[test]
     fn test_level_to_string() {
-        for item in vec![
+        for item in [
             (LevelFilter::Off, "off"),
             (LevelFilter::Error, "error"),
             (LevelFilter::Warn, "warn"),
@@ -99,4 +240,77 @@ mod tests {
             assert_eq!(item.0.to_string(), item.1)
         }
     }
+
+    #[test]
+    fn test_log_format() {
+        for item in [("text", LogFormat::Text), ("json", LogFormat::Json)] {
+            assert_eq!(LogFormat::from_str(item.0).unwrap(), item.1);
+            assert_eq!(item.1.to_string(), item.0);
+        }
+
+        assert!(LogFormat::from_str("xml").is_err());
+    }
+
+    #[test]
+    fn test_log_target() {
+        for item in [
+            ("stdout", LogTarget::Stdout),
+            ("journald", LogTarget::Journald),
+            ("syslog", LogTarget::Syslog),
+        ] {
+            assert_eq!(LogTarget::from_str(item.0).unwrap(), item.1);
+            assert_eq!(item.1.to_string(), item.0);
+        }
+
+        assert!(LogTarget::from_str("stderr").is_err());
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        // the simple levels are directives of their own
+        for level in [
+            LevelFilter::Off,
+            LevelFilter::Error,
+            LevelFilter::Warn,
+            LevelFilter::Info,
+            LevelFilter::Trace,
+            LevelFilter::Debug,
+        ] {
+            let directives = parse_directives(&level.to_string()).unwrap();
+            assert_eq!(directives.len(), 1);
+            assert_eq!(
+                directives[0].to_string(),
+                tracing_subscriber::filter::Directive::from(level).to_string()
+            );
+        }
+
+        let directives = parse_directives("zeronsd=debug, trust_dns_server=warn").unwrap();
+        assert_eq!(
+            directives
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["zeronsd=debug", "trust_dns_server=warn"]
+        );
+
+        let err = format!(
+            "{:?}",
+            parse_directives("zeronsd=loud,info,trust_dns_server=[").unwrap_err()
+        );
+        assert!(err.contains("zeronsd=loud"), "{}", err);
+        assert!(err.contains("trust_dns_server=["), "{}", err);
+        assert!(!err.contains("info ("), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(parse_filter("debug").unwrap().to_string(), "debug");
+        assert_eq!(
+            parse_filter("warn,zeronsd=debug").unwrap().to_string(),
+            "zeronsd=debug,warn"
+        );
+        assert!(parse_filter("").is_err());
+        assert!(parse_filter(" , ").is_err());
+        assert!(parse_filter("zeronsd=loud").is_err());
+    }
 }
@@ -0,0 +1,147 @@
+/// prometheus metrics, served in the text exposition format when a listen address is configured,
+/// along with a health check of the zones
+use error_stack::{Result, ResultExt};
+use lazy_static::lazy_static;
+use prometheus::{
+    core::Collector, Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, Registry, TextEncoder,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{authority::ZTAuthority, errors};
+
+lazy_static! {
+    pub static ref REGISTRY: Registry =
+        Registry::new_custom(Some("zeronsd".to_string()), None).unwrap();
+    // the number of members in the last member list fetched from central, including any beyond
+    // max_members.
+    pub static ref MEMBERS: IntGauge = register(
+        IntGauge::new("members", "Members of the network as of the last sync with Central")
+            .unwrap()
+    );
+    // how long each sync held a zone's lock while writing its changes, during which queries for
+    // the zone wait.
+    pub static ref ZONE_LOCK_HELD: Histogram = register(
+        Histogram::with_opts(
+            HistogramOpts::new(
+                "zone_lock_held_seconds",
+                "Time a sync held a zone's lock to write its changes"
+            )
+            .buckets(prometheus::exponential_buckets(0.0001, 4.0, 8).unwrap())
+        )
+        .unwrap()
+    );
+    // syncs whose changes to a zone were refused for taking it over max_records.
+    pub static ref RECORD_QUOTA_EXCEEDED: IntCounter = register(
+        IntCounter::new(
+            "record_quota_exceeded_total",
+            "Zone updates refused for exceeding the record quota"
+        )
+        .unwrap()
+    );
+    // times the sync loop panicked and was started over.
+    pub static ref SYNC_RESTARTS: IntCounter = register(
+        IntCounter::new("sync_restarts_total", "Times the sync loop was restarted after a panic")
+            .unwrap()
+    );
+    // queries refused for coming from a client over the query rate limit.
+    pub static ref QUERIES_THROTTLED: IntCounter = register(
+        IntCounter::new(
+            "queries_throttled_total",
+            "Queries refused for exceeding the per-client rate limit"
+        )
+        .unwrap()
+    );
+}
+
+fn register<C: Collector + Clone + 'static>(collector: C) -> C {
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+// render encodes every registered metric.
+pub fn render() -> Result<String, errors::Error> {
+    let mut buf = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buf)
+        .change_context(errors::Error::Server)?;
+    String::from_utf8(buf).change_context(errors::Error::Server)
+}
+
+// serve_metrics answers GET /metrics, and GET /health with whether every zone of zt passes its
+// health check, on listener until the process exits. GET /health?detail answers the same as JSON,
+// zone by zone and with the records of each.
+pub async fn serve_metrics(listener: TcpListener, zt: ZTAuthority) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let zt = zt.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, &zt).await {
+                        tracing::debug!("error serving metrics: {:?}", e);
+                    }
+                });
+            }
+            Err(e) => tracing::error!("error accepting metrics connection: {}", e),
+        }
+    }
+}
+
+// describe_network is the line naming the network in the plain health check, as
+// "network: <id> (<name>)".
+pub fn describe_network(summary: &serde_json::Value) -> String {
+    let id = summary["id"].as_str().unwrap_or_default();
+    match summary["name"].as_str() {
+        Some(name) => format!("network: {} ({})", id, name),
+        None => format!("network: {}", id),
+    }
+}
+
+async fn respond(mut stream: TcpStream, zt: &ZTAuthority) -> Result<(), errors::Error> {
+    let mut buf = [0; 1024];
+    let len = stream
+        .read(&mut buf)
+        .await
+        .change_context(errors::Error::Server)?;
+    let head = String::from_utf8_lossy(&buf[..len]);
+    let mut request = head.split(' ');
+
+    let (status, content_type, body) = match (request.next(), request.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", prometheus::TEXT_FORMAT, render()?),
+        (Some("GET"), Some("/health")) => {
+            let network = describe_network(&zt.network_summary().await);
+            match zt.health_check().await {
+                Ok(()) => ("200 OK", "text/plain", format!("ok\n{}\n", network)),
+                Err(e) => (
+                    "503 Service Unavailable",
+                    "text/plain",
+                    format!("{:?}\n{}\n", e, network),
+                ),
+            }
+        }
+        (Some("GET"), Some("/health?detail")) => {
+            let report = zt.health_report().await;
+            let status = if report["healthy"] == true {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            (status, "application/json", format!("{}\n", report))
+        }
+        _ => ("404 Not Found", "text/plain", String::new()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .change_context(errors::Error::Server)
+}
@@ -0,0 +1,73 @@
+/// SOA serial number handling. Serials are 32-bit and compared with RFC 1982 serial number
+/// arithmetic, so they are free to wrap as long as each step forward is less than 2^31.
+use std::str::FromStr;
+
+use error_stack::*;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::errors;
+
+// the largest step RFC 1982 allows a serial to take while still comparing as greater.
+pub const MAX_SERIAL_INCREMENT: u32 = (1 << 31) - 1;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SoaSerialPolicy {
+    // bump the serial by a fixed amount on every change
+    #[default]
+    Increment,
+    // use the time of the change, in seconds since the unix epoch
+    Timestamp,
+}
+
+impl FromStr for SoaSerialPolicy {
+    type Err = errors::ErrorReport;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "increment" => Ok(Self::Increment),
+            "timestamp" => Ok(Self::Timestamp),
+            _ => Err(errors::Error::Config)
+                .attach_printable("invalid serial policy: allowed values: [increment, timestamp]"),
+        }
+    }
+}
+
+impl SoaSerialPolicy {
+    // next_serial computes the serial to follow current after a change made at now (seconds since
+    // the unix epoch). The result always compares as greater than current.
+    pub fn next_serial(&self, current: u32, increment: u32, now: u64) -> u32 {
+        match self {
+            SoaSerialPolicy::Increment => {
+                let next = current.wrapping_add(increment.clamp(1, MAX_SERIAL_INCREMENT));
+                if next < current {
+                    warn!("SOA serial wrapped around from {} to {}", current, next);
+                }
+                next
+            }
+            SoaSerialPolicy::Timestamp => {
+                // past 2106 the timestamp no longer fits; carry on modulo 2^32.
+                let timestamp = now as u32;
+                if serial_gt(timestamp, current) {
+                    timestamp
+                } else {
+                    // more than one change in the same second, or the clock went backwards.
+                    if timestamp != current {
+                        warn!(
+                            "SOA serial {} is ahead of the clock ({}); incrementing it instead",
+                            current, timestamp
+                        );
+                    }
+                    current.wrapping_add(1)
+                }
+            }
+        }
+    }
+}
+
+// serial_gt reports whether serial a is greater than b, per RFC 1982.
+pub fn serial_gt(a: u32, b: u32) -> bool {
+    const HALF: u32 = 1 << 31;
+    (a < b && b - a > HALF) || (a > b && a - b < HALF)
+}
@@ -1,55 +1,128 @@
 use std::{
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, SocketAddr, SocketAddrV6},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
     time::Duration,
 };
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::errors;
+use crate::{errors, utils::read_file};
+use async_trait::async_trait;
+use dashmap::DashMap;
 use error_stack::{Result, ResultExt};
+use notify::{RecursiveMode, Watcher};
 use openssl::{
     pkey::{PKey, Private},
+    ssl::{Ssl, SslAcceptor, SslContext, SslMethod, SslVerifyMode},
     stack::Stack,
-    x509::X509,
+    x509::{store::X509StoreBuilder, X509Ref, X509},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::{TcpListener, UdpSocket},
+    time::Instant,
 };
-use tokio::net::{TcpListener, UdpSocket};
 
-use trust_dns_server::server::ServerFuture;
+use trust_dns_resolver::proto::{op::ResponseCode, rr::RecordType};
+use trust_dns_server::{
+    authority::{AuthorityObject, LookupError, LookupObject, LookupOptions, MessageRequest},
+    client::rr::LowerName,
+    proto::{iocompat::AsyncIoTokioAsStd, serialize::binary::BinDecodable, tcp::TcpStream},
+    server::{
+        Protocol, Request, RequestHandler, RequestInfo, ResponseHandle, ServerFuture, TimeoutStream,
+    },
+};
 
-use crate::authority::{init_catalog, ZTAuthority};
+use crate::{
+    authority::{init_catalog, ZTAuthority},
+    ecs::ClientSubnetHandler,
+};
 
 #[derive(Clone)]
-pub struct Server(ZTAuthority);
+pub struct Server {
+    authority: ZTAuthority,
+    interface: Option<String>,
+    activated: ActivatedSockets,
+}
 
 impl Server {
     pub fn new(zt: ZTAuthority) -> Self {
-        Self(zt)
+        Self {
+            authority: zt,
+            interface: None,
+            activated: ActivatedSockets::default(),
+        }
     }
 
-    // listener routine for TCP and UDP.
+    // with_interface sets the network interface used to scope link-local listen addresses.
+    pub fn with_interface(mut self, interface: Option<String>) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    // with_activated_sockets has listeners take the sockets a service manager passed for their
+    // addresses, instead of binding their own.
+    pub fn with_activated_sockets(mut self, activated: ActivatedSockets) -> Self {
+        self.activated = activated;
+        self
+    }
+
+    // listener routine for TCP and UDP, and DoT when given a certificate.
     pub async fn listen(
         self,
         ip: IpAddr,
         tcp_timeout: Duration,
-        certs: Option<X509>,
-        cert_chain: Option<Stack<X509>>,
-        key: Option<PKey<Private>>,
+        tls: Option<CertReloader>,
     ) -> Result<(), errors::Error> {
-        let sa = SocketAddr::new(ip, 53);
-        let tcp = TcpListener::bind(sa).await.change_context(errors::Error)?;
-        let udp = UdpSocket::bind(sa).await.change_context(errors::Error)?;
-
-        let mut sf = ServerFuture::new(init_catalog(self.0).await.change_context(errors::Error)?);
+        let sa = match listen_addr(ip, 53, self.interface.as_deref()) {
+            Some(sa) => sa,
+            None => {
+                warn!(
+                    "Could not determine the interface scope for link-local address {}; not listening on it",
+                    ip
+                );
+                return Ok(());
+            }
+        };
 
-        if let (Some(certs), Some(key)) = (certs.clone(), key.clone()) {
-            info!("Configuring DoT Listener");
-            let tls = TcpListener::bind(SocketAddr::new(ip, 853))
+        let tcp = match self.activated.take_tcp(sa)? {
+            Some(tcp) => tcp,
+            None => TcpListener::bind(sa)
+                .await
+                .change_context(errors::Error::Server)?,
+        };
+        let udp = match self.activated.take_udp(sa)? {
+            Some(udp) => udp,
+            None => UdpSocket::bind(sa)
                 .await
-                .change_context(errors::Error)?;
+                .change_context(errors::Error::Server)?,
+        };
 
-            match sf.register_tls_listener(tls, tcp_timeout, ((certs, cert_chain), key)) {
-                Ok(_) => {}
-                Err(e) => tracing::error!("Cannot start DoT listener: {}", e),
-            }
+        let mode = self.authority.ecs_mode;
+        let mut sf = ServerFuture::new(ClientSubnetHandler::new(
+            init_catalog(self.authority.clone()).await?,
+            mode,
+        ));
+
+        // trust-dns fixes the certificate when its TLS listener starts, so the handshake is ours;
+        // that way a renewed certificate is picked up, and clients can be asked for theirs.
+        if let Some(tls) = tls {
+            info!("Configuring DoT Listener");
+            let mut tls_sa = sa;
+            tls_sa.set_port(853);
+            let listener = match self.activated.take_tcp(tls_sa)? {
+                Some(listener) => listener,
+                None => TcpListener::bind(tls_sa)
+                    .await
+                    .change_context(errors::Error::Server)?,
+            };
+            let catalog = init_catalog(self.authority).await?;
+            tokio::spawn(serve_dot(
+                listener,
+                tls,
+                ClientSubnetHandler::new(catalog, mode),
+                tcp_timeout,
+            ));
         }
 
         sf.register_socket(udp);
@@ -58,6 +131,620 @@ impl Server {
         //     Ok(_) => Ok(()),
         //     Err(e) => Err(error_stack::report!()),
         // }
-        sf.block_until_done().await.change_context(errors::Error)
+        sf.block_until_done()
+            .await
+            .change_context(errors::Error::Server)
+    }
+}
+
+// ActivatedSocket is a socket passed to us by a service manager.
+#[derive(Debug)]
+pub enum ActivatedSocket {
+    Udp(std::net::UdpSocket),
+    Tcp(std::net::TcpListener),
+}
+
+impl ActivatedSocket {
+    fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Udp(socket) => socket.local_addr().ok(),
+            Self::Tcp(listener) => listener.local_addr().ok(),
+        }
+    }
+}
+
+// ActivatedSockets holds the sockets passed with systemd's socket activation until a listener on
+// their address takes them. Clones share the sockets, so each is taken only once.
+#[derive(Debug, Clone, Default)]
+pub struct ActivatedSockets(Arc<std::sync::Mutex<Vec<ActivatedSocket>>>);
+
+impl ActivatedSockets {
+    // from_env takes the sockets LISTEN_PID and LISTEN_FDS describe. It must only be called once,
+    // as the sockets are owned by what it returns.
+    #[cfg(unix)]
+    pub fn from_env() -> Self {
+        let var = |key| std::env::var(key).ok();
+        let fds = listen_fds(
+            var("LISTEN_PID").as_deref(),
+            var("LISTEN_FDS").as_deref(),
+            std::process::id(),
+            std::os::unix::process::parent_id(),
+        );
+        Self::from_fds(fds)
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_env() -> Self {
+        Self::default()
+    }
+
+    // from_fds takes ownership of fds, keeping those that are UDP sockets or TCP listeners.
+    #[cfg(unix)]
+    pub fn from_fds(fds: impl IntoIterator<Item = std::os::unix::io::RawFd>) -> Self {
+        let mut sockets = Vec::new();
+        for fd in fds {
+            match activated_socket(fd) {
+                Some(socket) => {
+                    info!(
+                        "Using socket {:?} passed by the service manager",
+                        socket.local_addr()
+                    );
+                    sockets.push(socket);
+                }
+                None => warn!(
+                    "Ignoring fd {} passed by the service manager; it is not a UDP or TCP socket",
+                    fd
+                ),
+            }
+        }
+        Self(Arc::new(std::sync::Mutex::new(sockets)))
+    }
+
+    fn take(&self, sa: SocketAddr, udp: bool) -> Option<ActivatedSocket> {
+        let mut sockets = self.0.lock().unwrap();
+        let i = sockets.iter().position(|socket| {
+            matches!(socket, ActivatedSocket::Udp(_)) == udp && socket.local_addr() == Some(sa)
+        })?;
+        Some(sockets.remove(i))
+    }
+
+    // take_udp hands over the UDP socket passed for sa, if there is one.
+    pub fn take_udp(&self, sa: SocketAddr) -> Result<Option<UdpSocket>, errors::Error> {
+        match self.take(sa, true) {
+            Some(ActivatedSocket::Udp(socket)) => {
+                socket
+                    .set_nonblocking(true)
+                    .change_context(errors::Error::Server)?;
+                Ok(Some(
+                    UdpSocket::from_std(socket).change_context(errors::Error::Server)?,
+                ))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // take_tcp hands over the TCP listener passed for sa, if there is one.
+    pub fn take_tcp(&self, sa: SocketAddr) -> Result<Option<TcpListener>, errors::Error> {
+        match self.take(sa, false) {
+            Some(ActivatedSocket::Tcp(listener)) => {
+                listener
+                    .set_nonblocking(true)
+                    .change_context(errors::Error::Server)?;
+                Ok(Some(
+                    TcpListener::from_std(listener).change_context(errors::Error::Server)?,
+                ))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// the first fd passed with socket activation; 0 to 2 are stdin, stdout and stderr.
+const LISTEN_FDS_START: i32 = 3;
+
+// listen_fds is the fds passed with socket activation, as sd_listen_fds(3) finds them: LISTEN_FDS
+// of them from fd 3 on, when LISTEN_PID names this process. It may also name our parent, which is
+// how `zeronsd supervise --foreground` passes on the sockets it was given.
+pub fn listen_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+    ppid: u32,
+) -> std::ops::Range<i32> {
+    let none = LISTEN_FDS_START..LISTEN_FDS_START;
+    match listen_pid.map(str::parse::<u32>) {
+        Some(Ok(listen_pid)) if listen_pid == pid || listen_pid == ppid => {}
+        _ => return none,
+    }
+    match listen_fds.map(str::parse::<i32>) {
+        Some(Ok(n)) if n > 0 => LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(n),
+        _ => none,
+    }
+}
+
+// activated_socket takes ownership of fd if it is a UDP socket or TCP listener.
+#[cfg(unix)]
+fn activated_socket(fd: std::os::unix::io::RawFd) -> Option<ActivatedSocket> {
+    use std::os::unix::io::FromRawFd;
+
+    let mut kind: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: getsockopt writes at most len bytes to kind, and fails on an fd that is no socket.
+    let ok = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            &mut kind as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    } == 0;
+    if !ok {
+        return None;
+    }
+
+    // SAFETY: the fds passed with socket activation are ours, and from_env is only called once,
+    // so each is owned by one socket. They are closed on exec, so nothing we start holds on to
+    // them.
+    unsafe {
+        libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        match kind {
+            libc::SOCK_DGRAM => Some(ActivatedSocket::Udp(std::net::UdpSocket::from_raw_fd(fd))),
+            libc::SOCK_STREAM => Some(ActivatedSocket::Tcp(std::net::TcpListener::from_raw_fd(fd))),
+            _ => None,
+        }
+    }
+}
+
+// dot_acceptor is the TLS setup for DoT. With client_ca, clients must also present a certificate
+// signed by one of them.
+pub fn dot_acceptor(
+    cert: X509,
+    chain: Option<Stack<X509>>,
+    key: PKey<Private>,
+    client_ca: Option<&[X509]>,
+) -> std::result::Result<SslAcceptor, openssl::error::ErrorStack> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+
+    builder.set_private_key(&key)?;
+    builder.set_certificate(&cert)?;
+    for cert in chain.iter().flatten() {
+        builder.add_extra_chain_cert(cert.to_owned())?;
+    }
+    builder.check_private_key()?;
+
+    if let Some(client_ca) = client_ca {
+        let mut store = X509StoreBuilder::new()?;
+        for ca in client_ca {
+            store.add_cert(ca.clone())?;
+            builder.add_client_ca(ca)?;
+        }
+        builder.set_verify_cert_store(store.build())?;
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+
+    Ok(builder.build())
+}
+
+// TlsFiles names the files the DoT certificate is read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub chain: Option<PathBuf>,
+    pub key: PathBuf,
+}
+
+// how long the certificate files must be left alone before they are read again; renewals write
+// the certificate and key one after the other.
+pub const CERT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+// CertReloader holds the TLS context DoT connections are accepted with. Reloading replaces it for
+// the connections accepted afterwards; those already open keep the one they started with.
+#[derive(Clone)]
+pub struct CertReloader {
+    context: Arc<RwLock<SslContext>>,
+    files: TlsFiles,
+    client_ca: Option<Arc<Vec<X509>>>,
+}
+
+impl CertReloader {
+    pub fn new(files: TlsFiles, client_ca: Option<Vec<X509>>) -> Result<Self, errors::Error> {
+        let client_ca = client_ca.map(Arc::new);
+        let context = Self::load(&files, client_ca.as_deref().map(Vec::as_slice))?;
+        Ok(Self {
+            context: Arc::new(RwLock::new(context)),
+            files,
+            client_ca,
+        })
+    }
+
+    fn load(files: &TlsFiles, client_ca: Option<&[X509]>) -> Result<SslContext, errors::Error> {
+        let pem = read_file(&files.cert, "TLS certificate (tls_cert)")?;
+        let cert = X509::from_pem(&pem).change_context(errors::Error::Tls)?;
+
+        let chain = match &files.chain {
+            Some(path) => {
+                let pem = read_file(path, "TLS chain certificate (chain_cert)")?;
+                let mut stack = Stack::new().change_context(errors::Error::Tls)?;
+                for cert in X509::stack_from_pem(&pem).change_context(errors::Error::Tls)? {
+                    stack.push(cert).change_context(errors::Error::Tls)?;
+                }
+                Some(stack)
+            }
+            None => None,
+        };
+
+        let pem = read_file(&files.key, "TLS key (tls_key)")?;
+        let key = PKey::private_key_from_pem(&pem).change_context(errors::Error::Tls)?;
+
+        Ok(dot_acceptor(cert, chain, key, client_ca)
+            .change_context(errors::Error::Tls)
+            .attach_printable_lazy(|| {
+                format!(
+                    "could not use {} and {} for DoT",
+                    files.cert.display(),
+                    files.key.display()
+                )
+            })?
+            .into_context())
+    }
+
+    // context is what a new connection is accepted with.
+    pub fn context(&self) -> SslContext {
+        self.context.read().unwrap().clone()
+    }
+
+    // reload reads the files again. Should they not make a usable certificate, such as halfway
+    // through a renewal, the one in use is kept.
+    pub fn reload(&self) -> Result<(), errors::Error> {
+        let context = Self::load(&self.files, self.client_ca.as_deref().map(Vec::as_slice))?;
+        *self.context.write().unwrap() = context;
+        Ok(())
+    }
+
+    // watch reloads the certificate whenever its files change. As with the hosts files, their
+    // directories are watched, so renewals which replace the files or their symlinks are seen.
+    pub async fn watch(self) -> Result<(), errors::Error> {
+        let files: Vec<PathBuf> = std::iter::once(&self.files.cert)
+            .chain(&self.files.chain)
+            .chain(std::iter::once(&self.files.key))
+            .cloned()
+            .collect();
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for file in &files {
+            let dir = match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    // reading the files ourselves must not count as a change.
+                    if !matches!(event.kind, notify::EventKind::Access(_))
+                        && event.paths.iter().any(|path| is_cert_file(&files, path))
+                    {
+                        let _ = tx.send(());
+                    }
+                }
+            })
+            .change_context(errors::Error::Io {
+                path: dirs[0].clone(),
+            })?;
+        for dir in &dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .change_context(errors::Error::Io { path: dir.clone() })
+                .attach_printable_lazy(|| format!("could not watch {}", dir.display()))?;
+        }
+
+        while rx.recv().await.is_some() {
+            while let Ok(Some(())) = tokio::time::timeout(CERT_DEBOUNCE, rx.recv()).await {}
+
+            match self.reload() {
+                Ok(_) => info!("Reloaded the DoT certificate"),
+                Err(e) => tracing::error!(
+                    "error reloading the DoT certificate; still using the previous one: {:?}",
+                    e
+                ),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// is_cert_file reports whether a change to path could be a change to one of files.
+fn is_cert_file(files: &[PathBuf], path: &Path) -> bool {
+    files
+        .iter()
+        .any(|file| path.file_name() == file.file_name())
+}
+
+// serve_dot answers DNS over TLS on listener with handler, as trust-dns does, with the
+// certificate tls holds when each connection is accepted.
+pub async fn serve_dot(
+    listener: TcpListener,
+    tls: CertReloader,
+    handler: impl RequestHandler,
+    timeout: Duration,
+) {
+    let handler = Arc::new(handler);
+
+    loop {
+        let (stream, src) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::debug!("error accepting DoT connection: {}", e);
+                continue;
+            }
+        };
+
+        let (context, handler) = (tls.context(), handler.clone());
+        tokio::spawn(async move {
+            let mut stream = match Ssl::new(&context)
+                .and_then(|ssl| tokio_openssl::SslStream::new(ssl, stream))
+            {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("error setting up TLS for {}: {}", src, e);
+                    return;
+                }
+            };
+
+            if let Err(e) = std::pin::Pin::new(&mut stream).accept().await {
+                match stream.ssl().peer_certificate() {
+                    Some(cert) => warn!(
+                        "Rejected DoT connection from {} with certificate {}: {}",
+                        src.ip(),
+                        subject(&cert),
+                        stream.ssl().verify_result()
+                    ),
+                    None => warn!("Rejected DoT connection from {}: {}", src.ip(), e),
+                }
+                return;
+            }
+
+            let (stream, handle) = TcpStream::from_stream(AsyncIoTokioAsStd(stream), src);
+            let mut messages = TimeoutStream::new(stream, timeout);
+            while let Some(Ok(message)) = futures_util::StreamExt::next(&mut messages).await {
+                let request = match MessageRequest::from_bytes(message.bytes()) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        tracing::debug!("bad DoT request from {}: {}", src, e);
+                        return;
+                    }
+                };
+
+                handler
+                    .handle_request(
+                        &Request::new(request, message.addr(), Protocol::Tls),
+                        ResponseHandle::new(message.addr(), handle.clone()),
+                    )
+                    .await;
+            }
+        });
+    }
+}
+
+// subject renders the subject of cert the way openssl's tools do, such as "CN=jura, O=example".
+fn subject(cert: &X509Ref) -> String {
+    cert.subject_name()
+        .entries()
+        .map(|entry| {
+            format!(
+                "{}={}",
+                entry.object().nid().short_name().unwrap_or("?"),
+                entry
+                    .data()
+                    .as_utf8()
+                    .map(|data| data.to_string())
+                    .unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// listen_addr builds the socket address to bind for an IP. Link-local IPv6 addresses are only
+// usable with the scope ID of the interface they live on; None is returned if the interface
+// cannot be resolved.
+pub fn listen_addr(ip: IpAddr, port: u16, interface: Option<&str>) -> Option<SocketAddr> {
+    match ip {
+        IpAddr::V6(ip) if ip.is_unicast_link_local() => {
+            let scope_id = interface_index(interface?)?;
+            Some(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id)))
+        }
+        _ => Some(SocketAddr::new(ip, port)),
+    }
+}
+
+// needs_interface reports whether any of ips is link-local, and so can only be listened on with
+// the interface known.
+pub fn needs_interface(ips: &[IpAddr]) -> bool {
+    ips.iter()
+        .any(|ip| matches!(ip, IpAddr::V6(ip) if ip.is_unicast_link_local()))
+}
+
+// interface_index resolves an interface name to its kernel index.
+#[cfg(target_os = "linux")]
+pub fn interface_index(name: &str) -> Option<u32> {
+    std::fs::read_to_string(format!("/sys/class/net/{}/ifindex", name))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn interface_index(_name: &str) -> Option<u32> {
+    None
+}
+
+// how often clients the rate limiter no longer needs to remember are forgotten.
+pub const RATE_LIMIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+// QueryRateLimitConfig is how many queries a second each client may make, and how many it may
+// make at once after being quiet for a while.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryRateLimitConfig {
+    pub requests_per_second: u32,
+    pub burst: u32,
+}
+
+// TokenBucket holds the queries a client has left, topped up at the configured rate.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+// QueryRateLimiter throttles queries by the address they come from. Clones share their buckets,
+// so one limiter covers every zone and listener.
+#[derive(Debug, Clone)]
+pub struct QueryRateLimiter {
+    config: QueryRateLimitConfig,
+    buckets: Arc<DashMap<IpAddr, TokenBucket>>,
+}
+
+impl QueryRateLimiter {
+    pub fn new(config: QueryRateLimitConfig) -> Result<Self, errors::Error> {
+        if config.requests_per_second == 0 || config.burst == 0 {
+            return Err(errors::Error::Config)
+                .attach_printable("query rate limit and burst must be at least 1");
+        }
+
+        Ok(Self {
+            config,
+            buckets: Default::default(),
+        })
+    }
+
+    // check takes a token from the bucket of ip, reporting whether one was left.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let burst = self.config.burst as f64;
+        let mut bucket = self.buckets.entry(ip).or_insert(TokenBucket {
+            tokens: burst,
+            last: now,
+        });
+
+        let refill =
+            now.duration_since(bucket.last).as_secs_f64() * self.config.requests_per_second as f64;
+        bucket.tokens = (bucket.tokens + refill).min(burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // refill_time is how long an emptied bucket takes to fill up again.
+    fn refill_time(&self) -> Duration {
+        Duration::from_secs_f64(self.config.burst as f64 / self.config.requests_per_second as f64)
+    }
+
+    // prune forgets clients whose buckets have filled up again; they are no different from one
+    // never seen.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        let refill_time = self.refill_time();
+        self.buckets
+            .retain(|_, bucket| now.duration_since(bucket.last) < refill_time);
+    }
+
+    pub fn clients(&self) -> usize {
+        self.buckets.len()
+    }
+
+    // prune_stale prunes every interval until the process exits.
+    pub async fn prune_stale(self, interval: Duration) {
+        let mut timer = tokio::time::interval(interval);
+        loop {
+            timer.tick().await;
+            self.prune();
+        }
+    }
+}
+
+// RateLimitedAuthority refuses queries from clients over the rate limit before they reach the
+// authority it serves.
+pub struct RateLimitedAuthority {
+    authority: Box<dyn AuthorityObject>,
+    limiter: QueryRateLimiter,
+}
+
+impl RateLimitedAuthority {
+    pub fn new(authority: Box<dyn AuthorityObject>, limiter: QueryRateLimiter) -> Self {
+        Self { authority, limiter }
+    }
+}
+
+#[async_trait]
+impl AuthorityObject for RateLimitedAuthority {
+    fn box_clone(&self) -> Box<dyn AuthorityObject> {
+        Box::new(Self {
+            authority: self.authority.box_clone(),
+            limiter: self.limiter.clone(),
+        })
+    }
+
+    fn zone_type(&self) -> trust_dns_server::authority::ZoneType {
+        self.authority.zone_type()
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        self.authority.is_axfr_allowed()
+    }
+
+    async fn update(
+        &self,
+        update: &MessageRequest,
+    ) -> trust_dns_server::authority::UpdateResult<bool> {
+        self.authority.update(update).await
+    }
+
+    fn origin(&self) -> &LowerName {
+        self.authority.origin()
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        self.authority.lookup(name, rtype, lookup_options).await
+    }
+
+    async fn search(
+        &self,
+        request_info: RequestInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        let ip = request_info.src.ip();
+        if !self.limiter.check(ip) {
+            tracing::debug!(client = %ip, "Refusing query over the rate limit");
+            crate::metrics::QUERIES_THROTTLED.inc();
+            return Err(LookupError::from(ResponseCode::Refused));
+        }
+
+        self.authority.search(request_info, lookup_options).await
+    }
+
+    async fn get_nsec_records(
+        &self,
+        name: &LowerName,
+        lookup_options: LookupOptions,
+    ) -> core::result::Result<Box<dyn LookupObject>, LookupError> {
+        self.authority.get_nsec_records(name, lookup_options).await
     }
 }
@@ -0,0 +1,101 @@
+/// on-disk snapshots of the network and member list, used to keep serving records when Central
+/// cannot be reached at startup.
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use error_stack::{Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use zerotier_api::central_api::types::{Member, Network};
+
+use crate::errors;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub network_id: String,
+    // seconds since the unix epoch at the time the snapshot was taken
+    pub timestamp: u64,
+    pub network: Network,
+    pub members: Vec<Member>,
+}
+
+impl Snapshot {
+    pub fn new(network_id: String, network: Network, members: Vec<Member>) -> Self {
+        Self {
+            network_id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            network,
+            members,
+        }
+    }
+
+    // path computes the location of the snapshot for a network within the cache directory.
+    pub fn path(cache_dir: &Path, network_id: &str) -> PathBuf {
+        cache_dir.join(format!("{}.json", network_id))
+    }
+
+    // age is how long ago the snapshot was taken.
+    pub fn age(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Duration::from_secs(now.saturating_sub(self.timestamp))
+    }
+
+    // save writes the snapshot to the cache directory. The file is written next to its final
+    // location and renamed into place so a crash never leaves a partial snapshot behind.
+    pub fn save(&self, cache_dir: &Path) -> Result<(), errors::Error> {
+        std::fs::create_dir_all(cache_dir)
+            .change_context(errors::Error::Io {
+                path: cache_dir.to_path_buf(),
+            })
+            .attach_printable_lazy(|| format!("Cache directory: {}", cache_dir.display()))?;
+
+        let path = Self::path(cache_dir, &self.network_id);
+        let tmp = path.with_extension("json.tmp");
+
+        let content =
+            serde_json::to_vec(self).change_context(errors::Error::Io { path: tmp.clone() })?;
+        std::fs::write(&tmp, content)
+            .change_context(errors::Error::Io { path: tmp.clone() })
+            .attach_printable_lazy(|| format!("Snapshot: {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path)
+            .change_context(errors::Error::Io { path: path.clone() })
+            .attach_printable_lazy(|| format!("Snapshot: {}", path.display()))?;
+
+        Ok(())
+    }
+
+    // load reads the snapshot for a network from the cache directory. Missing, corrupt, or
+    // mismatched snapshots are ignored.
+    pub fn load(cache_dir: &Path, network_id: &str) -> Option<Self> {
+        let path = Self::path(cache_dir, network_id);
+        let content = std::fs::read(&path).ok()?;
+
+        let snapshot: Self = match serde_json::from_slice(&content) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Ignoring corrupt snapshot {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        if snapshot.network_id != network_id {
+            warn!(
+                "Ignoring snapshot {}: it is for network {}, not {}",
+                path.display(),
+                snapshot.network_id,
+                network_id
+            );
+            return None;
+        }
+
+        Some(snapshot)
+    }
+}
@@ -1,7 +1,11 @@
 /// code to tickle various supervisors to enable the `zeronsd supervise` command.
 /// this code is hard to read but fundamentally launchd and systemd are controlled through a
 /// library called `tinytemplate` and of course serde.
-use std::path::{Path, PathBuf};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use crate::errors;
 use error_stack::*;
@@ -41,7 +45,7 @@ After=zerotier-one.service
 
 [Service]
 Type=simple
-ExecStart={binpath} start -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}
+ExecStart={binpath} start --log-target journald -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ for hosts in launcher.hosts }}-f {hosts} {{ endfor }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{{ if launcher.hosts_domain }}--hosts-domain {launcher.hosts_domain} {{ endif }}{launcher.network_id}
 TimeoutStopSec=30
 Restart=always
 
@@ -62,7 +66,7 @@ depend() \{
 
 description="zeronsd for network {launcher.network_id}"
 command="{binpath}"
-command_args="start -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ if launcher.hosts }}-f {launcher.hosts} {{ endif }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{launcher.network_id}"
+command_args="start -t {launcher.token} {{ if config }}-c {config} {{endif}}{{ if config_type_supplied }}--config-type {config_type} {{endif}}{{ if launcher.wildcard }}-w {{endif}}{{ if launcher.secret }}-s {launcher.secret} {{endif}}{{ for hosts in launcher.hosts }}-f {hosts} {{ endfor }}{{ if launcher.domain }}-d {launcher.domain} {{ endif }}{{ if launcher.hosts_domain }}--hosts-domain {launcher.hosts_domain} {{ endif }}{launcher.network_id}"
 command_background="yes"
 pidfile="/run/$RC_SVCNAME.pid"
 "#;
@@ -94,14 +98,18 @@ const SERVICE_TEMPLATE: &str = r#"
       <string>-s</string>
       <string>{launcher.secret}</string>
       {{endif}}
-      {{ if launcher.hosts }}
+      {{ for hosts in launcher.hosts }}
       <string>-f</string>
-      <string>{launcher.hosts}</string>
-      {{ endif }}
+      <string>{hosts}</string>
+      {{ endfor }}
       {{ if launcher.domain }}
       <string>-d</string>
       <string>{launcher.domain}</string>
       {{ endif }}
+      {{ if launcher.hosts_domain }}
+      <string>--hosts-domain</string>
+      <string>{launcher.hosts_domain}</string>
+      {{ endif }}
       {{ if config }}
       <string>-c</string>
       <string>{config}</string>
@@ -177,15 +185,16 @@ impl Properties {
     ) -> Result<Self, errors::Error> {
         let distro = if cfg!(target_os = "linux") {
             if let Ok(release) = std::fs::read_to_string(OS_RELEASE_FILE) {
-                let id_regex = Regex::new(r#"\nID=(.+)\n"#).change_context(errors::Error)?;
+                let id_regex =
+                    Regex::new(r#"\nID=(.+)\n"#).change_context(errors::Error::Supervise)?;
                 if let Some(caps) = id_regex.captures(&release) {
                     caps.get(1)
                         .map(|distro| distro.clone().as_str().to_string())
                 } else {
-                    return Err(errors::Error).attach_printable("Could not determine Linux distribution; you'll need to configure supervision manually. Sorry!");
+                    return Err(errors::Error::Supervise).attach_printable("Could not determine Linux distribution; you'll need to configure supervision manually. Sorry!");
                 }
             } else {
-                return Err(errors::Error).attach_printable("Could not determine Linux distribution; you'll need to configure supervision manually. Sorry!");
+                return Err(errors::Error::Supervise).attach_printable("Could not determine Linux distribution; you'll need to configure supervision manually. Sorry!");
             }
         } else {
             None
@@ -195,7 +204,7 @@ impl Properties {
             distro,
             binpath: String::from(
                 std::env::current_exe()
-                    .change_context(errors::Error)?
+                    .change_context(errors::Error::Supervise)?
                     .to_string_lossy(),
             ),
             config_type: config_type.clone(),
@@ -206,11 +215,22 @@ impl Properties {
     }
 
     pub fn validate(&mut self) -> Result<(), errors::Error> {
+        // without a configuration file, the service only gets the flags its template carries.
+        if self.config.is_none() {
+            let dropped = self.dropped_settings()?;
+            if !dropped.is_empty() {
+                return Err(errors::Error::Config).attach_printable(format!(
+                    "the service would be started without {}; put the settings in a configuration file and pass it with -c instead",
+                    dropped.join(", ")
+                ));
+            }
+        }
+
         self.config = match self.config.clone() {
             Some(config) => match config.canonicalize() {
                 Ok(res) => Some(res),
                 Err(e) => {
-                    return Err(errors::Error)
+                    return Err(errors::Error::Config)
                         .attach_printable(format!("Could not find token file: {}", e))
                 }
             },
@@ -223,12 +243,12 @@ impl Properties {
             .clone()
             .expect("Could not find token file: {}")
             .canonicalize()
-            .change_context(errors::Error)?;
+            .change_context(errors::Error::Config)?;
 
         let tstat = match std::fs::metadata(token.clone()) {
             Ok(ts) => ts,
             Err(e) => {
-                return Err(errors::Error).attach_printable(format!(
+                return Err(errors::Error::Config).attach_printable(format!(
                     "Could not stat token file {}: {}",
                     token.display(),
                     e
@@ -237,7 +257,7 @@ impl Properties {
         };
 
         if !tstat.is_file() {
-            return Err(errors::Error)
+            return Err(errors::Error::Config)
                 .attach_printable(format!("Token file {} is not a file", token.display()));
         }
 
@@ -249,14 +269,15 @@ impl Properties {
             .len()
             != 16
         {
-            return Err(errors::Error).attach_printable("Network ID must be 16 characters");
+            return Err(errors::Error::Config).attach_printable("Network ID must be 16 characters");
         }
 
-        if let Some(hosts_file) = self.launcher.hosts.clone() {
+        let mut hosts = Vec::new();
+        for hosts_file in self.launcher.hosts.clone() {
             let hstat = match std::fs::metadata(hosts_file.clone()) {
                 Ok(hs) => hs,
                 Err(e) => {
-                    return Err(errors::Error).attach_printable(format!(
+                    return Err(errors::Error::Config).attach_printable(format!(
                         "Could not stat hosts file {}: {}",
                         hosts_file.display(),
                         e
@@ -264,32 +285,49 @@ impl Properties {
                 }
             };
 
-            if !hstat.is_file() {
-                return Err(errors::Error).attach_printable(format!(
-                    "Hosts file {} is not a file",
+            if !hstat.is_file() && !hstat.is_dir() {
+                return Err(errors::Error::Config).attach_printable(format!(
+                    "Hosts file {} is not a file or directory",
                     hosts_file.display()
                 ));
             }
 
-            self.launcher.hosts = Some(hosts_file.canonicalize().change_context(errors::Error)?);
+            hosts.push(
+                hosts_file
+                    .canonicalize()
+                    .change_context(errors::Error::Config)?,
+            );
         }
+        self.launcher.hosts = hosts;
 
         if let Some(domain) = self.launcher.domain.clone() {
             if domain.trim().is_empty() {
-                return Err(errors::Error).attach_printable("Domain name cannot be empty");
+                return Err(errors::Error::Config).attach_printable("Domain name cannot be empty");
             }
 
             if let Err(e) = Name::parse(&domain, None) {
-                return Err(errors::Error)
+                return Err(errors::Error::Config)
                     .attach_printable(format!("Domain name is invalid: {}", e));
             }
         }
 
+        if let Some(hosts_domain) = self.launcher.hosts_domain.clone() {
+            if hosts_domain.trim().is_empty() {
+                return Err(errors::Error::Config)
+                    .attach_printable("Hosts domain name cannot be empty");
+            }
+
+            if let Err(e) = Name::parse(&hosts_domain, None) {
+                return Err(errors::Error::Config)
+                    .attach_printable(format!("Hosts domain name is invalid: {}", e));
+            }
+        }
+
         if let Some(authtoken) = self.launcher.secret.clone() {
             let hstat = match std::fs::metadata(authtoken.clone()) {
                 Ok(hs) => hs,
                 Err(e) => {
-                    return Err(errors::Error).attach_printable(format!(
+                    return Err(errors::Error::Config).attach_printable(format!(
                         "Could not stat authtoken file {}: {}",
                         authtoken.display(),
                         e
@@ -298,27 +336,59 @@ impl Properties {
             };
 
             if !hstat.is_file() {
-                return Err(errors::Error).attach_printable(format!(
+                return Err(errors::Error::Config).attach_printable(format!(
                     "launcher.secret file {} is not a file",
                     authtoken.display()
                 ));
             }
 
-            self.launcher.secret = Some(authtoken.canonicalize().change_context(errors::Error)?);
+            self.launcher.secret = Some(
+                authtoken
+                    .canonicalize()
+                    .change_context(errors::Error::Config)?,
+            );
         }
 
         Ok(())
     }
 
+    // dropped_settings lists the settings of the launcher the service templates have no flag for,
+    // by their names in the configuration file.
+    pub fn dropped_settings(&self) -> Result<Vec<String>, errors::Error> {
+        let carried = Launcher {
+            network_id: self.launcher.network_id.clone(),
+            token: self.launcher.token.clone(),
+            secret: self.launcher.secret.clone(),
+            hosts: self.launcher.hosts.clone(),
+            domain: self.launcher.domain.clone(),
+            hosts_domain: self.launcher.hosts_domain.clone(),
+            wildcard: self.launcher.wildcard,
+            ..Default::default()
+        };
+
+        let settings = |launcher: &Launcher| match serde_json::to_value(launcher) {
+            Ok(serde_json::Value::Object(settings)) => Ok(settings),
+            Ok(_) => Err(Report::new(errors::Error::Supervise)),
+            Err(e) => Err(Report::new(e).change_context(errors::Error::Supervise)),
+        };
+        let (given, carried) = (settings(&self.launcher)?, settings(&carried)?);
+
+        Ok(given
+            .into_iter()
+            .filter(|(key, value)| carried.get(key) != Some(value))
+            .map(|(key, _)| key)
+            .collect())
+    }
+
     pub fn supervise_template(&self) -> Result<String, errors::Error> {
         let template = self.get_service_template();
 
         let mut t = TinyTemplate::new();
         t.add_template("supervise", template)
-            .change_context(errors::Error)?;
+            .change_context(errors::Error::Supervise)?;
         match t.render("supervise", self) {
             Ok(x) => Ok(x),
-            Err(e) => Err(errors::Error).attach_printable(e),
+            Err(e) => Err(errors::Error::Supervise).attach_printable(e),
         }
     }
 
@@ -378,19 +448,22 @@ impl Properties {
     }
 
     pub fn install_supervisor(&mut self) -> Result<(), errors::Error> {
-        self.validate().change_context(errors::Error)?;
+        self.validate()?;
 
         if cfg!(target_os = "linux") {
             #[cfg(target_os = "linux")]
             let executable = self.distro.as_deref() == Some("alpine");
 
-            let template = self.supervise_template().change_context(errors::Error)?;
+            let template = self.supervise_template()?;
             let service_path = self.service_path();
 
             match std::fs::write(service_path.clone(), template) {
                 Ok(_) => {}
                 Err(e) => {
-                    return Err(errors::Error).attach_printable(format!(
+                    return Err(errors::Error::Io {
+                        path: service_path.clone(),
+                    })
+                    .attach_printable(format!(
                         "Could not write the template {}; are you root? ({})",
                         service_path
                             .to_str()
@@ -403,11 +476,16 @@ impl Properties {
             #[cfg(target_os = "linux")]
             if executable {
                 let mut perms = std::fs::metadata(service_path.clone())
-                    .change_context(errors::Error)?
+                    .change_context(errors::Error::Io {
+                        path: service_path.clone(),
+                    })?
                     .permissions();
                 perms.set_mode(0o755);
-                std::fs::set_permissions(service_path.clone(), perms)
-                    .change_context(errors::Error)?;
+                std::fs::set_permissions(service_path.clone(), perms).change_context(
+                    errors::Error::Io {
+                        path: service_path.clone(),
+                    },
+                )?;
             }
 
             let network = self
@@ -434,13 +512,16 @@ impl Properties {
                 help,
             );
         } else if cfg!(target_os = "macos") {
-            let template = self.supervise_template().change_context(errors::Error)?;
+            let template = self.supervise_template()?;
             let service_path = self.service_path();
 
             match std::fs::write(&service_path, template) {
                 Ok(_) => {}
                 Err(e) => {
-                    return Err(errors::Error).attach_printable(format!(
+                    return Err(errors::Error::Io {
+                        path: service_path.clone(),
+                    })
+                    .attach_printable(format!(
                         "Could not write the template {}; are you root? ({})",
                         service_path
                             .to_str()
@@ -456,7 +537,7 @@ impl Properties {
                 service_path.to_str().expect("Could not coerce service path to string")
             );
         } else {
-            return Err(errors::Error)
+            return Err(errors::Error::Supervise)
                 .attach_printable("Your platform is not supported for this command");
         }
         Ok(())
@@ -467,7 +548,10 @@ impl Properties {
             match std::fs::remove_file(self.service_path()) {
                 Ok(_) => {}
                 Err(e) => {
-                    return Err(errors::Error).attach_printable(format!(
+                    return Err(errors::Error::Io {
+                        path: self.service_path(),
+                    })
+                    .attach_printable(format!(
                         "Could not uninstall supervisor unit file ({}): {}",
                         self.service_path()
                             .to_str()
@@ -484,7 +568,10 @@ impl Properties {
             match std::fs::remove_file(self.service_path()) {
                 Ok(_) => {}
                 Err(e) => {
-                    return Err(errors::Error).attach_printable(format!(
+                    return Err(errors::Error::Io {
+                        path: self.service_path(),
+                    })
+                    .attach_printable(format!(
                         "Could not uninstall supervisor unit file ({}): {}",
                         self.service_path()
                             .to_str()
@@ -500,9 +587,216 @@ impl Properties {
                 self.service_name().replace(".plist", "")
             );
         } else {
-            return Err(errors::Error)
+            return Err(errors::Error::Supervise)
                 .attach_printable("Your platform is not supported for this command");
         }
         Ok(())
     }
 }
+
+// how long a nameserver supervised with --foreground is first given before it is restarted, and
+// the most the wait doubles to while it keeps failing.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// Foreground supervises `zeronsd start` from this process, for hosts without a service manager
+// and containers: the nameserver is restarted whenever it fails, waiting twice as long each time
+// it fails again before it has run for max_backoff.
+pub struct Foreground {
+    pub binpath: PathBuf,
+    pub args: Vec<OsString>,
+    pub once: bool,
+    pub log_file: Option<PathBuf>,
+    pub pid_file: Option<PathBuf>,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Foreground {
+    // from_env supervises this binary, started with the arguments this process was given bar the
+    // supervisor's own.
+    pub fn from_env(
+        once: bool,
+        log_file: Option<PathBuf>,
+        pid_file: Option<PathBuf>,
+    ) -> Result<Self, errors::Error> {
+        let binpath = std::env::current_exe().change_context(errors::Error::Supervise)?;
+        let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+
+        Ok(Self {
+            binpath,
+            args: child_args(&args),
+            once,
+            log_file,
+            pid_file,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        })
+    }
+
+    // run starts the nameserver and keeps it running until it exits cleanly, or this process is
+    // asked to stop, which is passed on to the nameserver. With once, it is not restarted, and
+    // failing fails the supervisor too.
+    pub async fn run(&self) -> Result<(), errors::Error> {
+        if let Some(pid_file) = &self.pid_file {
+            std::fs::write(pid_file, format!("{}\n", std::process::id())).change_context(
+                errors::Error::Io {
+                    path: pid_file.clone(),
+                },
+            )?;
+        }
+
+        let result = self.supervise().await;
+
+        if let Some(pid_file) = &self.pid_file {
+            let _ = std::fs::remove_file(pid_file);
+        }
+
+        result
+    }
+
+    async fn supervise(&self) -> Result<(), errors::Error> {
+        let mut backoff = None;
+
+        loop {
+            let started = Instant::now();
+            let mut child = self
+                .command()?
+                .spawn()
+                .change_context(errors::Error::Supervise)?;
+            tracing::info!(pid = ?child.id(), "Started the nameserver");
+
+            let status = tokio::select! {
+                status = child.wait() => status.change_context(errors::Error::Supervise)?,
+                _ = crate::cli::wait_for_shutdown() => {
+                    tracing::info!("Stopping the nameserver");
+                    stop(&mut child);
+                    child.wait().await.change_context(errors::Error::Supervise)?;
+                    return Ok(());
+                }
+            };
+
+            if status.success() {
+                tracing::info!("The nameserver exited");
+                return Ok(());
+            }
+
+            if self.once {
+                return Err(errors::Error::Supervise)
+                    .attach_printable(format!("the nameserver exited with {}", status));
+            }
+
+            let wait = next_backoff(
+                backoff,
+                started.elapsed(),
+                self.initial_backoff,
+                self.max_backoff,
+            );
+            backoff = Some(wait);
+            tracing::warn!(
+                "The nameserver exited with {}; restarting it in {:?}",
+                status,
+                wait
+            );
+
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = crate::cli::wait_for_shutdown() => return Ok(()),
+            }
+        }
+    }
+
+    // command is how the nameserver is started: its output goes to log_file when one is given.
+    // The sockets systemd passed us are inherited as fds 3 and up, which the supervisor never
+    // closes, so they stay open while the nameserver restarts. LISTEN_PID still names the
+    // supervisor; the nameserver takes the sockets as meant for it, being our child.
+    fn command(&self) -> Result<tokio::process::Command, errors::Error> {
+        let mut command = tokio::process::Command::new(&self.binpath);
+        command.args(&self.args).kill_on_drop(true);
+
+        if let Some(log_file) = &self.log_file {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_file)
+                .change_context(errors::Error::Io {
+                    path: log_file.clone(),
+                })?;
+            let stderr = file.try_clone().change_context(errors::Error::Io {
+                path: log_file.clone(),
+            })?;
+            command.stdout(file).stderr(stderr);
+        }
+
+        Ok(command)
+    }
+}
+
+// stop asks the nameserver to shut down as it would be by a service manager, so it can undo its
+// changes to Central first.
+#[cfg(unix)]
+fn stop(child: &mut tokio::process::Child) {
+    if let Some(pid) = child.id().and_then(|pid| i32::try_from(pid).ok()) {
+        // SAFETY: kill only sends a signal; the pid is of a child we have not yet waited on, so
+        // it cannot have been reused.
+        unsafe {
+            libc::kill(pid, libc::SIGTERM);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn stop(child: &mut tokio::process::Child) {
+    let _ = child.start_kill();
+}
+
+// next_backoff is how long to wait before restarting a nameserver that failed after running for
+// ran, given the wait before the previous restart: the first wait is initial, each after it twice
+// the last, and a run longer than max starts the doubling over.
+pub fn next_backoff(
+    previous: Option<Duration>,
+    ran: Duration,
+    initial: Duration,
+    max: Duration,
+) -> Duration {
+    match previous {
+        Some(previous) if ran < max => (previous * 2).min(max),
+        _ => initial,
+    }
+}
+
+// child_args turns the arguments of `zeronsd supervise --foreground` into those for `zeronsd
+// start`: the subcommand is swapped and the supervisor's own flags are dropped.
+pub fn child_args(args: &[OsString]) -> Vec<OsString> {
+    const FLAGS: [&str; 2] = ["--foreground", "--once"];
+    const OPTIONS: [&str; 2] = ["--log-file", "--pid-file"];
+
+    let mut out = Vec::new();
+    let mut subcommand = false;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.to_string_lossy().as_ref() {
+            "supervise" if !subcommand => {
+                subcommand = true;
+                out.push(OsString::from("start"));
+            }
+            "--" => {
+                out.push(arg.clone());
+                out.extend(iter.cloned());
+                break;
+            }
+            flag if FLAGS.contains(&flag) => {}
+            option if OPTIONS.contains(&option) => {
+                iter.next();
+            }
+            option
+                if OPTIONS
+                    .iter()
+                    .any(|o| option.starts_with(&format!("{}=", o))) => {}
+            _ => out.push(arg.clone()),
+        }
+    }
+
+    out
+}
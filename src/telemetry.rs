@@ -0,0 +1,53 @@
+/// export of spans to an OpenTelemetry collector over OTLP/HTTP
+use std::sync::OnceLock;
+
+use error_stack::{Result, ResultExt};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    export::trace::SpanExporter, runtime::Tokio, trace::TracerProvider, Resource,
+};
+use tracing::warn;
+
+use crate::{errors, utils::LogLayer};
+
+static PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+// provider sends spans to exporter in batches, in the background.
+pub fn provider<E: SpanExporter + 'static>(exporter: E) -> TracerProvider {
+    TracerProvider::builder()
+        .with_batch_exporter(exporter, Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", "zeronsd")]))
+        .build()
+}
+
+// layer turns spans into OpenTelemetry spans and hands them to provider.
+pub fn layer(provider: &TracerProvider) -> LogLayer {
+    Box::new(tracing_opentelemetry::layer().with_tracer(provider.tracer("zeronsd")))
+}
+
+// otlp_layer exports spans to endpoint, the full URL of a collector's traces resource, such as
+// http://localhost:4318/v1/traces.
+pub fn otlp_layer(endpoint: &str) -> Result<LogLayer, errors::Error> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .change_context(errors::Error::Config)
+        .attach_printable_lazy(|| format!("cannot export traces to {}", endpoint))?;
+
+    Ok(layer(PROVIDER.get_or_init(|| provider(exporter))))
+}
+
+// shutdown exports the spans still waiting for the next batch.
+pub async fn shutdown() {
+    let provider = match PROVIDER.get() {
+        Some(provider) => provider.clone(),
+        None => return,
+    };
+
+    // the batch processor blocks until the exporter is done.
+    if let Ok(Err(e)) = tokio::task::spawn_blocking(move || provider.shutdown()).await {
+        warn!("Could not export the remaining spans: {}", e);
+    }
+}
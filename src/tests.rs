@@ -4,15 +4,56 @@ use std::{
     str::FromStr,
 };
 
+use crate::errors;
 use crate::traits::ToHostname;
 use crate::utils::domain_or_default;
 
+mockall::mock! {
+    // MockCentralClient answers for Central with whatever each test programs it to.
+    pub CentralClient {}
+
+    #[async_trait::async_trait]
+    impl crate::traits::CentralClient for CentralClient {
+        async fn get_network_by_id(
+            &self,
+            network_id: &str,
+        ) -> error_stack::Result<zerotier_api::central_api::types::Network, errors::Error>;
+        async fn get_network_member_list(
+            &self,
+            network_id: &str,
+            max_pages: u32,
+        ) -> error_stack::Result<Vec<zerotier_api::central_api::types::Member>, errors::Error>;
+    }
+}
+
+// mock_central is a MockCentralClient with canned answers for network 1234567891011121: network
+// and members, as Central would return them as JSON.
+fn mock_central(network: serde_json::Value, members: serde_json::Value) -> MockCentralClient {
+    use mockall::predicate::eq;
+
+    let network: zerotier_api::central_api::types::Network =
+        serde_json::from_value(network).unwrap();
+    let members: Vec<zerotier_api::central_api::types::Member> =
+        serde_json::from_value(members).unwrap();
+
+    let mut central = MockCentralClient::new();
+    central
+        .expect_get_network_by_id()
+        .with(eq("1234567891011121"))
+        .returning(move |_| Ok(network.clone()));
+    central
+        .expect_get_network_member_list()
+        .with(eq("1234567891011121"), mockall::predicate::always())
+        .returning(move |_, _| Ok(members.clone()));
+    central
+}
+
 #[test]
 fn test_parse_member_name() {
     use crate::utils::parse_member_name;
 
     let actual_domains: &mut Vec<Option<&str>> =
-        &mut vec!["tld", "domain", "zerotier", "test.subdomain"]
+        &mut ["tld", "domain", "zerotier", "test.subdomain"]
             .iter()
             .map(|s| Some(*s))
             .collect::<Vec<Option<&str>>>();
@@ -65,12 +106,98 @@ fn test_parse_ip_from_cidr() {
 
     for (cidr, ip) in results {
         assert_eq!(
-            parse_ip_from_cidr(String::from(cidr)),
+            parse_ip_from_cidr(String::from(cidr)).unwrap(),
             IpAddr::from_str(ip).unwrap(),
             "{}",
             cidr
         );
     }
+
+    for bad in ["", "10.0.0", "10.0.0.1/33", "fe80::abcd/129", "bogus"] {
+        assert_eq!(
+            parse_ip_from_cidr(String::from(bad))
+                .unwrap_err()
+                .current_context(),
+            &errors::Error::Address,
+            "{}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn test_parse_ips_from_cidrs() {
+    use crate::utils::parse_ips_from_cidrs;
+
+    assert_eq!(
+        parse_ips_from_cidrs(&[
+            "10.0.0.1/24".to_string(),
+            "bogus".to_string(),
+            "fd00::1/88".to_string()
+        ]),
+        vec![
+            IpAddr::from_str("10.0.0.1").unwrap(),
+            IpAddr::from_str("fd00::1").unwrap()
+        ]
+    );
+}
+
+#[test]
+fn test_normalize_listen_ips() {
+    use crate::utils::normalize_listen_ips;
+    use ipnetwork::IpNetwork;
+
+    let to_strings = |list: &[&str]| list.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+    // overlapping v4 networks collapse into the broadest one
+    let (ips, networks) = normalize_listen_ips(&to_strings(&[
+        "10.0.0.5/24",
+        "10.0.0.5/16",
+        "10.0.1.9/24",
+        "192.168.1.1/24",
+    ]));
+    assert_eq!(
+        ips,
+        vec![
+            IpAddr::from_str("10.0.0.5").unwrap(),
+            IpAddr::from_str("10.0.1.9").unwrap(),
+            IpAddr::from_str("192.168.1.1").unwrap(),
+        ]
+    );
+    assert_eq!(
+        networks,
+        vec![
+            IpNetwork::from_str("10.0.0.0/16").unwrap(),
+            IpNetwork::from_str("192.168.1.0/24").unwrap(),
+        ]
+    );
+
+    // duplicate v6 entries are dropped, as are unusable addresses
+    let (ips, networks) = normalize_listen_ips(&to_strings(&[
+        "fd00::1/88",
+        "fd00::1/88",
+        "fd00:0:0:0::1/88",
+        "::/0",
+        "0.0.0.0/0",
+        "169.254.1.1/16",
+        "fe80::1/64",
+    ]));
+    assert_eq!(
+        ips,
+        vec![
+            IpAddr::from_str("fd00::1").unwrap(),
+            IpAddr::from_str("fe80::1").unwrap(),
+        ]
+    );
+    assert_eq!(
+        networks,
+        vec![
+            IpNetwork::from_str("fe80::/64").unwrap(),
+            IpNetwork::from_str("fd00::/88").unwrap(),
+        ]
+    );
+
+    assert_eq!(normalize_listen_ips(&[]), (vec![], vec![]));
 }
 
 #[test]
@@ -94,7 +221,12 @@ fn test_domain_or_default() {
     );
 
     for bad in ["bad.", "~", "!", ".", ""] {
-        assert!(domain_or_default(Some(bad)).is_err(), "{}", bad);
+        assert_eq!(
+            domain_or_default(Some(bad)).unwrap_err().current_context(),
+            &errors::Error::DnsName,
+            "{}",
+            bad
+        );
     }
 }
 
@@ -102,14 +234,17 @@ fn test_domain_or_default() {
 fn test_central_token() {
     use crate::utils::central_token;
 
-    assert!(central_token(None).is_err());
+    assert_eq!(
+        central_token(None).unwrap_err().current_context(),
+        &errors::Error::Config
+    );
     std::env::set_var("ZEROTIER_CENTRAL_TOKEN", "abcdef");
-    assert_eq!(central_token(None).unwrap(), "abcdef");
+    assert_eq!(central_token(None).unwrap().expose(), "abcdef");
 
     let hosts = std::fs::read_to_string("/etc/hosts").unwrap();
     let token = central_token(Some(Path::new("/etc/hosts")));
     assert!(token.is_ok());
-    assert_eq!(token.unwrap(), hosts.trim());
+    assert_eq!(token.unwrap().expose(), hosts.trim());
 }
 
 #[test]
@@ -119,6 +254,83 @@ fn test_central_token_panic() {
     central_token(Some(Path::new("/nonexistent"))).unwrap();
 }
 
+#[test]
+fn test_read_network_ids_from_dir() {
+    use crate::utils::read_network_ids_from_dir;
+
+    let dir = tempdir();
+
+    assert!(read_network_ids_from_dir(dir.path()).unwrap().is_empty());
+
+    for name in [
+        "8056c2e21c000001.conf",
+        "1C33C1CED0000002.conf",
+        "8056c2e21c000001.local.conf",
+        "8056c2e21c00001.conf",
+        "not-a-network.conf",
+        "a09acf0233000003.txt",
+    ] {
+        std::fs::write(dir.path().join(name), "").unwrap();
+    }
+
+    assert_eq!(
+        read_network_ids_from_dir(dir.path()).unwrap(),
+        vec!["1c33c1ced0000002", "8056c2e21c000001"]
+    );
+
+    let path = dir.path().to_path_buf();
+    dir.close().unwrap();
+    assert_eq!(
+        read_network_ids_from_dir(&path)
+            .unwrap_err()
+            .current_context(),
+        &errors::Error::Io { path }
+    );
+}
+
+#[test]
+fn test_authtoken_path() {
+    use crate::utils::{authtoken_path, check_authtoken};
+
+    let dir = tempdir();
+    let secret = dir.path().join("authtoken.secret");
+
+    // an explicit path always wins
+    std::env::set_var("ZEROTIER_HOME", dir.path());
+    assert_eq!(
+        authtoken_path(Some(Path::new("/nonexistent"))).unwrap(),
+        PathBuf::from("/nonexistent")
+    );
+
+    assert_eq!(authtoken_path(None).unwrap(), secret);
+    std::env::remove_var("ZEROTIER_HOME");
+
+    std::env::set_var("ZT_HOME", dir.path());
+    assert_eq!(authtoken_path(None).unwrap(), secret);
+    std::env::remove_var("ZT_HOME");
+
+    #[cfg(target_os = "linux")]
+    assert_eq!(
+        authtoken_path(None).unwrap(),
+        PathBuf::from("/var/lib/zerotier-one/authtoken.secret")
+    );
+
+    // missing and empty files are reported
+    assert_eq!(
+        check_authtoken(&secret).unwrap_err().current_context(),
+        &errors::Error::Io {
+            path: secret.clone()
+        }
+    );
+    std::fs::write(&secret, "\n").unwrap();
+    assert_eq!(
+        check_authtoken(&secret).unwrap_err().current_context(),
+        &errors::Error::Config
+    );
+    std::fs::write(&secret, "abcdef\n").unwrap();
+    assert!(check_authtoken(&secret).is_ok());
+}
+
 #[test]
 #[cfg(target_os = "linux")]
 fn test_supervise_systemd_green() {
@@ -148,7 +360,7 @@ fn test_supervise_systemd_green() {
                     token: Some(PathBuf::from("/proc/cpuinfo")),
                     domain: Some(String::from("zerotier")),
                     secret: Some(PathBuf::from("/var/lib/zerotier-one/authtoken.secret")),
-                    hosts: Some(PathBuf::from("/etc/hosts")),
+                    hosts: vec![PathBuf::from("/etc/hosts")],
                     wildcard: true,
                     ..Default::default()
                 },
@@ -159,7 +371,7 @@ fn test_supervise_systemd_green() {
     ];
 
     let write = match std::env::var("WRITE_FIXTURES") {
-        Ok(var) => var != "",
+        Ok(var) => !var.is_empty(),
         Err(_) => false,
     };
 
@@ -168,7 +380,11 @@ fn test_supervise_systemd_green() {
     }
 
     for (name, mut props) in table {
-        let path = std::path::PathBuf::from(format!("../testdata/supervise/systemd/{}.unit", name));
+        let path = std::path::PathBuf::from(format!(
+            "{}/testdata/supervise/systemd/{}.unit",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ));
 
         if !write {
             let path = path.canonicalize();
@@ -242,20 +458,20 @@ fn test_supervise_systemd_red() {
                 launcher: Launcher {
                     network_id: Some(String::from("1234567891011121")),
                     token: Some(PathBuf::from("/proc/cpuinfo")),
-                    hosts: Some(PathBuf::from("~")),
+                    hosts: vec![PathBuf::from("~")],
                     ..Default::default()
                 },
                 ..Default::default()
             },
         ),
         (
-            "bad hosts (dir)",
+            "bad hosts (not a file or directory)",
             crate::supervise::Properties {
                 binpath: String::from("zeronsd"),
                 launcher: Launcher {
                     network_id: Some(String::from("1234567891011121")),
                     token: Some(PathBuf::from("/proc/cpuinfo")),
-                    hosts: Some(PathBuf::from(".")),
+                    hosts: vec![PathBuf::from("/dev/null")],
                     ..Default::default()
                 },
                 ..Default::default()
@@ -300,6 +516,19 @@ fn test_supervise_systemd_red() {
                 ..Default::default()
             },
         ),
+        (
+            "bad hosts domain (invalid)",
+            crate::supervise::Properties {
+                binpath: String::from("zeronsd"),
+                launcher: Launcher {
+                    network_id: Some(String::from("1234567891011121")),
+                    token: Some(PathBuf::from("/proc/cpuinfo")),
+                    hosts_domain: Some(String::from("-")),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ),
         (
             "bad domain (invalid)",
             crate::supervise::Properties {
@@ -316,8 +545,90 @@ fn test_supervise_systemd_red() {
     ];
 
     for (name, mut props) in table {
-        assert!(props.validate().is_err(), "{}", name);
+        assert_eq!(
+            props.validate().unwrap_err().current_context(),
+            &errors::Error::Config,
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_supervise_dropped_settings() {
+    use crate::{
+        cli::{Cli, Command},
+        init::Launcher,
+        supervise::Properties,
+    };
+    use clap::Parser;
+
+    let launcher = |args: &[&str]| -> Launcher {
+        let args = ["zeronsd", "supervise"].iter().chain(args);
+        match Cli::try_parse_from(args).unwrap().command {
+            Command::Supervise(args) => args.start.into(),
+            _ => unreachable!(),
+        }
+    };
+    let dropped = |launcher| {
+        Properties {
+            launcher,
+            ..Default::default()
+        }
+        .dropped_settings()
+        .unwrap()
+    };
+
+    // the flags the templates carry, and defaults, go through
+    assert!(dropped(launcher(&[
+        "-t",
+        "/proc/cpuinfo",
+        "-d",
+        "zerotier",
+        "-f",
+        "/etc/hosts",
+        "-w",
+        "1234567891011121",
+    ]))
+    .is_empty());
+
+    // others would be lost, so supervise refuses them
+    let props = launcher(&[
+        "-t",
+        "/proc/cpuinfo",
+        "--max-records",
+        "5",
+        "--ecs-mode",
+        "strip",
+        "-l",
+        "debug",
+        "1234567891011121",
+    ]);
+    assert_eq!(
+        dropped(props.clone()),
+        ["ecs_mode", "log_level", "max_records"]
+    );
+    let err = Properties {
+        launcher: props.clone(),
+        ..Default::default()
     }
+    .validate()
+    .unwrap_err();
+    assert_eq!(err.current_context(), &errors::Error::Config);
+    assert!(format!("{:?}", err).contains("without ecs_mode, log_level, max_records"));
+
+    // unless they come from a configuration file, which the service is given instead
+    let dir = tempdir();
+    let config = dir.path().join("zeronsd.yaml");
+    std::fs::write(&config, "max_records: 5\n").unwrap();
+    assert!(Properties {
+        launcher: props,
+        config: Some(config),
+        ..Default::default()
+    }
+    .validate()
+    .is_ok());
 }
 
 #[test]
@@ -331,7 +642,6 @@ fn test_parse_hosts() {
 
     for path in std::fs::read_dir(crate::utils::TEST_HOSTS_DIR)
         .unwrap()
-        .into_iter()
         .map(|p| p.unwrap())
     {
         if path.metadata().unwrap().is_file() {
@@ -391,7 +701,10 @@ fn test_parse_hosts_duplicate() {
     let domain = Name::from_str("zombocom").unwrap();
 
     let res = parse_hosts(
-        Some(PathBuf::from("../testdata/hosts-files/duplicates")),
+        Some(PathBuf::from(format!(
+            "{}/duplicates",
+            crate::utils::TEST_HOSTS_DIR
+        ))),
         domain.clone(),
     );
 
@@ -415,3 +728,7176 @@ fn test_parse_hosts_duplicate() {
             .unwrap()
     ));
 }
+
+#[test]
+fn test_parse_hosts_ipv6_notation() {
+    use crate::hosts::parse_hosts;
+    use trust_dns_resolver::Name;
+
+    let domain = Name::from_str("zombocom").unwrap();
+
+    let table = parse_hosts(
+        Some(PathBuf::from(format!(
+            "{}/ipv6-notation",
+            crate::utils::TEST_HOSTS_DIR
+        ))),
+        domain.clone(),
+    )
+    .unwrap();
+
+    let names = |ip: &str| -> Vec<Name> {
+        table
+            .get(&IpAddr::from_str(ip).unwrap())
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    let fqdns = |hosts: &[&str]| -> Vec<Name> {
+        hosts
+            .iter()
+            .map(|h| Name::from_str(h).unwrap().append_domain(&domain).unwrap())
+            .collect()
+    };
+
+    // loopback, in both notations
+    assert_eq!(names("::1"), fqdns(&["localhost", "loopback-expanded"]));
+    // link-local, with the zone index dropped
+    assert_eq!(names("fe80::1"), fqdns(&["link-local"]));
+    // compressed
+    assert_eq!(names("fd::1"), fqdns(&["compressed"]));
+    // fully expanded, merged with its compressed form
+    assert_eq!(
+        names("fd00:0:0:0:0:0:0:2"),
+        fqdns(&["expanded", "expanded-twin"])
+    );
+    // embedded IPv4
+    assert_eq!(names("::ffff:10.0.0.1"), fqdns(&["mapped"]));
+    assert!(names("10.0.0.1").is_empty());
+}
+
+#[test]
+fn test_listen_addr() {
+    use crate::server::{listen_addr, needs_interface};
+    use std::net::SocketAddr;
+
+    let ips = |ips: &[&str]| -> Vec<IpAddr> {
+        ips.iter().map(|ip| IpAddr::from_str(ip).unwrap()).collect()
+    };
+    assert!(!needs_interface(&ips(&["10.0.0.1", "fd00::1"])));
+    assert!(needs_interface(&ips(&["10.0.0.1", "fe80::1"])));
+    assert!(!needs_interface(&[]));
+
+    assert_eq!(
+        listen_addr(IpAddr::from_str("10.0.0.1").unwrap(), 53, None),
+        Some(SocketAddr::from_str("10.0.0.1:53").unwrap())
+    );
+
+    assert_eq!(
+        listen_addr(IpAddr::from_str("fd00::1").unwrap(), 53, None),
+        Some(SocketAddr::from_str("[fd00::1]:53").unwrap())
+    );
+
+    assert_eq!(
+        listen_addr(IpAddr::from_str("fe80::1").unwrap(), 53, None),
+        None
+    );
+
+    assert_eq!(
+        listen_addr(
+            IpAddr::from_str("fe80::1").unwrap(),
+            53,
+            Some("nonexistent-interface")
+        ),
+        None
+    );
+
+    #[cfg(target_os = "linux")]
+    if let Some(index) = crate::server::interface_index("lo") {
+        assert_eq!(
+            listen_addr(IpAddr::from_str("fe80::1").unwrap(), 53, Some("lo")),
+            Some(SocketAddr::from_str(&format!("[fe80::1%{}]:53", index)).unwrap())
+        );
+    }
+}
+
+#[test]
+fn test_snapshot() {
+    use crate::snapshot::Snapshot;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let dir = tempdir();
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121" })).unwrap();
+    let member: Member = serde_json::from_value(serde_json::json!({
+        "nodeId": "abcdef0123",
+        "name": "islay",
+    }))
+    .unwrap();
+
+    assert!(Snapshot::load(dir.path(), "1234567891011121").is_none());
+
+    let snapshot = Snapshot::new("1234567891011121".to_string(), network, vec![member]);
+    assert!(snapshot.save(dir.path()).is_ok());
+
+    let loaded = Snapshot::load(dir.path(), "1234567891011121").unwrap();
+    assert_eq!(loaded.network.id, Some("1234567891011121".to_string()));
+    assert_eq!(loaded.members.len(), 1);
+    assert_eq!(loaded.members[0].name, Some("islay".to_string()));
+
+    // a snapshot recorded for another network is never served
+    std::fs::copy(
+        Snapshot::path(dir.path(), "1234567891011121"),
+        Snapshot::path(dir.path(), "2222222222222222"),
+    )
+    .unwrap();
+    assert!(Snapshot::load(dir.path(), "2222222222222222").is_none());
+
+    std::fs::write(Snapshot::path(dir.path(), "1234567891011121"), "{ not json").unwrap();
+    assert!(Snapshot::load(dir.path(), "1234567891011121").is_none());
+}
+
+#[tokio::test]
+async fn test_upsert_batch() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_resolver::proto::rr::{RData, RecordType};
+    use trust_dns_resolver::Name;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    let domain = LowerName::from_str("home.arpa.").unwrap();
+    let authority = RecordAuthority::new(
+        domain.clone(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let islay = Name::from_str("islay.home.arpa.").unwrap();
+    let jura = Name::from_str("jura.home.arpa.").unwrap();
+
+    authority
+        .upsert_batch(vec![
+            (islay.clone(), vec![RData::A("10.0.0.1".parse().unwrap())]),
+            (islay.clone(), vec![RData::A("10.0.0.2".parse().unwrap())]),
+            (
+                jura.clone(),
+                vec![
+                    RData::A("10.0.0.3".parse().unwrap()),
+                    RData::AAAA("fd00::3".parse().unwrap()),
+                ],
+            ),
+        ])
+        .await;
+
+    let count = |name: Name, rt: RecordType| {
+        let authority = authority.clone();
+        async move {
+            authority
+                .lookup(&name.into(), rt, LookupOptions::default())
+                .await
+                .map(|lookup| lookup.iter().count())
+                .unwrap_or(0)
+        }
+    };
+
+    assert_eq!(count(islay.clone(), RecordType::A).await, 2);
+    assert_eq!(count(jura.clone(), RecordType::A).await, 1);
+    assert_eq!(count(jura.clone(), RecordType::AAAA).await, 1);
+
+    // a second batch replaces, rather than appends to, the existing record sets
+    authority
+        .upsert_batch(vec![(
+            islay.clone(),
+            vec![RData::A("10.0.0.4".parse().unwrap())],
+        )])
+        .await;
+
+    assert_eq!(count(islay, RecordType::A).await, 1);
+    assert_eq!(count(jura, RecordType::A).await, 1);
+}
+
+#[test]
+fn test_merge_dns_servers() {
+    use crate::utils::merge_dns_servers;
+
+    let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+    let first = strings(&["10.0.0.1"]);
+    let second = strings(&["10.0.0.2", "fd00::2"]);
+
+    // two instances updating alternately should never stomp on each other
+    let mut servers = Vec::new();
+    for _ in 0..3 {
+        servers = merge_dns_servers(&servers, &first, None);
+        servers = merge_dns_servers(&servers, &second, None);
+    }
+    assert_eq!(servers, strings(&["10.0.0.1", "10.0.0.2", "fd00::2"]));
+
+    // merging is idempotent
+    assert_eq!(merge_dns_servers(&servers, &first, None), servers);
+
+    // the oldest servers that aren't ours are dropped to honor the limit
+    assert_eq!(
+        merge_dns_servers(&servers, &strings(&["10.0.0.3"]), Some(2)),
+        strings(&["fd00::2", "10.0.0.3"])
+    );
+    assert_eq!(
+        merge_dns_servers(&servers, &second, Some(2)),
+        strings(&["10.0.0.2", "fd00::2"])
+    );
+    assert_eq!(
+        merge_dns_servers(&[], &second, Some(1)),
+        strings(&["10.0.0.2"])
+    );
+}
+
+// MockCentral is a bare-bones stand-in for the Central API: it serves a single network and its
+// members over plain HTTP and records how many times the network was updated. With drop_updates set, updates are accepted but
+// not stored, like Central occasionally does. Responses can be delayed to simulate a hung Central.
+struct MockCentral {
+    url: String,
+    network: std::sync::Arc<std::sync::Mutex<serde_json::Value>>,
+    updates: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    delay: std::sync::Arc<std::sync::Mutex<std::time::Duration>>,
+}
+
+impl MockCentral {
+    async fn new(network: serde_json::Value, drop_updates: bool) -> Self {
+        Self::with_members(network, serde_json::json!([]), drop_updates).await
+    }
+
+    async fn with_members(
+        network: serde_json::Value,
+        members: serde_json::Value,
+        drop_updates: bool,
+    ) -> Self {
+        use std::sync::{atomic::Ordering, Arc, Mutex};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let network = Arc::new(Mutex::new(network));
+        let updates = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let delay = Arc::new(Mutex::new(std::time::Duration::ZERO));
+
+        let (n, m, u, d) = (network.clone(), members, updates.clone(), delay.clone());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (n, m, u, d) = (n.clone(), m.clone(), u.clone(), d.clone());
+
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0; 4096];
+
+                    // read the headers, then however much body content-length says there is.
+                    let body_start = loop {
+                        let len = stream.read(&mut chunk).await.unwrap();
+                        buf.extend_from_slice(&chunk[..len]);
+                        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break pos + 4;
+                        }
+                    };
+
+                    let head = String::from_utf8_lossy(&buf[..body_start]).to_lowercase();
+                    let content_length: usize = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("content-length:"))
+                        .map_or(0, |l| l.trim().parse().unwrap());
+
+                    while buf.len() < body_start + content_length {
+                        let len = stream.read(&mut chunk).await.unwrap();
+                        buf.extend_from_slice(&chunk[..len]);
+                    }
+
+                    let delay = *d.lock().unwrap();
+                    tokio::time::sleep(delay).await;
+
+                    if head.starts_with("post") {
+                        u.fetch_add(1, Ordering::SeqCst);
+                        if !drop_updates {
+                            *n.lock().unwrap() =
+                                serde_json::from_slice(&buf[body_start..]).unwrap();
+                        }
+                    }
+
+                    let body = if head.lines().next().unwrap_or_default().contains("/member ") {
+                        m.to_string()
+                    } else {
+                        n.lock().unwrap().to_string()
+                    };
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    // the client may have given up on us already.
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        Self {
+            url,
+            network,
+            updates,
+            delay,
+        }
+    }
+
+    fn set_delay(&self, delay: std::time::Duration) {
+        *self.delay.lock().unwrap() = delay;
+    }
+
+    fn client(&self) -> zerotier_api::central_api::Client {
+        zerotier_api::central_api::Client::new(&self.url)
+    }
+
+    fn updates(&self) -> usize {
+        self.updates.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn dns(&self) -> serde_json::Value {
+        self.network.lock().unwrap()["config"]["dns"].clone()
+    }
+}
+
+#[tokio::test]
+async fn test_push_central_dns() {
+    use crate::{init::Launcher, utils::domain_or_default};
+
+    let network = serde_json::json!({
+        "id": "1234567891011121",
+        "config": { "dns": { "domain": "other", "servers": ["10.0.0.2"] } },
+    });
+
+    // with no_update_dns, central is never written to
+    let central = MockCentral::new(network.clone(), false).await;
+    let launcher = Launcher {
+        network_id: Some("1234567891011121".to_string()),
+        no_update_dns: true,
+        ..Default::default()
+    };
+    launcher
+        .push_central_dns(
+            domain_or_default(None).unwrap(),
+            vec!["10.0.0.1".to_string()],
+            central.client(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(central.updates(), 0);
+    assert_eq!(central.dns()["servers"], serde_json::json!(["10.0.0.2"]));
+
+    let launcher = Launcher {
+        no_update_dns: false,
+        ..launcher
+    };
+    launcher
+        .push_central_dns(
+            domain_or_default(None).unwrap(),
+            vec!["10.0.0.1".to_string()],
+            central.client(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(central.updates(), 1);
+    assert_eq!(central.dns()["domain"], "home.arpa");
+    assert_eq!(
+        central.dns()["servers"],
+        serde_json::json!(["10.0.0.2", "10.0.0.1"])
+    );
+}
+
+#[tokio::test]
+async fn test_verify_central_dns() {
+    use crate::utils::{update_central_dns, verify_central_dns};
+
+    let network = serde_json::json!({ "id": "1234567891011121", "config": {} });
+    let servers = vec!["10.0.0.1".to_string()];
+
+    for drop_updates in [false, true] {
+        let central = MockCentral::new(network.clone(), drop_updates).await;
+
+        // a dropped update only warns; it is not an error.
+        update_central_dns(
+            crate::utils::domain_or_default(None).unwrap(),
+            servers.clone(),
+            central.client(),
+            "1234567891011121".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(central.updates(), 1);
+
+        assert_eq!(
+            verify_central_dns("home.arpa", &servers, &central.client(), "1234567891011121")
+                .await
+                .unwrap(),
+            !drop_updates,
+            "drop_updates: {}",
+            drop_updates
+        );
+    }
+}
+
+// tempdir is a directory of the test's own, removed with everything in it when dropped, so also when
+// the test panics.
+fn tempdir() -> tempfile::TempDir {
+    tempfile::Builder::new()
+        .prefix("zeronsd-")
+        .tempdir()
+        .unwrap()
+}
+
+// zt_authority builds a ZTAuthority for home.arpa without any reverse zones.
+async fn zt_authority(
+    client: impl crate::traits::CentralClient + 'static,
+) -> crate::authority::ZTAuthority {
+    use crate::authority::{RecordAuthority, ZTAuthority};
+    use trust_dns_server::client::rr::LowerName;
+
+    ZTAuthority::new(
+        "1234567891011121".to_string(),
+        Some(std::sync::Arc::new(client)),
+        RecordAuthority::new(
+            LowerName::from_str("home.arpa.").unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap(),
+        Default::default(),
+        std::time::Duration::new(30, 0),
+    )
+}
+
+#[tokio::test]
+async fn test_network_info() {
+    let central = MockCentral::with_members(
+        serde_json::json!({ "id": "1234567891011121", "config": { "name": "islay" } }),
+        serde_json::json!([{ "nodeId": "abcdef0123", "config": {} }]),
+        false,
+    )
+    .await;
+
+    let zt = zt_authority(central.client()).await;
+    assert!(zt.network_info().await.is_none());
+    assert_eq!(
+        crate::metrics::describe_network(&zt.network_summary().await),
+        "network: 1234567891011121"
+    );
+
+    let (_, members) = zt.get_members().await.unwrap();
+    assert_eq!(members.len(), 1);
+
+    let network = zt.network_info().await.unwrap();
+    assert_eq!(network.id, Some("1234567891011121".to_string()));
+    assert_eq!(network.config.unwrap().name, Some("islay".to_string()));
+    assert_eq!(
+        zt.network_summary().await,
+        serde_json::json!({ "id": "1234567891011121", "name": "islay" })
+    );
+    assert_eq!(
+        crate::metrics::describe_network(&zt.network_summary().await),
+        "network: 1234567891011121 (islay)"
+    );
+}
+
+#[tokio::test]
+async fn test_http_timeout() {
+    use crate::{
+        init::{HumanDuration, Launcher},
+        utils::{local_client, HttpOptions, Secret, DEFAULT_SERVICE_API_TIMEOUT},
+    };
+    use std::time::{Duration, Instant};
+
+    let central = MockCentral::with_members(
+        serde_json::json!({ "id": "1234567891011121", "config": {} }),
+        serde_json::json!([{ "nodeId": "abcdef0123", "config": {} }]),
+        false,
+    )
+    .await;
+
+    let options = HttpOptions {
+        request_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let client = zerotier_api::central_api::Client::new_with_client(
+        &central.url,
+        options.client_builder().build().unwrap(),
+    );
+    let zt = zt_authority(client).await;
+
+    // a hung central fails the sync quickly instead of stalling it
+    central.set_delay(Duration::from_secs(5));
+    let start = Instant::now();
+    let err = zt.get_members().await.unwrap_err();
+    assert!(
+        matches!(err.current_context(), errors::Error::CentralApi { .. }),
+        "{:?}",
+        err
+    );
+    assert!(err.current_context().is_retryable());
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    // and the next tick picks up where it left off
+    central.set_delay(Duration::ZERO);
+    let (_, members) = zt.get_members().await.unwrap();
+    assert_eq!(members.len(), 1);
+
+    // a hung zerotier-one fails requests to it just the same
+    assert_eq!(
+        Launcher::default().service_api_timeout(),
+        DEFAULT_SERVICE_API_TIMEOUT
+    );
+    let launcher = Launcher {
+        service_api_timeout: Some(HumanDuration(Duration::from_secs(1))),
+        ..Default::default()
+    };
+    assert_eq!(launcher.service_api_timeout(), Duration::from_secs(1));
+    // a bare number is still seconds
+    let launcher: Launcher = serde_yml::from_str("service_api_timeout: 5\n").unwrap();
+    assert_eq!(launcher.service_api_timeout(), Duration::from_secs(5));
+    let launcher: Launcher = serde_yml::from_str("service_api_timeout: 500ms\n").unwrap();
+    assert_eq!(launcher.service_api_timeout(), Duration::from_millis(500));
+
+    central.set_delay(Duration::from_secs(5));
+    let local = local_client(
+        Secret::new("token"),
+        central.url.clone(),
+        Duration::from_millis(200),
+    )
+    .unwrap();
+    let start = Instant::now();
+    local.get_status().await.unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn test_initial_sync() {
+    use crate::authority::initial_sync;
+    use std::time::Duration;
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+
+    let central = MockCentral::with_members(
+        serde_json::json!({ "id": "1234567891011121", "config": {} }),
+        serde_json::json!([
+            { "nodeId": "abcdef0123", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+        ]),
+        false,
+    )
+    .await;
+    let cache_dir = tempdir();
+
+    let found = |zt: &crate::authority::ZTAuthority| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str("islay.home.arpa.").unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+
+    // a hung central fails the first sync once the timeout runs out, with nothing to serve
+    let mut zt = zt_authority(central.client()).await;
+    zt.cache_dir = Some(cache_dir.path().to_path_buf());
+    central.set_delay(Duration::from_secs(5));
+    assert!(initial_sync(&zt, Duration::from_millis(200)).await.is_err());
+    assert!(!found(&zt).await);
+
+    // once central answers, members are served as soon as the first sync returns
+    central.set_delay(Duration::ZERO);
+    initial_sync(&zt, Duration::from_secs(5)).await.unwrap();
+    assert!(found(&zt).await);
+
+    // failing that, the snapshot the last sync left behind is served
+    let mut zt = zt_authority(central.client()).await;
+    zt.cache_dir = Some(cache_dir.path().to_path_buf());
+    central.set_delay(Duration::from_secs(5));
+    assert!(initial_sync(&zt, Duration::from_millis(200)).await.is_err());
+    assert!(found(&zt).await);
+}
+
+#[tokio::test]
+async fn test_get_members_concurrent() {
+    use std::time::{Duration, Instant};
+
+    let central = MockCentral::with_members(
+        serde_json::json!({ "id": "1234567891011121", "config": {} }),
+        serde_json::json!([{ "nodeId": "abcdef0123", "config": {} }]),
+        false,
+    )
+    .await;
+    central.set_delay(Duration::from_millis(500));
+
+    let zt = zt_authority(central.client()).await;
+    let start = Instant::now();
+    let (network, members) = zt.get_members().await.unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(network.id, Some("1234567891011121".to_string()));
+    assert_eq!(members.len(), 1);
+    // both requests are in flight at once, so this takes about as long as the slower one.
+    assert!(elapsed >= Duration::from_millis(500), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(900), "{:?}", elapsed);
+}
+
+#[test]
+fn test_calculator_random_ids() {
+    use crate::addresses::Calculator;
+    use std::net::Ipv6Addr;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    // the layouts written out bit by bit, to check the calculators against
+    let sixplane = |network: u64, node: u64| {
+        let folded = (network ^ (network >> 32)) & 0xffff_ffff;
+        Ipv6Addr::from((0xfc_u128 << 120) | ((folded as u128) << 88) | ((node as u128) << 48) | 1)
+    };
+    let rfc4193 = |network: u64, node: u64| {
+        Ipv6Addr::from(
+            (0xfd_u128 << 120) | ((network as u128) << 56) | (0x9993_u128 << 40) | node as u128,
+        )
+    };
+
+    for _ in 0..1000 {
+        let network_id = rand::random::<u64>();
+        let node_id = rand::random::<u64>() & 0xff_ffff_ffff;
+        let (network_hex, node_hex) = (format!("{:016x}", network_id), format!("{:010x}", node_id));
+
+        let network: Network =
+            serde_json::from_value(serde_json::json!({ "id": network_hex })).unwrap();
+        let member: Member = serde_json::from_value(serde_json::json!({
+            // IDs are read whatever their case
+            "networkId": network_hex.to_uppercase(),
+            "nodeId": node_hex,
+        }))
+        .unwrap();
+
+        let address = member.sixplane().unwrap();
+        assert_eq!(
+            address.ip(),
+            sixplane(network_id, node_id),
+            "{}",
+            network_hex
+        );
+        assert_eq!(address.prefix(), 80);
+        assert!(network.sixplane().unwrap().contains(address.ip()));
+
+        let address = member.rfc4193().unwrap();
+        assert_eq!(
+            address.ip(),
+            rfc4193(network_id, node_id),
+            "{}",
+            network_hex
+        );
+        assert_eq!(address.prefix(), 128);
+        assert!(network.rfc4193().unwrap().contains(address.ip()));
+    }
+}
+
+#[test]
+fn test_calculator_malformed_ids() {
+    use crate::addresses::Calculator;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    for id in [
+        serde_json::json!(null),
+        serde_json::json!(""),
+        serde_json::json!("123456789101112"),
+        serde_json::json!("12345678910111213"),
+        serde_json::json!("123456789101112g"),
+        serde_json::json!("0x23456789101112"),
+        serde_json::json!("12345678 0111213"),
+    ] {
+        let network: Network = serde_json::from_value(serde_json::json!({ "id": id })).unwrap();
+        for err in [
+            network.sixplane().unwrap_err(),
+            network.rfc4193().unwrap_err(),
+        ] {
+            assert_eq!(err.current_context(), &errors::Error::Address, "{}", id);
+            assert!(format!("{:?}", err).contains("network ID"), "{:?}", err);
+        }
+    }
+
+    for (network_id, node_id) in [
+        (
+            serde_json::json!("1234567891011121"),
+            serde_json::json!(null),
+        ),
+        (serde_json::json!(null), serde_json::json!("abcdef0001")),
+        (
+            serde_json::json!("1234567891011121"),
+            serde_json::json!("abcdef001"),
+        ),
+        (
+            serde_json::json!("1234567891011121"),
+            serde_json::json!("abcdef00001"),
+        ),
+        (
+            serde_json::json!("1234567891011121"),
+            serde_json::json!("abcdefzz01"),
+        ),
+        (
+            serde_json::json!("123456789101112"),
+            serde_json::json!("abcdef0001"),
+        ),
+    ] {
+        let member: Member = serde_json::from_value(serde_json::json!({
+            "networkId": network_id,
+            "nodeId": node_id,
+        }))
+        .unwrap();
+        for err in [
+            member.sixplane().unwrap_err(),
+            member.rfc4193().unwrap_err(),
+        ] {
+            assert_eq!(
+                err.current_context(),
+                &errors::Error::Address,
+                "{} {}",
+                network_id,
+                node_id
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_configure_members_malformed_id() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    let network: zerotier_api::central_api::types::Network =
+        serde_json::from_value(serde_json::json!({
+            "id": "1234567891011121",
+            "config": { "v6AssignMode": { "rfc4193": true, "6plane": true } },
+        }))
+        .unwrap();
+    let members: Vec<zerotier_api::central_api::types::Member> =
+        serde_json::from_value(serde_json::json!([
+            {
+                "nodeId": "abcdef0001",
+                "networkId": "1234567891011121",
+                "name": "islay",
+                "config": { "ipAssignments": ["10.0.0.1"] },
+            },
+            {
+                "nodeId": "abcdef02",
+                "networkId": "1234567891011121",
+                "name": "jura",
+                "config": { "ipAssignments": ["10.0.0.2"] },
+            },
+        ]))
+        .unwrap();
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network, members).await.unwrap();
+
+    // the good member is served as usual, the other not at all rather than at a wrong address
+    let lookup = |name: &'static str, rtype| {
+        let zt = zt.clone();
+        async move {
+            zt.forward_authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    rtype,
+                    LookupOptions::default(),
+                )
+                .await
+                .map(|lookup| lookup.iter().count())
+                .unwrap_or(0)
+        }
+    };
+    assert_eq!(lookup("islay.home.arpa.", RecordType::A).await, 1);
+    assert_eq!(lookup("islay.home.arpa.", RecordType::AAAA).await, 2);
+    assert_eq!(lookup("jura.home.arpa.", RecordType::A).await, 0);
+    assert_eq!(lookup("jura.home.arpa.", RecordType::AAAA).await, 0);
+}
+
+#[tokio::test]
+async fn test_prune_hosts() {
+    use crate::authority::RecordAuthority;
+    use crate::hosts::HostsFile;
+    use trust_dns_resolver::proto::rr::RData;
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+    let name = |s: &str| Name::from_str(s).unwrap();
+    let ip = |s: &str| IpAddr::from_str(s).unwrap();
+
+    authority
+        .upsert_batch(vec![
+            (
+                name("islay.home.arpa."),
+                vec![RData::A("10.0.0.1".parse().unwrap())],
+            ),
+            (
+                name("jura.home.arpa."),
+                vec![
+                    RData::A("10.0.0.2".parse().unwrap()),
+                    RData::AAAA("fd00::2".parse().unwrap()),
+                ],
+            ),
+            (
+                name("skye.home.arpa."),
+                vec![RData::A("10.0.0.3".parse().unwrap())],
+            ),
+        ])
+        .await;
+
+    let rdata = |authority: RecordAuthority, record: &'static str| async move {
+        let mut rdata: Vec<String> = authority
+            .export_json()
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["name"] == record)
+            .map(|r| {
+                format!(
+                    "{} {}",
+                    r["type"].as_str().unwrap(),
+                    r["rdata"].as_str().unwrap()
+                )
+            })
+            .collect();
+        rdata.sort();
+        rdata
+    };
+
+    // records matching the file are left alone
+    let mut hosts = HostsFile::new();
+    hosts.insert(ip("10.0.0.1"), vec![name("islay.home.arpa.")]);
+    hosts.insert(ip("10.0.0.2"), vec![name("jura.home.arpa.")]);
+    hosts.insert(ip("fd00::2"), vec![name("jura.home.arpa.")]);
+    let serial = authority.serial().await;
+    authority.prune_hosts(&hosts).await.unwrap();
+    assert_eq!(authority.serial().await, serial);
+
+    // jura moved and lost its IPv6 address; names not in the file are not touched
+    let mut hosts = HostsFile::new();
+    hosts.insert(ip("10.0.0.1"), vec![name("islay.home.arpa.")]);
+    hosts.insert(ip("10.0.0.4"), vec![name("JURA.home.arpa.")]);
+    authority.prune_hosts(&hosts).await.unwrap();
+    assert_ne!(authority.serial().await, serial);
+
+    assert_eq!(
+        rdata(authority.clone(), "islay.home.arpa.").await,
+        vec!["A 10.0.0.1"]
+    );
+    assert_eq!(
+        rdata(authority.clone(), "jura.home.arpa.").await,
+        vec!["A 10.0.0.4"]
+    );
+    assert_eq!(
+        rdata(authority.clone(), "skye.home.arpa.").await,
+        vec!["A 10.0.0.3"]
+    );
+}
+
+#[tokio::test]
+async fn test_prune_hosts_record_types() {
+    use crate::authority::RecordAuthority;
+    use crate::hosts::HostsFile;
+    use trust_dns_resolver::proto::rr::{rdata::TXT, RData};
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+    let name = |s: &str| Name::from_str(s).unwrap();
+    let ip = |s: &str| IpAddr::from_str(s).unwrap();
+
+    authority
+        .upsert_batch(vec![
+            (
+                name("islay.home.arpa."),
+                vec![
+                    RData::A("10.0.0.1".parse().unwrap()),
+                    RData::AAAA("fd00::1".parse().unwrap()),
+                    RData::TXT(TXT::new(vec!["peat".to_string()])),
+                ],
+            ),
+            (
+                name("jura.home.arpa."),
+                vec![RData::AAAA("fd00::2".parse().unwrap())],
+            ),
+        ])
+        .await;
+
+    let rdata = |authority: RecordAuthority, record: &'static str| async move {
+        let mut rdata: Vec<String> = authority
+            .export_json()
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["name"] == record)
+            .map(|r| {
+                format!(
+                    "{} {}",
+                    r["type"].as_str().unwrap(),
+                    r["rdata"].as_str().unwrap()
+                )
+            })
+            .collect();
+        rdata.sort();
+        rdata
+    };
+
+    // each type is compared on its own, so matching sets of both are left alone
+    let mut hosts = HostsFile::new();
+    hosts.insert(ip("10.0.0.1"), vec![name("islay.home.arpa.")]);
+    hosts.insert(ip("fd00::1"), vec![name("islay.home.arpa.")]);
+    hosts.insert(ip("fd00::2"), vec![name("jura.home.arpa.")]);
+    let serial = authority.serial().await;
+    authority.prune_hosts(&hosts).await.unwrap();
+    assert_eq!(authority.serial().await, serial);
+
+    // islay's IPv4 address changed and its IPv6 one went; its AAAA set goes, its TXT record stays
+    let mut hosts = HostsFile::new();
+    hosts.insert(ip("10.0.0.3"), vec![name("islay.home.arpa.")]);
+    hosts.insert(ip("fd00::2"), vec![name("jura.home.arpa.")]);
+    authority.prune_hosts(&hosts).await.unwrap();
+    assert_ne!(authority.serial().await, serial);
+    assert_eq!(
+        rdata(authority.clone(), "islay.home.arpa.").await,
+        vec!["A 10.0.0.3", "TXT peat"]
+    );
+
+    // an IPv4 address added to an IPv6-only host leaves its AAAA set as it was
+    let mut hosts = HostsFile::new();
+    hosts.insert(ip("10.0.0.3"), vec![name("islay.home.arpa.")]);
+    hosts.insert(ip("10.0.0.2"), vec![name("jura.home.arpa.")]);
+    hosts.insert(ip("fd00::2"), vec![name("jura.home.arpa.")]);
+    authority.prune_hosts(&hosts).await.unwrap();
+    assert_eq!(
+        rdata(authority.clone(), "jura.home.arpa.").await,
+        vec!["A 10.0.0.2", "AAAA fd00::2"]
+    );
+    assert_eq!(
+        rdata(authority.clone(), "islay.home.arpa.").await,
+        vec!["A 10.0.0.3", "TXT peat"]
+    );
+
+    // names the zone has no addresses for are not added
+    let mut hosts = HostsFile::new();
+    hosts.insert(ip("10.0.0.5"), vec![name("skye.home.arpa.")]);
+    let serial = authority.serial().await;
+    authority.prune_hosts(&hosts).await.unwrap();
+    assert_eq!(authority.serial().await, serial);
+    assert!(rdata(authority.clone(), "skye.home.arpa.").await.is_empty());
+}
+
+#[tokio::test]
+async fn test_upsert_zones() {
+    use crate::authority::{RecordAuthority, Rpz};
+    use std::{net::SocketAddr, sync::Arc};
+    use trust_dns_resolver::proto::{
+        op::{Header, Query, ResponseCode},
+        rr::{RData, RecordType},
+    };
+    use trust_dns_server::{
+        authority::{Catalog, LookupError, LookupOptions},
+        client::rr::{LowerName, Name},
+        server::{Protocol, RequestInfo},
+    };
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.reverse_authority_map.insert(
+        "10.0.0.0/24".parse().unwrap(),
+        RecordAuthority::new(
+            LowerName::from_str("0.0.10.in-addr.arpa.").unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    zt.rpz = Some(Arc::new(Rpz::parse("blocked.home.arpa\n").unwrap()));
+
+    // a catalog of the embedding program's own, with no forwarder of ours at the root
+    let mut catalog = Catalog::new();
+    zt.upsert_zones(&mut catalog).unwrap();
+    assert!(catalog
+        .find(&LowerName::from_str("example.com.").unwrap())
+        .is_none());
+    assert_eq!(
+        catalog
+            .find(&LowerName::from_str("1.0.0.10.in-addr.arpa.").unwrap())
+            .unwrap()
+            .origin(),
+        &LowerName::from_str("0.0.10.in-addr.arpa.").unwrap()
+    );
+
+    // records written after the zones were handed over are answered
+    for name in ["islay.home.arpa.", "blocked.home.arpa."] {
+        zt.forward_authority
+            .upsert_batch(vec![(
+                Name::from_str(name).unwrap(),
+                vec![RData::A("10.0.0.1".parse().unwrap())],
+            )])
+            .await;
+    }
+
+    let header = Header::new();
+    let search = |name: &str| {
+        let name = LowerName::from_str(name).unwrap();
+        let query = Query::query(Name::from(&name), RecordType::A).into();
+        let authority = catalog.find(&name).unwrap().box_clone();
+        async move {
+            let request = RequestInfo::new(
+                SocketAddr::from_str("10.0.0.2:53").unwrap(),
+                Protocol::Udp,
+                &header,
+                &query,
+            );
+            authority
+                .search(request, LookupOptions::default())
+                .await
+                .map(|lookup| {
+                    lookup
+                        .iter()
+                        .filter_map(|r| r.data().cloned())
+                        .collect::<Vec<_>>()
+                })
+        }
+    };
+
+    assert_eq!(
+        search("islay.home.arpa.").await.unwrap(),
+        vec![RData::A("10.0.0.1".parse().unwrap())]
+    );
+    // the response policy applies just as it does in the catalog we serve
+    assert!(matches!(
+        search("blocked.home.arpa.").await,
+        Err(LookupError::ResponseCode(ResponseCode::NXDomain))
+    ));
+
+    // so does pausing
+    zt.pause(None);
+    assert!(matches!(
+        search("islay.home.arpa.").await,
+        Err(LookupError::ResponseCode(ResponseCode::ServFail))
+    ));
+}
+
+#[tokio::test]
+async fn test_match_or_insert_unchanged() {
+    use trust_dns_server::client::rr::Name;
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(
+        &hosts_file,
+        "10.0.0.1 islay\nfd00::1 islay\n10.0.0.2 jura\n10.0.0.3 jura\nfd00::4 skye\nfd00::5 skye\n",
+    )
+    .unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.hosts_files = vec![hosts_file];
+    let authority = zt.forward_authority.clone();
+
+    zt.configure_hosts().await.unwrap();
+    let serial = authority.serial().await;
+
+    // a second sync of the same file changes nothing
+    zt.configure_hosts().await.unwrap();
+    assert_eq!(authority.serial().await, serial);
+
+    let name = |s: &str| Name::from_str(s).unwrap();
+    let ip = |s: &str| IpAddr::from_str(s).unwrap();
+    for (host, ips) in [
+        ("islay.home.arpa.", vec![ip("fd00::1"), ip("10.0.0.1")]),
+        (
+            "jura.home.arpa.",
+            vec![ip("10.0.0.3"), ip("10.0.0.2"), ip("10.0.0.3")],
+        ),
+        ("skye.home.arpa.", vec![ip("fd00::5"), ip("fd00::4")]),
+    ] {
+        assert!(
+            !authority.match_or_insert(name(host), &ips).await,
+            "{}",
+            host
+        );
+    }
+    assert_eq!(authority.serial().await, serial);
+
+    // a real difference in either type is written
+    assert!(
+        authority
+            .match_or_insert(
+                name("islay.home.arpa."),
+                &[ip("10.0.0.1"), ip("fd00::1"), ip("fd00::2")]
+            )
+            .await
+    );
+    assert!(
+        authority
+            .match_or_insert(name("jura.home.arpa."), &[ip("10.0.0.2")])
+            .await
+    );
+    assert_ne!(authority.serial().await, serial);
+    assert!(
+        !authority
+            .match_or_insert(name("jura.home.arpa."), &[ip("10.0.0.2")])
+            .await
+    );
+}
+
+#[tokio::test]
+async fn test_match_or_insert_matrix() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_resolver::proto::rr::RData;
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    let name = Name::from_str("islay.home.arpa.").unwrap();
+    let ip = |s: &str| IpAddr::from_str(s).unwrap();
+    let rdata = |ip: &IpAddr| match ip {
+        IpAddr::V4(ip) => RData::A(*ip),
+        IpAddr::V6(ip) => RData::AAAA(*ip),
+    };
+    let (v4, v6) = (ip("10.0.0.1"), ip("fd00::1"));
+
+    // what the zone holds for the name before, in both types
+    let stored = |case| match case {
+        "empty" => vec![],
+        "equal" => vec![v4, v6],
+        "different" => vec![ip("10.0.0.9"), ip("fd00::9")],
+        _ => unreachable!(),
+    };
+    let desired = |case| match case {
+        "v4" => vec![v4],
+        "v6" => vec![v6],
+        "both" => vec![v4, v6],
+        "none" => vec![],
+        _ => unreachable!(),
+    };
+
+    // each type is written only when asked for and not already held; a type with no addresses
+    // asked for keeps what it had.
+    let table: [(&str, &str, bool, &[&str]); 12] = [
+        ("empty", "v4", true, &["10.0.0.1"]),
+        ("empty", "v6", true, &["fd00::1"]),
+        ("empty", "both", true, &["10.0.0.1", "fd00::1"]),
+        ("empty", "none", false, &[]),
+        ("equal", "v4", false, &["10.0.0.1", "fd00::1"]),
+        ("equal", "v6", false, &["10.0.0.1", "fd00::1"]),
+        ("equal", "both", false, &["10.0.0.1", "fd00::1"]),
+        ("equal", "none", false, &["10.0.0.1", "fd00::1"]),
+        ("different", "v4", true, &["10.0.0.1", "fd00::9"]),
+        ("different", "v6", true, &["10.0.0.9", "fd00::1"]),
+        ("different", "both", true, &["10.0.0.1", "fd00::1"]),
+        ("different", "none", false, &["10.0.0.9", "fd00::9"]),
+    ];
+
+    for (stored_case, desired_case, should_change, expected) in table {
+        let (stored, desired) = (stored(stored_case), desired(desired_case));
+        let authority = RecordAuthority::new(
+            LowerName::from_str("home.arpa.").unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap();
+        if !stored.is_empty() {
+            authority
+                .upsert_batch(vec![(name.clone(), stored.iter().map(rdata).collect())])
+                .await;
+        }
+        let serial = authority.serial().await;
+
+        let case = format!("stored {}, desired {}", stored_case, desired_case);
+        assert_eq!(
+            authority.match_or_insert(name.clone(), &desired).await,
+            should_change,
+            "{}",
+            case
+        );
+        assert_eq!(
+            authority.serial().await != serial,
+            should_change,
+            "{}",
+            case
+        );
+
+        let mut held: Vec<String> = authority
+            .export_json()
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["name"] == "islay.home.arpa.")
+            .map(|r| r["rdata"].as_str().unwrap().to_string())
+            .collect();
+        held.sort();
+        assert_eq!(held, expected, "{}", case);
+
+        // and asking again changes nothing
+        assert!(
+            !authority.match_or_insert(name.clone(), &desired).await,
+            "{}",
+            case
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_rpz() {
+    use crate::authority::{RecordAuthority, Rpz, RpzAction, RpzAuthority};
+    use std::{net::SocketAddr, sync::Arc};
+    use trust_dns_resolver::proto::{
+        op::{Header, Query, ResponseCode},
+        rr::{RData, RecordType},
+    };
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupError, LookupOptions},
+        client::rr::{LowerName, Name},
+        server::{Protocol, RequestInfo},
+    };
+
+    let rpz = Rpz::parse(
+        "# malware\n\
+         c2.example.com\n\
+         *.tracker.example.net   # and everything under it\n\
+         \n\
+         adult.example.org CNAME walled-garden.example.com\n\
+         gone.example.org cname .\n",
+    )
+    .unwrap();
+    assert_eq!(rpz.len(), 4);
+
+    let action = |name: &str| rpz.action(&LowerName::from_str(name).unwrap()).cloned();
+    assert_eq!(action("c2.example.com."), Some(RpzAction::Block));
+    assert_eq!(action("C2.Example.COM."), Some(RpzAction::Block));
+    assert_eq!(action("www.c2.example.com."), None);
+    assert_eq!(action("a.b.tracker.example.net."), Some(RpzAction::Block));
+    assert_eq!(action("tracker.example.net."), None);
+    assert_eq!(
+        action("adult.example.org."),
+        Some(RpzAction::Redirect(
+            Name::from_str("walled-garden.example.com.").unwrap()
+        ))
+    );
+    assert_eq!(action("gone.example.org."), Some(RpzAction::Block));
+    assert_eq!(action("example.com."), None);
+
+    for invalid in ["a b", "a CNAME", "a A 10.0.0.1", "a CNAME b c"] {
+        assert!(Rpz::parse(invalid).is_err(), "{}", invalid);
+    }
+
+    let records = RecordAuthority::new(
+        LowerName::from_str("example.com.").unwrap(),
+        LowerName::from_str("ns.example.com.").unwrap(),
+    )
+    .await
+    .unwrap();
+    records
+        .upsert_batch(vec![
+            (
+                Name::from_str("c2.example.com.").unwrap(),
+                vec![RData::A("10.0.0.1".parse().unwrap())],
+            ),
+            (
+                Name::from_str("www.example.com.").unwrap(),
+                vec![RData::A("10.0.0.2".parse().unwrap())],
+            ),
+        ])
+        .await;
+    let authority = RpzAuthority::new(Box::new(records), Arc::new(rpz));
+
+    let header = Header::new();
+    let search = |name: &str| {
+        let query = Query::query(Name::from_str(name).unwrap(), RecordType::A).into();
+        let authority = authority.box_clone();
+        async move {
+            let request = RequestInfo::new(
+                SocketAddr::from_str("10.0.0.3:53").unwrap(),
+                Protocol::Udp,
+                &header,
+                &query,
+            );
+            authority
+                .search(request, LookupOptions::default())
+                .await
+                .map(|lookup| {
+                    lookup
+                        .iter()
+                        .filter_map(|r| r.data().cloned())
+                        .collect::<Vec<_>>()
+                })
+        }
+    };
+
+    assert!(matches!(
+        search("c2.example.com.").await,
+        Err(LookupError::ResponseCode(ResponseCode::NXDomain))
+    ));
+    assert_eq!(
+        search("www.example.com.").await.unwrap(),
+        vec![RData::A("10.0.0.2".parse().unwrap())]
+    );
+    assert_eq!(
+        search("adult.example.org.").await.unwrap(),
+        vec![RData::CNAME(
+            Name::from_str("walled-garden.example.com.").unwrap()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_apply_batch() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_resolver::proto::rr::{RData, RecordType};
+    use trust_dns_server::client::rr::{LowerName, Name, RrKey};
+
+    let name = |s: &str| Name::from_str(s).unwrap();
+    let a = |s: &str| RData::A(s.parse().unwrap());
+    let aaaa = |s: &str| RData::AAAA(s.parse().unwrap());
+
+    let seeded = || async {
+        let authority = RecordAuthority::new(
+            LowerName::from_str("home.arpa.").unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap();
+        authority
+            .upsert_batch(vec![
+                (name("islay.home.arpa."), vec![a("10.0.0.1")]),
+                (
+                    name("jura.home.arpa."),
+                    vec![a("10.0.0.2"), aaaa("fd00::2")],
+                ),
+                (name("skye.home.arpa."), vec![a("10.0.0.3")]),
+                (
+                    name("www.home.arpa."),
+                    vec![RData::CNAME(name("islay.home.arpa."))],
+                ),
+            ])
+            .await;
+        authority
+    };
+    // every record but the SOA, whose serial the two paths move on by different amounts.
+    let contents = |authority: RecordAuthority| async move {
+        let mut records: Vec<String> = authority
+            .export_json()
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["type"] != "SOA")
+            .map(|r| format!("{} {} {}", r["name"], r["type"], r["rdata"]))
+            .collect();
+        records.sort();
+        records
+    };
+
+    let records = vec![
+        (name("islay.home.arpa."), vec![a("10.0.0.4")]),
+        (
+            name("arran.home.arpa."),
+            vec![a("10.0.0.5"), aaaa("fd00::5")],
+        ),
+    ];
+    let keep = vec![
+        (LowerName::from_str("home.arpa.").unwrap(), RecordType::NS),
+        (
+            LowerName::from_str("jura.home.arpa.").unwrap(),
+            RecordType::AAAA,
+        ),
+    ];
+
+    let batched = seeded().await;
+    let serial = batched.serial().await;
+    batched
+        .apply_batch(records.clone(), keep.clone(), usize::MAX)
+        .await
+        .unwrap();
+    assert_ne!(batched.serial().await, serial);
+
+    // the same changes made the unbatched way: upserts, then deleting whatever is left over
+    let unbatched = seeded().await;
+    unbatched.upsert_batch(records.clone()).await;
+    let mut kept = keep.clone();
+    kept.extend(records.iter().flat_map(|(name, rdatas)| {
+        rdatas
+            .iter()
+            .map(move |rdata| (LowerName::from(name), rdata.to_record_type()))
+    }));
+    let stale = unbatched
+        .export_json()
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| {
+            (
+                LowerName::from_str(r["name"].as_str().unwrap()).unwrap(),
+                RecordType::from_str(r["type"].as_str().unwrap()).unwrap(),
+            )
+        })
+        .filter(|key| key.1 != RecordType::SOA && !kept.contains(key))
+        .map(|(name, rtype)| RrKey::new(name, rtype))
+        .collect();
+    unbatched.bulk_delete(stale).await;
+
+    assert_eq!(
+        contents(batched.clone()).await,
+        contents(unbatched.clone()).await
+    );
+    assert_eq!(
+        contents(batched.clone()).await,
+        vec![
+            "\"arran.home.arpa.\" \"A\" \"10.0.0.5\"",
+            "\"arran.home.arpa.\" \"AAAA\" \"fd00::5\"",
+            "\"home.arpa.\" \"NS\" \"zt-abcdef0123.home.arpa.\"",
+            "\"islay.home.arpa.\" \"A\" \"10.0.0.4\"",
+            "\"jura.home.arpa.\" \"AAAA\" \"fd00::2\"",
+        ]
+    );
+
+    // applying the same changes again leaves the zone and its serial alone
+    let serial = batched.serial().await;
+    batched
+        .apply_batch(records, keep, usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(batched.serial().await, serial);
+}
+
+#[tokio::test]
+async fn test_record_authority_match_or_insert() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+    let name = Name::from_str("islay.home.arpa.").unwrap();
+    assert!(
+        authority
+            .contains(&Name::from_str("home.arpa.").unwrap(), RecordType::SOA)
+            .await
+    );
+    assert!(!authority.contains(&name, RecordType::A).await);
+
+    authority
+        .match_or_insert(name.clone(), &[IpAddr::from_str("10.0.0.1").unwrap()])
+        .await;
+    assert!(authority.contains(&name, RecordType::A).await);
+    assert!(!authority.contains(&name, RecordType::AAAA).await);
+
+    // records already matching are left alone
+    let serial = authority.serial().await;
+    authority
+        .match_or_insert(name.clone(), &[IpAddr::from_str("10.0.0.1").unwrap()])
+        .await;
+    assert_eq!(authority.serial().await, serial);
+
+    authority
+        .match_or_insert(name.clone(), &[IpAddr::from_str("fd00::1").unwrap()])
+        .await;
+    assert!(authority.contains(&name, RecordType::AAAA).await);
+    assert_ne!(authority.serial().await, serial);
+}
+
+#[tokio::test]
+async fn test_configure_members_bogus_assignment() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+
+    let network: zerotier_api::central_api::types::Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<zerotier_api::central_api::types::Member> =
+        serde_json::from_value(serde_json::json!([
+            { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+            { "nodeId": "abcdef0002", "name": "jura", "config": { "ipAssignments": ["not-an-ip", "10.0.0.2"] } },
+            { "nodeId": "abcdef0003", "name": "arran", "config": { "ipAssignments": ["10.0.0.3"] } },
+        ]))
+        .unwrap();
+
+    let zt = zt_authority(
+        MockCentral::new(serde_json::json!({}), false)
+            .await
+            .client(),
+    )
+    .await;
+    zt.configure_members(network, members).await.unwrap();
+
+    for (name, ip) in [
+        ("islay.home.arpa.", "10.0.0.1"),
+        ("jura.home.arpa.", "10.0.0.2"),
+        ("arran.home.arpa.", "10.0.0.3"),
+    ] {
+        let lookup = zt
+            .forward_authority
+            .lookup(
+                &trust_dns_server::client::rr::LowerName::from_str(name).unwrap(),
+                RecordType::A,
+                LookupOptions::default(),
+            )
+            .await
+            .unwrap();
+        let ips: Vec<IpAddr> = lookup
+            .iter()
+            .filter_map(|r| r.data().and_then(|d| d.to_ip_addr()))
+            .collect();
+        assert_eq!(ips, vec![IpAddr::from_str(ip).unwrap()], "{}", name);
+    }
+}
+
+#[test]
+fn test_forward_name_servers() {
+    use crate::authority::forward_name_servers;
+    use std::net::SocketAddr;
+    use trust_dns_resolver::config::{NameServerConfig, Protocol};
+
+    let server = |protocol| NameServerConfig {
+        socket_addr: SocketAddr::from_str("10.0.0.1:53").unwrap(),
+        protocol,
+        tls_dns_name: None,
+        trust_nx_responses: false,
+        bind_addr: Some(SocketAddr::from_str("0.0.0.0:5353").unwrap()),
+    };
+
+    let servers = vec![server(Protocol::Udp), server(Protocol::Tcp)];
+
+    let randomized = forward_name_servers(&servers, true);
+    assert_eq!(randomized[0].bind_addr.unwrap().port(), 0);
+    assert_eq!(randomized[1].bind_addr.unwrap().port(), 5353);
+
+    let fixed = forward_name_servers(&servers, false);
+    assert_eq!(fixed[0].bind_addr.unwrap().port(), 5353);
+}
+
+#[test]
+fn test_ecs_mode() {
+    use crate::ecs::EcsMode;
+    use crate::init::Launcher;
+    use trust_dns_resolver::proto::{
+        op::Edns,
+        rr::rdata::opt::{EdnsCode, EdnsOption},
+    };
+
+    // 192.0.2.77/32 and 2001:db8:1234:5678::/64, as a client would send them.
+    let v4 = vec![0, 1, 32, 0, 192, 0, 2, 77];
+    let v6 = vec![0, 2, 64, 0, 0x20, 0x01, 0x0d, 0xb8, 0x12, 0x34, 0x56, 0x78];
+
+    let preprocess = |mode: EcsMode, data: &[u8]| {
+        let mut edns = Edns::new();
+        edns.options_mut()
+            .insert(EdnsOption::Unknown(8, data.to_vec()));
+        mode.preprocess(&mut edns);
+        match edns.option(EdnsCode::Subnet) {
+            Some(EdnsOption::Unknown(8, data)) => Some(data.clone()),
+            Some(option) => panic!("unexpected option {:?}", option),
+            None => None,
+        }
+    };
+
+    assert_eq!(preprocess(EcsMode::Strip, &v4), None);
+    assert_eq!(preprocess(EcsMode::PassThrough, &v4), Some(v4.clone()));
+    assert_eq!(
+        preprocess(EcsMode::AnonymizePrefix(24), &v4),
+        Some(vec![0, 1, 24, 0, 192, 0, 2])
+    );
+    assert_eq!(
+        preprocess(EcsMode::AnonymizePrefix(20), &v4),
+        Some(vec![0, 1, 20, 0, 192, 0, 0])
+    );
+    assert_eq!(
+        preprocess(EcsMode::AnonymizePrefix(0), &v4),
+        Some(vec![0, 1, 0, 0])
+    );
+    assert_eq!(
+        preprocess(EcsMode::AnonymizePrefix(48), &v6),
+        Some(vec![0, 2, 48, 0, 0x20, 0x01, 0x0d, 0xb8, 0x12, 0x34])
+    );
+    // a prefix longer than the client's leaves the subnet as it was.
+    assert_eq!(preprocess(EcsMode::AnonymizePrefix(128), &v6), Some(v6));
+
+    // an option with more address than its prefix length is not passed on.
+    let malformed = vec![0, 1, 8, 0, 192, 0, 2, 77];
+    assert_eq!(preprocess(EcsMode::PassThrough, &malformed), None);
+    assert_eq!(preprocess(EcsMode::AnonymizePrefix(24), &malformed), None);
+
+    // queries without a client subnet are left alone.
+    let mut edns = Edns::new();
+    EcsMode::Strip.preprocess(&mut edns);
+    assert!(edns.options().as_ref().is_empty());
+
+    for (s, mode) in [
+        ("strip", EcsMode::Strip),
+        ("pass-through", EcsMode::PassThrough),
+        ("anonymize-prefix:24", EcsMode::AnonymizePrefix(24)),
+    ] {
+        assert_eq!(EcsMode::from_str(s).unwrap(), mode);
+        assert_eq!(mode.to_string(), s);
+    }
+    for s in ["", "anonymize-prefix", "anonymize-prefix:129", "forward"] {
+        let err = EcsMode::from_str(s).unwrap_err();
+        assert_eq!(err.current_context(), &errors::Error::Config, "{}", s);
+    }
+
+    let launcher: Launcher = serde_yml::from_str("ecs_mode: anonymize-prefix:48\n").unwrap();
+    assert_eq!(launcher.ecs_mode, Some(EcsMode::AnonymizePrefix(48)));
+    let launcher = Launcher::from_env([(
+        String::from("ZERONSD_ECS_MODE"),
+        String::from("pass-through"),
+    )])
+    .unwrap();
+    assert_eq!(launcher.ecs_mode, Some(EcsMode::PassThrough));
+}
+
+// ecs_upstream answers every A query with 192.0.2.1 on a UDP port of its own, sending the client
+// subnet option of each query it gets, if there is one, down the channel returned.
+async fn ecs_upstream() -> (
+    std::net::SocketAddr,
+    tokio::sync::mpsc::UnboundedReceiver<Option<Vec<u8>>>,
+) {
+    use trust_dns_resolver::proto::{
+        op::{Message, MessageType},
+        rr::{
+            rdata::opt::{EdnsCode, EdnsOption},
+            RData, Record, RecordType,
+        },
+    };
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut buf = [0; 4096];
+        loop {
+            let (len, src) = socket.recv_from(&mut buf).await.unwrap();
+            let query = Message::from_vec(&buf[..len]).unwrap();
+            let subnet =
+                query
+                    .extensions()
+                    .as_ref()
+                    .and_then(|edns| match edns.option(EdnsCode::Subnet) {
+                        Some(EdnsOption::Unknown(_, data)) => Some(data.clone()),
+                        _ => None,
+                    });
+            tx.send(subnet).unwrap();
+
+            let mut answer = Message::new();
+            answer
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .set_recursion_desired(true)
+                .set_recursion_available(true)
+                .add_queries(query.queries().to_vec());
+            for q in query.queries() {
+                if q.query_type() == RecordType::A {
+                    answer.add_answer(Record::from_rdata(
+                        q.name().clone(),
+                        60,
+                        RData::A("192.0.2.1".parse().unwrap()),
+                    ));
+                }
+            }
+            socket
+                .send_to(&answer.to_vec().unwrap(), src)
+                .await
+                .unwrap();
+        }
+    });
+
+    (addr, rx)
+}
+
+// ecs_server forwards every query to upstream with the given ECS mode, serving them over UDP on
+// a port of its own.
+async fn ecs_server(
+    upstream: std::net::SocketAddr,
+    mode: crate::ecs::EcsMode,
+) -> std::net::SocketAddr {
+    use crate::ecs::{ClientSubnetHandler, SubnetForwarder};
+    use trust_dns_resolver::config::NameServerConfigGroup;
+    use trust_dns_server::{
+        authority::{Catalog, ZoneType},
+        client::rr::Name,
+        server::ServerFuture,
+        store::forwarder::{ForwardAuthority, ForwardConfig},
+    };
+
+    let config = ForwardConfig {
+        name_servers: NameServerConfigGroup::from_ips_clear(
+            &[upstream.ip()],
+            upstream.port(),
+            true,
+        ),
+        options: None,
+    };
+    let forwarder =
+        ForwardAuthority::try_from_config(Name::root(), ZoneType::Primary, &config).unwrap();
+    let mut catalog = Catalog::new();
+    catalog.upsert(
+        Name::root().into(),
+        Box::new(SubnetForwarder::new(
+            Box::new(std::sync::Arc::new(forwarder)),
+            &config,
+        )),
+    );
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let mut server = ServerFuture::new(ClientSubnetHandler::new(catalog, mode));
+    server.register_socket(socket);
+    tokio::spawn(async move { server.block_until_done().await });
+    addr
+}
+
+// ecs_lookup asks server for the A records of islay.example.com, with subnet as the client subnet
+// option if given, returning the addresses answered.
+async fn ecs_lookup(server: std::net::SocketAddr, subnet: Option<Vec<u8>>) -> Vec<IpAddr> {
+    use trust_dns_resolver::proto::{
+        op::{Edns, Message, Query},
+        rr::{rdata::opt::EdnsOption, RData, RecordType},
+    };
+    use trust_dns_server::client::rr::Name;
+
+    let mut message = Message::new();
+    message
+        .set_id(1234)
+        .set_recursion_desired(true)
+        .add_query(Query::query(
+            Name::from_str("islay.example.com.").unwrap(),
+            RecordType::A,
+        ));
+    if let Some(subnet) = subnet {
+        let mut edns = Edns::new();
+        edns.options_mut().insert(EdnsOption::Unknown(8, subnet));
+        message.set_edns(edns);
+    }
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket
+        .send_to(&message.to_vec().unwrap(), server)
+        .await
+        .unwrap();
+    let mut buf = [0; 4096];
+    let len = tokio::time::timeout(std::time::Duration::from_secs(10), socket.recv(&mut buf))
+        .await
+        .expect("no answer from the server")
+        .unwrap();
+
+    Message::from_vec(&buf[..len])
+        .unwrap()
+        .answers()
+        .iter()
+        .filter_map(|record| record.data().and_then(RData::to_ip_addr))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_ecs_forwarding() {
+    use crate::ecs::EcsMode;
+
+    // 192.0.2.77/32, as a client would send it.
+    let v4 = vec![0, 1, 32, 0, 192, 0, 2, 77];
+
+    for (mode, sent, forwarded) in [
+        (EcsMode::Strip, Some(v4.clone()), None),
+        (EcsMode::PassThrough, Some(v4.clone()), Some(v4.clone())),
+        (
+            EcsMode::AnonymizePrefix(24),
+            Some(v4.clone()),
+            Some(vec![0, 1, 24, 0, 192, 0, 2]),
+        ),
+        // queries without a subnet go out without one.
+        (EcsMode::PassThrough, None, None),
+    ] {
+        let (upstream, mut seen) = ecs_upstream().await;
+        let server = ecs_server(upstream, mode).await;
+
+        assert_eq!(
+            ecs_lookup(server, sent).await,
+            vec![IpAddr::from_str("192.0.2.1").unwrap()],
+            "{}",
+            mode
+        );
+        assert_eq!(seen.recv().await.unwrap(), forwarded, "{}", mode);
+    }
+}
+
+#[test]
+fn test_launcher_to_config_string() {
+    use crate::init::{ConfigFormat, Launcher};
+
+    let launcher = Launcher {
+        domain: Some(String::from("zombocom")),
+        hosts: vec![PathBuf::from("/etc/hosts")],
+        hosts_domain: Some(String::from("hosts.zombocom")),
+        token: Some(PathBuf::from("/token.txt")),
+        wildcard: true,
+        log_level: Some(crate::log::LevelFilter::Debug),
+        dns_servers_limit: Some(3),
+        remove_dns_on_shutdown: true,
+        randomize_udp_port: false,
+        update_interval: Some(crate::init::HumanDuration(std::time::Duration::from_secs(
+            90,
+        ))),
+        ..Default::default()
+    };
+
+    for format in [ConfigFormat::JSON, ConfigFormat::YAML, ConfigFormat::TOML] {
+        let s = launcher.to_config_string(format.clone()).unwrap();
+        assert_eq!(
+            Launcher::parse_format(&s, format.clone()).unwrap(),
+            launcher,
+            "{:?}",
+            format
+        );
+
+        let parsed = Launcher::parse(&s, String::from("1234567890abcdef"), format).unwrap();
+        assert_eq!(parsed.network_id.as_deref(), Some("1234567890abcdef"));
+    }
+
+    assert_eq!(
+        Launcher::default()
+            .to_config_string(ConfigFormat::YAML)
+            .map(|s| Launcher::parse_format(&s, ConfigFormat::YAML).unwrap())
+            .unwrap(),
+        Launcher::default()
+    );
+}
+
+#[test]
+fn test_launcher_from_env() {
+    use crate::init::Launcher;
+    use std::time::Duration;
+
+    let vars = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let launcher = Launcher::from_env(vars(&[
+        ("ZERONSD_DOMAIN", "home.arpa"),
+        ("ZERONSD_HOSTS", "/etc/hosts"),
+        ("ZERONSD_WILDCARD", "true"),
+        ("ZERONSD_LOG_LEVEL", "debug"),
+        ("ZERONSD_UPDATE_INTERVAL", "1m30s"),
+        ("ZERONSD_MAX_MEMBERS", "10"),
+        ("ZERONSD_MAX_MEMBERS", "20"),
+        ("PATH", "/usr/bin"),
+    ]))
+    .unwrap();
+    assert_eq!(launcher.domain.as_deref(), Some("home.arpa"));
+    assert_eq!(launcher.hosts, vec![PathBuf::from("/etc/hosts")]);
+    assert!(launcher.wildcard);
+    assert_eq!(launcher.log_level, Some(crate::log::LevelFilter::Debug));
+    assert_eq!(
+        launcher.update_interval.map(Duration::from),
+        Some(Duration::from_secs(90))
+    );
+    assert_eq!(launcher.max_members, Some(20));
+
+    assert!(Launcher::from_env(vars(&[("ZERONSD_WILDCARD", "sometimes")])).is_err());
+    assert_eq!(Launcher::from_env(vars(&[])).unwrap().domain, None);
+}
+
+#[test]
+fn test_launcher_from_env_file() {
+    use crate::init::Launcher;
+
+    let dir = tempdir();
+    let env_file = dir.path().join("zeronsd.env");
+    std::fs::write(
+        &env_file,
+        "# written for EnvironmentFile=\n\
+         ZERONSD_DOMAIN=\"home.arpa\"\n\
+         ZERONSD_HOSTS_DOMAIN='hosts.arpa'\n\
+         ZERONSD_USER_AGENT_SUFFIX=from-file\n",
+    )
+    .unwrap();
+
+    // nothing else reads this variable, so setting it cannot disturb other tests.
+    std::env::set_var("ZERONSD_USER_AGENT_SUFFIX", "from-env");
+    let launcher = Launcher::from_env_file(&env_file);
+    std::env::remove_var("ZERONSD_USER_AGENT_SUFFIX");
+    let launcher = launcher.unwrap();
+
+    assert_eq!(launcher.domain.as_deref(), Some("home.arpa"));
+    assert_eq!(launcher.hosts_domain.as_deref(), Some("hosts.arpa"));
+    assert_eq!(launcher.user_agent_suffix.as_deref(), Some("from-env"));
+
+    std::fs::write(&env_file, "ZERONSD_DOMAIN=\"home.arpa\n").unwrap();
+    assert!(Launcher::from_env_file(&env_file).is_err());
+    assert!(Launcher::from_env_file(&dir.path().join("missing.env")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_prefixed_vars() {
+    use crate::init::prefixed_vars;
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    let os = |s: &str| OsString::from(s);
+    let bad = |prefix: &str| {
+        let mut bytes = prefix.as_bytes().to_vec();
+        bytes.push(0xff);
+        OsString::from_vec(bytes)
+    };
+
+    // someone else's variables may hold anything.
+    assert_eq!(
+        prefixed_vars([
+            (os("ZERONSD_DOMAIN"), os("home.arpa")),
+            (bad("LANG"), os("C")),
+            (os("OTHER"), bad("value")),
+            (os("PATH"), os("/usr/bin")),
+        ])
+        .unwrap(),
+        vec![(String::from("ZERONSD_DOMAIN"), String::from("home.arpa"))]
+    );
+
+    // ours can't be read, so they are refused rather than left out.
+    assert!(prefixed_vars([(bad("ZERONSD_"), os("home.arpa"))]).is_err());
+    assert!(prefixed_vars([(os("ZERONSD_DOMAIN"), bad("home"))]).is_err());
+}
+
+#[test]
+fn test_human_duration() {
+    use crate::init::{ConfigFormat, HumanDuration, Launcher};
+    use std::time::Duration;
+
+    let interval = |s: &str, format| {
+        Launcher::parse_format(s, format)
+            .map(|launcher| launcher.update_interval.map(Duration::from))
+            .ok()
+            .flatten()
+    };
+
+    assert_eq!(
+        interval(
+            r#"{"wildcard": false, "update_interval": "1m30s"}"#,
+            ConfigFormat::JSON
+        ),
+        Some(Duration::from_secs(90))
+    );
+    assert_eq!(
+        interval(
+            r#"{"wildcard": false, "update_interval": 30}"#,
+            ConfigFormat::JSON
+        ),
+        Some(Duration::from_secs(30))
+    );
+    assert_eq!(
+        interval(
+            "wildcard: false\nupdate_interval: 500ms",
+            ConfigFormat::YAML
+        ),
+        Some(Duration::from_millis(500))
+    );
+    assert_eq!(
+        interval(
+            "wildcard = false\nupdate_interval = \"2h\"",
+            ConfigFormat::TOML
+        ),
+        Some(Duration::from_secs(7200))
+    );
+    assert_eq!(
+        interval(
+            "wildcard = false\nupdate_interval = \"45\"",
+            ConfigFormat::TOML
+        ),
+        Some(Duration::from_secs(45))
+    );
+    assert_eq!(interval(r#"{"wildcard": false}"#, ConfigFormat::JSON), None);
+    assert!(Launcher::parse_format(
+        r#"{"wildcard": false, "update_interval": "soon"}"#,
+        ConfigFormat::JSON
+    )
+    .is_err());
+    assert!(Launcher::parse_format(
+        r#"{"wildcard": false, "update_interval": -5}"#,
+        ConfigFormat::JSON
+    )
+    .is_err());
+
+    // the HTTP timeouts were bare seconds before, and still may be.
+    let options = Launcher::parse_format(
+        r#"{"wildcard": false, "http_connect_timeout": 5, "http_timeout": "1m"}"#,
+        ConfigFormat::JSON,
+    )
+    .unwrap()
+    .http_options();
+    assert_eq!(options.connect_timeout, Duration::from_secs(5));
+    assert_eq!(options.request_timeout, Duration::from_secs(60));
+
+    assert_eq!(
+        HumanDuration::from_str("1m30s").unwrap(),
+        HumanDuration(Duration::from_secs(90))
+    );
+    assert_eq!(HumanDuration(Duration::from_secs(90)).to_string(), "1m 30s");
+    assert!(HumanDuration::from_str("").is_err());
+}
+
+// mock_server answers each request with the status, extra headers, and body respond returns for
+// its path, returning the URL to reach it at.
+async fn mock_server<F>(respond: F) -> String
+where
+    F: Fn(&str) -> (u16, String, String) + Send + Sync + 'static,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let len = stream.read(&mut buf).await.unwrap_or_default();
+            let head = String::from_utf8_lossy(&buf[..len]);
+            let path = head.split(' ').nth(1).unwrap_or_default();
+
+            let (status, headers, body) = respond(path);
+            let response = format!(
+                "HTTP/1.1 {} X\r\ncontent-type: application/json\r\n{}content-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                headers,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+
+    url
+}
+
+// mock_response serves the same canned response to every request.
+async fn mock_response(status: u16, body: &'static str) -> String {
+    mock_server(move |_| (status, String::new(), body.to_string())).await
+}
+
+#[tokio::test]
+async fn test_derive_domain_from_network() {
+    use crate::utils::derive_domain_from_network;
+    use zerotier_api::central_api::Client;
+
+    for (name, domain) in [
+        ("home", "home.arpa."),
+        ("Corp", "corp.arpa."),
+        ("Home Lab", "home-lab.arpa."),
+        ("  lab\tnet  ", "lab-net.arpa."),
+        ("Bob's Net!", "bobs-net.arpa."),
+    ] {
+        let body = serde_json::json!({ "id": "1234567891011121", "config": { "name": name } });
+        let url = mock_server(move |_| (200, String::new(), body.to_string())).await;
+        assert_eq!(
+            derive_domain_from_network(&Client::new(&url), "1234567891011121")
+                .await
+                .unwrap()
+                .to_string(),
+            domain,
+            "{:?}",
+            name
+        );
+    }
+
+    // a network without a usable name cannot name a domain
+    for body in [
+        r#"{"id": "1234567891011121", "config": {}}"#,
+        r#"{"id": "1234567891011121", "config": {"name": "!!!"}}"#,
+    ] {
+        let url = mock_response(200, body).await;
+        assert_eq!(
+            derive_domain_from_network(&Client::new(&url), "1234567891011121")
+                .await
+                .unwrap_err()
+                .current_context(),
+            &errors::Error::DnsName
+        );
+    }
+
+    let url = mock_response(404, "{}").await;
+    assert_eq!(
+        derive_domain_from_network(&Client::new(&url), "1234567891011121")
+            .await
+            .unwrap_err()
+            .current_context(),
+        &errors::Error::CentralApi { status: Some(404) }
+    );
+
+    let parsed = crate::init::Launcher::parse_format(
+        "auto_domain: true\nwildcard: false\n",
+        crate::init::ConfigFormat::YAML,
+    )
+    .unwrap();
+    assert!(parsed.auto_domain);
+}
+
+#[tokio::test]
+async fn test_validate_token() {
+    use crate::utils::{validate_token, TokenStatus};
+    use zerotier_api::central_api::Client;
+
+    let url = mock_response(
+        200,
+        r#"[{"id": "1234567891011121"}, {"id": "abcdef0123456789"}]"#,
+    )
+    .await;
+    let status = validate_token(&Client::new(&url)).await;
+    assert_eq!(status, TokenStatus::Valid(2));
+    assert_eq!(status.exit_code(), 0);
+
+    let url = mock_response(401, "{}").await;
+    let status = validate_token(&Client::new(&url)).await;
+    assert_eq!(status, TokenStatus::Invalid);
+    assert_eq!(status.exit_code(), 1);
+
+    let url = mock_response(403, "{}").await;
+    let status = validate_token(&Client::new(&url)).await;
+    assert_eq!(status, TokenStatus::Forbidden);
+    assert_eq!(status.exit_code(), 1);
+
+    // nothing is listening here once the listener is dropped
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let status = validate_token(&Client::new(&url)).await;
+    assert!(
+        matches!(status, TokenStatus::Unreachable(_)),
+        "{:?}",
+        status
+    );
+    assert_eq!(status.exit_code(), 2);
+}
+
+#[tokio::test]
+async fn test_get_members_paginated() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+
+    let member = |i: usize| {
+        serde_json::json!({
+            "nodeId": format!("abcdef{:04}", i),
+            "config": { "ipAssignments": [format!("10.0.{}.{}", i / 200, i % 200 + 1)] },
+        })
+    };
+
+    // three pages of 200 members, each linking to the next
+    let url = mock_server(move |path| match path {
+        "/network/1234567891011121" => (
+            200,
+            String::new(),
+            serde_json::json!({ "id": "1234567891011121", "config": {} }).to_string(),
+        ),
+        _ if path.starts_with("/network/1234567891011121/member") => {
+            let page: usize = path
+                .split_once("?page=")
+                .map_or(1, |(_, page)| page.parse().unwrap());
+            let link = if page < 3 {
+                format!(
+                    "link: </network/1234567891011121/member?page={}>; rel=\"next\"\r\n",
+                    page + 1
+                )
+            } else {
+                String::new()
+            };
+            let members: Vec<_> = ((page - 1) * 200..page * 200).map(member).collect();
+            (200, link, serde_json::Value::from(members).to_string())
+        }
+        _ => (404, String::new(), "{}".to_string()),
+    })
+    .await;
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new(&url)).await;
+    let (network, members) = zt.get_members().await.unwrap();
+    assert_eq!(members.len(), 600);
+
+    zt.configure_members(network, members).await.unwrap();
+
+    for i in [0, 199, 200, 399, 400, 599] {
+        let name = format!("zt-abcdef{:04}.home.arpa.", i);
+        let lookup = zt
+            .forward_authority
+            .lookup(
+                &trust_dns_server::client::rr::LowerName::from_str(&name).unwrap(),
+                RecordType::A,
+                LookupOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(lookup.iter().count(), 1, "{}", name);
+    }
+
+    // max_pages stops short of the last page
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new(&url)).await;
+    zt.max_pages = Some(2);
+    let (_, members) = zt.get_members().await.unwrap();
+    assert_eq!(members.len(), 400);
+
+    // a server which links back to a page already fetched doesn't loop forever
+    let url = mock_server(move |path| {
+        (
+            200,
+            "link: </network/1234567891011121/member>; rel=\"next\"\r\n".to_string(),
+            match path {
+                "/network/1234567891011121" => serde_json::json!({ "id": "1234567891011121" }),
+                _ => serde_json::Value::from(vec![member(0)]),
+            }
+            .to_string(),
+        )
+    })
+    .await;
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new(&url)).await;
+    let (_, members) = zt.get_members().await.unwrap();
+    assert_eq!(members.len(), 1);
+}
+
+#[tokio::test]
+async fn test_configure_peers() {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+
+    let gone = Arc::new(AtomicBool::new(false));
+    let g = gone.clone();
+    let url = mock_server(move |_| {
+        let mut peers = vec![
+            // relayed; no latency
+            serde_json::json!({ "address": "abcdef0001", "latency": -1, "paths": [] }),
+            // only an expired path
+            serde_json::json!({
+                "address": "abcdef0002", "latency": 20,
+                "paths": [{ "address": "203.0.113.2/9993", "active": true, "expired": true }],
+            }),
+            // the preferred path wins
+            serde_json::json!({
+                "address": "abcdef0003", "latency": 10,
+                "paths": [
+                    { "address": "203.0.113.30/9993", "active": true, "expired": false },
+                    { "address": "2001:db8::3/9993", "active": true, "expired": false, "preferred": true },
+                ],
+            }),
+        ];
+        if !g.load(Ordering::SeqCst) {
+            peers.push(serde_json::json!({
+                "address": "abcdef0004", "latency": 5,
+                "paths": [{ "address": "203.0.113.4/9993", "active": true, "expired": false }],
+            }));
+        }
+        (200, String::new(), serde_json::Value::from(peers).to_string())
+    })
+    .await;
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new(&url)).await;
+    zt.peer_client = Some(zerotier_api::service_api::Client::new(&url));
+    zt.peer_dns = true;
+
+    let network: zerotier_api::central_api::types::Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+
+    let lookup = |zt: &crate::authority::ZTAuthority, name: &str, rtype| {
+        let authority = zt.forward_authority.clone();
+        let name = trust_dns_server::client::rr::LowerName::from_str(name).unwrap();
+        async move {
+            match authority
+                .lookup(&name, rtype, LookupOptions::default())
+                .await
+            {
+                Ok(lookup) => lookup
+                    .iter()
+                    .filter_map(|r| r.data().and_then(|d| d.to_ip_addr()))
+                    .collect(),
+                Err(_) => Vec::new(),
+            }
+        }
+    };
+
+    zt.configure_peers().await.unwrap();
+    // syncing members must not prune the peers
+    zt.configure_members(network.clone(), Vec::new())
+        .await
+        .unwrap();
+
+    assert!(lookup(&zt, "zt-abcdef0001-peer.home.arpa.", RecordType::A)
+        .await
+        .is_empty());
+    assert!(lookup(&zt, "zt-abcdef0002-peer.home.arpa.", RecordType::A)
+        .await
+        .is_empty());
+    assert_eq!(
+        lookup(&zt, "zt-abcdef0003-peer.home.arpa.", RecordType::AAAA).await,
+        vec![IpAddr::from_str("2001:db8::3").unwrap()]
+    );
+    assert_eq!(
+        lookup(&zt, "zt-abcdef0004-peer.home.arpa.", RecordType::A).await,
+        vec![IpAddr::from_str("203.0.113.4").unwrap()]
+    );
+
+    // peers which go away are pruned on the next sync
+    gone.store(true, Ordering::SeqCst);
+    zt.configure_peers().await.unwrap();
+    zt.configure_members(network, Vec::new()).await.unwrap();
+    assert!(lookup(&zt, "zt-abcdef0004-peer.home.arpa.", RecordType::A)
+        .await
+        .is_empty());
+    assert_eq!(
+        lookup(&zt, "zt-abcdef0003-peer.home.arpa.", RecordType::AAAA).await,
+        vec![IpAddr::from_str("2001:db8::3").unwrap()]
+    );
+}
+
+#[test]
+fn test_soa_serial_policy() {
+    use crate::serial::{serial_gt, SoaSerialPolicy, MAX_SERIAL_INCREMENT};
+
+    let increment = SoaSerialPolicy::Increment;
+    assert_eq!(increment.next_serial(1, 1, 0), 2);
+    assert_eq!(increment.next_serial(1, 10, 0), 11);
+    // an increment of 0 would never move; too large would compare as going backwards
+    assert_eq!(increment.next_serial(1, 0, 0), 2);
+    assert_eq!(
+        increment.next_serial(1, u32::MAX, 0),
+        1 + MAX_SERIAL_INCREMENT
+    );
+    // wrapping is modular
+    assert_eq!(increment.next_serial(u32::MAX, 1, 0), 0);
+    assert_eq!(increment.next_serial(u32::MAX - 1, 5, 0), 3);
+
+    let timestamp = SoaSerialPolicy::Timestamp;
+    assert_eq!(timestamp.next_serial(1, 1, 1_700_000_000), 1_700_000_000);
+    // changes within the same second still move the serial
+    assert_eq!(
+        timestamp.next_serial(1_700_000_000, 1, 1_700_000_000),
+        1_700_000_001
+    );
+    // as does a clock that went backwards
+    assert_eq!(
+        timestamp.next_serial(1_700_000_000, 1, 1_600_000_000),
+        1_700_000_001
+    );
+    // past 2106 the timestamp wraps, and still compares as greater
+    let after_2106 = (1u64 << 32) + 100;
+    assert_eq!(timestamp.next_serial(u32::MAX - 10, 1, after_2106), 100);
+
+    for (a, b) in [(2, 1), (0, u32::MAX), (100, u32::MAX - 10), (1 << 30, 0)] {
+        assert!(serial_gt(a, b), "{} > {}", a, b);
+        assert!(!serial_gt(b, a), "{} > {}", b, a);
+    }
+    assert!(!serial_gt(1, 1));
+
+    assert_eq!(
+        SoaSerialPolicy::from_str("timestamp").unwrap(),
+        SoaSerialPolicy::Timestamp
+    );
+    assert!(SoaSerialPolicy::from_str("bogus").is_err());
+}
+
+#[tokio::test]
+async fn test_soa_serial_bumps() {
+    use crate::{authority::RecordAuthority, serial::SoaSerialPolicy};
+    use trust_dns_resolver::{proto::rr::RData, Name};
+    use trust_dns_server::client::rr::LowerName;
+
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap()
+    .with_serial_policy(SoaSerialPolicy::Increment, 5);
+
+    assert_eq!(authority.serial().await, 1);
+
+    let batch = vec![(
+        Name::from_str("islay.home.arpa.").unwrap(),
+        vec![RData::A("10.0.0.1".parse().unwrap())],
+    )];
+
+    authority.upsert_batch(batch.clone()).await;
+    assert_eq!(authority.serial().await, 6);
+
+    // nothing changed, so neither does the serial
+    authority.upsert_batch(batch).await;
+    assert_eq!(authority.serial().await, 6);
+}
+
+#[tokio::test]
+async fn test_sync_gives_up_on_rejected_token() {
+    use crate::authority::find_members;
+    use zerotier_api::central_api::Client;
+
+    let url = mock_response(401, "{}").await;
+    let zt = zt_authority(Client::new(&url)).await;
+
+    let err = zt.get_members().await.unwrap_err();
+    assert_eq!(
+        err.current_context(),
+        &errors::Error::CentralApi { status: Some(401) }
+    );
+    assert!(!err.current_context().is_retryable());
+
+    // the sync loop stops instead of retrying forever
+    let err = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        find_members(std::sync::Arc::new(zt)),
+    )
+    .await
+    .unwrap()
+    .unwrap_err();
+    assert!(!err.current_context().is_retryable());
+
+    let url = mock_response(500, "{}").await;
+    let zt = zt_authority(Client::new(&url)).await;
+    let err = zt.get_members().await.unwrap_err();
+    assert_eq!(
+        err.current_context(),
+        &errors::Error::CentralApi { status: Some(500) }
+    );
+    assert!(err.current_context().is_retryable());
+}
+
+#[test]
+fn test_read_errors_name_path() {
+    use crate::init::{ConfigFormat, Launcher};
+    use crate::utils::{central_token, read_file};
+
+    let dir = tempdir();
+    let missing = dir.path().join("token");
+
+    let err = central_token(Some(&missing)).unwrap_err();
+    assert_eq!(
+        err.current_context(),
+        &errors::Error::Io {
+            path: missing.clone()
+        }
+    );
+    let report = format!("{:?}", err);
+    assert!(
+        report.contains(&missing.display().to_string()),
+        "{}",
+        report
+    );
+    assert!(report.contains("token file"), "{}", report);
+    assert!(report.contains("not found"), "{}", report);
+
+    let err = read_file(&missing, "TLS key (tls_key)").unwrap_err();
+    let report = format!("{:?}", err);
+    assert!(
+        report.contains(&missing.display().to_string()),
+        "{}",
+        report
+    );
+    assert!(report.contains("tls_key"), "{}", report);
+
+    let err = Launcher::new_from_config(missing.to_str().unwrap(), ConfigFormat::YAML).unwrap_err();
+    let report = format!("{:?}", err);
+    assert!(
+        report.contains(&missing.display().to_string()),
+        "{}",
+        report
+    );
+    assert!(report.contains("config file"), "{}", report);
+}
+
+#[cfg(feature = "etcd-backend")]
+#[test]
+fn test_etcd_records() {
+    use crate::backends::etcd::{decode_records, encode_records, EtcdRecordAuthority};
+    use trust_dns_resolver::proto::rr::{RData, Record, RecordType};
+    use trust_dns_server::client::rr::{LowerName, Name};
+
+    let name = Name::from_str("islay.home.arpa.").unwrap();
+    assert_eq!(
+        EtcdRecordAuthority::record_key(
+            "1234567891011121",
+            &LowerName::from(name.clone()),
+            RecordType::AAAA
+        ),
+        "/zeronsd/1234567891011121/records/islay.home.arpa./AAAA"
+    );
+
+    let records = vec![
+        Record::from_rdata(name.clone(), 60, RData::A("10.0.0.1".parse().unwrap())),
+        Record::from_rdata(name.clone(), 60, RData::A("10.0.0.2".parse().unwrap())),
+    ];
+    let decoded = decode_records(&encode_records(&records).unwrap()).unwrap();
+    assert_eq!(decoded, records);
+}
+
+#[tokio::test]
+async fn test_bulk_delete() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_resolver::{
+        proto::rr::{RData, RecordType},
+        Name,
+    };
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::{LowerName, RrKey},
+    };
+
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let names: Vec<Name> = (1..=3)
+        .map(|i| Name::from_str(&format!("host{}.home.arpa.", i)).unwrap())
+        .collect();
+    authority
+        .upsert_batch(
+            names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    (
+                        name.clone(),
+                        vec![RData::A(format!("10.0.0.{}", i + 1).parse().unwrap())],
+                    )
+                })
+                .collect(),
+        )
+        .await;
+    let serial = authority.serial().await;
+
+    authority
+        .bulk_delete(
+            names[..2]
+                .iter()
+                .map(|name| RrKey::new(name.into(), RecordType::A))
+                .collect(),
+        )
+        .await;
+
+    for (name, present) in names.iter().zip([false, false, true]) {
+        let found = authority
+            .lookup(&name.into(), RecordType::A, LookupOptions::default())
+            .await
+            .is_ok();
+        assert_eq!(found, present, "{}", name);
+    }
+    assert!(authority.serial().await > serial);
+
+    // removing what is already gone changes nothing
+    let serial = authority.serial().await;
+    authority
+        .bulk_delete(vec![RrKey::new((&names[0]).into(), RecordType::A)])
+        .await;
+    assert_eq!(authority.serial().await, serial);
+}
+
+#[tokio::test]
+async fn test_watch_hosts() {
+    use crate::authority::watch_hosts;
+    use std::time::{Duration, Instant};
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.0.0.1 islay\n").unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.hosts_files = vec![hosts_file.clone()];
+    // the sync loop is not running, so only the watcher can pick changes up
+    let authority = zt.forward_authority.clone();
+    tokio::spawn(watch_hosts(std::sync::Arc::new(zt)));
+
+    let found = |name: &'static str| {
+        let authority = authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+    let wait_for = |name: &'static str, present: bool| async move {
+        let start = Instant::now();
+        while found(name).await != present {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "{} present: {}",
+                name,
+                !present
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+
+    wait_for("islay.home.arpa.", true).await;
+
+    // editors commonly save by writing a new file and renaming it over the old one
+    let saved = dir.path().join("hosts.swp");
+    std::fs::write(&saved, "10.0.0.2 jura\n").unwrap();
+    std::fs::rename(&saved, &hosts_file).unwrap();
+    wait_for("jura.home.arpa.", true).await;
+    wait_for("islay.home.arpa.", false).await;
+
+    // and the replaced file is still watched
+    std::fs::write(&hosts_file, "10.0.0.2 jura\n10.0.0.3 skye\n").unwrap();
+    wait_for("skye.home.arpa.", true).await;
+}
+
+#[tokio::test]
+async fn test_find_members_cancel() {
+    use crate::authority::find_members;
+    use std::time::Duration;
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.client = None;
+
+    // waiting out a long interval, the loop still stops as soon as it is told to
+    let sync = tokio::spawn(find_members(std::sync::Arc::new(zt.clone())));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!sync.is_finished());
+    zt.cancel.cancel();
+    tokio::time::timeout(Duration::from_secs(1), sync)
+        .await
+        .expect("find_members kept running after being cancelled")
+        .unwrap()
+        .unwrap();
+
+    // and within one update interval of a short one
+    let interval = Duration::from_millis(200);
+    let zt = crate::authority::ZTAuthority {
+        cancel: Default::default(),
+        hosts_stamp: Default::default(),
+        hosts_parses: Default::default(),
+        update_interval: std::sync::Arc::new(tokio::sync::RwLock::new(interval)),
+        ..zt
+    };
+    let sync = tokio::spawn(find_members(std::sync::Arc::new(zt.clone())));
+    tokio::time::sleep(interval * 2).await;
+    zt.cancel.cancel();
+    tokio::time::timeout(interval, sync)
+        .await
+        .expect("find_members kept running after being cancelled")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_shared_hosts() {
+    use crate::authority::find_members;
+    use std::time::{Duration, Instant};
+    use trust_dns_resolver::Name;
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.0.0.1 islay\n").unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.client = None;
+    zt.hosts_files = vec![hosts_file.clone()];
+    *zt.update_interval.write().await = Duration::from_millis(50);
+
+    // the copy the server answers from, taken before the loop has read anything
+    let server = zt.clone();
+    assert!(server.hosts.read().await.is_none());
+    tokio::spawn(find_members(std::sync::Arc::new(zt)));
+
+    let has = |name: &'static str| {
+        let server = server.clone();
+        async move {
+            let name = Name::from_str(name).unwrap();
+            server
+                .hosts
+                .read()
+                .await
+                .as_ref()
+                .map(|hosts| hosts.values().flatten().any(|n| *n == name))
+                .unwrap_or(false)
+        }
+    };
+    let wait_for = |name: &'static str, present: bool| async move {
+        let start = Instant::now();
+        while has(name).await != present {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "{} present: {}",
+                name,
+                !present
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    };
+
+    wait_for("islay.home.arpa.", true).await;
+
+    std::fs::write(&hosts_file, "10.0.0.2 jura\n").unwrap();
+    wait_for("jura.home.arpa.", true).await;
+    wait_for("islay.home.arpa.", false).await;
+}
+
+#[tokio::test]
+async fn test_prune_by_record_type() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members = |ips: serde_json::Value| -> Vec<Member> {
+        serde_json::from_value(serde_json::json!([
+            { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ips } },
+        ]))
+        .unwrap()
+    };
+
+    let zt = zt_authority(
+        MockCentral::new(serde_json::json!({}), false)
+            .await
+            .client(),
+    )
+    .await;
+    let found = |rtype| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &trust_dns_server::client::rr::LowerName::from_str("islay.home.arpa.").unwrap(),
+                    rtype,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+
+    zt.configure_members(network.clone(), members(serde_json::json!(["10.0.0.1"])))
+        .await
+        .unwrap();
+    assert!(found(RecordType::A).await);
+    assert!(!found(RecordType::AAAA).await);
+
+    zt.configure_members(
+        network.clone(),
+        members(serde_json::json!(["10.0.0.1", "fd00::1"])),
+    )
+    .await
+    .unwrap();
+    assert!(found(RecordType::A).await);
+    assert!(found(RecordType::AAAA).await);
+
+    // losing the IPv4 assignment takes only the A record with it
+    zt.configure_members(network, members(serde_json::json!(["fd00::1"])))
+        .await
+        .unwrap();
+    assert!(!found(RecordType::A).await);
+    assert!(found(RecordType::AAAA).await);
+}
+
+#[test]
+fn test_parse_hosts_files() {
+    use crate::hosts::{hosts_file_list, parse_hosts_files};
+    use crate::init::{ConfigFormat, Launcher};
+    use trust_dns_server::client::rr::Name;
+
+    let dir = tempdir();
+    let hosts_d = dir.path().join("hosts.d");
+    std::fs::create_dir_all(&hosts_d).unwrap();
+    std::fs::write(
+        hosts_d.join("10-base.hosts"),
+        "10.0.0.1 islay jura\n10.0.0.2 arran\nfd00::1 islay\n",
+    )
+    .unwrap();
+    std::fs::write(hosts_d.join("20-team.hosts"), "10.0.0.3 jura\n").unwrap();
+    // not a *.hosts file, so not read
+    std::fs::write(hosts_d.join("30-notes.txt"), "10.0.0.9 skye\n").unwrap();
+    let extra = dir.path().join("extra");
+    std::fs::write(&extra, "10.0.0.4 arran\n").unwrap();
+
+    let paths = vec![hosts_d.clone(), extra.clone()];
+    assert_eq!(
+        hosts_file_list(&paths).unwrap(),
+        vec![
+            hosts_d.join("10-base.hosts"),
+            hosts_d.join("20-team.hosts"),
+            extra.clone()
+        ]
+    );
+
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let (hosts, cnames) = parse_hosts_files(&paths, domain.clone(), false, false).unwrap();
+    assert!(cnames.is_empty());
+    let ips = |name: &str| -> Vec<IpAddr> {
+        let name = name.to_fqdn(domain.clone()).unwrap();
+        let mut ips: Vec<IpAddr> = hosts
+            .iter()
+            .filter(|(_, names)| names.contains(&name))
+            .map(|(ip, _)| *ip)
+            .collect();
+        ips.sort();
+        ips
+    };
+
+    // untouched names keep all their addresses
+    assert_eq!(
+        ips("islay"),
+        vec![
+            IpAddr::from_str("10.0.0.1").unwrap(),
+            IpAddr::from_str("fd00::1").unwrap()
+        ]
+    );
+    // later files replace the addresses of names earlier files gave
+    assert_eq!(ips("jura"), vec![IpAddr::from_str("10.0.0.3").unwrap()]);
+    assert_eq!(ips("arran"), vec![IpAddr::from_str("10.0.0.4").unwrap()]);
+    assert!(ips("skye").is_empty());
+    assert!(!hosts.contains_key(&IpAddr::from_str("10.0.0.2").unwrap()));
+
+    // a single path still reads as before
+    let launcher =
+        Launcher::parse_format("wildcard: false\nhosts: /etc/hosts", ConfigFormat::YAML).unwrap();
+    assert_eq!(launcher.hosts, vec![PathBuf::from("/etc/hosts")]);
+    let launcher = Launcher::parse_format(
+        "wildcard: false\nhosts: [/etc/hosts, /etc/hosts.d]",
+        ConfigFormat::YAML,
+    )
+    .unwrap();
+    assert_eq!(
+        launcher.hosts,
+        vec![PathBuf::from("/etc/hosts"), PathBuf::from("/etc/hosts.d")]
+    );
+    let launcher = Launcher::parse_format("wildcard: false", ConfigFormat::YAML).unwrap();
+    assert!(launcher.hosts.is_empty());
+}
+
+#[tokio::test]
+async fn test_network_info_summary() {
+    use crate::info::{format_timestamp, get_network_info};
+    use zerotier_api::central_api::Client;
+
+    let url = mock_server(|path| {
+        let body = if path.ends_with("/member") {
+            r#"[
+                {"nodeId": "aaaaaaaaaa", "config": {"authorized": true}},
+                {"nodeId": "bbbbbbbbbb", "config": {"authorized": true}},
+                {"nodeId": "cccccccccc", "config": {"authorized": false}}
+            ]"#
+        } else {
+            r#"{
+                "id": "1234567890abcdef",
+                "config": {
+                    "name": "office",
+                    "creationTime": 1650000000000,
+                    "dns": {"domain": "office.example", "servers": ["10.0.0.1"]},
+                    "v4AssignMode": {"zt": true},
+                    "v6AssignMode": {"zt": false, "rfc4193": true, "6plane": true}
+                }
+            }"#
+        };
+        (200, String::new(), body.to_string())
+    })
+    .await;
+
+    let info = get_network_info(&Client::new(&url), "1234567890abcdef")
+        .await
+        .unwrap();
+    assert_eq!(info.name.as_deref(), Some("office"));
+    assert_eq!(info.dns_domain.as_deref(), Some("office.example"));
+    assert_eq!(info.dns_servers, vec!["10.0.0.1".to_string()]);
+    assert_eq!(
+        (info.members, info.authorized, info.unauthorized),
+        (3, 2, 1)
+    );
+    assert_eq!(info.v4_assign_mode, vec!["zt"]);
+    assert_eq!(info.v6_assign_mode, vec!["rfc4193", "6plane"]);
+    assert_eq!(info.update_interval, None);
+
+    let interval = std::time::Duration::from_secs(30);
+    let elsewhere = info
+        .clone()
+        .managed_by(&["10.0.0.2".parse().unwrap()], interval);
+    assert_eq!(elsewhere.update_interval, None);
+    let here = info.managed_by(&["10.0.0.1".parse().unwrap()], interval);
+    assert_eq!(here.update_interval, Some(30));
+
+    let table = here.to_string();
+    assert!(
+        table.contains("Created          2022-04-15 05:20:00 UTC"),
+        "{}",
+        table
+    );
+    assert!(table.contains("Update interval  30s"), "{}", table);
+
+    let json: serde_json::Value = serde_json::to_value(&here).unwrap();
+    assert_eq!(json["authorized"], 2);
+    assert_eq!(json["update_interval"], 30);
+
+    assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+
+    let url = mock_response(404, "{}").await;
+    let err = get_network_info(&Client::new(&url), "1234567890abcdef")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.current_context(),
+        &errors::Error::CentralApi { status: Some(404) }
+    );
+}
+
+#[test]
+fn test_address_plan() {
+    use crate::addresses::AddressPlan;
+
+    // golden values from ZeroTier's documented layouts: 6PLANE folds the network ID to 32 bits
+    // after fc, RFC4193 keeps it whole after fd and before 9993.
+    let plan = AddressPlan::new("8056c2e21c000001", None).unwrap();
+    assert_eq!(plan.sixplane_prefix, "fc9c:56c2:e300::/40");
+    assert_eq!(plan.rfc4193_prefix, "fd80:56c2:e21c:0:199:9300::/88");
+    assert_eq!(plan.sixplane, None);
+    assert_eq!(plan.rfc4193, None);
+    assert_eq!(
+        plan.to_string(),
+        "Network         8056c2e21c000001\n\
+         6PLANE prefix   fc9c:56c2:e300::/40\n\
+         RFC4193 prefix  fd80:56c2:e21c:0:199:9300::/88\n"
+    );
+
+    let plan = AddressPlan::new("8056C2E21C000001", Some("EFCC1B0947")).unwrap();
+    assert_eq!(plan.network_id, "8056c2e21c000001");
+    assert_eq!(plan.node_id.as_deref(), Some("efcc1b0947"));
+    assert_eq!(
+        plan.sixplane.as_deref(),
+        Some("fc9c:56c2:e3ef:cc1b:947::1/80")
+    );
+    assert_eq!(
+        plan.rfc4193.as_deref(),
+        Some("fd80:56c2:e21c:0:199:93ef:cc1b:947")
+    );
+    assert_eq!(
+        plan.to_string(),
+        "Network          8056c2e21c000001\n\
+         6PLANE prefix    fc9c:56c2:e300::/40\n\
+         RFC4193 prefix   fd80:56c2:e21c:0:199:9300::/88\n\
+         Node             efcc1b0947\n\
+         6PLANE address   fc9c:56c2:e3ef:cc1b:947::1/80\n\
+         RFC4193 address  fd80:56c2:e21c:0:199:93ef:cc1b:947\n"
+    );
+
+    let json = serde_json::to_value(&plan).unwrap();
+    assert_eq!(json["rfc4193"], "fd80:56c2:e21c:0:199:93ef:cc1b:947");
+    assert_eq!(json["sixplane_prefix"], "fc9c:56c2:e300::/40");
+
+    for (network_id, node_id) in [
+        ("8056c2e21c00001", None),
+        ("8056c2e21c0000011", None),
+        ("8056c2e21c00000g", None),
+        ("8056c2e21c000001", Some("efcc1b094")),
+        ("8056c2e21c000001", Some("efcc1b094z")),
+    ] {
+        let err = AddressPlan::new(network_id, node_id).unwrap_err();
+        assert_eq!(err.current_context(), &errors::Error::Address);
+    }
+}
+
+#[test]
+fn test_parse_hosts_cnames() {
+    use crate::hosts::{parse_hosts_entries, parse_hosts_files};
+    use trust_dns_server::client::rr::Name;
+
+    let domain = Name::from_str("zombocom").unwrap();
+    let fqdn = |name: &str| name.to_fqdn(domain.clone()).unwrap();
+
+    let (hosts, cnames) = parse_hosts_entries(
+        &PathBuf::from(format!("{}/cnames", crate::utils::TEST_HOSTS_DIR)),
+        domain.clone(),
+        false,
+    )
+    .unwrap();
+
+    // addresses are read as before, and directives do not leak into them
+    assert_eq!(hosts.len(), 4);
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.5").unwrap()),
+        Some(&vec![fqdn("jura")])
+    );
+
+    assert_eq!(
+        cnames.into_iter().collect::<Vec<_>>(),
+        vec![
+            // targets ending in a dot are outside the zone
+            (fqdn("docs"), Name::from_str("docs.example.com.").unwrap()),
+            (fqdn("mail"), fqdn("ghost")),
+            (fqdn("wiki"), fqdn("jura")),
+            (fqdn("www"), fqdn("islay")),
+        ]
+    );
+
+    // a later file may turn a name with addresses into an alias, and back
+    let dir = tempdir();
+    std::fs::write(
+        dir.path().join("10-base.hosts"),
+        "10.0.0.1 islay www\ncname jura islay\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("20-team.hosts"),
+        "cname www islay\n10.0.0.2 jura\n",
+    )
+    .unwrap();
+
+    let (hosts, cnames) =
+        parse_hosts_files(&[dir.path().to_path_buf()], domain.clone(), false, false).unwrap();
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.1").unwrap()),
+        Some(&vec![fqdn("islay")])
+    );
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.2").unwrap()),
+        Some(&vec![fqdn("jura")])
+    );
+    assert_eq!(
+        cnames.into_iter().collect::<Vec<_>>(),
+        vec![(fqdn("www"), fqdn("islay"))]
+    );
+}
+
+#[tokio::test]
+async fn test_configure_cnames() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::{LowerName, Name},
+    };
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(
+        &hosts_file,
+        "10.0.0.1 islay\ncname www islay\ncname mail ghost\ncname docs docs.example.com.\n",
+    )
+    .unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.hosts_files = vec![hosts_file.clone()];
+    zt.configure_hosts().await.unwrap();
+
+    let authority = zt.forward_authority.clone();
+    let has_cname = |name: &'static str| {
+        let authority = authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::CNAME,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+
+    assert!(has_cname("www.home.arpa.").await);
+    assert!(has_cname("docs.home.arpa.").await);
+    // only the in-zone target without records is reported
+    assert_eq!(
+        zt.dangling_cnames().await,
+        vec![(
+            Name::from_str("mail.home.arpa.").unwrap(),
+            Name::from_str("ghost.home.arpa.").unwrap()
+        )]
+    );
+
+    // the sync with central keeps the aliases
+    let network = serde_json::from_value(
+        serde_json::json!({ "id": "1234567891011121", "config": { "name": "islay" } }),
+    )
+    .unwrap();
+    zt.configure_members(network, Vec::new()).await.unwrap();
+    assert!(has_cname("www.home.arpa.").await);
+    assert!(has_cname("mail.home.arpa.").await);
+
+    // and a reload removes the ones dropped from the file
+    std::fs::write(&hosts_file, "10.0.0.1 islay\ncname www islay\n").unwrap();
+    zt.reload_hosts().await.unwrap();
+    assert!(has_cname("www.home.arpa.").await);
+    assert!(!has_cname("mail.home.arpa.").await);
+    assert!(!has_cname("docs.home.arpa.").await);
+    assert!(zt.dangling_cnames().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_max_members() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members = |names: &[&str]| -> Vec<Member> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                serde_json::from_value(serde_json::json!({
+                    "nodeId": format!("abcdef000{}", i),
+                    "name": name,
+                    "config": { "ipAssignments": [format!("10.0.0.{}", i + 1)] },
+                }))
+                .unwrap()
+            })
+            .collect()
+    };
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.max_members = Some(1);
+    let found = |name: &'static str| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+
+    zt.configure_members(network.clone(), members(&["islay"]))
+        .await
+        .unwrap();
+    assert!(found("islay.home.arpa.").await);
+
+    // too many members leaves the records as they were
+    zt.configure_members(network, members(&["jura", "arran"]))
+        .await
+        .unwrap();
+    assert!(found("islay.home.arpa.").await);
+    assert!(!found("jura.home.arpa.").await);
+    assert!(!found("arran.home.arpa.").await);
+
+    // the member count is exported either way
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(crate::metrics::serve_metrics(listener, zt.clone()));
+
+    let response = reqwest::get(format!("{}/metrics", url)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("# TYPE zeronsd_members gauge"), "{}", body);
+
+    let response = reqwest::get(format!("{}/other", url)).await.unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn test_hosts_wildcards() {
+    use crate::hosts::parse_hosts_entries;
+    use trust_dns_resolver::proto::rr::{RData, RecordType};
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::{LowerName, Name},
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(
+        &hosts_file,
+        "10.0.0.100 *.lab\n10.0.0.101 *\n10.0.0.102 foo.*.lab\n",
+    )
+    .unwrap();
+
+    // misplaced wildcards are skipped
+    let (hosts, _) =
+        parse_hosts_entries(&hosts_file, Name::from_str("home.arpa.").unwrap(), false).unwrap();
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.100").unwrap()),
+        Some(&vec![Name::from_str("*.lab.home.arpa.").unwrap()])
+    );
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.101").unwrap()),
+        Some(&Vec::new())
+    );
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.102").unwrap()),
+        Some(&Vec::new())
+    );
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "bar.lab", "config": { "ipAssignments": ["10.0.0.1"] } },
+    ]))
+    .unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.hosts_files = vec![hosts_file.clone()];
+    zt.configure_hosts().await.unwrap();
+    zt.configure_members(network, members).await.unwrap();
+
+    let authority = zt.forward_authority.clone();
+    let address = |name: &'static str| {
+        let authority = authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .ok()
+                .and_then(|lookup| lookup.iter().next().and_then(|r| r.data().cloned()))
+        }
+    };
+
+    // names under the wildcard resolve through it, while the member keeps its own address
+    assert_eq!(
+        address("foo.lab.home.arpa.").await,
+        Some(RData::A("10.0.0.100".parse().unwrap()))
+    );
+    assert_eq!(
+        address("bar.lab.home.arpa.").await,
+        Some(RData::A("10.0.0.1".parse().unwrap()))
+    );
+
+    // deleting the line takes the wildcard with it
+    std::fs::write(&hosts_file, "").unwrap();
+    zt.reload_hosts().await.unwrap();
+    assert_eq!(address("foo.lab.home.arpa.").await, None);
+    assert_eq!(
+        address("bar.lab.home.arpa.").await,
+        Some(RData::A("10.0.0.1".parse().unwrap()))
+    );
+}
+
+#[tokio::test]
+async fn test_ipv4_only() {
+    use crate::addresses::ipv6_to_ipv4_synthetic;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use trust_dns_resolver::proto::rr::{RData, RecordType};
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    assert_eq!(
+        ipv6_to_ipv4_synthetic(Ipv6Addr::from_str("64:ff9b::c000:221").unwrap()),
+        Ipv4Addr::new(192, 0, 2, 33)
+    );
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["fd00::a00:5"] } },
+        { "nodeId": "abcdef0002", "name": "jura", "config": { "ipAssignments": ["10.0.0.2", "fd00::a00:6"] } },
+    ]))
+    .unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    let lookup = |name: &'static str, rtype| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    rtype,
+                    LookupOptions::default(),
+                )
+                .await
+                .map(|lookup| {
+                    lookup
+                        .iter()
+                        .filter_map(|r| r.data().cloned())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        }
+    };
+
+    zt.configure_members(network.clone(), members.clone())
+        .await
+        .unwrap();
+    assert!(lookup("islay.home.arpa.", RecordType::A).await.is_empty());
+
+    zt.ipv4_only = true;
+    zt.configure_members(network, members).await.unwrap();
+    assert_eq!(
+        lookup("islay.home.arpa.", RecordType::A).await,
+        vec![RData::A(Ipv4Addr::new(10, 0, 0, 5))]
+    );
+    assert_eq!(
+        lookup("islay.home.arpa.", RecordType::AAAA).await,
+        vec![RData::AAAA(Ipv6Addr::from_str("fd00::a00:5").unwrap())]
+    );
+    // members with an IPv4 address of their own are left alone
+    assert_eq!(
+        lookup("jura.home.arpa.", RecordType::A).await,
+        vec![RData::A(Ipv4Addr::new(10, 0, 0, 2))]
+    );
+}
+
+#[test]
+fn test_check_hosts() {
+    use crate::hosts::{check_hosts_file, check_hosts_files, parse_hosts_files, Reason};
+    use trust_dns_resolver::Name;
+
+    let domain = Name::from_str("zombocom").unwrap();
+    let expected = |file: &str| -> Vec<(usize, Reason)> {
+        match file {
+            "cnames" => vec![
+                (11, Reason::BadDirective),
+                (12, Reason::BadDirective),
+                (13, Reason::BadDirective),
+                (14, Reason::BadDirective),
+            ],
+            "invalid" => vec![
+                (4, Reason::BadAddress),
+                (5, Reason::BadHostname),
+                (6, Reason::Duplicate),
+                (7, Reason::BadHostname),
+            ],
+            // names without an address on their line, unless lenient
+            "ip-per-host" => vec![
+                (6, Reason::BadAddress),
+                (8, Reason::BadAddress),
+                (11, Reason::BadAddress),
+                (13, Reason::BadAddress),
+            ],
+            _ => Vec::new(),
+        }
+    };
+
+    for path in std::fs::read_dir(crate::utils::TEST_HOSTS_DIR)
+        .unwrap()
+        .map(|p| p.unwrap().path())
+        .filter(|p| p.is_file())
+    {
+        let (_, _, diagnostics) = check_hosts_file(&path, domain.clone(), false).unwrap();
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.line, d.reason))
+                .collect::<Vec<_>>(),
+            expected(path.file_name().unwrap().to_str().unwrap()),
+            "{}: {:#?}",
+            path.display(),
+            diagnostics
+        );
+    }
+
+    let invalid = PathBuf::from(format!("{}/invalid", crate::utils::TEST_HOSTS_DIR));
+    let diagnostics =
+        check_hosts_files(std::slice::from_ref(&invalid), domain.clone(), false).unwrap();
+    assert_eq!(diagnostics[0].content, "10.0.0.300\ttoobig");
+    assert!(
+        diagnostics[1]
+            .to_string()
+            .contains("invalid:5: bad hostname: bad."),
+        "{}",
+        diagnostics[1]
+    );
+    assert!(!diagnostics[2].is_error());
+
+    // the rest of a bad file is still used, unless strict
+    let (hosts, _) =
+        parse_hosts_files(std::slice::from_ref(&invalid), domain.clone(), false, false).unwrap();
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.7").unwrap()),
+        Some(&vec![
+            "also-ok".to_fqdn(domain.clone()).unwrap(),
+            "ok".to_fqdn(domain.clone()).unwrap()
+        ])
+    );
+    let err = parse_hosts_files(&[invalid], domain.clone(), true, false).unwrap_err();
+    assert_eq!(err.current_context(), &errors::Error::Config);
+    assert!(format!("{:?}", err).contains("invalid:4: bad address"));
+
+    // duplicates alone do not fail a strict parse
+    let dir = tempdir();
+    let duplicate = dir.path().join("hosts");
+    std::fs::write(&duplicate, "10.0.0.1 islay\n10.0.0.1 islay\n").unwrap();
+    assert!(parse_hosts_files(std::slice::from_ref(&duplicate), domain, true, false).is_ok());
+}
+
+#[tokio::test]
+async fn test_hosts_precedence() {
+    use crate::hosts::HostsPrecedence;
+    use trust_dns_resolver::proto::rr::{RData, RecordType};
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+        { "nodeId": "abcdef0002", "name": "jura", "config": { "ipAssignments": ["10.0.0.2"] } },
+    ]))
+    .unwrap();
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.0.0.100 islay\n10.0.0.101 arran\n").unwrap();
+
+    for (precedence, expected) in [
+        (HostsPrecedence::HostsWins, vec!["10.0.0.100"]),
+        (HostsPrecedence::MemberWins, vec!["10.0.0.1"]),
+        (HostsPrecedence::Merge, vec!["10.0.0.1", "10.0.0.100"]),
+    ] {
+        let mut zt =
+            zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+        zt.hosts_files = vec![hosts_file.clone()];
+        zt.hosts_precedence = precedence;
+
+        let authority = zt.forward_authority.clone();
+        let addresses = |name: &'static str| {
+            let authority = authority.clone();
+            async move {
+                let mut ips: Vec<String> = authority
+                    .lookup(
+                        &LowerName::from_str(name).unwrap(),
+                        RecordType::A,
+                        LookupOptions::default(),
+                    )
+                    .await
+                    .map(|lookup| {
+                        lookup
+                            .iter()
+                            .filter_map(|r| match r.data() {
+                                Some(RData::A(ip)) => Some(ip.to_string()),
+                                _ => None,
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                ips.sort();
+                ips
+            }
+        };
+
+        let mut serials = Vec::new();
+        for cycle in 0..4 {
+            // the same order find_members syncs in
+            zt.configure_hosts().await.unwrap();
+            zt.configure_members(network.clone(), members.clone())
+                .await
+                .unwrap();
+
+            assert_eq!(
+                addresses("islay.home.arpa.").await,
+                expected,
+                "{:?} cycle {}",
+                precedence,
+                cycle
+            );
+            // names only one side gives are unaffected
+            assert_eq!(addresses("jura.home.arpa.").await, vec!["10.0.0.2"]);
+            assert_eq!(addresses("arran.home.arpa.").await, vec!["10.0.0.101"]);
+            serials.push(authority.serial().await);
+        }
+
+        // once settled, nothing changes from one sync to the next
+        assert!(
+            serials[1..].iter().all(|s| *s == serials[1]),
+            "{:?}: {:?}",
+            precedence,
+            serials
+        );
+        assert_eq!(
+            zt.collisions.read().await.keys().collect::<Vec<_>>(),
+            vec![&trust_dns_server::client::rr::Name::from_str("islay.home.arpa.").unwrap()]
+        );
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_bind_control() {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let dir = tempdir();
+    let path = dir.path().join("control.sock");
+
+    // a socket left behind is replaced, and the new one is the user's alone.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    drop(crate::control::bind_control(&path).unwrap());
+    let metadata = std::fs::symlink_metadata(&path).unwrap();
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+    // anything else at the path is kept.
+    let file = dir.path().join("control.txt");
+    std::fs::write(&file, "keep me").unwrap();
+    assert!(crate::control::bind_control(&file).is_err());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep me");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_control_set_interval() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let dir = tempdir();
+    let path = dir.path().join("control.sock");
+
+    let zt = zt_authority(
+        MockCentral::new(serde_json::json!({}), false)
+            .await
+            .client(),
+    )
+    .await;
+
+    let listener = crate::control::bind_control(&path).unwrap();
+    tokio::spawn(crate::control::serve_control(listener, zt.clone()));
+
+    let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    for (command, response) in [
+        (r#"{"cmd":"set_interval","secs":60}"#, r#"{"ok":true}"#),
+        (
+            r#"{"cmd":"set_interval","secs":0}"#,
+            r#"{"ok":false,"error":"interval must be at least one second"}"#,
+        ),
+        // not yet synced, so central hasn't told us the name.
+        (
+            r#"{"cmd":"network"}"#,
+            r#"{"ok":true,"data":{"id":"1234567891011121","name":null}}"#,
+        ),
+    ] {
+        write
+            .write_all(format!("{}\n", command).as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), response);
+    }
+
+    write.write_all(b"{\"cmd\":\"reboot\"}\n").await.unwrap();
+    assert!(lines
+        .next_line()
+        .await
+        .unwrap()
+        .unwrap()
+        .starts_with(r#"{"ok":false,"error":"invalid command"#));
+
+    // the clone handed to the control socket shares the interval with the sync loop
+    assert_eq!(
+        *zt.update_interval.read().await,
+        std::time::Duration::from_secs(60)
+    );
+}
+
+#[test]
+fn test_ztrecord_display_from_str() {
+    use crate::authority::ZTRecord;
+    use trust_dns_resolver::Name;
+    use zerotier_api::central_api::types::Member;
+
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let table = [
+        (
+            serde_json::json!({
+                "nodeId": "abcdef0001",
+                "name": "islay",
+                "config": { "ipAssignments": ["10.0.0.1", "fd00::1"] },
+            }),
+            "zt-abcdef0001.home.arpa. 10.0.0.1,fd00::1 islay.home.arpa.",
+            "zt-abcdef0001.home.arpa. [10.0.0.1, fd00::1] (ptr: islay.home.arpa.)",
+        ),
+        (
+            serde_json::json!({
+                "nodeId": "abcdef0002",
+                "config": { "ipAssignments": ["10.0.0.2"] },
+            }),
+            "zt-abcdef0002.home.arpa. 10.0.0.2 zt-abcdef0002.home.arpa.",
+            "zt-abcdef0002.home.arpa. [10.0.0.2] (ptr: zt-abcdef0002.home.arpa.)",
+        ),
+    ];
+
+    for (member, text, display) in table {
+        let member: Member = serde_json::from_value(member).unwrap();
+        let record = ZTRecord::new(&member, None, None, domain.clone(), false, &[]).unwrap();
+
+        assert_eq!(ZTRecord::from_str(text).unwrap(), record, "{}", text);
+        assert_eq!(record.to_string(), display);
+        assert_eq!(ZTRecord::from_str(text).unwrap().to_string(), display);
+    }
+
+    for bad in [
+        "",
+        "islay.home.arpa. 10.0.0.1",
+        "islay.home.arpa. 10.0.0.1 islay.home.arpa. extra",
+        "islay.home.arpa. 10.0.0.300 islay.home.arpa.",
+        "islay..home.arpa. 10.0.0.1 islay.home.arpa.",
+    ] {
+        assert!(ZTRecord::from_str(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[tokio::test]
+async fn test_collisions() {
+    use crate::collision::{Collision, RecordSource};
+    use trust_dns_resolver::proto::rr::{RData, RecordType};
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::{LowerName, Name},
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0003", "name": "islay", "config": { "ipAssignments": ["10.0.0.3"] } },
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+        { "nodeId": "abcdef0002", "name": "jura", "config": { "ipAssignments": ["10.0.0.2"] } },
+        { "nodeId": "abcdef0004", "name": "zt-abcdef0002", "config": { "ipAssignments": ["10.0.0.4"] } },
+        { "nodeId": "abcdef0005", "name": "arran", "config": { "ipAssignments": ["10.0.0.5"] } },
+    ]))
+    .unwrap();
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.0.0.100 jura\ncname arran islay\n").unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.hosts_files = vec![hosts_file.clone()];
+
+    let authority = zt.forward_authority.clone();
+    let lookup = |name: &'static str, rt: RecordType| {
+        let authority = authority.clone();
+        async move {
+            let mut data: Vec<String> = authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    rt,
+                    LookupOptions::default(),
+                )
+                .await
+                .map(|lookup| {
+                    lookup
+                        .iter()
+                        .filter_map(|r| match r.data() {
+                            Some(RData::A(ip)) => Some(ip.to_string()),
+                            Some(RData::CNAME(name)) => Some(name.to_string()),
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            data.sort();
+            data
+        }
+    };
+
+    let mut serials = Vec::new();
+    for _ in 0..3 {
+        zt.configure_hosts().await.unwrap();
+        zt.configure_members(network.clone(), members.clone())
+            .await
+            .unwrap();
+        serials.push(authority.serial().await);
+    }
+    assert!(
+        serials[1..].iter().all(|s| *s == serials[1]),
+        "{:?}",
+        serials
+    );
+
+    // the lowest node ID keeps a name two members share; the other keeps its zt- name
+    assert_eq!(
+        lookup("islay.home.arpa.", RecordType::A).await,
+        vec!["10.0.0.1"]
+    );
+    assert_eq!(
+        lookup("zt-abcdef0003.home.arpa.", RecordType::A).await,
+        vec!["10.0.0.3"]
+    );
+    // a member's own zt- name is never taken by another member's name
+    assert_eq!(
+        lookup("zt-abcdef0002.home.arpa.", RecordType::A).await,
+        vec!["10.0.0.2"]
+    );
+    // the hosts files win by default, for addresses and aliases alike
+    assert_eq!(
+        lookup("jura.home.arpa.", RecordType::A).await,
+        vec!["10.0.0.100"]
+    );
+    assert_eq!(
+        lookup("arran.home.arpa.", RecordType::CNAME).await,
+        vec!["islay.home.arpa."]
+    );
+
+    let name = |name: &str| Name::from_str(name).unwrap();
+    let member = |node_id: &str| RecordSource::Member(node_id.to_string());
+    assert_eq!(
+        zt.collisions().await,
+        vec![
+            Collision {
+                name: name("arran.home.arpa."),
+                sources: vec![member("abcdef0005"), RecordSource::Cname],
+                winner: Some(RecordSource::Cname),
+            },
+            Collision {
+                name: name("islay.home.arpa."),
+                sources: vec![member("abcdef0001"), member("abcdef0003")],
+                winner: Some(member("abcdef0001")),
+            },
+            Collision {
+                name: name("jura.home.arpa."),
+                sources: vec![member("abcdef0002"), RecordSource::Hosts],
+                winner: Some(RecordSource::Hosts),
+            },
+            Collision {
+                name: name("zt-abcdef0002.home.arpa."),
+                sources: vec![member("abcdef0002"), member("abcdef0004")],
+                winner: Some(member("abcdef0002")),
+            },
+        ]
+    );
+
+    // with the members winning, the alias is dropped
+    zt.hosts_precedence = crate::hosts::HostsPrecedence::MemberWins;
+    for _ in 0..2 {
+        zt.configure_hosts().await.unwrap();
+        zt.configure_members(network.clone(), members.clone())
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        lookup("jura.home.arpa.", RecordType::A).await,
+        vec!["10.0.0.2"]
+    );
+    assert!(lookup("arran.home.arpa.", RecordType::CNAME)
+        .await
+        .is_empty());
+    assert_eq!(
+        lookup("arran.home.arpa.", RecordType::A).await,
+        vec!["10.0.0.5"]
+    );
+
+    // the control socket lists them too
+    #[cfg(unix)]
+    {
+        let response = crate::control::handle(&zt, crate::control::Command::Collisions).await;
+        assert_eq!(
+            serde_json::to_value(&response).unwrap()["data"][2],
+            serde_json::json!({
+                "name": "jura.home.arpa.",
+                "sources": ["member abcdef0002", "hosts file"],
+                "winner": "member abcdef0002",
+            })
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_offline() {
+    use crate::{authority::find_members, init::Launcher};
+    use std::time::{Duration, Instant};
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.0.0.1 islay\n").unwrap();
+
+    // offline needs hosts files, and no token
+    let launcher = Launcher {
+        offline: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        launcher.central_client().unwrap_err().current_context(),
+        &errors::Error::Config
+    );
+    let launcher = Launcher {
+        offline: true,
+        hosts: vec![hosts_file.clone()],
+        token: Some(PathBuf::from("/nonexistent")),
+        ..Default::default()
+    };
+    assert!(launcher.central_client().unwrap().is_none());
+    let launcher = Launcher {
+        hosts: vec![hosts_file.clone()],
+        token: Some(PathBuf::from("/nonexistent")),
+        ..Default::default()
+    };
+    assert!(format!("{:?}", launcher.central_client().unwrap_err()).contains("set offline"));
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.client = None;
+    zt.hosts_files = vec![hosts_file.clone()];
+    *zt.update_interval.write().await = Duration::from_millis(50);
+    let authority = zt.forward_authority.clone();
+    let sync = tokio::spawn(find_members(std::sync::Arc::new(zt)));
+
+    let found = |name: &'static str| {
+        let authority = authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+    let wait_for = |name: &'static str, present: bool| async move {
+        let start = Instant::now();
+        while found(name).await != present {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "{} present: {}",
+                name,
+                !present
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+
+    wait_for("islay.home.arpa.", true).await;
+
+    // the sync loop keeps refreshing the hosts files in place of members
+    std::fs::write(&hosts_file, "10.0.0.2 jura\n").unwrap();
+    wait_for("jura.home.arpa.", true).await;
+    wait_for("islay.home.arpa.", false).await;
+    assert!(!sync.is_finished());
+
+    sync.abort();
+}
+
+#[tokio::test]
+async fn test_latency_aware() {
+    use crate::geodnslite::{nearest, LatencyAwareRecordAuthority};
+    use std::{collections::HashMap, net::SocketAddr};
+    use trust_dns_resolver::proto::{
+        op::{Header, Query},
+        rr::{RData, Record, RecordType},
+    };
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::Name,
+        server::{Protocol, RequestInfo},
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let ip = |s: &str| IpAddr::from_str(s).unwrap();
+    let name = Name::from_str("islay.home.arpa.").unwrap();
+    let records: Vec<Record> = ["10.0.0.1", "10.0.0.2", "10.0.0.3"]
+        .into_iter()
+        .map(|s| match ip(s) {
+            IpAddr::V4(v4) => Record::from_rdata(name.clone(), 60, RData::A(v4)),
+            IpAddr::V6(_) => unreachable!(),
+        })
+        .collect();
+    let addresses = |records: Vec<Record>| -> Vec<IpAddr> {
+        records
+            .iter()
+            .filter_map(|r| r.data().and_then(|d| d.to_ip_addr()))
+            .collect()
+    };
+
+    let nodes: HashMap<IpAddr, String> = [
+        ("10.0.0.1", "abcdef0001"),
+        ("10.0.0.2", "abcdef0002"),
+        ("10.0.0.3", "abcdef0003"),
+        ("10.0.0.9", "abcdef0009"),
+    ]
+    .into_iter()
+    .map(|(a, n)| (ip(a), n.to_string()))
+    .collect();
+    let latencies: HashMap<String, i64> =
+        [("abcdef0001", 40), ("abcdef0002", 5), ("abcdef0003", -1)]
+            .into_iter()
+            .map(|(n, l)| (n.to_string(), l))
+            .collect();
+
+    // the lowest latency wins; relayed peers have none
+    assert_eq!(
+        addresses(nearest(records.clone(), ip("10.0.0.9"), &nodes, &latencies)),
+        vec![ip("10.0.0.2")]
+    );
+    // a querier is nearest to itself
+    assert_eq!(
+        addresses(nearest(records.clone(), ip("10.0.0.3"), &nodes, &latencies)),
+        vec![ip("10.0.0.3")]
+    );
+    // with nothing known, every address is given
+    assert_eq!(
+        addresses(nearest(
+            records.clone(),
+            ip("10.0.0.9"),
+            &nodes,
+            &HashMap::new()
+        )),
+        vec![ip("10.0.0.1"), ip("10.0.0.2"), ip("10.0.0.3")]
+    );
+
+    // served through the catalog, a name two members share answers with the nearer
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+        { "nodeId": "abcdef0002", "name": "jura", "config": { "ipAssignments": ["10.0.0.2"] } },
+    ]))
+    .unwrap();
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network, members).await.unwrap();
+    // a name with the addresses of both, as a merged hosts file entry gives
+    zt.forward_authority
+        .upsert_batch(vec![(
+            Name::from_str("db.home.arpa.").unwrap(),
+            vec![
+                RData::A("10.0.0.1".parse().unwrap()),
+                RData::A("10.0.0.2".parse().unwrap()),
+            ],
+        )])
+        .await;
+    *zt.peer_latencies.write().await = latencies;
+
+    let authority = LatencyAwareRecordAuthority::new(
+        zt.forward_authority.clone(),
+        zt.member_nodes.clone(),
+        zt.peer_latencies.clone(),
+    );
+    let header = Header::new();
+    let query = Query::query(Name::from_str("db.home.arpa.").unwrap(), RecordType::A).into();
+    let search = |src: &str| {
+        let request =
+            RequestInfo::new(SocketAddr::new(ip(src), 53), Protocol::Udp, &header, &query);
+        authority.search(request, LookupOptions::default())
+    };
+    let found: Vec<IpAddr> = search("10.0.0.9")
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|r| r.data().and_then(|d| d.to_ip_addr()))
+        .collect();
+    assert_eq!(found, vec![ip("10.0.0.2")]);
+    let found: Vec<IpAddr> = search("10.0.0.1")
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|r| r.data().and_then(|d| d.to_ip_addr()))
+        .collect();
+    assert_eq!(found, vec![ip("10.0.0.1")]);
+}
+
+#[tokio::test]
+async fn test_disable_ptr() {
+    use crate::init::{ConfigFormat, Launcher};
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network = serde_json::from_value(serde_json::json!({
+        "id": "1234567891011121",
+        "config": { "v6AssignMode": { "rfc4193": true } },
+    }))
+    .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        {
+            "nodeId": "abcdef0001",
+            "networkId": "1234567891011121",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.1"] },
+        },
+    ]))
+    .unwrap();
+
+    // with disable_ptr there are no reverse authorities at all, not even for RFC4193
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.disable_ptr = true;
+    assert!(zt.reverse_authority_map.is_empty());
+    zt.configure_members(network, members).await.unwrap();
+
+    for rtype in [RecordType::A, RecordType::AAAA] {
+        assert!(
+            zt.forward_authority
+                .lookup(
+                    &LowerName::from_str("islay.home.arpa.").unwrap(),
+                    rtype,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok(),
+            "{}",
+            rtype
+        );
+    }
+
+    let parsed =
+        Launcher::parse_format("disable_ptr: true\nwildcard: false\n", ConfigFormat::YAML).unwrap();
+    assert!(parsed.disable_ptr);
+}
+
+#[tokio::test]
+async fn test_classless_reverse_zones() {
+    use crate::{authority::RecordAuthority, traits::ToPointerSOA};
+    use ipnetwork::IpNetwork;
+    use trust_dns_resolver::{proto::rr::RecordType, IntoName};
+    use trust_dns_server::{
+        authority::LookupOptions,
+        client::rr::{LowerName, Name},
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    for (networks, ips, outside) in [
+        (
+            vec!["10.40.0.0/22"],
+            vec!["10.40.0.1", "10.40.1.200", "10.40.3.254"],
+            vec!["10.40.4.1", "10.41.0.1"],
+        ),
+        (
+            vec!["10.40.7.128/25"],
+            vec!["10.40.7.129", "10.40.7.254"],
+            vec!["10.40.7.1"],
+        ),
+        (
+            vec!["172.16.0.0/12"],
+            vec!["172.16.0.1", "172.31.255.254"],
+            vec!["172.32.0.1", "172.1.0.1"],
+        ),
+        // two networks next to each other are served side by side
+        (
+            vec!["10.50.0.0/23", "10.50.2.0/23"],
+            vec!["10.50.0.1", "10.50.3.1"],
+            vec!["10.50.4.1"],
+        ),
+    ] {
+        let networks: Vec<IpNetwork> = networks.iter().map(|n| n.parse().unwrap()).collect();
+
+        let mut zt =
+            zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+        for network in &networks {
+            for zone in network.to_ptr_zones() {
+                if let std::collections::hash_map::Entry::Vacant(e) =
+                    zt.reverse_authority_map.entry(zone)
+                {
+                    let name = zone.to_ptr_soa_name().unwrap();
+                    e.insert(RecordAuthority::new(name.clone(), name).await.unwrap());
+                }
+            }
+        }
+
+        let network: Network = serde_json::from_value(serde_json::json!({
+            "id": "1234567891011121",
+            "config": {},
+        }))
+        .unwrap();
+        let members: Vec<Member> = serde_json::from_value(serde_json::Value::Array(
+            ips.iter()
+                .enumerate()
+                .map(|(i, ip)| {
+                    serde_json::json!({
+                        "nodeId": format!("abcdef000{}", i),
+                        "name": format!("member{}", i),
+                        "config": { "ipAssignments": [ip] },
+                    })
+                })
+                .collect(),
+        ))
+        .unwrap();
+        zt.configure_members(network, members).await.unwrap();
+
+        let zones: Vec<LowerName> = zt
+            .reverse_authority_map
+            .keys()
+            .map(|zone| zone.to_ptr_soa_name().unwrap())
+            .collect();
+        let catalog = crate::authority::init_catalog(zt).await.unwrap();
+
+        for (i, ip) in ips.iter().enumerate() {
+            let ip: std::net::IpAddr = ip.parse().unwrap();
+            let name = LowerName::from(ip.into_name().unwrap());
+
+            // the query lands in our reverse zone, not the forwarder at the root
+            let authority = catalog.find(&name).unwrap();
+            assert_ne!(authority.origin(), &LowerName::from(Name::root()), "{}", ip);
+            assert!(
+                zones.contains(authority.origin()),
+                "{}: {}",
+                ip,
+                authority.origin()
+            );
+
+            let lookup = authority
+                .lookup(&name, RecordType::PTR, LookupOptions::default())
+                .await
+                .unwrap_or_else(|e| panic!("{}: {}", ip, e));
+            let ptrs: Vec<String> = lookup
+                .iter()
+                .filter_map(|r| r.data())
+                .map(ToString::to_string)
+                .collect();
+            assert_eq!(ptrs, vec![format!("member{}.home.arpa.", i)], "{}", ip);
+        }
+
+        // space next to the network is left to the forwarder
+        for ip in outside {
+            let ip: std::net::IpAddr = ip.parse().unwrap();
+            let name = LowerName::from(ip.into_name().unwrap());
+            let authority = catalog.find(&name).unwrap();
+            assert_eq!(authority.origin(), &LowerName::from(Name::root()), "{}", ip);
+        }
+    }
+}
+
+#[test]
+fn test_json_logs() {
+    use crate::{log::LogFormat, utils::log_subscriber};
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::filter::EnvFilter;
+    use trust_dns_resolver::proto::rr::RData;
+    use trust_dns_server::client::rr::Name;
+
+    // Captured collects everything written to it, for inspection once logging is done.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = log_subscriber(EnvFilter::new("info"), LogFormat::Json, move || {
+        writer.clone()
+    });
+
+    tracing::subscriber::with_default(subscriber, || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let _span = tracing::info_span!("network", network_id = "1234567891011121").entered();
+
+        runtime.block_on(async {
+            let zt =
+                zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+            zt.forward_authority
+                .upsert_batch(vec![(
+                    Name::from_str("islay.home.arpa.").unwrap(),
+                    vec![RData::A("10.0.0.1".parse().unwrap())],
+                )])
+                .await;
+        });
+        crate::utils::parse_member_name(
+            Some(".".to_string()),
+            Name::from_str("home.arpa.").unwrap(),
+        );
+    });
+
+    let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {}", e, line)))
+        .collect();
+
+    let added = lines
+        .iter()
+        .find(|line| line["message"] == "Adding new record")
+        .unwrap();
+    assert_eq!(added["level"], "INFO");
+    assert_eq!(added["record"], "islay.home.arpa.");
+    assert_eq!(added["rdata"], "10.0.0.1");
+    assert_eq!(added["span"]["network_id"], "1234567891011121");
+
+    let member = lines
+        .iter()
+        .find(|line| line["message"] == "Member name not entered into catalog")
+        .unwrap();
+    assert_eq!(member["member_name"], ".");
+}
+
+#[tokio::test]
+async fn test_export_json() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network = serde_json::from_value(serde_json::json!({
+        "id": "1234567891011121",
+        "config": { "routes": [{ "target": "10.0.0.0/24" }] },
+    }))
+    .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+    ]))
+    .unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.reverse_authority_map.insert(
+        "10.0.0.0/24".parse().unwrap(),
+        RecordAuthority::new(
+            LowerName::from_str("0.0.10.in-addr.arpa.").unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    zt.configure_members(network, members).await.unwrap();
+
+    let records = zt.forward_authority.export_json().await;
+    let records = records.as_array().unwrap();
+    assert!(records.contains(&serde_json::json!({
+        "name": "islay.home.arpa.",
+        "type": "A",
+        "ttl": 60,
+        "rdata": "10.0.0.1",
+    })));
+    assert!(records
+        .iter()
+        .any(|record| record["type"] == "SOA" && record["name"] == "home.arpa."));
+
+    // the control socket lists the reverse zones after the forward one
+    #[cfg(unix)]
+    {
+        let dir = tempdir();
+        let path = dir.path().join("control.sock");
+
+        let listener = crate::control::bind_control(&path).unwrap();
+        tokio::spawn(crate::control::serve_control(listener, zt.clone()));
+
+        let response = crate::control::request(&path, serde_json::json!({"cmd": "list_records"}))
+            .await
+            .unwrap();
+        let listed = response["data"].as_array().unwrap();
+        assert_eq!(&listed[..records.len()], &records[..]);
+        assert!(listed[records.len()..].contains(&serde_json::json!({
+            "name": "1.0.0.10.in-addr.arpa.",
+            "type": "PTR",
+            "ttl": 60,
+            "rdata": "islay.home.arpa.",
+        })));
+    }
+}
+
+#[tokio::test]
+async fn test_query_rate_limit() {
+    use crate::server::{QueryRateLimitConfig, QueryRateLimiter, RateLimitedAuthority};
+    use std::{
+        net::{IpAddr, SocketAddr},
+        time::Duration,
+    };
+    use trust_dns_resolver::proto::{
+        op::{Header, Query, ResponseCode},
+        rr::RecordType,
+    };
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupError, LookupOptions},
+        client::rr::Name,
+        server::{Protocol, RequestInfo},
+    };
+
+    assert!(QueryRateLimiter::new(QueryRateLimitConfig {
+        requests_per_second: 0,
+        burst: 1,
+    })
+    .is_err());
+
+    let limiter = QueryRateLimiter::new(QueryRateLimitConfig {
+        requests_per_second: 20,
+        burst: 3,
+    })
+    .unwrap();
+    let ip = |s: &str| IpAddr::from_str(s).unwrap();
+
+    // the burst is spent, then one query is allowed every 50ms
+    assert_eq!(
+        (0..4)
+            .map(|_| limiter.check(ip("10.0.0.1")))
+            .collect::<Vec<_>>(),
+        vec![true, true, true, false]
+    );
+    assert!(limiter.check(ip("10.0.0.2")));
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(limiter.check(ip("10.0.0.1")));
+    assert!(!limiter.check(ip("10.0.0.1")));
+
+    // clients are forgotten once their bucket would be full again
+    assert_eq!(limiter.clients(), 2);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    limiter.prune();
+    assert_eq!(limiter.clients(), 0);
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    let authority = RateLimitedAuthority::new(
+        zt.forward_authority.box_clone(),
+        QueryRateLimiter::new(QueryRateLimitConfig {
+            requests_per_second: 1,
+            burst: 1,
+        })
+        .unwrap(),
+    );
+    let header = Header::new();
+    let query = Query::query(Name::from_str("home.arpa.").unwrap(), RecordType::SOA).into();
+    let search = || {
+        let request = RequestInfo::new(
+            SocketAddr::new(ip("10.0.0.1"), 53),
+            Protocol::Udp,
+            &header,
+            &query,
+        );
+        authority.search(request, LookupOptions::default())
+    };
+
+    assert!(search().await.is_ok());
+    assert!(matches!(
+        search().await,
+        Err(LookupError::ResponseCode(ResponseCode::Refused))
+    ));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pause() {
+    use crate::control::{handle, Command};
+    use std::net::SocketAddr;
+    use trust_dns_resolver::proto::{
+        op::{Header, Query, ResponseCode},
+        rr::RecordType,
+    };
+    use trust_dns_server::{
+        authority::{LookupError, LookupOptions},
+        client::rr::{LowerName, Name},
+        server::{Protocol, RequestInfo},
+    };
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    let catalog = crate::authority::init_catalog(zt.clone()).await.unwrap();
+    let authority = catalog
+        .find(&LowerName::from_str("home.arpa.").unwrap())
+        .unwrap();
+
+    let header = Header::new();
+    let query = Query::query(Name::from_str("home.arpa.").unwrap(), RecordType::SOA).into();
+    let search = || {
+        let request = RequestInfo::new(
+            SocketAddr::from_str("10.0.0.1:53").unwrap(),
+            Protocol::Udp,
+            &header,
+            &query,
+        );
+        authority.search(request, LookupOptions::default())
+    };
+    let servfail = |result: core::result::Result<_, LookupError>| {
+        matches!(
+            result,
+            Err(LookupError::ResponseCode(ResponseCode::ServFail))
+        )
+    };
+
+    assert!(search().await.is_ok());
+
+    // the catalog's copy of the zone follows the pause
+    assert!(handle(&zt, Command::Pause { timeout: None }).await.ok);
+    assert!(zt.is_paused());
+    assert!(servfail(search().await));
+
+    assert!(handle(&zt, Command::Resume).await.ok);
+    assert!(!zt.is_paused());
+    assert!(search().await.is_ok());
+
+    assert!(!handle(&zt, Command::Pause { timeout: Some(0) }).await.ok);
+    assert!(!zt.is_paused());
+
+    // a pause with a timeout ends by itself
+    assert!(handle(&zt, Command::Pause { timeout: Some(1) }).await.ok);
+    assert!(servfail(search().await));
+    tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+    assert!(!zt.is_paused());
+    assert!(search().await.is_ok());
+
+    // but not a later pause without one
+    zt.pause(Some(std::time::Duration::from_secs(1)));
+    zt.resume();
+    zt.pause(None);
+    tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+    assert!(zt.is_paused());
+    assert!(servfail(search().await));
+    zt.resume();
+
+    assert_eq!(
+        serde_json::from_str::<Command>(r#"{"cmd":"pause","timeout":30}"#).unwrap(),
+        Command::Pause { timeout: Some(30) }
+    );
+    assert_eq!(
+        serde_json::from_str::<Command>(r#"{"cmd":"pause"}"#).unwrap(),
+        Command::Pause { timeout: None }
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_syslog_logs() {
+    use crate::{log::LogFormat, utils::syslog_subscriber};
+    use error_stack::{Report, ResultExt};
+    use std::os::unix::net::UnixDatagram;
+    use tracing_subscriber::filter::EnvFilter;
+
+    let (send, recv) = UnixDatagram::pair().unwrap();
+    recv.set_nonblocking(true).unwrap();
+    let logger = syslog::Logger::new(
+        syslog::LoggerBackend::Unix(send),
+        syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_DAEMON,
+            hostname: None,
+            process: "zeronsd".to_string(),
+            pid: 1,
+        },
+    );
+
+    Report::set_color_mode(error_stack::fmt::ColorMode::None);
+    let report = std::result::Result::<(), _>::Err(std::io::Error::other("refused"))
+        .change_context(crate::errors::Error::Server)
+        .attach_printable("could not reach central")
+        .unwrap_err();
+
+    tracing::subscriber::with_default(
+        syslog_subscriber(EnvFilter::new("info"), LogFormat::Text, logger),
+        || {
+            tracing::warn!(record = "islay.home.arpa.", "Replacing host record");
+            tracing::debug!("not logged at info");
+            tracing::error!("giving up syncing members: {:?}", report);
+        },
+    );
+
+    let mut buf = [0; 4096];
+    let mut entries = Vec::new();
+    while let Ok(n) = recv.recv(&mut buf) {
+        entries.push(String::from_utf8_lossy(&buf[..n]).into_owned());
+    }
+
+    assert_eq!(entries.len(), 2, "{:?}", entries);
+    // daemon facility, with the severity of the event
+    assert!(entries[0].starts_with("<28>"), "{}", entries[0]);
+    assert!(entries[0].contains("Replacing host record record=\"islay.home.arpa.\""));
+    assert!(entries[1].starts_with("<27>"), "{}", entries[1]);
+    // the whole report is one message
+    assert!(entries[1].contains("could not reach central"));
+    assert!(entries[1].lines().count() > 1);
+}
+
+#[test]
+fn test_lenient_hosts() {
+    use crate::hosts::{check_hosts_file, parse_hosts_files, Reason};
+    use std::net::IpAddr;
+    use trust_dns_resolver::Name;
+
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let fqdn = |s: &str| s.to_fqdn(domain.clone()).unwrap();
+    let path = PathBuf::from(format!("{}/ip-per-host", crate::utils::TEST_HOSTS_DIR));
+
+    let (hosts, _, diagnostics) = check_hosts_file(&path, domain.clone(), true).unwrap();
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.1").unwrap()),
+        Some(&vec![fqdn("jura")])
+    );
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("10.0.0.2").unwrap()),
+        Some(&vec![fqdn("arran"), fqdn("arran-alias")])
+    );
+    // the standard entries are read as usual
+    assert_eq!(
+        hosts.get(&IpAddr::from_str("127.0.1.1").unwrap()),
+        Some(&vec![fqdn("islay.localdomain"), fqdn("islay")])
+    );
+    // an address only applies to the line right after it, and a comment that isn't one is just a
+    // comment
+    assert!(!hosts.contains_key(&IpAddr::from_str("10.0.0.3").unwrap()));
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|d| (d.line, d.reason))
+            .collect::<Vec<_>>(),
+        vec![(11, Reason::BadAddress), (13, Reason::BadAddress)]
+    );
+
+    // without lenient, none of the commented addresses are used
+    let (hosts, _) =
+        parse_hosts_files(std::slice::from_ref(&path), domain.clone(), false, false).unwrap();
+    assert!(!hosts.contains_key(&IpAddr::from_str("10.0.0.1").unwrap()));
+    let (hosts, _) = parse_hosts_files(&[path], domain.clone(), false, true).unwrap();
+    assert!(hosts.contains_key(&IpAddr::from_str("10.0.0.1").unwrap()));
+}
+
+#[tokio::test]
+async fn test_health_check() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::client::rr::{LowerName, RrKey};
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+    ]))
+    .unwrap();
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network, members).await.unwrap();
+    zt.forward_authority.health_check().await.unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/health", listener.local_addr().unwrap());
+    tokio::spawn(crate::metrics::serve_metrics(listener, zt.clone()));
+
+    let response = reqwest::get(&url).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "ok\nnetwork: 1234567891011121\n"
+    );
+
+    // the detailed report lists each zone with its records.
+    let detail = format!("{}?detail", url);
+    let zone = |report: &serde_json::Value| {
+        report["zones"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|zone| zone["zone"] == "home.arpa.")
+            .cloned()
+            .unwrap()
+    };
+    let response = reqwest::get(&detail).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["healthy"], true);
+    assert_eq!(report["network"]["id"], "1234567891011121");
+    assert!(zone(&report)["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|record| record["name"] == "islay.home.arpa." && record["rdata"] == "10.0.0.1"));
+
+    let origin = LowerName::from_str("home.arpa.").unwrap();
+    zt.forward_authority
+        .bulk_delete(vec![RrKey::new(origin.clone(), RecordType::NS)])
+        .await;
+    let err = zt.forward_authority.health_check().await.unwrap_err();
+    assert!(format!("{:?}", err).contains("home.arpa. has no NS record"));
+
+    zt.forward_authority
+        .bulk_delete(vec![RrKey::new(origin, RecordType::SOA)])
+        .await;
+    let err = zt.health_check().await.unwrap_err();
+    assert!(format!("{:?}", err).contains("home.arpa. has no valid SOA record"));
+
+    let response = reqwest::get(&url).await.unwrap();
+    assert_eq!(response.status(), 503);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("has no valid SOA record"));
+
+    let response = reqwest::get(&detail).await.unwrap();
+    assert_eq!(response.status(), 503);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["healthy"], false);
+    assert_eq!(zone(&report)["healthy"], false);
+    assert!(zone(&report)["error"]
+        .as_str()
+        .unwrap()
+        .contains("has no valid SOA record"));
+}
+
+#[cfg(feature = "otlp")]
+#[test]
+fn test_otlp_spans() {
+    use opentelemetry::{KeyValue, Value};
+    use opentelemetry_sdk::{
+        export::trace::{ExportResult, SpanData, SpanExporter},
+        trace::TracerProvider,
+    };
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::filter::EnvFilter;
+
+    // Exported keeps the spans handed to it, for inspection once they are flushed.
+    #[derive(Debug, Clone, Default)]
+    struct Exported(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for Exported {
+        fn export(
+            &mut self,
+            batch: Vec<SpanData>,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ExportResult> + Send>> {
+            self.0.lock().unwrap().extend(batch);
+            Box::pin(std::future::ready(Ok(())))
+        }
+    }
+
+    let exported = Exported::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exported.clone())
+        .build();
+    let subscriber = crate::utils::subscriber(
+        EnvFilter::new("info"),
+        vec![crate::telemetry::layer(&provider)],
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let central = MockCentral::with_members(
+                    serde_json::json!({ "id": "1234567891011121", "config": {} }),
+                    serde_json::json!([
+                        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+                        { "nodeId": "abcdef0002", "name": "jura", "config": { "ipAssignments": ["10.0.0.2"] } },
+                    ]),
+                    false,
+                )
+                .await;
+                let zt = zt_authority(central.client()).await;
+
+                let (network, members) = zt.get_members().await.unwrap();
+                zt.configure_members(network, members).await.unwrap();
+            });
+    });
+    for result in provider.force_flush() {
+        result.unwrap();
+    }
+
+    let spans = exported.0.lock().unwrap().clone();
+    let attribute = |name: &str, key: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("no {} span", name))
+            .attributes
+            .iter()
+            .find(|KeyValue { key: k, .. }| k.as_str() == key)
+            .map(|kv| kv.value.clone())
+    };
+
+    assert_eq!(attribute("get_members", "members"), Some(Value::I64(2)));
+    assert_eq!(
+        attribute("configure_members", "members"),
+        Some(Value::I64(2))
+    );
+    assert_eq!(
+        attribute("prune", "zone"),
+        Some(Value::from("home.arpa.".to_string()))
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_control_log_filter() {
+    use crate::control::{handle, Command};
+
+    let zt = zt_authority(
+        MockCentral::new(serde_json::json!({}), false)
+            .await
+            .client(),
+    )
+    .await;
+
+    // the logger is global, so it starts out silent to keep the other tests' output clean.
+    crate::utils::init_logger(
+        crate::log::parse_filter("off").unwrap(),
+        crate::log::LogFormat::Text,
+        crate::log::LogTarget::Stdout,
+        None,
+    );
+    assert_eq!(crate::utils::log_filter().as_deref(), Some("off"));
+
+    let response = handle(
+        &zt,
+        Command::SetLogFilter {
+            filter: "zeronsd::nothing=debug".to_string(),
+        },
+    )
+    .await;
+    assert!(response.ok);
+    assert_eq!(
+        response.data,
+        Some(serde_json::json!("zeronsd::nothing=debug"))
+    );
+
+    let response = handle(&zt, Command::LogFilter).await;
+    assert_eq!(
+        response.data,
+        Some(serde_json::json!("zeronsd::nothing=debug"))
+    );
+
+    let response = handle(
+        &zt,
+        Command::SetLogFilter {
+            filter: "zeronsd=loud".to_string(),
+        },
+    )
+    .await;
+    assert!(!response.ok);
+    let error = response.error.unwrap();
+    assert!(error.contains("zeronsd=loud"), "{}", error);
+    assert_eq!(
+        crate::utils::log_filter().as_deref(),
+        Some("zeronsd::nothing=debug")
+    );
+
+    // SIGUSR2 goes to debug from anything else, and from debug back to where it started.
+    assert_eq!(crate::utils::toggle_debug().unwrap(), "debug");
+    assert_eq!(crate::utils::toggle_debug().unwrap(), "off");
+}
+
+// test_cert makes a certificate for name, signed by issuer, or a CA signing itself without one.
+fn test_cert(
+    name: &str,
+    issuer: Option<(
+        &openssl::x509::X509,
+        &openssl::pkey::PKey<openssl::pkey::Private>,
+    )>,
+) -> (
+    openssl::x509::X509,
+    openssl::pkey::PKey<openssl::pkey::Private>,
+) {
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        x509::{extension::BasicConstraints, X509Name, X509},
+    };
+
+    let key = PKey::from_ec_key(
+        EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap(),
+    )
+    .unwrap();
+    let mut subject = X509Name::builder().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    match issuer {
+        Some((issuer, issuer_key)) => {
+            builder.set_issuer_name(issuer.subject_name()).unwrap();
+            builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        }
+        None => {
+            builder.set_issuer_name(&subject).unwrap();
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+            builder.sign(&key, MessageDigest::sha256()).unwrap();
+        }
+    }
+    (builder.build(), key)
+}
+
+// write_tls_files writes cert and key where a CertReloader will read them.
+fn write_tls_files(
+    dir: &std::path::Path,
+    (cert, key): &(
+        openssl::x509::X509,
+        openssl::pkey::PKey<openssl::pkey::Private>,
+    ),
+) -> crate::server::TlsFiles {
+    let files = crate::server::TlsFiles {
+        cert: dir.join("cert.pem"),
+        chain: None,
+        key: dir.join("key.pem"),
+    };
+    std::fs::write(&files.cert, cert.to_pem().unwrap()).unwrap();
+    std::fs::write(&files.key, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    files
+}
+
+// dot_connect opens a DoT connection to addr, presenting identity if given, returning None when
+// the handshake fails.
+async fn dot_connect(
+    addr: std::net::SocketAddr,
+    identity: Option<&(
+        openssl::x509::X509,
+        openssl::pkey::PKey<openssl::pkey::Private>,
+    )>,
+) -> Option<tokio_openssl::SslStream<tokio::net::TcpStream>> {
+    use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.set_verify(SslVerifyMode::NONE);
+    if let Some((cert, key)) = identity {
+        connector.set_certificate(cert).unwrap();
+        connector.set_private_key(key).unwrap();
+    }
+    let ssl = connector
+        .build()
+        .configure()
+        .unwrap()
+        .into_ssl("dns.home.arpa")
+        .unwrap();
+
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut stream = tokio_openssl::SslStream::new(ssl, tcp).unwrap();
+    std::pin::Pin::new(&mut stream).connect().await.ok()?;
+    Some(stream)
+}
+
+// dot_lookup asks for the A records of name over stream, returning the addresses answered.
+async fn dot_lookup(
+    stream: &mut tokio_openssl::SslStream<tokio::net::TcpStream>,
+    name: &str,
+) -> Option<Vec<IpAddr>> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use trust_dns_resolver::proto::{
+        op::{Message, Query},
+        rr::{RData, RecordType},
+    };
+    use trust_dns_server::client::rr::Name;
+
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str(name).unwrap(), RecordType::A));
+    let bytes = message.to_vec().unwrap();
+    stream
+        .write_all(&(bytes.len() as u16).to_be_bytes())
+        .await
+        .ok()?;
+    stream.write_all(&bytes).await.ok()?;
+
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).await.ok()?;
+    let mut answer = vec![0; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut answer).await.ok()?;
+
+    Some(
+        Message::from_vec(&answer)
+            .unwrap()
+            .answers()
+            .iter()
+            .filter_map(|record| record.data().and_then(RData::to_ip_addr))
+            .collect(),
+    )
+}
+
+// dot_server serves zt over DoT on a port of its own, with the certificate in tls.
+async fn dot_server(
+    zt: crate::authority::ZTAuthority,
+    tls: crate::server::CertReloader,
+) -> std::net::SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(crate::server::serve_dot(
+        listener,
+        tls,
+        crate::authority::init_catalog(zt).await.unwrap(),
+        std::time::Duration::from_secs(5),
+    ));
+    addr
+}
+
+async fn dot_zt_authority() -> crate::authority::ZTAuthority {
+    use trust_dns_resolver::proto::rr::RData;
+    use trust_dns_server::client::rr::Name;
+
+    let zt = zt_authority(
+        MockCentral::new(serde_json::json!({}), false)
+            .await
+            .client(),
+    )
+    .await;
+    zt.forward_authority
+        .upsert_batch(vec![(
+            Name::from_str("islay.home.arpa.").unwrap(),
+            vec![RData::A("10.0.0.1".parse().unwrap())],
+        )])
+        .await;
+    zt
+}
+
+#[tokio::test]
+async fn test_dot_client_ca() {
+    use crate::server::CertReloader;
+
+    let (ca, ca_key) = test_cert("zeronsd test CA", None);
+    let (other_ca, other_ca_key) = test_cert("someone else", None);
+    let server = test_cert("dns.home.arpa", Some((&ca, &ca_key)));
+    let client = test_cert("islay", Some((&ca, &ca_key)));
+    let stranger = test_cert("jura", Some((&other_ca, &other_ca_key)));
+
+    let dir = tempdir();
+    let tls =
+        CertReloader::new(write_tls_files(dir.path(), &server), Some(vec![ca.clone()])).unwrap();
+    let addr = dot_server(dot_zt_authority().await, tls).await;
+
+    // query looks up islay over DoT, presenting identity if given, and returns the addresses
+    // answered, or None when the connection is refused.
+    let query = |identity| async move {
+        dot_lookup(&mut dot_connect(addr, identity).await?, "islay.home.arpa.").await
+    };
+
+    assert_eq!(
+        query(Some(&client)).await,
+        Some(vec![IpAddr::from_str("10.0.0.1").unwrap()])
+    );
+    assert_eq!(query(Some(&stranger)).await, None);
+    assert_eq!(query(None).await, None);
+}
+
+#[tokio::test]
+async fn test_dot_cert_reload() {
+    use crate::server::CertReloader;
+
+    let dir = tempdir();
+    let old = test_cert("old.home.arpa", None);
+    let new = test_cert("new.home.arpa", None);
+
+    let tls = CertReloader::new(write_tls_files(dir.path(), &old), None).unwrap();
+    tokio::spawn(tls.clone().watch());
+    let addr = dot_server(dot_zt_authority().await, tls.clone()).await;
+
+    let presented = |stream: &tokio_openssl::SslStream<tokio::net::TcpStream>| {
+        stream.ssl().peer_certificate().unwrap().to_der().unwrap()
+    };
+    let mut open = dot_connect(addr, None).await.unwrap();
+    assert_eq!(presented(&open), old.0.to_der().unwrap());
+
+    // a renewal is picked up by the watch
+    write_tls_files(dir.path(), &new);
+    let start = std::time::Instant::now();
+    loop {
+        let stream = dot_connect(addr, None).await.unwrap();
+        if presented(&stream) == new.0.to_der().unwrap() {
+            break;
+        }
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(10),
+            "the new certificate was never served"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    // while the connection made before it carries on
+    assert_eq!(
+        dot_lookup(&mut open, "islay.home.arpa.").await,
+        Some(vec![IpAddr::from_str("10.0.0.1").unwrap()])
+    );
+
+    // and a key which does not go with the certificate leaves the new one in use
+    std::fs::write(
+        dir.path().join("key.pem"),
+        old.1.private_key_to_pem_pkcs8().unwrap(),
+    )
+    .unwrap();
+    assert!(tls.reload().is_err());
+    let mut stream = dot_connect(addr, None).await.unwrap();
+    assert_eq!(presented(&stream), new.0.to_der().unwrap());
+    assert!(dot_lookup(&mut stream, "islay.home.arpa.").await.is_some());
+}
+
+#[tokio::test]
+async fn test_redacted_secrets() {
+    use crate::{
+        errors::ApiResultExt,
+        log::LogFormat,
+        utils::{
+            central_client_with_url, local_client, log_subscriber, HttpOptions, Secret,
+            DEFAULT_SERVICE_API_TIMEOUT,
+        },
+    };
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::filter::EnvFilter;
+
+    const TOKEN: &str = "dummy-token-5ee1f00d";
+
+    // Captured collects everything written to it, for inspection once logging is done.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let _guard = tracing::subscriber::set_default(log_subscriber(
+        EnvFilter::new("debug"),
+        LogFormat::Text,
+        move || writer.clone(),
+    ));
+
+    let secret = Secret::new(TOKEN);
+    assert_eq!(secret.expose(), TOKEN);
+    tracing::info!("{} {:?}", secret, secret);
+
+    // a proxy copying the credentials of the request into its response
+    let url = mock_server(|_| (502, format!("x-echo: {}\r\n", TOKEN), "{}".to_string())).await;
+    let local = local_client(Secret::new(TOKEN), url, DEFAULT_SERVICE_API_TIMEOUT).unwrap();
+    tracing::info!("{:?}", local);
+    let e = local.get_status().await.local_context().unwrap_err();
+    tracing::error!("{:?}", e);
+    tracing::error!("{}", e);
+
+    let central = central_client_with_url(
+        "https://127.0.0.1:1",
+        Secret::new(TOKEN),
+        &HttpOptions::default(),
+    )
+    .unwrap();
+    tracing::info!("{:?}", central);
+    let e = central
+        .get_network_by_id("1234567891011121")
+        .await
+        .central_context()
+        .unwrap_err();
+    tracing::error!("{:?}", e);
+
+    let captured = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    assert!(captured.contains("[redacted] [redacted]"), "{}", captured);
+    assert!(captured.contains("502"), "{}", captured);
+    assert!(!captured.contains(TOKEN), "{}", captured);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_member_records_parallel() {
+    use crate::{
+        addresses::Calculator,
+        authority::{member_records, member_records_parallel},
+    };
+    use trust_dns_resolver::Name;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network = serde_json::from_value(serde_json::json!({
+        "id": "1234567891011121",
+        "config": { "v6AssignMode": { "rfc4193": true, "6plane": true } },
+    }))
+    .unwrap();
+    let (sixplane, rfc4193) = (network.sixplane().ok(), network.rfc4193().ok());
+
+    // every thousandth member has an ID too short to work addresses out from.
+    let members: Vec<Member> = (0..5000u32)
+        .map(|i| {
+            let node_id = if i % 1000 == 999 {
+                format!("{:08x}", i)
+            } else {
+                format!("{:010x}", 0xab_0000_0000u64 + i as u64)
+            };
+            serde_json::from_value(serde_json::json!({
+                "nodeId": node_id,
+                "networkId": "1234567891011121",
+                "name": if i % 3 == 0 { format!("host-{}", i) } else { String::new() },
+                "config": { "ipAssignments": [std::net::Ipv4Addr::from(0x0a00_0000 + i).to_string()] },
+            }))
+            .unwrap()
+        })
+        .collect();
+
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let records = member_records(
+        members.clone(),
+        sixplane,
+        rfc4193,
+        domain.clone(),
+        false,
+        &[],
+    )
+    .unwrap();
+    let parallel = member_records_parallel(members, sixplane, rfc4193, domain, false, &[])
+        .await
+        .unwrap();
+
+    assert_eq!(records.len(), 4995);
+    assert_eq!(parallel, records);
+}
+
+#[tokio::test]
+async fn test_central_tls_fingerprint() {
+    use crate::utils::{central_client_with_url, parse_fingerprint, HttpOptions, Secret};
+    use openssl::{
+        hash::MessageDigest,
+        ssl::{Ssl, SslAcceptor, SslMethod},
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (cert, key) = test_cert("central.example", None);
+
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+    acceptor.set_certificate(&cert).unwrap();
+    acceptor.set_private_key(&key).unwrap();
+    let acceptor = acceptor.build();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("https://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let ssl = Ssl::new(acceptor.context()).unwrap();
+            let mut stream = tokio_openssl::SslStream::new(ssl, stream).unwrap();
+            tokio::spawn(async move {
+                if std::pin::Pin::new(&mut stream).accept().await.is_err() {
+                    return;
+                }
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let body = r#"{"id":"1234567891011121","config":{}}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+
+    let fingerprint = hex::encode(cert.digest(MessageDigest::sha256()).unwrap())
+        .to_uppercase()
+        .as_bytes()
+        .chunks(2)
+        .map(|c| std::str::from_utf8(c).unwrap())
+        .collect::<Vec<_>>()
+        .join(":");
+    assert_eq!(
+        parse_fingerprint(&fingerprint).unwrap().to_vec(),
+        cert.digest(MessageDigest::sha256()).unwrap().to_vec()
+    );
+
+    let get = |fingerprint: Option<String>| {
+        let url = url.clone();
+        async move {
+            let options = HttpOptions {
+                central_tls_fingerprint: fingerprint,
+                ..Default::default()
+            };
+            central_client_with_url(&url, Secret::new("token".to_string()), &options)
+                .unwrap()
+                .get_network_by_id("1234567891011121")
+                .await
+        }
+    };
+
+    // a self-signed certificate is only trusted through its fingerprint
+    assert_eq!(
+        get(Some(fingerprint)).await.unwrap().id.as_deref(),
+        Some("1234567891011121")
+    );
+    assert!(get(None).await.is_err());
+    assert!(get(Some("00".repeat(32))).await.is_err());
+
+    for bad in ["", "abc", &"zz".repeat(32), &"00".repeat(33)] {
+        assert!(parse_fingerprint(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[tokio::test]
+async fn test_configure_hosts_unchanged() {
+    use std::sync::atomic::Ordering;
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::{LowerName, Name},
+    };
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.0.0.1 islay\n").unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.hosts_files = vec![hosts_file.clone()];
+    let parses = || zt.hosts_parses.load(Ordering::Relaxed);
+    let has_a = |name: &'static str| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+
+    assert!(zt.configure_hosts().await.unwrap());
+    assert_eq!(parses(), 1);
+
+    // nothing changed, so nothing is parsed
+    assert!(!zt.configure_hosts().await.unwrap());
+    zt.reload_hosts().await.unwrap();
+    assert_eq!(parses(), 1);
+
+    // a rewrite of the same length is still noticed
+    std::fs::write(&hosts_file, "10.0.0.2 islay\n").unwrap();
+    assert!(zt.configure_hosts().await.unwrap());
+    assert_eq!(parses(), 2);
+
+    // as is a change to the members the names are resolved against
+    zt.member_addresses.write().await.insert(
+        Name::from_str("jura.home.arpa.").unwrap(),
+        vec!["10.0.0.9".parse().unwrap()],
+    );
+    assert!(zt.configure_hosts().await.unwrap());
+    assert_eq!(parses(), 3);
+
+    // deleting the file prunes its names once
+    std::fs::remove_file(&hosts_file).unwrap();
+    zt.reload_hosts().await.unwrap();
+    assert_eq!(parses(), 4);
+    assert!(!has_a("islay.home.arpa.").await);
+    zt.reload_hosts().await.unwrap();
+    assert_eq!(parses(), 4);
+
+    // and bringing it back serves them again
+    std::fs::write(&hosts_file, "10.0.0.1 islay\n").unwrap();
+    zt.reload_hosts().await.unwrap();
+    assert_eq!(parses(), 5);
+    assert!(has_a("islay.home.arpa.").await);
+
+    // a file that was never there is still an error
+    zt.hosts_files.push(dir.path().join("missing"));
+    assert!(zt.configure_hosts().await.is_err());
+}
+
+#[tokio::test]
+async fn test_record_quota() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    let network = || -> zerotier_api::central_api::types::Network {
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap()
+    };
+    let members = |count: u32| -> Vec<zerotier_api::central_api::types::Member> {
+        (0..count)
+            .map(|i| {
+                serde_json::from_value(serde_json::json!({
+                    "nodeId": format!("{:010x}", 0xab_0000_0000u64 + i as u64),
+                    "name": format!("host-{}", i),
+                    "config": { "ipAssignments": [std::net::Ipv4Addr::from(0x0a00_0000 + i).to_string()] },
+                }))
+                .unwrap()
+            })
+            .collect()
+    };
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.max_records = Some(50);
+    zt.configure_members(network(), members(10)).await.unwrap();
+
+    let has_a = |name: String| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(&name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+    let before = zt.forward_authority.export_json().await;
+    assert!(has_a("host-9.home.arpa.".to_string()).await);
+
+    // each member brings two A records, so 100 of them are well over the quota
+    let err = zt
+        .configure_members(network(), members(100))
+        .await
+        .unwrap_err();
+    assert_eq!(err.current_context(), &errors::Error::RecordQuota);
+
+    // and the zone is served as it was, serial included
+    assert_eq!(zt.forward_authority.export_json().await, before);
+    assert!(has_a("host-9.home.arpa.".to_string()).await);
+    assert!(!has_a("host-10.home.arpa.".to_string()).await);
+
+    // a sync back under the quota applies as usual
+    zt.configure_members(network(), members(12)).await.unwrap();
+    assert!(has_a("host-11.home.arpa.".to_string()).await);
+}
+
+#[test]
+fn test_member_mac() {
+    use crate::addresses::member_mac;
+
+    assert_eq!(
+        member_mac("8056c2e21c000001", "efcc1b0947").unwrap(),
+        0x02ef_cc07_eb85
+    );
+    assert_eq!(
+        member_mac("1234567891011121", "abcdef0001").unwrap(),
+        0x22ba_cc7e_7857
+    );
+    // 52: is left to virtual machines
+    assert_eq!(
+        member_mac("1234567891011150", "abcdef0001").unwrap(),
+        0x32ba_cc7e_7857
+    );
+    assert!(member_mac("1234567891011121", "abcdef01").is_err());
+}
+
+#[tokio::test]
+async fn test_bridge_dns() {
+    use crate::{addresses::member_mac, authority::bridged_records, collision::RecordSource};
+    use trust_dns_resolver::{proto::rr::RecordType, Name};
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+
+    let member = member_mac("1234567891011121", "abcdef0001").unwrap();
+    let arp = "\
+IP address       HW type     Flags       HW address            Mask     Device
+10.0.0.1         0x1         0x2         22:ba:cc:7e:78:57     *        ztabcdefgh
+10.0.0.50        0x1         0x2         aa:bb:cc:00:00:50     *        ztabcdefgh
+10.0.0.51        0x1         0x0         00:00:00:00:00:00     *        ztabcdefgh
+192.168.1.1      0x1         0x2         aa:bb:cc:00:00:01     *        eth0
+10.0.0.52        0x1         0x6         aa:bb:cc:00:00:52     *        ztabcdefgh
+";
+
+    // the member, the incomplete entry and the other interface are left out
+    let domain = Name::from_str("home.arpa.").unwrap();
+    let records = bridged_records(arp, "ztabcdefgh", &[member].into(), domain);
+    assert_eq!(
+        records,
+        vec![
+            (
+                Name::from_str("bridge-aabbcc000050.home.arpa.").unwrap(),
+                IpAddr::from_str("10.0.0.50").unwrap()
+            ),
+            (
+                Name::from_str("bridge-aabbcc000052.home.arpa.").unwrap(),
+                IpAddr::from_str("10.0.0.52").unwrap()
+            ),
+        ]
+    );
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    let has_a = |name: &'static str| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+
+    zt.serve_bridged(records).await;
+    assert!(has_a("bridge-aabbcc000050.home.arpa.").await);
+
+    // syncing members keeps them, bar one whose name a member has taken
+    let network: zerotier_api::central_api::types::Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members = serde_json::from_value(serde_json::json!([{
+        "nodeId": "abcdef0001",
+        "networkId": "1234567891011121",
+        "name": "bridge-aabbcc000052",
+        "config": { "ipAssignments": ["10.0.0.1"] },
+    }]))
+    .unwrap();
+    zt.configure_members(network, members).await.unwrap();
+    assert!(has_a("bridge-aabbcc000050.home.arpa.").await);
+    let collision = zt
+        .collisions
+        .read()
+        .await
+        .get(&Name::from_str("bridge-aabbcc000052.home.arpa.").unwrap())
+        .cloned()
+        .unwrap();
+    assert_eq!(collision.sources[1], RecordSource::Bridge);
+}
+
+#[tokio::test]
+async fn test_malformed_member() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+
+    // a deleted member Central still lists has no node ID; another here has no config.
+    let central = MockCentral::with_members(
+        serde_json::json!({ "id": "1234567891011121", "config": {} }),
+        serde_json::json!([
+            { "nodeId": null, "name": "ghost", "config": { "ipAssignments": ["10.0.0.9"] } },
+            { "nodeId": "abcdef0002", "name": "jura", "config": null },
+            { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+        ]),
+        false,
+    )
+    .await;
+    let zt = zt_authority(central.client()).await;
+
+    let (network, members) = zt.get_members().await.unwrap();
+    assert_eq!(members.len(), 3);
+    zt.configure_members(network, members).await.unwrap();
+
+    let found = |name: &str| {
+        let authority = zt.forward_authority.clone();
+        let name = LowerName::from_str(name).unwrap();
+        async move {
+            authority
+                .lookup(&name, RecordType::A, LookupOptions::default())
+                .await
+                .is_ok()
+        }
+    };
+
+    // the malformed members are skipped and everyone else is served
+    assert!(found("islay.home.arpa.").await);
+    assert!(found("zt-abcdef0001.home.arpa.").await);
+    assert!(!found("ghost.home.arpa.").await);
+    assert!(!found("jura.home.arpa.").await);
+}
+
+#[tokio::test]
+async fn test_ttl_scan() {
+    use std::time::Duration;
+    use trust_dns_resolver::{proto::rr::RecordType, Name};
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+    ]))
+    .unwrap();
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network, members).await.unwrap();
+
+    // nothing is served for less than 30 seconds
+    let authority = &zt.forward_authority;
+    assert!(authority.ttl_scan(Duration::from_secs(30)).await.is_empty());
+
+    // the SOA and NS records are, and member records for a minute
+    let domain = Name::from_str("home.arpa.").unwrap();
+    assert_eq!(
+        authority.ttl_scan(Duration::from_secs(31)).await,
+        vec![
+            (domain.clone(), RecordType::NS, 30),
+            (domain.clone(), RecordType::SOA, 30),
+        ]
+    );
+    let found = authority.ttl_scan(Duration::from_secs(61)).await;
+    assert!(found.contains(&(
+        Name::from_str("islay.home.arpa.").unwrap(),
+        RecordType::A,
+        60
+    )));
+    assert!(found.contains(&(domain, RecordType::SOA, 30)));
+
+    #[cfg(unix)]
+    {
+        let dir = tempdir();
+        let path = dir.path().join("control.sock");
+
+        let listener = crate::control::bind_control(&path).unwrap();
+        tokio::spawn(crate::control::serve_control(listener, zt.clone()));
+
+        let response = crate::control::request(
+            &path,
+            serde_json::json!({"cmd": "ttl_scan", "threshold": 31}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response["data"],
+            serde_json::json!([
+                { "name": "home.arpa.", "type": "NS", "ttl": 30 },
+                { "name": "home.arpa.", "type": "SOA", "ttl": 30 },
+            ])
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_address_filter() {
+    use std::net::IpAddr;
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        {
+            "nodeId": "abcdef0001",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.1", "169.254.0.1", "fd00::1", "fe80::1"] },
+        },
+    ]))
+    .unwrap();
+
+    let addresses = |zt: &crate::authority::ZTAuthority, rtype| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            let mut ips: Vec<IpAddr> = authority
+                .lookup(
+                    &LowerName::from_str("islay.home.arpa.").unwrap(),
+                    rtype,
+                    LookupOptions::default(),
+                )
+                .await
+                .unwrap()
+                .iter()
+                .filter_map(|record| record.data().and_then(|rdata| rdata.to_ip_addr()))
+                .collect();
+            ips.sort();
+            ips
+        }
+    };
+    let ip = |s: &str| IpAddr::from_str(s).unwrap();
+
+    // link-local addresses are left out by default
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network.clone(), members.clone())
+        .await
+        .unwrap();
+    assert_eq!(addresses(&zt, RecordType::A).await, vec![ip("10.0.0.1")]);
+    assert_eq!(addresses(&zt, RecordType::AAAA).await, vec![ip("fd00::1")]);
+
+    // an empty filter lets everything through, and any network can be filtered
+    zt.address_filter = Vec::new();
+    zt.configure_members(network.clone(), members.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        addresses(&zt, RecordType::A).await,
+        vec![ip("10.0.0.1"), ip("169.254.0.1")]
+    );
+
+    zt.address_filter = vec!["10.0.0.0/24".parse().unwrap()];
+    zt.configure_members(network, members).await.unwrap();
+    assert_eq!(addresses(&zt, RecordType::A).await, vec![ip("169.254.0.1")]);
+    assert_eq!(
+        addresses(&zt, RecordType::AAAA).await,
+        vec![ip("fd00::1"), ip("fe80::1")]
+    );
+}
+
+#[tokio::test]
+async fn test_junk_ip_assignments() {
+    use crate::{addresses::Calculator, authority::RecordAuthority, traits::ToPointerSOA};
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_resolver::IntoName;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network = serde_json::from_value(serde_json::json!({
+        "id": "1234567891011121",
+        "config": { "v6AssignMode": { "rfc4193": true } },
+    }))
+    .unwrap();
+    // as Central sent during a controller upgrade, among others
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        {
+            "nodeId": "abcdef0001",
+            "networkId": "1234567891011121",
+            "name": "islay",
+            "config": { "ipAssignments": ["null", "10.0.0.1", "", "10.0.0.300", "fd00::1", "fd00::zz"] },
+        },
+        {
+            "nodeId": "abcdef0002",
+            "networkId": "1234567891011121",
+            "name": "jura",
+            "config": { "ipAssignments": ["null"] },
+        },
+    ]))
+    .unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.reverse_authority_map.insert(
+        "10.0.0.0/24".parse().unwrap(),
+        RecordAuthority::new(
+            LowerName::from_str("0.0.10.in-addr.arpa.").unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    let rfc4193 = network.rfc4193().unwrap().to_ptr_zones()[0];
+    zt.reverse_authority_map.insert(
+        rfc4193,
+        RecordAuthority::new(
+            rfc4193.to_ptr_soa_name().unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    let islay_rfc4193 = members[0].rfc4193().unwrap().ip().into_name().unwrap();
+    zt.configure_members(network, members).await.unwrap();
+
+    let found = |authority: &RecordAuthority, name: &str, rtype| {
+        let (authority, name) = (authority.clone(), LowerName::from_str(name).unwrap());
+        async move {
+            authority
+                .lookup(&name, rtype, LookupOptions::default())
+                .await
+                .is_ok()
+        }
+    };
+
+    // the junk is skipped, and the valid assignments served forward and reverse
+    assert!(found(&zt.forward_authority, "islay.home.arpa.", RecordType::A).await);
+    assert!(found(&zt.forward_authority, "islay.home.arpa.", RecordType::AAAA).await);
+    let reverse = &zt.reverse_authority_map[&"10.0.0.0/24".parse().unwrap()];
+    assert!(found(reverse, "1.0.0.10.in-addr.arpa.", RecordType::PTR).await);
+    let reverse = &zt.reverse_authority_map[&rfc4193];
+    assert!(found(reverse, &islay_rfc4193.to_string(), RecordType::PTR).await);
+
+    // nor does a member with nothing valid get any addresses
+    assert!(!found(&zt.forward_authority, "jura.home.arpa.", RecordType::A).await);
+}
+
+#[tokio::test]
+async fn test_rfc4193_toggled() {
+    use crate::{
+        addresses::Calculator, authority::RecordAuthority, log::LogFormat, traits::ToPointerSOA,
+        utils::log_subscriber,
+    };
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::EnvFilter;
+    use trust_dns_resolver::{proto::rr::RecordType, IntoName};
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network = |rfc4193: bool| -> Network {
+        serde_json::from_value(serde_json::json!({
+            "id": "1234567891011121",
+            "config": { "v6AssignMode": { "rfc4193": rfc4193 } },
+        }))
+        .unwrap()
+    };
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        {
+            "nodeId": "abcdef0001",
+            "networkId": "1234567891011121",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.1"] },
+        },
+    ]))
+    .unwrap();
+    let ptr = LowerName::from(members[0].rfc4193().unwrap().ip().into_name().unwrap());
+    let zone = network(true).rfc4193().unwrap().to_ptr_zones()[0];
+
+    let found = |authority: &RecordAuthority, name: &LowerName, rtype| {
+        let (authority, name) = (authority.clone(), name.clone());
+        async move {
+            authority
+                .lookup(&name, rtype, LookupOptions::default())
+                .await
+                .is_ok()
+        }
+    };
+    let islay = LowerName::from_str("islay.home.arpa.").unwrap();
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let guard = tracing::subscriber::set_default(log_subscriber(
+        EnvFilter::new("warn"),
+        LogFormat::Text,
+        move || writer.clone(),
+    ));
+    let warnings = || {
+        String::from_utf8(captured.0.lock().unwrap().clone())
+            .unwrap()
+            .matches("turned on after startup")
+            .count()
+    };
+
+    // turned on after startup, there is no reverse zone; the forward records are still served
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network(false), members.clone())
+        .await
+        .unwrap();
+    assert!(!found(&zt.forward_authority, &islay, RecordType::AAAA).await);
+    zt.configure_members(network(true), members.clone())
+        .await
+        .unwrap();
+    assert!(found(&zt.forward_authority, &islay, RecordType::AAAA).await);
+
+    // which is warned about once, not at every sync, and again once turned off and on
+    zt.configure_members(network(true), members.clone())
+        .await
+        .unwrap();
+    assert_eq!(warnings(), 1);
+    zt.configure_members(network(false), members.clone())
+        .await
+        .unwrap();
+    zt.configure_members(network(true), members.clone())
+        .await
+        .unwrap();
+    assert_eq!(warnings(), 2);
+    drop(guard);
+
+    // on at startup and turned off since, its zone loses the PTR records along with the addresses
+    zt.reverse_authority_map.insert(
+        zone,
+        RecordAuthority::new(
+            zone.to_ptr_soa_name().unwrap(),
+            LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    zt.configure_members(network(true), members.clone())
+        .await
+        .unwrap();
+    let reverse = zt.reverse_authority_map[&zone].clone();
+    assert!(found(&reverse, &ptr, RecordType::PTR).await);
+
+    zt.configure_members(network(false), members.clone())
+        .await
+        .unwrap();
+    assert!(!found(&reverse, &ptr, RecordType::PTR).await);
+    assert!(!found(&zt.forward_authority, &islay, RecordType::AAAA).await);
+
+    // and gets them back when it is turned on again
+    zt.configure_members(network(true), members).await.unwrap();
+    assert!(found(&reverse, &ptr, RecordType::PTR).await);
+}
+
+#[test]
+fn test_completions() {
+    use crate::cli::Cli;
+    use clap::{CommandFactory, Parser};
+    use clap_complete::{generate, Shell};
+
+    assert!(Cli::try_parse_from(["zeronsd", "completions", "tcsh"]).is_err());
+
+    let script = |shell| {
+        let mut out = Vec::new();
+        generate(shell, &mut Cli::command(), "zeronsd", &mut out);
+        String::from_utf8(out).unwrap()
+    };
+
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+        let script = script(shell);
+
+        // subcommands, and the flags of several of them, globals included
+        for word in [
+            "start",
+            "supervise",
+            "network-info",
+            "completions",
+            "hosts-strict",
+            "cert-fingerprint",
+            "control-socket",
+            "lenient-hosts",
+            "version",
+        ] {
+            assert!(script.contains(word), "{} completions lack {}", shell, word);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_missing_network_config() {
+    use crate::utils::update_central_dns;
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        {
+            "nodeId": "abcdef0001",
+            "networkId": "1234567891011121",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.1"] },
+        },
+        { "nodeId": "abcdef0002", "networkId": "1234567891011121", "config": null },
+        { "nodeId": "abcdef0003", "config": { "ipAssignments": null } },
+    ]))
+    .unwrap();
+    let islay = LowerName::from_str("islay.home.arpa.").unwrap();
+
+    // a missing or null config, and v6 modes on a network without an ID, sync the assigned IPs
+    for network in [
+        serde_json::json!({ "id": "1234567891011121" }),
+        serde_json::json!({ "id": "1234567891011121", "config": null }),
+        serde_json::json!({ "id": "1234567891011121", "config": { "v6AssignMode": null } }),
+        serde_json::json!({ "config": { "v6AssignMode": { "6plane": true, "rfc4193": true } } }),
+    ] {
+        let network: Network = serde_json::from_value(network).unwrap();
+        let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+        zt.configure_members(network.clone(), members.clone())
+            .await
+            .unwrap();
+        assert!(
+            zt.forward_authority
+                .lookup(&islay, RecordType::A, LookupOptions::default())
+                .await
+                .is_ok(),
+            "{:?}",
+            network
+        );
+    }
+
+    // the same network from central syncs too, and its DNS settings are left alone
+    let central = MockCentral::with_members(
+        serde_json::json!({ "id": "1234567891011121" }),
+        serde_json::to_value(&members).unwrap(),
+        false,
+    )
+    .await;
+    let zt = zt_authority(central.client()).await;
+    let (network, members) = zt.get_members().await.unwrap();
+    assert!(network.config.is_none());
+    assert_eq!(members.len(), 3);
+    zt.configure_members(network, members).await.unwrap();
+
+    update_central_dns(
+        crate::utils::domain_or_default(None).unwrap(),
+        vec!["10.0.0.1".to_string()],
+        central.client(),
+        "1234567891011121".to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(central.updates(), 0);
+    assert!(central.dns().is_null());
+}
+
+#[tokio::test]
+async fn test_clone_with_domain() {
+    use std::time::Duration;
+    use trust_dns_resolver::{proto::rr::RecordType, Name};
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.0.0.1"] } },
+    ]))
+    .unwrap();
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network, members).await.unwrap();
+
+    // the new zone has its own SOA and the same nameserver, and none of the members
+    let sub = zt
+        .forward_authority
+        .clone_with_domain(LowerName::from_str("db.home.arpa.").unwrap())
+        .await
+        .unwrap();
+    let domain = Name::from_str("db.home.arpa.").unwrap();
+    assert_eq!(
+        sub.ttl_scan(Duration::from_secs(3600)).await,
+        vec![
+            (domain.clone(), RecordType::NS, 30),
+            (domain, RecordType::SOA, 30),
+        ]
+    );
+    let ns = sub
+        .export_json()
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|record| record["type"] == "NS")
+        .unwrap()["rdata"]
+        .clone();
+    assert_eq!(ns, "zt-abcdef0123.home.arpa.");
+}
+
+#[tokio::test]
+async fn test_mock_central_client() {
+    use crate::authority::find_members;
+    use std::{sync::Arc, time::Duration};
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+
+    let central = mock_central(
+        serde_json::json!({
+            "id": "1234567891011121",
+            "config": { "v6AssignMode": { "rfc4193": true } },
+        }),
+        serde_json::json!([
+            {
+                "nodeId": "abcdef0001",
+                "networkId": "1234567891011121",
+                "name": "islay",
+                "config": { "ipAssignments": ["10.0.0.1"] },
+            },
+            { "nodeId": "abcdef0002", "networkId": "1234567891011121", "config": {} },
+        ]),
+    );
+
+    // a whole sync, without a server to answer for Central
+    let zt = zt_authority(central).await;
+    let (network, members) = zt.get_members().await.unwrap();
+    assert_eq!(members.len(), 2);
+    zt.configure_members(network, members).await.unwrap();
+
+    let found = |name: &'static str, rtype| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    rtype,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+    assert!(found("islay.home.arpa.", RecordType::A).await);
+    assert!(found("islay.home.arpa.", RecordType::AAAA).await);
+    assert!(found("zt-abcdef0002.home.arpa.", RecordType::AAAA).await);
+    assert_eq!(
+        zt.network_info().await.unwrap().id.as_deref(),
+        Some("1234567891011121")
+    );
+
+    // an error from Central fails the sync, and the sync loop keeps going past it
+    let mut central = MockCentralClient::new();
+    central.expect_get_network_by_id().returning(|_| {
+        Err(errors::Report::new(errors::Error::CentralApi {
+            status: Some(502),
+        }))
+    });
+    central
+        .expect_get_network_member_list()
+        .times(2..)
+        .returning(|_, _| Ok(Vec::new()));
+
+    let zt = zt_authority(central).await;
+    *zt.update_interval.write().await = Duration::from_millis(10);
+    assert!(zt.get_members().await.is_err());
+    assert!(zt.network_info().await.is_none());
+
+    let cancel = zt.cancel.clone();
+    let sync = tokio::spawn(find_members(Arc::new(zt)));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    cancel.cancel();
+    sync.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_ip_change_replaces_record() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+    use zerotier_api::central_api::types::Member;
+
+    let member = |ip: &str| -> Vec<Member> {
+        serde_json::from_value(serde_json::json!([{
+            "nodeId": "abcdef0001",
+            "networkId": "1234567891011121",
+            "name": "islay",
+            "config": { "ipAssignments": [ip] },
+        }]))
+        .unwrap()
+    };
+
+    // central hands out the member with one address, then another
+    let mut central = MockCentralClient::new();
+    central.expect_get_network_by_id().returning(|_| {
+        Ok(
+            serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+                .unwrap(),
+        )
+    });
+    let (first, second) = (member("10.0.0.1"), member("10.0.0.2"));
+    central
+        .expect_get_network_member_list()
+        .times(1)
+        .returning(move |_, _| Ok(first.clone()));
+    central
+        .expect_get_network_member_list()
+        .times(1)
+        .returning(move |_, _| Ok(second.clone()));
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.0.1.1 jura\n").unwrap();
+
+    let mut zt = zt_authority(central).await;
+    zt.hosts_files = vec![hosts_file.clone()];
+
+    let addresses = |name: &'static str| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            let mut ips: Vec<String> = authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .unwrap()
+                .iter()
+                .filter_map(|record| record.data().and_then(|rdata| rdata.to_ip_addr()))
+                .map(|ip| ip.to_string())
+                .collect();
+            ips.sort();
+            ips
+        }
+    };
+
+    for (member_ip, host_ip) in [("10.0.0.1", "10.0.1.1"), ("10.0.0.2", "10.0.1.2")] {
+        std::fs::write(&hosts_file, format!("{} jura\n", host_ip)).unwrap();
+        zt.configure_hosts().await.unwrap();
+        let (network, members) = zt.get_members().await.unwrap();
+        zt.configure_members(network, members).await.unwrap();
+
+        // only the current address is served, for members and hosts alike
+        assert_eq!(addresses("islay.home.arpa.").await, vec![member_ip]);
+        assert_eq!(addresses("jura.home.arpa.").await, vec![host_ip]);
+    }
+}
+
+#[test]
+fn test_supervise_child_args() {
+    use crate::supervise::child_args;
+    use std::ffi::OsString;
+
+    let args = |s: &str| -> Vec<OsString> { s.split_whitespace().map(OsString::from).collect() };
+
+    for (given, expected) in [
+        (
+            "supervise --foreground 1234567891011121",
+            "start 1234567891011121",
+        ),
+        (
+            "-v supervise --foreground --once --log-file /var/log/zeronsd.log -d zt 1234567891011121",
+            "-v start -d zt 1234567891011121",
+        ),
+        (
+            "supervise --pid-file=/run/zeronsd.pid --foreground -f /etc/hosts --log-file=/tmp/log 1234567891011121",
+            "start -f /etc/hosts 1234567891011121",
+        ),
+        // a network named like the subcommand, or a flag after --, is passed on as it is
+        (
+            "supervise --foreground -d supervise -- --once",
+            "start -d supervise -- --once",
+        ),
+    ] {
+        assert_eq!(child_args(&args(given)), args(expected), "{}", given);
+    }
+}
+
+#[test]
+fn test_next_backoff() {
+    use crate::supervise::next_backoff;
+    use std::time::Duration;
+
+    let (initial, max) = (Duration::from_secs(1), Duration::from_secs(60));
+    let secs = Duration::from_secs;
+
+    // the first restart waits initial, and each after it doubles the wait, up to max
+    let mut previous = None;
+    let waits: Vec<Duration> = (0..8)
+        .map(|_| {
+            let wait = next_backoff(previous, secs(0), initial, max);
+            previous = Some(wait);
+            wait
+        })
+        .collect();
+    assert_eq!(waits, [1, 2, 4, 8, 16, 32, 60, 60].map(secs));
+    assert_eq!(
+        next_backoff(Some(secs(60)), secs(59), initial, max),
+        secs(60)
+    );
+
+    // a run as long as max starts it over
+    assert_eq!(
+        next_backoff(Some(secs(60)), secs(60), initial, max),
+        secs(1)
+    );
+    assert_eq!(
+        next_backoff(Some(secs(8)), secs(3600), initial, max),
+        secs(1)
+    );
+}
+
+#[test]
+fn test_supervise_flags_need_foreground() {
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    for args in [
+        vec!["zeronsd", "supervise", "--once", "1234567891011121"],
+        vec![
+            "zeronsd",
+            "supervise",
+            "--log-file",
+            "/tmp/log",
+            "1234567891011121",
+        ],
+        vec![
+            "zeronsd",
+            "supervise",
+            "--pid-file",
+            "/tmp/pid",
+            "1234567891011121",
+        ],
+    ] {
+        assert!(Cli::try_parse_from(&args).is_err(), "{:?}", args);
+    }
+
+    let cli = Cli::try_parse_from([
+        "zeronsd",
+        "supervise",
+        "--foreground",
+        "--once",
+        "--pid-file",
+        "/tmp/pid",
+        "1234567891011121",
+    ])
+    .unwrap();
+    match cli.command {
+        Command::Supervise(args) => {
+            assert!(args.foreground && args.once);
+            assert_eq!(args.pid_file, Some(PathBuf::from("/tmp/pid")));
+            assert_eq!(args.start.network_id.as_deref(), Some("1234567891011121"));
+        }
+        _ => panic!("not parsed as supervise"),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_foreground_supervisor() {
+    use crate::supervise::Foreground;
+    use std::{ffi::OsString, time::Duration};
+
+    let dir = tempdir();
+    let (counter, log_file, pid_file) = (
+        dir.path().join("runs"),
+        dir.path().join("log"),
+        dir.path().join("pid"),
+    );
+
+    // a nameserver that fails twice, then exits cleanly; it checks the pid file as it goes
+    let script = format!(
+        "echo run >> {counter}; test -s {pid}; echo oops >&2; test $(wc -l < {counter}) -ge 3",
+        counter = counter.display(),
+        pid = pid_file.display(),
+    );
+    let supervisor = |once| Foreground {
+        binpath: PathBuf::from("/bin/sh"),
+        args: vec![OsString::from("-c"), OsString::from(&script)],
+        once,
+        log_file: Some(log_file.clone()),
+        pid_file: Some(pid_file.clone()),
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_secs(5),
+    };
+
+    // with once, the first failure is the supervisor's too
+    assert!(supervisor(true).run().await.is_err());
+    assert_eq!(
+        std::fs::read_to_string(&counter).unwrap().lines().count(),
+        1
+    );
+
+    // otherwise it is restarted until it succeeds
+    supervisor(false).run().await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&counter).unwrap().lines().count(),
+        3
+    );
+    assert!(!pid_file.exists());
+
+    let log = std::fs::read_to_string(&log_file).unwrap();
+    assert_eq!(log.matches("oops").count(), 3, "{}", log);
+}
+
+#[test]
+fn test_listen_fds() {
+    use crate::server::listen_fds;
+
+    for (listen_pid, fds, expected) in [
+        // the sockets are ours, or our supervisor's to pass on
+        (Some("100"), Some("2"), 3..5),
+        (Some("50"), Some("1"), 3..4),
+        // or meant for another process altogether
+        (Some("7"), Some("2"), 3..3),
+        (None, Some("2"), 3..3),
+        (Some("100"), None, 3..3),
+        (Some("100"), Some("0"), 3..3),
+        (Some("100"), Some("-1"), 3..3),
+        (Some("100"), Some("two"), 3..3),
+        (Some("pid"), Some("2"), 3..3),
+    ] {
+        assert_eq!(
+            listen_fds(listen_pid, fds, 100, 50),
+            expected,
+            "{:?} {:?}",
+            listen_pid,
+            fds
+        );
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_activated_sockets() {
+    use crate::server::ActivatedSockets;
+    use std::os::unix::io::IntoRawFd;
+
+    let udp = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let (udp_addr, tcp_addr) = (udp.local_addr().unwrap(), tcp.local_addr().unwrap());
+    let file = std::fs::File::open("/dev/null").unwrap();
+
+    let sockets =
+        ActivatedSockets::from_fds([udp.into_raw_fd(), tcp.into_raw_fd(), file.into_raw_fd()]);
+
+    // each socket goes to the listener on its address, and to that listener alone
+    assert!(sockets.take_tcp(udp_addr).unwrap().is_none());
+    let udp = sockets.take_udp(udp_addr).unwrap().unwrap();
+    assert_eq!(udp.local_addr().unwrap(), udp_addr);
+    assert!(sockets.clone().take_udp(udp_addr).unwrap().is_none());
+
+    let tcp = sockets.take_tcp(tcp_addr).unwrap().unwrap();
+    assert_eq!(tcp.local_addr().unwrap(), tcp_addr);
+    assert!(sockets.take_tcp(tcp_addr).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_prune_keeps_unmanaged_types() {
+    use crate::authority::RecordAuthority;
+    use trust_dns_resolver::proto::rr::{rdata::TXT, RData, RecordType};
+    use trust_dns_server::client::rr::{LowerName, Name};
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let name = |s: &str| Name::from_str(s).unwrap();
+    let txt = |s: &str| RData::TXT(TXT::new(vec![s.to_string()]));
+    let network: Network =
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap();
+    let members = |names: &[&str]| -> Vec<Member> {
+        names
+            .iter()
+            .map(|member| {
+                let i = if *member == "islay" { 1 } else { 2 };
+                serde_json::from_value(serde_json::json!({
+                    "nodeId": format!("abcdef000{}", i),
+                    "name": member,
+                    "config": { "ipAssignments": [format!("10.0.0.{}", i)] },
+                }))
+                .unwrap()
+            })
+            .collect()
+    };
+    let contents = |authority: RecordAuthority| async move {
+        let mut records: Vec<String> = authority
+            .export_json()
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["type"] != "SOA")
+            .map(|r| {
+                format!(
+                    "{} {} {}",
+                    r["name"].as_str().unwrap(),
+                    r["type"].as_str().unwrap(),
+                    r["rdata"].as_str().unwrap()
+                )
+            })
+            .collect();
+        records.sort();
+        records
+    };
+
+    let zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.configure_members(network.clone(), members(&["islay", "jura"]))
+        .await
+        .unwrap();
+
+    // records syncing never writes, at the apex and at a member's name, and a delegation
+    zt.forward_authority
+        .upsert_batch(vec![
+            (name("home.arpa."), vec![txt("v=spf1 -all")]),
+            (name("islay.home.arpa."), vec![txt("peat")]),
+            (
+                name("lab.home.arpa."),
+                vec![RData::NS(name("ns.lab.example.com."))],
+            ),
+        ])
+        .await;
+
+    // they outlive several syncs, the last of which drops islay's address
+    for names in [&["islay", "jura"][..], &["jura"], &["jura"]] {
+        zt.configure_members(network.clone(), members(names))
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        contents(zt.forward_authority.clone()).await,
+        vec![
+            "home.arpa. NS zt-abcdef0123.home.arpa.",
+            "home.arpa. TXT v=spf1 -all",
+            "islay.home.arpa. TXT peat",
+            "jura.home.arpa. A 10.0.0.2",
+            "lab.home.arpa. NS ns.lab.example.com.",
+            "zt-abcdef0002.home.arpa. A 10.0.0.2",
+        ]
+    );
+    assert!(zt.forward_authority.health_check().await.is_ok());
+
+    // a zone managing more types prunes them too, but never its own SOA and NS
+    let authority = RecordAuthority::new(
+        LowerName::from_str("home.arpa.").unwrap(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap()
+    .with_managed_types(&[
+        RecordType::A,
+        RecordType::TXT,
+        RecordType::NS,
+        RecordType::SOA,
+    ]);
+    authority
+        .upsert_batch(vec![
+            (name("home.arpa."), vec![txt("v=spf1 -all")]),
+            (
+                name("lab.home.arpa."),
+                vec![RData::NS(name("ns.lab.example.com."))],
+            ),
+        ])
+        .await;
+    for _ in 0..3 {
+        authority
+            .apply_batch(Vec::new(), Vec::new(), usize::MAX)
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        contents(authority.clone()).await,
+        vec!["home.arpa. NS zt-abcdef0123.home.arpa."]
+    );
+    assert!(authority.health_check().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_wildcard_pruned_with_member() {
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::authority::{AuthorityObject, LookupOptions};
+    use trust_dns_server::client::rr::LowerName;
+
+    let myhost = serde_json::json!({
+        "nodeId": "abcdef0001",
+        "name": "myhost",
+        "config": { "ipAssignments": ["10.0.0.1"] },
+    });
+    let jura = serde_json::json!({
+        "nodeId": "abcdef0002",
+        "name": "jura",
+        "config": { "ipAssignments": ["10.0.0.2"] },
+    });
+
+    // central lists the member, then no longer does
+    let mut central = MockCentralClient::new();
+    central.expect_get_network_by_id().returning(|_| {
+        Ok(
+            serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+                .unwrap(),
+        )
+    });
+    let (before, after) = (
+        serde_json::from_value::<Vec<_>>(serde_json::json!([myhost, jura])).unwrap(),
+        serde_json::from_value::<Vec<_>>(serde_json::json!([jura])).unwrap(),
+    );
+    central
+        .expect_get_network_member_list()
+        .times(1)
+        .returning(move |_, _| Ok(before.clone()));
+    central
+        .expect_get_network_member_list()
+        .times(1)
+        .returning(move |_, _| Ok(after.clone()));
+
+    let mut zt = zt_authority(central).await;
+    zt.wildcard = true;
+
+    let found = |name: &'static str| {
+        let authority = zt.forward_authority.clone();
+        async move {
+            authority
+                .lookup(
+                    &LowerName::from_str(name).unwrap(),
+                    RecordType::A,
+                    LookupOptions::default(),
+                )
+                .await
+                .is_ok()
+        }
+    };
+    let wildcards = || {
+        let authority = zt.forward_authority.clone();
+        async move {
+            let mut names: Vec<String> = authority
+                .export_json()
+                .await
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|r| r["name"].as_str())
+                .filter(|name| name.starts_with("*."))
+                .map(String::from)
+                .collect();
+            names.sort();
+            names
+        }
+    };
+
+    let (network, members) = zt.get_members().await.unwrap();
+    zt.configure_members(network, members).await.unwrap();
+    assert!(found("myhost.home.arpa.").await);
+    assert!(found("www.myhost.home.arpa.").await);
+    assert!(wildcards()
+        .await
+        .contains(&"*.myhost.home.arpa.".to_string()));
+
+    // both the name and its wildcard go with the member; the other member keeps its own
+    let (network, members) = zt.get_members().await.unwrap();
+    zt.configure_members(network, members).await.unwrap();
+    assert!(!found("myhost.home.arpa.").await);
+    assert!(!found("www.myhost.home.arpa.").await);
+    assert!(!found("zt-abcdef0001.home.arpa.").await);
+    assert!(!found("www.zt-abcdef0001.home.arpa.").await);
+    assert_eq!(
+        wildcards().await,
+        vec!["*.jura.home.arpa.", "*.zt-abcdef0002.home.arpa."]
+    );
+    assert!(found("www.jura.home.arpa.").await);
+}
+
+#[tokio::test]
+async fn test_ptr_through_catalog() {
+    use crate::{addresses::Calculator, authority::RecordAuthority, traits::ToPointerSOA};
+    use ipnetwork::IpNetwork;
+    use std::net::SocketAddr;
+    use trust_dns_resolver::proto::{
+        op::{Header, Query},
+        rr::{RData, RecordType},
+    };
+    use trust_dns_server::{
+        authority::LookupOptions,
+        client::rr::{LowerName, Name},
+        server::{Protocol, RequestInfo},
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network: Network = serde_json::from_value(serde_json::json!({
+        "id": "1234567891011121",
+        "config": { "v6AssignMode": { "rfc4193": true } },
+    }))
+    .unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        {
+            "nodeId": "abcdef0001",
+            "networkId": "1234567891011121",
+            "name": "islay",
+            "config": { "ipAssignments": ["10.0.0.1", "10.1.2.3", "10.2.5.6"] },
+        },
+    ]))
+    .unwrap();
+    let rfc4193 = network.rfc4193().unwrap();
+
+    // a /24 and a /16 are served as they are, a /22 as four /24s, and RFC4193 from its /88
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    for cidr in [
+        IpNetwork::from_str("10.0.0.0/24").unwrap(),
+        IpNetwork::from_str("10.1.0.0/16").unwrap(),
+        IpNetwork::from_str("10.2.4.0/22").unwrap(),
+        rfc4193,
+    ] {
+        for zone in cidr.to_ptr_zones() {
+            zt.reverse_authority_map.insert(
+                zone,
+                RecordAuthority::new(
+                    zone.to_ptr_soa_name().unwrap(),
+                    LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+                )
+                .await
+                .unwrap(),
+            );
+        }
+    }
+    zt.configure_members(network, members.clone())
+        .await
+        .unwrap();
+    let catalog = crate::authority::init_catalog(zt.clone()).await.unwrap();
+
+    for (ip, origin) in [
+        ("10.0.0.1", "0.0.10.in-addr.arpa."),
+        ("10.1.2.3", "1.10.in-addr.arpa."),
+        ("10.2.5.6", "5.2.10.in-addr.arpa."),
+        (
+            &members[0].rfc4193().unwrap().ip().to_string(),
+            &rfc4193.to_ptr_soa_name().unwrap().to_string(),
+        ),
+    ] {
+        let ptr = IpAddr::from_str(ip).unwrap().to_hostname().unwrap();
+        let authority = catalog.find(&LowerName::from(&ptr)).unwrap();
+        assert_eq!(authority.origin().to_string(), origin, "{}", ip);
+
+        let header = Header::new();
+        let query = Query::query(ptr.clone(), RecordType::PTR).into();
+        let request = RequestInfo::new(
+            SocketAddr::from_str("10.0.0.9:53").unwrap(),
+            Protocol::Udp,
+            &header,
+            &query,
+        );
+        let answers: Vec<RData> = authority
+            .search(request, LookupOptions::default())
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|record| record.data().cloned())
+            .collect();
+        assert_eq!(
+            answers,
+            vec![RData::PTR(Name::from_str("islay.home.arpa.").unwrap())],
+            "{}",
+            ip
+        );
+    }
+
+    // a zone narrower than the network it is keyed by only takes the PTRs under its origin
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    let narrow = RecordAuthority::new(
+        LowerName::from_str("4.3.10.in-addr.arpa.").unwrap(),
+        LowerName::from_str("zt-abcdef0123.home.arpa.").unwrap(),
+    )
+    .await
+    .unwrap();
+    zt.reverse_authority_map
+        .insert(IpNetwork::from_str("10.3.0.0/16").unwrap(), narrow.clone());
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "islay", "config": { "ipAssignments": ["10.3.4.1"] } },
+        { "nodeId": "abcdef0002", "name": "jura", "config": { "ipAssignments": ["10.3.9.1"] } },
+    ]))
+    .unwrap();
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap(),
+        members,
+    )
+    .await
+    .unwrap();
+    let ptrs: Vec<String> = narrow
+        .export_json()
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|r| r["type"] == "PTR")
+        .map(|r| r["name"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(ptrs, vec!["1.4.3.10.in-addr.arpa."]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_wildcard_any_depth() {
+    use std::net::SocketAddr;
+    use trust_dns_resolver::proto::{
+        op::{Header, Query},
+        rr::{RData, RecordType},
+    };
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupError, LookupOptions},
+        client::rr::Name,
+        server::{Protocol, RequestInfo},
+    };
+    use zerotier_api::central_api::types::{Member, Network};
+
+    let network = || -> Network {
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap()
+    };
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "jura", "config": { "ipAssignments": ["10.0.0.1"] } },
+    ]))
+    .unwrap();
+
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.wildcard = true;
+    zt.configure_members(network(), members).await.unwrap();
+
+    let search = |name: &str, rtype: RecordType| {
+        let (authority, name) = (zt.forward_authority.clone(), Name::from_str(name).unwrap());
+        async move {
+            let header = Header::new();
+            let query = Query::query(name, rtype).into();
+            let request = RequestInfo::new(
+                SocketAddr::from_str("10.0.0.9:53").unwrap(),
+                Protocol::Udp,
+                &header,
+                &query,
+            );
+            authority
+                .search(request, LookupOptions::default())
+                .await
+                .map(|lookup| {
+                    lookup
+                        .iter()
+                        .map(|record| (record.name().to_string(), record.data().cloned()))
+                        .collect::<Vec<_>>()
+                })
+        }
+    };
+    let nxdomain = |result: Result<Vec<_>, LookupError>| match result {
+        Err(e) => e.is_nx_domain(),
+        Ok(_) => false,
+    };
+
+    // the custom name and the node name answer at any depth, under the name asked for
+    for base in ["jura.home.arpa.", "zt-abcdef0001.home.arpa."] {
+        for prefix in ["www.", "a.b.", "x.y.z."] {
+            let name = format!("{}{}", prefix, base);
+            assert_eq!(
+                search(&name, RecordType::A).await.expect(&name),
+                vec![(name.clone(), Some(RData::A("10.0.0.1".parse().unwrap())))],
+                "{}",
+                name
+            );
+        }
+    }
+
+    // other types are no data rather than no such name; names under no member are still unknown
+    let aaaa = search("a.b.jura.home.arpa.", RecordType::AAAA).await;
+    assert!(aaaa.is_err() && !nxdomain(aaaa));
+    assert!(nxdomain(
+        search("a.b.islay.home.arpa.", RecordType::A).await
+    ));
+
+    // names that exist between the query and the member stop the wildcard, as in RFC 4592
+    zt.forward_authority
+        .upsert_batch(vec![(
+            Name::from_str("b.jura.home.arpa.").unwrap(),
+            vec![RData::A("10.0.0.2".parse().unwrap())],
+        )])
+        .await;
+    assert!(nxdomain(search("a.b.jura.home.arpa.", RecordType::A).await));
+    assert!(search("a.c.jura.home.arpa.", RecordType::A).await.is_ok());
+    assert!(
+        zt.forward_authority
+            .has_name(&Name::from_str("x.y.jura.home.arpa.").unwrap())
+            .await
+    );
+
+    // once the member is gone, so is every name under it
+    zt.configure_members(network(), Vec::new()).await.unwrap();
+    for name in [
+        "www.jura.home.arpa.",
+        "x.y.z.jura.home.arpa.",
+        "a.b.zt-abcdef0001.home.arpa.",
+    ] {
+        assert!(nxdomain(search(name, RecordType::A).await), "{}", name);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_missing_resolv_conf() {
+    use crate::control::{handle, Command};
+    use trust_dns_server::client::rr::LowerName;
+
+    // without a resolver configuration our zone is served, and nothing else
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.resolv_conf = Some(PathBuf::from("/nonexistent/resolv.conf"));
+    let catalog = crate::authority::init_catalog(zt.clone()).await.unwrap();
+    assert!(catalog
+        .find(&LowerName::from_str("myhost.home.arpa.").unwrap())
+        .is_some());
+    assert!(catalog
+        .find(&LowerName::from_str("example.com.").unwrap())
+        .is_none());
+    assert_eq!(
+        handle(&zt, Command::Forwarding).await.data,
+        Some(serde_json::json!(false))
+    );
+
+    // once it's there, everything else is forwarded
+    let dir = tempdir();
+    let path = dir.path().join("resolv.conf");
+    std::fs::write(&path, "nameserver 10.0.0.53\n").unwrap();
+    zt.resolv_conf = Some(path.clone());
+    let catalog = crate::authority::init_catalog(zt.clone()).await.unwrap();
+    assert!(catalog
+        .find(&LowerName::from_str("example.com.").unwrap())
+        .is_some());
+    assert_eq!(
+        handle(&zt, Command::Forwarding).await.data,
+        Some(serde_json::json!(true))
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_max_member_age() {
+    use crate::authority::active_members;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use trust_dns_resolver::proto::rr::RecordType;
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::LowerName,
+    };
+    use zerotier_api::central_api::types::Member;
+
+    let now = SystemTime::now();
+    let hours_ago = |hours: u64| {
+        (now - Duration::from_secs(hours * 3600))
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64
+    };
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        {
+            "nodeId": "abcdef0001", "name": "recent", "lastSeen": hours_ago(1),
+            "config": { "ipAssignments": ["10.0.0.1"] },
+        },
+        {
+            "nodeId": "abcdef0002", "name": "stale", "lastSeen": hours_ago(100),
+            "config": { "ipAssignments": ["10.0.0.2"], "lastAuthorizedTime": hours_ago(200) },
+        },
+        {
+            "nodeId": "abcdef0003", "name": "reauthorized", "lastSeen": hours_ago(100),
+            "config": { "ipAssignments": ["10.0.0.3"], "lastAuthorizedTime": hours_ago(2) },
+        },
+        {
+            "nodeId": "abcdef0004", "name": "reset", "lastSeen": 0,
+            "config": { "ipAssignments": ["10.0.0.4"], "lastAuthorizedTime": 0 },
+        },
+        {
+            "nodeId": "abcdef0005", "name": "unknown",
+            "config": { "ipAssignments": ["10.0.0.5"] },
+        },
+    ]))
+    .unwrap();
+
+    let names = |members: Vec<Member>| -> Vec<String> {
+        members
+            .into_iter()
+            .map(|member| member.name.unwrap_or_default())
+            .collect()
+    };
+    assert_eq!(
+        names(active_members(
+            members.clone(),
+            Duration::from_secs(48 * 3600),
+            now
+        )),
+        vec!["recent", "reauthorized", "reset", "unknown"]
+    );
+    assert_eq!(
+        names(active_members(
+            members.clone(),
+            Duration::from_secs(3600 / 2),
+            now
+        )),
+        vec!["reset", "unknown"]
+    );
+    // an age longer than there have been milliseconds keeps everyone
+    assert_eq!(
+        names(active_members(members.clone(), Duration::MAX, now)).len(),
+        5
+    );
+
+    // the most hours there are is kept as the longest age, not wrapped around
+    let launcher = crate::init::Launcher {
+        max_member_age_hours: Some(u64::MAX),
+        ..Default::default()
+    };
+    assert_eq!(
+        launcher.max_member_age(),
+        Some(Duration::from_secs(u64::MAX))
+    );
+
+    // written as a duration, or in hours under the old key
+    let launcher: crate::init::Launcher = serde_yml::from_str("max_member_age: 30d\n").unwrap();
+    assert_eq!(
+        launcher.max_member_age(),
+        Some(Duration::from_secs(30 * 24 * 3600))
+    );
+    let launcher: crate::init::Launcher =
+        serde_yml::from_str("max_member_age_hours: 48\n").unwrap();
+    assert_eq!(
+        launcher.max_member_age(),
+        Some(Duration::from_secs(48 * 3600))
+    );
+
+    // a stale member gets no records, and loses those it had
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    let network = || {
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap()
+    };
+    zt.configure_members(network(), members.clone())
+        .await
+        .unwrap();
+    zt.max_member_age = Some(Duration::from_secs(48 * 3600));
+    zt.configure_members(network(), members).await.unwrap();
+
+    for (name, found) in [
+        ("recent.home.arpa.", true),
+        ("stale.home.arpa.", false),
+        ("zt-abcdef0002.home.arpa.", false),
+        ("reauthorized.home.arpa.", true),
+        ("reset.home.arpa.", true),
+        ("unknown.home.arpa.", true),
+    ] {
+        let lookup = zt
+            .forward_authority
+            .lookup(
+                &LowerName::from_str(name).unwrap(),
+                RecordType::A,
+                LookupOptions::default(),
+            )
+            .await;
+        assert_eq!(lookup.is_ok(), found, "{}", name);
+    }
+}
+
+#[test]
+fn test_discovered_name_servers() {
+    use crate::{authority::discovered_name_servers, traits::UpstreamDiscovery};
+    use std::net::SocketAddr;
+    use trust_dns_resolver::config::Protocol;
+
+    struct Fake(Vec<&'static str>);
+
+    impl UpstreamDiscovery for Fake {
+        fn name_servers(&self) -> error_stack::Result<Vec<IpAddr>, errors::Error> {
+            Ok(self
+                .0
+                .iter()
+                .map(|ip| IpAddr::from_str(ip).unwrap())
+                .collect())
+        }
+    }
+
+    let listen_ips = vec![
+        IpAddr::from_str("10.147.17.1").unwrap(),
+        IpAddr::from_str("fd00::1").unwrap(),
+    ];
+
+    // our own addresses, as ZeroTier hands them to this host too, are left out, as are repeats
+    let servers = discovered_name_servers(
+        &Fake(vec![
+            "10.147.17.1",
+            "192.168.1.1",
+            "fd00::1",
+            "0.0.0.0",
+            "192.168.1.1",
+            "2001:db8::53",
+        ]),
+        &listen_ips,
+        true,
+    )
+    .unwrap();
+    let servers: Vec<(SocketAddr, Protocol)> = servers
+        .iter()
+        .map(|server| (server.socket_addr, server.protocol))
+        .collect();
+    let server = |addr: &str, protocol| (SocketAddr::from_str(addr).unwrap(), protocol);
+    assert_eq!(
+        servers,
+        vec![
+            server("192.168.1.1:53", Protocol::Udp),
+            server("192.168.1.1:53", Protocol::Tcp),
+            server("[2001:db8::53]:53", Protocol::Udp),
+            server("[2001:db8::53]:53", Protocol::Tcp),
+        ]
+    );
+
+    // with nobody but ourselves to ask, there is no forwarder
+    assert!(discovered_name_servers(&Fake(vec!["10.147.17.1"]), &listen_ips, true).is_err());
+    assert!(discovered_name_servers(&Fake(vec![]), &listen_ips, true).is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_hosts_wildcard_of_member() {
+    use crate::collision::RecordSource;
+    use trust_dns_resolver::proto::rr::{RData, RecordType};
+    use trust_dns_server::{
+        authority::{AuthorityObject, LookupOptions},
+        client::rr::{LowerName, Name},
+    };
+    use zerotier_api::central_api::types::Member;
+
+    let dir = tempdir();
+    let hosts_file = dir.path().join("hosts");
+    std::fs::write(&hosts_file, "10.9.9.9 *.jura\n").unwrap();
+
+    // with -w the member writes *.jura too, which the hosts file claims
+    let mut zt = zt_authority(zerotier_api::central_api::Client::new("http://127.0.0.1:1")).await;
+    zt.wildcard = true;
+    zt.hosts_files = vec![hosts_file];
+    zt.configure_hosts().await.unwrap();
+    let members: Vec<Member> = serde_json::from_value(serde_json::json!([
+        { "nodeId": "abcdef0001", "name": "jura", "config": { "ipAssignments": ["10.0.0.1"] } },
+    ]))
+    .unwrap();
+    zt.configure_members(
+        serde_json::from_value(serde_json::json!({ "id": "1234567891011121", "config": {} }))
+            .unwrap(),
+        members,
+    )
+    .await
+    .unwrap();
+
+    let wildcard = Name::from_str("*.jura.home.arpa.").unwrap();
+    let collisions = zt.collisions().await;
+    let collision = collisions
+        .iter()
+        .find(|collision| collision.name == wildcard)
+        .unwrap();
+    assert_eq!(
+        collision.sources,
+        vec![
+            RecordSource::Member("abcdef0001".to_string()),
+            RecordSource::Hosts
+        ]
+    );
+
+    // the hosts file wins by default; the member keeps its own name
+    let a = |name: &str| {
+        let (authority, name) = (
+            zt.forward_authority.clone(),
+            LowerName::from_str(name).unwrap(),
+        );
+        async move {
+            authority
+                .lookup(&name, RecordType::A, LookupOptions::default())
+                .await
+                .unwrap()
+                .iter()
+                .filter_map(|record| record.data().cloned())
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(
+        a("*.jura.home.arpa.").await,
+        vec![RData::A("10.9.9.9".parse().unwrap())]
+    );
+    assert_eq!(
+        a("jura.home.arpa.").await,
+        vec![RData::A("10.0.0.1".parse().unwrap())]
+    );
+}
+
+#[test]
+fn test_rollover() {
+    use crate::dnssec::{KeyState, Phase, Rollover, RolloverTiming};
+    use std::time::{Duration, SystemTime};
+
+    let hours = |n: u64| Duration::from_secs(n * 3600);
+    let timing = RolloverTiming {
+        propagation_delay: hours(1),
+        key_ttl: hours(2),
+        signature_ttl: hours(4),
+        safety_margin: hours(1),
+    };
+    // Dprp + max(TTLkey, TTLsig) + the margin
+    assert_eq!(timing.min_overlap(), hours(6));
+    assert_eq!(RolloverTiming::default().min_overlap(), hours(2));
+
+    let start = SystemTime::UNIX_EPOCH + hours(1000);
+    let rollover = Rollover::new(start, timing, None).unwrap();
+    assert_eq!(rollover.completes_at(), start + hours(6));
+    assert_eq!(rollover.new_key_active_at(), start + hours(3));
+
+    // (time, phase, old key, new key)
+    for (at, phase, old, new) in [
+        (
+            start - hours(1),
+            Phase::Pending,
+            KeyState::Active,
+            KeyState::Generated,
+        ),
+        (
+            start,
+            Phase::DoubleSigned,
+            KeyState::Active,
+            KeyState::Published,
+        ),
+        (
+            start + hours(3),
+            Phase::DoubleSigned,
+            KeyState::Active,
+            KeyState::Active,
+        ),
+        (
+            start + hours(6),
+            Phase::Complete,
+            KeyState::Removed,
+            KeyState::Active,
+        ),
+    ] {
+        assert_eq!(rollover.phase(at), phase, "{:?}", at);
+        assert_eq!(rollover.old_key(at), old, "{:?}", at);
+        assert_eq!(rollover.new_key(at), new, "{:?}", at);
+    }
+
+    // while double-signed, both keys sign; before and after, only one does
+    assert!(
+        rollover.signs_with_old(start - hours(1)) && !rollover.signs_with_new(start - hours(1))
+    );
+    assert!(rollover.signs_with_old(start) && rollover.signs_with_new(start));
+    assert!(
+        !rollover.signs_with_old(start + hours(6)) && rollover.signs_with_new(start + hours(6))
+    );
+
+    // a longer overlap is taken as given, a shorter one refused
+    let longer = Rollover::new(start, timing, Some(hours(24))).unwrap();
+    assert_eq!(longer.phase(start + hours(12)), Phase::DoubleSigned);
+    assert_eq!(
+        Rollover::new(start, timing, Some(hours(5)))
+            .unwrap_err()
+            .current_context(),
+        &errors::Error::Config
+    );
+}
+
+#[test]
+fn test_rotate_key_unsigned() {
+    use crate::cli::{rotate_key, Cli, Command};
+    use clap::Parser;
+
+    let args =
+        |overlap: &str| match Cli::try_parse_from(["zeronsd", "rotate-key", "--overlap", overlap])
+            .unwrap()
+            .command
+        {
+            Command::RotateKey(args) => args,
+            _ => panic!("not parsed as rotate-key"),
+        };
+
+    // there is no key to rotate, but a bad overlap is still pointed out first
+    assert_eq!(
+        rotate_key(args("3h")).unwrap_err().current_context(),
+        &errors::Error::Unsigned
+    );
+    assert_eq!(
+        rotate_key(args("10m")).unwrap_err().current_context(),
+        &errors::Error::Config
+    );
+}
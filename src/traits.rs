@@ -1,37 +1,133 @@
-use std::str::FromStr;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
 
-use crate::errors;
+use crate::{
+    errors::{self, ApiResultExt},
+    utils::get_network_members,
+};
+use async_trait::async_trait;
 use error_stack::*;
 use ipnetwork::IpNetwork;
 use lazy_static::lazy_static;
 use regex::Regex;
 use trust_dns_resolver::{proto::error::ProtoError, IntoName, Name};
 use trust_dns_server::client::rr::LowerName;
-use zerotier_api::central_api::types::Member;
+use zerotier_api::central_api::{
+    self,
+    types::{Member, Network},
+};
+
+// CentralClient is what syncing needs from ZeroTier Central: the network and its members. It is
+// a trait so tests can hand ZTAuthority canned answers instead of a server.
+#[async_trait]
+pub trait CentralClient: Send + Sync {
+    async fn get_network_by_id(&self, network_id: &str) -> Result<Network, errors::Error>;
+    // get_network_member_list fetches the members, following at most max_pages pages of them.
+    async fn get_network_member_list(
+        &self,
+        network_id: &str,
+        max_pages: u32,
+    ) -> Result<Vec<Member>, errors::Error>;
+}
+
+#[async_trait]
+impl CentralClient for central_api::Client {
+    async fn get_network_by_id(&self, network_id: &str) -> Result<Network, errors::Error> {
+        Ok(central_api::Client::get_network_by_id(self, network_id)
+            .await
+            .central_context()?
+            .into_inner())
+    }
+
+    async fn get_network_member_list(
+        &self,
+        network_id: &str,
+        max_pages: u32,
+    ) -> Result<Vec<Member>, errors::Error> {
+        get_network_members(self, network_id, max_pages).await
+    }
+}
+
+// UpstreamDiscovery finds the name servers the system itself resolves with, where there is no
+// resolv.conf to read them from. It is a trait so the filtering of what it finds can be tested
+// without the system.
+pub trait UpstreamDiscovery {
+    fn name_servers(&self) -> Result<Vec<IpAddr>, errors::Error>;
+}
 
 pub trait ToPointerSOA {
+    fn to_ptr_zones(&self) -> Vec<IpNetwork>;
     fn to_ptr_soa_name(&self) -> Result<LowerName, ProtoError>;
 }
 
+// ptr_octet_factor is how many bits each label of a reverse name stands for.
+fn ptr_octet_factor(network: &IpNetwork) -> u8 {
+    match network {
+        IpNetwork::V4(_) => 8,
+        IpNetwork::V6(_) => 4,
+    }
+}
+
 impl ToPointerSOA for IpNetwork {
+    // to_ptr_zones is the networks reverse zones can be made for that together cover exactly this
+    // one: itself on an octet (IPv4) or nibble (IPv6) boundary, otherwise its subnets at the next
+    // boundary down, so a /22 is served as four /24s and no unrelated space is claimed.
+    fn to_ptr_zones(&self) -> Vec<IpNetwork> {
+        let octet_factor = ptr_octet_factor(self);
+        let prefix = self.prefix().div_ceil(octet_factor) * octet_factor;
+        if prefix == self.prefix() {
+            return vec![IpNetwork::new(self.network(), prefix).unwrap()];
+        }
+
+        // the prefix grew by less than a label, so there are at most 128 zones and the shifts
+        // below stay in range.
+        let count = 1u32 << (prefix - self.prefix());
+        match self {
+            IpNetwork::V4(network) => {
+                let base = u32::from(network.network());
+                let step = 1u32 << (32 - prefix);
+                (0..count)
+                    .map(|i| {
+                        IpNetwork::new(Ipv4Addr::from(base + i * step).into(), prefix).unwrap()
+                    })
+                    .collect()
+            }
+            IpNetwork::V6(network) => {
+                let base = u128::from(network.network());
+                let step = 1u128 << (128 - prefix);
+                (0..count as u128)
+                    .map(|i| {
+                        IpNetwork::new(Ipv6Addr::from(base + i * step).into(), prefix).unwrap()
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // to_ptr_soa_name only names zones on a boundary; anything else has to be split up with
+    // to_ptr_zones first.
     fn to_ptr_soa_name(&self) -> Result<LowerName, ProtoError> {
-        // how many bits in each ptr octet
-        let octet_factor = match self {
-            IpNetwork::V4(_) => 8,
-            IpNetwork::V6(_) => 4,
-        };
+        let octet_factor = ptr_octet_factor(self);
+        if !self.prefix().is_multiple_of(octet_factor) {
+            return Err(Report::new(ProtoError::from(format!(
+                "{} is not on a reverse zone boundary",
+                self
+            ))));
+        }
 
         Ok(self
             .network()
             .into_name()?
             // round off the subnet, account for in-addr.arpa.
-            .trim_to((self.prefix() as usize / octet_factor) + 2)
+            .trim_to((self.prefix() as usize / octet_factor as usize) + 2)
             .into())
     }
 }
 
 pub trait ToWildcard {
-    fn to_wildcard(&self) -> Name;
+    fn to_wildcard(&self) -> Self;
 }
 
 impl ToWildcard for Name {
@@ -41,6 +137,13 @@ impl ToWildcard for Name {
     }
 }
 
+impl ToWildcard for LowerName {
+    // to_wildcard goes through Name, so wildcard keys compare equal however they were built.
+    fn to_wildcard(&self) -> LowerName {
+        Name::from(self).to_wildcard().into()
+    }
+}
+
 lazy_static! {
     static ref TRANSLATION_TABLE: Box<[(Regex, &'static str)]> = Box::new([
         (Regex::new(r"\s+").unwrap(), "-"), // translate whitespace to `-`
@@ -59,24 +162,30 @@ impl ToHostname for &str {
     }
 
     fn to_fqdn(&self, domain: Name) -> Result<Name, errors::Error> {
-        Ok(self
-            .to_hostname()
-            .change_context(errors::Error)?
-            .append_domain(&domain)
-            .unwrap())
+        Ok(self.to_hostname()?.append_domain(&domain).unwrap())
     }
 }
 
 impl ToHostname for Member {
     fn to_hostname(&self) -> Result<Name, errors::Error> {
-        ("zt-".to_string() + &self.node_id.clone().unwrap()).to_hostname()
+        member_hostname(self)?.to_hostname()
     }
 
     fn to_fqdn(&self, domain: Name) -> Result<Name, errors::Error> {
-        ("zt-".to_string() + &self.node_id.clone().unwrap()).to_fqdn(domain)
+        member_hostname(self)?.to_fqdn(domain)
     }
 }
 
+// member_hostname is zt-<node id>, for members Central gave a node ID.
+fn member_hostname(member: &Member) -> Result<String, errors::Error> {
+    member
+        .node_id
+        .as_deref()
+        .map(|node_id| format!("zt-{}", node_id))
+        .ok_or(errors::Error::Member)
+        .attach_printable("member has no node ID")
+}
+
 impl ToHostname for String {
     // to_hostname turns member names into trust-dns compatible dns names.
     fn to_hostname(&self) -> Result<Name, errors::Error> {
@@ -88,23 +197,69 @@ impl ToHostname for String {
         let s = s.trim();
 
         if s == "." || s.ends_with('.') {
-            return Err(errors::Error).attach_printable(format!("Record {} not entered into catalog: '.' and records that ends in '.' are disallowed", s));
+            return Err(errors::Error::DnsName).attach_printable(format!("Record {} not entered into catalog: '.' and records that ends in '.' are disallowed", s));
         }
 
         if s.is_empty() {
-            return Err(errors::Error)
+            return Err(errors::Error::DnsName)
                 .attach_printable(format!("translated hostname {} is an empty string", self));
         }
 
-        Ok(s.trim().into_name().change_context(errors::Error)?)
+        s.trim().into_name().change_context(errors::Error::DnsName)
     }
 
     fn to_fqdn(&self, domain: Name) -> Result<Name, errors::Error> {
-        Ok(self
-            .to_hostname()
-            .change_context(errors::Error)?
-            .append_domain(&domain)
-            .unwrap())
+        Ok(self.to_hostname()?.append_domain(&domain).unwrap())
+    }
+}
+
+// addresses name their PTR records: 10.0.0.1 is 1.0.0.10.in-addr.arpa., and IPv6 addresses are
+// spelled out a nibble at a time under ip6.arpa. Those names are already rooted, so to_fqdn
+// ignores the domain.
+impl ToHostname for IpAddr {
+    fn to_hostname(&self) -> Result<Name, errors::Error> {
+        match self {
+            IpAddr::V4(ip) => ip.to_hostname(),
+            IpAddr::V6(ip) => ip.to_hostname(),
+        }
+    }
+
+    fn to_fqdn(&self, _domain: Name) -> Result<Name, errors::Error> {
+        self.to_hostname()
+    }
+}
+
+impl ToHostname for Ipv4Addr {
+    fn to_hostname(&self) -> Result<Name, errors::Error> {
+        let labels = self
+            .octets()
+            .iter()
+            .rev()
+            .map(u8::to_string)
+            .collect::<Vec<_>>();
+        Name::from_str(&format!("{}.in-addr.arpa.", labels.join(".")))
+            .change_context(errors::Error::DnsName)
+    }
+
+    fn to_fqdn(&self, _domain: Name) -> Result<Name, errors::Error> {
+        self.to_hostname()
+    }
+}
+
+impl ToHostname for Ipv6Addr {
+    fn to_hostname(&self) -> Result<Name, errors::Error> {
+        let labels = self
+            .octets()
+            .iter()
+            .rev()
+            .map(|octet| format!("{:x}.{:x}", octet & 0xf, octet >> 4))
+            .collect::<Vec<_>>();
+        Name::from_str(&format!("{}.ip6.arpa.", labels.join(".")))
+            .change_context(errors::Error::DnsName)
+    }
+
+    fn to_fqdn(&self, _domain: Name) -> Result<Name, errors::Error> {
+        self.to_hostname()
     }
 }
 
@@ -114,13 +269,14 @@ mod tests {
 
     use super::{ToHostname, ToPointerSOA, ToWildcard};
     use ipnetwork::IpNetwork;
-    use trust_dns_resolver::Name;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use trust_dns_resolver::{IntoName, Name};
     use trust_dns_server::client::rr::LowerName;
     use zerotier_api::central_api::types::Member;
 
     #[test]
     fn test_to_ptr_soa_name() {
-        for item in vec![
+        for item in [
             (
                 IpNetwork::from_str("1.2.3.4/24").unwrap(),
                 LowerName::from_str("3.2.1.in-addr.arpa").unwrap(),
@@ -134,23 +290,155 @@ mod tests {
                 LowerName::from_str("1.in-addr.arpa").unwrap(),
             ),
             (
-                IpNetwork::from_str("1.2.3.4/12").unwrap(),
-                LowerName::from_str("1.in-addr.arpa").unwrap(),
+                IpNetwork::from_str("1.2.3.4/32").unwrap(),
+                LowerName::from_str("4.3.2.1.in-addr.arpa").unwrap(),
             ),
+        ] {
+            assert_eq!(item.0.to_ptr_soa_name().unwrap(), item.1);
+        }
+
+        // networks off a boundary have no zone of their own.
+        for network in ["1.2.3.4/12", "1.2.3.4/22", "1.2.3.4/26", "fd00:1:2:3::/62"] {
+            assert!(IpNetwork::from_str(network)
+                .unwrap()
+                .to_ptr_soa_name()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_to_ptr_zones() {
+        for (network, zones) in [
             (
-                IpNetwork::from_str("1.2.3.4/22").unwrap(),
-                LowerName::from_str("2.1.in-addr.arpa").unwrap(),
+                "10.40.0.0/22",
+                vec![
+                    "10.40.0.0/24",
+                    "10.40.1.0/24",
+                    "10.40.2.0/24",
+                    "10.40.3.0/24",
+                ],
             ),
+            ("10.0.0.0/24", vec!["10.0.0.0/24"]),
+            ("10.1.2.3/24", vec!["10.1.2.0/24"]),
+            ("10.1.2.3/32", vec!["10.1.2.3/32"]),
+            ("10.1.2.3/0", vec!["0.0.0.0/0"]),
             (
-                IpNetwork::from_str("1.2.3.4/26").unwrap(),
-                LowerName::from_str("3.2.1.in-addr.arpa").unwrap(),
+                "fd80:56c2:e21c:0:199:9300::/88",
+                vec!["fd80:56c2:e21c:0:199:9300::/88"],
             ),
             (
-                IpNetwork::from_str("1.2.3.4/32").unwrap(),
-                LowerName::from_str("4.3.2.1.in-addr.arpa").unwrap(),
+                "fd00:1:2:3::/62",
+                vec![
+                    "fd00:1:2:0::/64",
+                    "fd00:1:2:1::/64",
+                    "fd00:1:2:2::/64",
+                    "fd00:1:2:3::/64",
+                ],
             ),
         ] {
-            assert_eq!(item.0.to_ptr_soa_name().unwrap(), item.1);
+            let network = IpNetwork::from_str(network).unwrap();
+            let zones: Vec<IpNetwork> = zones
+                .iter()
+                .map(|zone| IpNetwork::from_str(zone).unwrap())
+                .collect();
+            assert_eq!(network.to_ptr_zones(), zones, "{}", network);
+            for zone in zones {
+                assert!(zone.to_ptr_soa_name().is_ok());
+            }
+        }
+
+        // a /12 is sixteen /16s, and a /25 128 host zones; neither reaches outside the network.
+        let zones = IpNetwork::from_str("172.16.0.0/12").unwrap().to_ptr_zones();
+        assert_eq!(zones.len(), 16);
+        assert_eq!(zones[0], IpNetwork::from_str("172.16.0.0/16").unwrap());
+        assert_eq!(zones[15], IpNetwork::from_str("172.31.0.0/16").unwrap());
+
+        let zones = IpNetwork::from_str("10.40.7.128/25")
+            .unwrap()
+            .to_ptr_zones();
+        assert_eq!(zones.len(), 128);
+        assert_eq!(zones[0], IpNetwork::from_str("10.40.7.128/32").unwrap());
+        assert_eq!(zones[127], IpNetwork::from_str("10.40.7.255/32").unwrap());
+    }
+
+    #[test]
+    fn test_to_hostname_ipv4() {
+        for (ip, name) in [
+            ("1.2.3.4", "4.3.2.1.in-addr.arpa."),
+            ("10.0.0.1", "1.0.0.10.in-addr.arpa."),
+            ("0.0.0.0", "0.0.0.0.in-addr.arpa."),
+            ("255.255.255.255", "255.255.255.255.in-addr.arpa."),
+            ("192.168.100.12", "12.100.168.192.in-addr.arpa."),
+        ] {
+            let ip = Ipv4Addr::from_str(ip).unwrap();
+            let name = Name::from_str(name).unwrap();
+            assert_eq!(ip.to_hostname().unwrap(), name);
+            assert!(ip.to_hostname().unwrap().is_fqdn());
+            assert_eq!(IpAddr::V4(ip).to_hostname().unwrap(), name);
+            assert_eq!(IpAddr::V4(ip).into_name().unwrap(), name);
+            assert_eq!(
+                ip.to_fqdn(Name::from_str("home.arpa").unwrap()).unwrap(),
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_hostname_ipv6() {
+        for (ip, name) in [
+            (
+                "::1",
+                "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa.",
+            ),
+            (
+                "::",
+                "0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa.",
+            ),
+            (
+                "2001:db8::567:89ab",
+                "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa.",
+            ),
+            (
+                "fd80:56c2:e21c:0:199:9300:abcd:ef01",
+                "1.0.f.e.d.c.b.a.0.0.3.9.9.9.1.0.0.0.0.0.c.1.2.e.2.c.6.5.0.8.d.f.ip6.arpa.",
+            ),
+            (
+                "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+                "f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.f.ip6.arpa.",
+            ),
+        ] {
+            let ip = Ipv6Addr::from_str(ip).unwrap();
+            let name = Name::from_str(name).unwrap();
+            assert_eq!(ip.to_hostname().unwrap(), name);
+            assert!(ip.to_hostname().unwrap().is_fqdn());
+            assert_eq!(IpAddr::V6(ip).to_hostname().unwrap(), name);
+            assert_eq!(IpAddr::V6(ip).into_name().unwrap(), name);
+            assert_eq!(
+                ip.to_fqdn(Name::from_str("home.arpa").unwrap()).unwrap(),
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_hostname_in_ptr_zone() {
+        // every address is named under the reverse zone holding it
+        for (network, ip) in [
+            ("10.40.0.0/22", "10.40.3.7"),
+            (
+                "fd80:56c2:e21c:0:199:9300::/88",
+                "fd80:56c2:e21c:0:199:9300:abcd:ef01",
+            ),
+        ] {
+            let network = IpNetwork::from_str(network).unwrap();
+            let ip = IpAddr::from_str(ip).unwrap();
+            let zone = network
+                .to_ptr_zones()
+                .into_iter()
+                .find(|zone| zone.contains(ip))
+                .unwrap();
+            let zone = Name::from(zone.to_ptr_soa_name().unwrap());
+            assert!(zone.zone_of(&ip.to_hostname().unwrap()), "{}", ip);
         }
     }
 
@@ -159,6 +447,16 @@ mod tests {
         let hostname = "test.home.arpa".to_hostname().unwrap();
         let wildcard = hostname.to_wildcard();
         assert_eq!(wildcard.to_string(), "*.test.home.arpa.");
+
+        let lower = LowerName::from(&hostname).to_wildcard();
+        assert_eq!(lower.to_string(), "*.test.home.arpa.");
+        assert_eq!(lower, LowerName::from(&wildcard));
+        assert_eq!(
+            LowerName::from_str("Test.Home.Arpa.")
+                .unwrap()
+                .to_wildcard(),
+            lower
+        );
     }
 
     #[test]
@@ -204,6 +502,12 @@ mod tests {
         assert!(member
             .to_fqdn(Name::from_str("home.arpa").unwrap())
             .is_err());
+
+        member.node_id = None;
+        assert_eq!(
+            member.to_hostname().unwrap_err().current_context(),
+            &crate::errors::Error::Member
+        );
     }
 
     #[test]
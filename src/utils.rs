@@ -1,18 +1,26 @@
-use std::{net::IpAddr, path::Path, str::FromStr, sync::Once};
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Once, OnceLock},
+    time::Duration,
+};
 
 use ipnetwork::IpNetwork;
 use reqwest::header::{HeaderMap, HeaderValue};
-use tracing::warn;
+use tracing::{info, warn};
+use tracing_subscriber::filter::EnvFilter;
 use trust_dns_server::client::rr::{LowerName, Name};
 
-use crate::errors;
+use crate::errors::{self, ApiResultExt};
 use crate::traits::ToHostname;
 use error_stack::*;
 
 use zerotier_api::{central_api, service_api};
 
 // collections of test hosts files
-pub const TEST_HOSTS_DIR: &str = "../testdata/hosts-files";
+pub const TEST_HOSTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/hosts-files");
 pub const DEFAULT_DOMAIN_NAME: &str = "home.arpa.";
 // zeronsd version calculated from Cargo.toml
 pub const VERSION_STRING: &str = env!("CARGO_PKG_VERSION");
@@ -27,94 +35,745 @@ fn version() -> String {
 }
 
 static LOGGER: Once = Once::new();
+static LOG_TARGET: OnceLock<crate::log::LogTarget> = OnceLock::new();
+// the filter the logger was installed with, and the handle to change it through.
+static LOG_FILTER: OnceLock<(String, LogFilterHandle)> = OnceLock::new();
 
-// initializes a logger
-pub fn init_logger(level: Option<tracing::Level>) {
+// LogFilter is the filter in front of every layer, which can be swapped while running.
+pub type LogFilter = tracing_subscriber::reload::Layer<EnvFilter, tracing_subscriber::Registry>;
+
+// LogLayer is one of the things init_logger hands what the filter lets through to.
+pub type LogLayer = Box<
+    dyn tracing_subscriber::Layer<
+            tracing_subscriber::layer::Layered<LogFilter, tracing_subscriber::Registry>,
+        > + Send
+        + Sync,
+>;
+
+type LogFilterHandle = tracing_subscriber::reload::Handle<EnvFilter, tracing_subscriber::Registry>;
+
+// subscriber hands what filter lets through to each of layers.
+pub fn subscriber(
+    filter: EnvFilter,
+    layers: Vec<LogLayer>,
+) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    reloadable_subscriber(filter, layers).0
+}
+
+// reloadable_subscriber is subscriber, along with a handle to change its filter through.
+fn reloadable_subscriber(
+    filter: EnvFilter,
+    layers: Vec<LogLayer>,
+) -> (Box<dyn tracing::Subscriber + Send + Sync>, LogFilterHandle) {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let (filter, handle) = tracing_subscriber::reload::Layer::new(filter);
+    (
+        Box::new(tracing_subscriber::registry().with(filter).with(layers)),
+        handle,
+    )
+}
+
+// log_layer writes to writer. JSON lines carry the fields of events at the top level, along with
+// those of the span they happened in.
+pub fn log_layer<W>(format: crate::log::LogFormat, writer: W) -> LogLayer
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+
+    match format {
+        crate::log::LogFormat::Text => Box::new(layer),
+        crate::log::LogFormat::Json => Box::new(
+            layer
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false),
+        ),
+    }
+}
+
+// log_subscriber builds the subscriber init_logger installs for stdout, writing to writer.
+pub fn log_subscriber<W>(
+    filter: EnvFilter,
+    format: crate::log::LogFormat,
+    writer: W,
+) -> Box<dyn tracing::Subscriber + Send + Sync>
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    subscriber(filter, vec![log_layer(format, writer)])
+}
+
+// log_target is where init_logger ended up sending logs, once it has run.
+pub fn log_target() -> Option<crate::log::LogTarget> {
+    LOG_TARGET.get().copied()
+}
+
+// log_filter is the filter in effect, once init_logger has run.
+pub fn log_filter() -> Option<String> {
+    let (_, handle) = LOG_FILTER.get()?;
+    handle.with_current(ToString::to_string).ok()
+}
+
+// set_log_filter replaces the filter in effect, returning the new one.
+pub fn set_log_filter(filter: EnvFilter) -> Result<String, errors::Error> {
+    let (_, handle) = LOG_FILTER
+        .get()
+        .ok_or(errors::Error::Config)
+        .attach_printable("logging has not been set up")?;
+    let shown = filter.to_string();
+
+    handle
+        .reload(filter)
+        .change_context(errors::Error::Config)
+        .attach_printable("could not change the log filter")?;
+    info!("Log filter set to {}", shown);
+    Ok(shown)
+}
+
+// toggle_debug switches to logging everything at debug, or back to the filter the logger was
+// installed with when already there.
+pub fn toggle_debug() -> Result<String, errors::Error> {
+    let initial = LOG_FILTER
+        .get()
+        .map(|(initial, _)| initial.clone())
+        .ok_or(errors::Error::Config)
+        .attach_printable("logging has not been set up")?;
+
+    let filter = match log_filter() {
+        Some(current) if current == "debug" && initial != "debug" => initial,
+        _ => "debug".to_string(),
+    };
+    set_log_filter(crate::log::parse_filter(&filter)?)
+}
+
+// debug_on_sigusr2 toggles debug logging each time SIGUSR2 is received.
+#[cfg(unix)]
+pub async fn debug_on_sigusr2() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = match signal(SignalKind::user_defined2()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("Not listening for SIGUSR2 to toggle debug logging: {}", e);
+            return;
+        }
+    };
+
+    while signals.recv().await.is_some() {
+        if let Err(e) = toggle_debug() {
+            warn!("Could not toggle debug logging: {:?}", e);
+        }
+    }
+}
+
+// LogSink is a log target that has been reached.
+enum LogSink {
+    Stdout,
+    #[cfg(unix)]
+    Journald(tracing_journald::Layer),
+    #[cfg(unix)]
+    Syslog(SyslogLogger),
+}
+
+// open_target reaches target, failing when it is unavailable.
+fn open_target(target: crate::log::LogTarget) -> std::result::Result<LogSink, String> {
+    match target {
+        crate::log::LogTarget::Stdout => Ok(LogSink::Stdout),
+        #[cfg(unix)]
+        crate::log::LogTarget::Journald => tracing_journald::layer()
+            .map(LogSink::Journald)
+            .map_err(|e| e.to_string()),
+        #[cfg(unix)]
+        crate::log::LogTarget::Syslog => syslog::unix(syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_DAEMON,
+            hostname: None,
+            process: "zeronsd".to_string(),
+            pid: std::process::id(),
+        })
+        .map(LogSink::Syslog)
+        .map_err(|e| e.to_string()),
+        #[cfg(not(unix))]
+        _ => Err("only available on unix".to_string()),
+    }
+}
+
+fn sink_layer(sink: LogSink, format: crate::log::LogFormat) -> LogLayer {
+    match sink {
+        LogSink::Stdout => log_layer(format, std::io::stdout),
+        // journald keeps fields as fields, so there is no format to choose.
+        #[cfg(unix)]
+        LogSink::Journald(layer) => Box::new(layer),
+        #[cfg(unix)]
+        LogSink::Syslog(logger) => syslog_layer(format, logger),
+    }
+}
+
+// syslog_layer writes each event as one syslog message at its severity. syslog stamps messages
+// with the time and severity itself, so they are left out of the message.
+#[cfg(unix)]
+fn syslog_layer(format: crate::log::LogFormat, logger: SyslogLogger) -> LogLayer {
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(SyslogWriter {
+            logger: std::sync::Arc::new(std::sync::Mutex::new(logger)),
+        })
+        .with_ansi(false)
+        .without_time();
+
+    match format {
+        crate::log::LogFormat::Text => Box::new(layer.with_level(false)),
+        crate::log::LogFormat::Json => Box::new(
+            layer
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false),
+        ),
+    }
+}
+
+#[cfg(unix)]
+pub fn syslog_subscriber(
+    filter: EnvFilter,
+    format: crate::log::LogFormat,
+    logger: SyslogLogger,
+) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    subscriber(filter, vec![syslog_layer(format, logger)])
+}
+
+#[cfg(unix)]
+pub type SyslogLogger = syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>;
+
+// SyslogWriter hands out a SyslogEntry for each event.
+#[cfg(unix)]
+struct SyslogWriter {
+    logger: std::sync::Arc<std::sync::Mutex<SyslogLogger>>,
+}
+
+// SyslogEntry collects an event as it is formatted and sends it once done.
+#[cfg(unix)]
+struct SyslogEntry {
+    logger: std::sync::Arc<std::sync::Mutex<SyslogLogger>>,
+    level: tracing::Level,
+    buf: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for SyslogWriter {
+    type Writer = SyslogEntry;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SyslogEntry {
+            logger: self.logger.clone(),
+            level: tracing::Level::INFO,
+            buf: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+        SyslogEntry {
+            logger: self.logger.clone(),
+            level: *meta.level(),
+            buf: Vec::new(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl std::io::Write for SyslogEntry {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SyslogEntry {
+    fn drop(&mut self) {
+        let message = String::from_utf8_lossy(&self.buf);
+        let message = message.trim_end();
+        if message.is_empty() {
+            return;
+        }
+
+        if let Ok(mut logger) = self.logger.lock() {
+            // nothing is left to report a failure to send a log message to.
+            let _ = match self.level {
+                tracing::Level::ERROR => logger.err(message),
+                tracing::Level::WARN => logger.warning(message),
+                tracing::Level::INFO => logger.info(message),
+                _ => logger.debug(message),
+            };
+        }
+    }
+}
+
+// initializes a logger, writing what filter lets through. When target cannot be reached, logs go
+// to stdout instead, with a warning. Spans also go to telemetry, when given.
+pub fn init_logger(
+    filter: EnvFilter,
+    format: crate::log::LogFormat,
+    target: crate::log::LogTarget,
+    telemetry: Option<LogLayer>,
+) {
     LOGGER.call_once(|| {
-        let loglevel = std::env::var("ZERONSD_LOG").or_else(|_| std::env::var("RUST_LOG"));
+        tracing_log::log_tracer::LogTracer::init().expect("initializing logger failed");
 
-        let level = if let Ok(loglevel) = loglevel {
-            crate::log::LevelFilter::from_str(&loglevel)
-                .expect("invalid log level")
-                .to_log()
-        } else {
-            level
+        let (sink, used, unavailable) = match open_target(target) {
+            Ok(sink) => (sink, target, None),
+            Err(e) => (LogSink::Stdout, crate::log::LogTarget::Stdout, Some(e)),
         };
 
-        tracing_log::log_tracer::LogTracer::init().expect("initializing logger failed");
+        // a report is a single entry in journald or syslog, where emphasis is only noise.
+        if used != crate::log::LogTarget::Stdout {
+            Report::set_color_mode(error_stack::fmt::ColorMode::None);
+        }
 
-        if let Some(level) = level {
-            let subscriber = tracing_subscriber::FmtSubscriber::builder()
-                // all spans/events with a level higher than TRACE (e.g, debug, info, warn, etc.)
-                // will be written to stdout.
-                .with_max_level(level)
-                // completes the builder.
-                .finish();
+        let layers = std::iter::once(sink_layer(sink, format))
+            .chain(telemetry)
+            .collect();
+        let initial = filter.to_string();
+        let (subscriber, handle) = reloadable_subscriber(filter, layers);
+        tracing::subscriber::set_global_default(subscriber)
+            .expect("setting default subscriber failed");
+        let _ = LOG_FILTER.set((initial, handle));
+        let _ = LOG_TARGET.set(used);
 
-            tracing::subscriber::set_global_default(subscriber)
-                .expect("setting default subscriber failed");
+        if let Some(e) = unavailable {
+            warn!("Logging to stdout; {} is unavailable: {}", target, e);
         }
     })
 }
 
+// default time allowed to establish a connection to an API.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// default time allowed for an API request to complete.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// default time allowed for a request to zerotier-one, which is local and should answer at once.
+pub const DEFAULT_SERVICE_API_TIMEOUT: Duration = Duration::from_secs(10);
+
+// HttpOptions tunes the HTTP clients used to talk to Central and zerotier-one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpOptions {
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    // appended to the user agent, for those who front Central with something that keys on it.
+    pub user_agent_suffix: Option<String>,
+    // SHA-256 of the certificate Central must present, in hex. Set, it replaces the system CAs.
+    pub central_tls_fingerprint: Option<String>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            user_agent_suffix: None,
+            central_tls_fingerprint: None,
+        }
+    }
+}
+
+impl HttpOptions {
+    fn user_agent(&self) -> String {
+        match &self.user_agent_suffix {
+            Some(suffix) => format!("{} {}", version(), suffix),
+            None => version(),
+        }
+    }
+
+    // client_builder starts a reqwest client with the timeouts and user agent applied. Requests
+    // which time out fail like any other, so the sync loop simply tries again on the next tick.
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        reqwest::Client::builder()
+            .user_agent(self.user_agent())
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+    }
+}
+
+// Secret is a credential, kept out of logs and error reports by formatting as [redacted]. Only
+// expose hands out the value.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    // header is the value of a header carrying the secret, marked sensitive so reqwest and http
+    // leave it out of their Debug output too.
+    fn header(&self, prefix: &str) -> Result<HeaderValue, errors::Error> {
+        let mut value = HeaderValue::from_str(&format!("{}{}", prefix, self.0))
+            .change_context(errors::Error::Config)
+            .attach_printable("credential is not a valid header value")?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
 // this provides the production configuration for talking to central through the openapi libraries.
-pub fn central_client(token: String) -> Result<central_api::Client, errors::Error> {
+pub fn central_client(token: Secret) -> Result<central_api::Client, errors::Error> {
+    central_client_with_options(token, &HttpOptions::default())
+}
+
+pub fn central_client_with_options(
+    token: Secret,
+    options: &HttpOptions,
+) -> Result<central_api::Client, errors::Error> {
+    central_client_with_url(
+        &std::env::var("ZEROTIER_CENTRAL_INSTANCE").unwrap_or(CENTRAL_BASEURL.to_string()),
+        token,
+        options,
+    )
+}
+
+pub fn central_client_with_url(
+    url: &str,
+    token: Secret,
+    options: &HttpOptions,
+) -> Result<central_api::Client, errors::Error> {
     let mut headers = HeaderMap::new();
-    headers.insert(
-        "Authorization",
-        HeaderValue::from_str(&format!("bearer {}", token)).change_context(errors::Error)?,
-    );
+    headers.insert("Authorization", token.header("bearer ")?);
+
+    let mut builder = options
+        .client_builder()
+        .https_only(true)
+        .default_headers(headers);
+    if let Some(fingerprint) = &options.central_tls_fingerprint {
+        builder =
+            builder.use_preconfigured_tls(pinned_tls_config(parse_fingerprint(fingerprint)?)?);
+    }
 
     Ok(central_api::Client::new_with_client(
-        &std::env::var("ZEROTIER_CENTRAL_INSTANCE").unwrap_or(CENTRAL_BASEURL.to_string()),
-        reqwest::Client::builder()
-            .user_agent(version())
-            .https_only(true)
-            .default_headers(headers)
-            .build()
-            .change_context(errors::Error)?,
+        url,
+        builder.build().change_context(errors::Error::Config)?,
     ))
 }
 
+// parse_fingerprint reads a SHA-256 fingerprint as 64 hex digits, optionally separated by colons
+// as openssl prints them.
+pub fn parse_fingerprint(s: &str) -> Result<[u8; 32], errors::Error> {
+    let mut fingerprint = [0; 32];
+    hex::decode_to_slice(s.trim().replace(':', ""), &mut fingerprint)
+        .change_context(errors::Error::Config)
+        .attach_printable_lazy(|| {
+            format!(
+                "invalid certificate fingerprint {:?}: expected SHA-256 in hex",
+                s
+            )
+        })?;
+    Ok(fingerprint)
+}
+
+// pinned_tls_config only trusts a server presenting the certificate with this fingerprint. CAs,
+// names and expiry do not come into it; the handshake signatures are still checked, so the
+// server must also hold the certificate's key.
+fn pinned_tls_config(fingerprint: [u8; 32]) -> Result<rustls::ClientConfig, errors::Error> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinnedCertVerifier {
+        fingerprint,
+        algorithms: provider.signature_verification_algorithms,
+    };
+
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .change_context(errors::Error::Tls)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    fingerprint: [u8; 32],
+    algorithms: rustls::crypto::WebPkiSupportedAlgorithms,
+}
+
+impl rustls::client::danger::ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> std::result::Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        if openssl::sha::sha256(end_entity) == self.fingerprint {
+            Ok(rustls::client::danger::ServerCertVerified::assertion())
+        } else {
+            warn!(
+                "Central presented a certificate with fingerprint {}, not the pinned one",
+                hex::encode(openssl::sha::sha256(end_entity))
+            );
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> std::result::Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> std::result::Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
 // extracts the ip from the CIDR. 10.0.0.1/32 becomes 10.0.0.1
-pub fn parse_ip_from_cidr(ip_with_cidr: String) -> IpAddr {
-    IpNetwork::from_str(&ip_with_cidr)
-        .expect("Could not parse IP from CIDR")
-        .ip()
+pub fn parse_ip_from_cidr(ip_with_cidr: String) -> Result<IpAddr, errors::Error> {
+    Ok(IpNetwork::from_str(&ip_with_cidr)
+        .change_context(errors::Error::Address)
+        .attach_printable_lazy(|| format!("Could not parse IP from CIDR {}", ip_with_cidr))?
+        .ip())
+}
+
+// extracts the ips from a list of CIDRs, skipping any that cannot be parsed.
+pub fn parse_ips_from_cidrs(ips_with_cidr: &[String]) -> Vec<IpAddr> {
+    ips_with_cidr
+        .iter()
+        .filter_map(|cidr| match parse_ip_from_cidr(cidr.clone()) {
+            Ok(ip) => Some(ip),
+            Err(e) => {
+                warn!("Ignoring invalid address {}: {:?}", cidr, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// normalize_listen_ips cleans up the addresses reported by zerotier-one. Duplicate IPs are
+// dropped, as are unspecified and IPv4 link-local addresses that cannot be served from; IPv6
+// link-local addresses are kept as they are bound with the interface scope. The networks the
+// remaining IPs live in are returned alongside them for building reverse zones, with networks
+// contained in a broader one collapsed into it.
+pub fn normalize_listen_ips(ips_with_cidr: &[String]) -> (Vec<IpAddr>, Vec<IpNetwork>) {
+    let mut listen_ips: Vec<IpAddr> = Vec::new();
+    let mut networks: Vec<IpNetwork> = Vec::new();
+
+    for cidr in ips_with_cidr {
+        let cidr = match IpNetwork::from_str(cidr) {
+            Ok(cidr) => cidr,
+            Err(e) => {
+                warn!("Ignoring invalid listen address {}: {}", cidr, e);
+                continue;
+            }
+        };
+
+        let ip = cidr.ip();
+        let unusable = match ip {
+            IpAddr::V4(v4) => v4.is_unspecified() || v4.is_link_local(),
+            IpAddr::V6(v6) => v6.is_unspecified(),
+        };
+
+        if unusable {
+            warn!("Ignoring unusable listen address {}", cidr);
+            continue;
+        }
+
+        // the prefix is always valid here as it came from a valid network.
+        let network = IpNetwork::new(cidr.network(), cidr.prefix()).unwrap();
+        if !networks.contains(&network) {
+            networks.push(network);
+        }
+
+        if listen_ips.contains(&ip) {
+            info!("Ignoring duplicate listen address {}", cidr);
+            continue;
+        }
+
+        listen_ips.push(ip);
+    }
+
+    // broadest networks first, so narrower ones can be collapsed into them.
+    networks.sort_by_key(|network| network.prefix());
+
+    let mut collapsed: Vec<IpNetwork> = Vec::new();
+    for network in networks {
+        match collapsed.iter().find(|broader| {
+            broader.is_ipv4() == network.is_ipv4() && broader.contains(network.ip())
+        }) {
+            Some(broader) => info!("Collapsing network {} into {}", network, broader),
+            None => collapsed.push(network),
+        }
+    }
+
+    (listen_ips, collapsed)
+}
+
+// read_file reads a whole file. what describes the file for the error report, which also names
+// the path and the kind of IO error.
+pub fn read_file(path: &Path, what: &str) -> Result<Vec<u8>, errors::Error> {
+    io_context(std::fs::read(path), path, what)
+}
+
+// read_file_to_string is read_file for text files.
+pub fn read_file_to_string(path: &Path, what: &str) -> Result<String, errors::Error> {
+    io_context(std::fs::read_to_string(path), path, what)
+}
+
+fn io_context<T>(result: std::io::Result<T>, path: &Path, what: &str) -> Result<T, errors::Error> {
+    result.map_err(|e| {
+        let kind = e.kind();
+        Report::new(e)
+            .change_context(errors::Error::Io {
+                path: path.to_path_buf(),
+            })
+            .attach_printable(format!(
+                "could not read {} {}: {}",
+                what,
+                path.display(),
+                kind
+            ))
+    })
 }
 
 // load and prepare the central API token
-pub fn central_token(arg: Option<&Path>) -> Result<String, errors::Error> {
+pub fn central_token(arg: Option<&Path>) -> Result<Secret, errors::Error> {
     if let Some(path) = arg {
-        return Ok(std::fs::read_to_string(path)
-            .expect("Could not load token file")
-            .trim()
-            .to_string());
+        return Ok(Secret::new(read_file_to_string(path, "token file")?.trim()));
     }
 
     if let Ok(token) = std::env::var("ZEROTIER_CENTRAL_TOKEN") {
+        let token = token.trim();
         if !token.is_empty() {
-            return Ok(token);
+            return Ok(Secret::new(token));
         }
     }
 
-    return Err(errors::Error).attach_printable("missing zerotier central token: set ZEROTIER_CENTRAL_TOKEN in environment, or pass a file containing it with -t");
+    Err(errors::Error::Config).attach_printable("missing zerotier central token: set ZEROTIER_CENTRAL_TOKEN in environment, or pass a file containing it with -t")
 }
 
-// determine the path of the authtoken.secret
-pub fn authtoken_path(arg: Option<&Path>) -> &Path {
+// determine the path of the authtoken.secret. An explicit path wins, followed by the home
+// directory set in ZEROTIER_HOME or ZT_HOME, then the platform default.
+pub fn authtoken_path(arg: Option<&Path>) -> Result<PathBuf, errors::Error> {
     if let Some(arg) = arg {
-        return arg;
+        return Ok(arg.to_path_buf());
+    }
+
+    for var in ["ZEROTIER_HOME", "ZT_HOME"] {
+        if let Ok(home) = std::env::var(var) {
+            if !home.is_empty() {
+                return Ok(Path::new(&home).join("authtoken.secret"));
+            }
+        }
     }
 
-    if cfg!(target_os = "linux") {
-        Path::new("/var/lib/zerotier-one/authtoken.secret")
+    let path = if cfg!(target_os = "linux") {
+        "/var/lib/zerotier-one/authtoken.secret"
     } else if cfg!(target_os = "windows") {
-        Path::new("C:/ProgramData/ZeroTier/One/authtoken.secret")
+        "C:/ProgramData/ZeroTier/One/authtoken.secret"
     } else if cfg!(target_os = "macos") {
-        Path::new("/Library/Application Support/ZeroTier/One/authtoken.secret")
+        "/Library/Application Support/ZeroTier/One/authtoken.secret"
+    } else if cfg!(any(target_os = "freebsd", target_os = "openbsd")) {
+        "/var/db/zerotier-one/authtoken.secret"
     } else {
-        panic!("authtoken.secret not found; please provide the -s option to provide a custom path")
+        return Err(errors::Error::Config).attach_printable(
+            "authtoken.secret not found; please provide the -s option to provide a custom path",
+        );
+    };
+
+    Ok(PathBuf::from(path))
+}
+
+// read_network_ids_from_dir lists the networks zerotier-one has joined, from the <network id>.conf
+// files in its networks.d directory, sorted. Other files, such as <network id>.local.conf, are
+// skipped.
+pub fn read_network_ids_from_dir(dir: &Path) -> Result<Vec<String>, errors::Error> {
+    let entries = std::fs::read_dir(dir).change_context(errors::Error::Io {
+        path: dir.to_path_buf(),
+    })?;
+
+    let mut ids = Vec::new();
+    for entry in entries {
+        let path = entry
+            .change_context(errors::Error::Io {
+                path: dir.to_path_buf(),
+            })?
+            .path();
+
+        if path.extension().and_then(|e| e.to_str()) != Some("conf") {
+            continue;
+        }
+
+        if let Some(id) = path.file_stem().and_then(|s| s.to_str()) {
+            if id.len() == 16 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+                ids.push(id.to_ascii_lowercase());
+            }
+        }
+    }
+
+    ids.sort();
+    Ok(ids)
+}
+
+// check_authtoken ensures the authtoken.secret is readable and not empty, so problems are reported
+// up front instead of as a failed request to zerotier-one.
+pub fn check_authtoken(path: &Path) -> Result<(), errors::Error> {
+    match std::fs::read_to_string(path) {
+        Ok(token) if token.trim().is_empty() => {
+            Err(errors::Error::Config).attach_printable(format!(
+                "{} is empty; is zerotier-one installed and running?",
+                path.display()
+            ))
+        }
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Err(e)
+            .change_context(errors::Error::Io {
+                path: path.to_path_buf(),
+            })
+            .attach_printable(format!(
+                "cannot read {}: zeronsd must run as root or as a user in the zerotier-one group",
+                path.display()
+            )),
+        Err(e) => Err(e)
+            .change_context(errors::Error::Io {
+                path: path.to_path_buf(),
+            })
+            .attach_printable(format!(
+                "cannot read {}: please provide the -s option to provide a custom path",
+                path.display()
+            )),
     }
 }
 
@@ -122,14 +781,41 @@ pub fn authtoken_path(arg: Option<&Path>) -> &Path {
 pub fn domain_or_default(tld: Option<&str>) -> Result<Name, errors::Error> {
     if let Some(tld) = tld {
         if !tld.is_empty() {
-            return Ok(Name::from_str(&format!("{}.", tld)).change_context(errors::Error)?);
+            return Name::from_str(&format!("{}.", tld)).change_context(errors::Error::DnsName);
         } else {
-            return Err(errors::Error)
+            return Err(errors::Error::DnsName)
                 .attach_printable("Domain name must not be empty if provided.");
         }
     };
 
-    Ok(Name::from_str(DEFAULT_DOMAIN_NAME).change_context(errors::Error)?)
+    Name::from_str(DEFAULT_DOMAIN_NAME).change_context(errors::Error::DnsName)
+}
+
+// derive_domain_from_network names the domain after the network in Central: its name in
+// lowercase, with spaces as hyphens, under arpa. A network named "Home Lab" is home-lab.arpa.
+pub async fn derive_domain_from_network(
+    client: &central_api::Client,
+    network_id: &str,
+) -> Result<Name, errors::Error> {
+    let network = client
+        .get_network_by_id(network_id)
+        .await
+        .central_context()?
+        .into_inner();
+    let name = network
+        .config
+        .and_then(|c| c.name)
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let label = name.to_hostname().attach_printable_lazy(|| {
+        format!("network {} has no usable name for a domain", network_id)
+    })?;
+
+    label
+        .append_domain(&Name::from_str("arpa.").unwrap())
+        .change_context(errors::Error::DnsName)
+        .attach_printable_lazy(|| format!("network name {:?} is too long for a domain", name))
 }
 
 // parse_member_name ensures member names are DNS compliant
@@ -140,7 +826,7 @@ pub fn parse_member_name(name: Option<String>, domain_name: Name) -> Option<Name
             match name.to_fqdn(domain_name) {
                 Ok(record) => return Some(record),
                 Err(e) => {
-                    warn!("Record {} not entered into catalog: {}", name, e);
+                    warn!(member_name = name, error = %e, "Member name not entered into catalog");
                     return None;
                 }
             };
@@ -154,53 +840,50 @@ pub async fn get_member_name(
     authtoken_path: &Path,
     domain_name: Name,
     local_url: String,
+    timeout: Duration,
 ) -> Result<LowerName, errors::Error> {
-    let client = local_client_from_file(authtoken_path, local_url).change_context(errors::Error)?;
+    let client = local_client_from_file(authtoken_path, local_url, timeout)?;
 
-    let status = client
-        .get_status()
-        .await
-        .change_context(errors::Error)?
-        .into_inner();
+    let status = client.get_status().await.local_context()?.into_inner();
     if let Some(address) = &status.address {
-        return Ok(("zt-".to_string() + address)
-            .to_fqdn(domain_name)
-            .change_context(errors::Error)?
-            .into());
+        return Ok(("zt-".to_string() + address).to_fqdn(domain_name)?.into());
     }
 
-    Err(errors::Error).attach_printable(
-        "No member found for this instance; is zerotier connected to this network.change_context(errors::Error)?"
+    Err(errors::Error::LocalApi).attach_printable(
+        "No member found for this instance; is zerotier connected to this network?",
     )
 }
 
-fn local_client_from_file(
+pub fn local_client_from_file(
     authtoken_path: &Path,
     local_url: String,
+    timeout: Duration,
 ) -> Result<service_api::Client, errors::Error> {
-    let authtoken = std::fs::read_to_string(authtoken_path)
-        .attach_printable_lazy(|| format!("Auth Token: {}", authtoken_path.display()))
-        .change_context(errors::Error)?;
-    local_client(authtoken, local_url)
+    let authtoken = read_file_to_string(authtoken_path, "authtoken.secret")?;
+    local_client(Secret::new(authtoken), local_url, timeout)
 }
 
+// local_client talks to zerotier-one. Requests give up after timeout, so a hung daemon fails
+// startup, or the request at hand, rather than blocking it forever.
 pub fn local_client(
-    authtoken: String,
+    authtoken: Secret,
     local_url: String,
+    timeout: Duration,
 ) -> Result<service_api::Client, errors::Error> {
     let mut headers = HeaderMap::new();
-    headers.insert(
-        "X-ZT1-Auth",
-        HeaderValue::from_str(&authtoken).change_context(errors::Error)?,
-    );
+    headers.insert("X-ZT1-Auth", authtoken.header("")?);
 
+    let options = HttpOptions {
+        request_timeout: timeout,
+        ..Default::default()
+    };
     Ok(service_api::Client::new_with_client(
         &local_url,
-        reqwest::Client::builder()
-            .user_agent(version())
+        options
+            .client_builder()
             .default_headers(headers)
             .build()
-            .change_context(errors::Error)?,
+            .change_context(errors::Error::Config)?,
     ))
 }
 
@@ -210,55 +893,326 @@ pub async fn get_listen_ips(
     authtoken_path: &Path,
     network_id: &str,
     local_url: String,
+    timeout: Duration,
 ) -> Result<Vec<String>, errors::Error> {
-    let client = local_client_from_file(authtoken_path, local_url).change_context(errors::Error)?;
+    let client = local_client_from_file(authtoken_path, local_url, timeout)?;
 
     match client.get_network(network_id).await {
-        Err(error) => Err(errors::Error).attach_printable_lazy(|| {
-            format!(
-                "Error: {}. Are you joined to {}.change_context(errors::Error)?",
-                error, network_id
-            )
-        }),
+        Err(error) => Err(Report::new(error).change_context(errors::Error::LocalApi))
+            .attach_printable_lazy(|| format!("Are you joined to {}?", network_id)),
         Ok(listen) => {
             let assigned = listen.into_inner().assigned_addresses.to_owned();
             if !assigned.is_empty() {
                 Ok(assigned)
             } else {
-                Err(errors::Error).attach_printable("No listen IPs available on this network")
+                Err(errors::Error::LocalApi)
+                    .attach_printable("No listen IPs available on this network")
             }
         }
     }
 }
 
-// update_central_dns pushes the search records
+// get_listen_interface returns the name of the interface zerotier-one created for the network, if
+// it has reported one. This is needed to scope link-local listen addresses.
+pub async fn get_listen_interface(
+    authtoken_path: &Path,
+    network_id: &str,
+    local_url: String,
+    timeout: Duration,
+) -> Result<Option<String>, errors::Error> {
+    let client = local_client_from_file(authtoken_path, local_url, timeout)?;
+
+    let network = client
+        .get_network(network_id)
+        .await
+        .local_context()?
+        .into_inner();
+
+    Ok(network.port_device_name)
+}
+
+// merge_dns_servers unions our IPs into the DNS servers already configured for a network,
+// dropping duplicates and preserving the existing order. When a limit is supplied, servers which
+// are not ours are dropped oldest-first to make room for ours.
+pub fn merge_dns_servers(
+    existing: &[String],
+    ours: &[String],
+    limit: Option<usize>,
+) -> Vec<String> {
+    let mut servers: Vec<String> = Vec::new();
+
+    for server in existing.iter().chain(ours.iter()) {
+        if !servers.contains(server) {
+            servers.push(server.clone());
+        }
+    }
+
+    if let Some(limit) = limit {
+        while servers.len() > limit {
+            match servers.iter().position(|s| !ours.contains(s)) {
+                Some(pos) => {
+                    servers.remove(pos);
+                }
+                None => servers.truncate(limit),
+            }
+        }
+    }
+
+    servers
+}
+
+// update_central_dns pushes the search records, merging our IPs into any servers already
+// configured for the network. Central is only written to when something changed.
 pub async fn update_central_dns(
     domain_name: Name,
     ips: Vec<String>,
     client: central_api::Client,
     network: String,
+    limit: Option<usize>,
 ) -> Result<(), errors::Error> {
-    let mut zt_network = client
-        .get_network_by_id(&network)
-        .await
-        .change_context(errors::Error)?;
+    let mut zt_network = client.get_network_by_id(&network).await.central_context()?;
 
     let mut domain_name = domain_name;
     domain_name.set_fqdn(false);
 
-    let dns = Some(central_api::types::Dns {
-        domain: Some(domain_name.to_string()),
-        servers: Some(ips),
-    });
-
     if let Some(mut zt_network_config) = zt_network.config.to_owned() {
-        zt_network_config.dns = dns;
+        let (existing_domain, existing_servers) = match &zt_network_config.dns {
+            Some(dns) => (dns.domain.clone(), dns.servers.clone()),
+            None => (None, None),
+        };
+        let servers =
+            merge_dns_servers(existing_servers.as_deref().unwrap_or_default(), &ips, limit);
+
+        if existing_domain == Some(domain_name.to_string())
+            && existing_servers.as_ref() == Some(&servers)
+        {
+            tracing::debug!("DNS settings in Central are already up to date");
+            return Ok(());
+        }
+
+        zt_network_config.dns = Some(central_api::types::Dns {
+            domain: Some(domain_name.to_string()),
+            servers: Some(servers.clone()),
+        });
         zt_network.config = Some(zt_network_config);
         client
             .update_network(&network, &zt_network)
             .await
-            .change_context(errors::Error)?;
+            .central_context()?;
+
+        // central occasionally drops updates; read back what it stored to catch that.
+        if !verify_central_dns(&domain_name.to_string(), &servers, &client, &network).await? {
+            warn!(
+                "Central did not store the DNS settings for network {}; clients may not use this server",
+                network
+            );
+        }
+    } else {
+        // writing a config of our own would clobber the real one once central sends it again.
+        warn!(
+            "Network {} has no config; not updating its DNS settings in Central",
+            network
+        );
+    }
+
+    Ok(())
+}
+
+// verify_central_dns reports whether Central's DNS settings for the network match what we wrote.
+pub async fn verify_central_dns(
+    domain: &str,
+    servers: &[String],
+    client: &central_api::Client,
+    network: &str,
+) -> Result<bool, errors::Error> {
+    let zt_network = client.get_network_by_id(network).await.central_context()?;
+
+    Ok(zt_network
+        .config
+        .as_ref()
+        .and_then(|config| config.dns.as_ref())
+        .is_some_and(|dns| {
+            dns.domain.as_deref() == Some(domain) && dns.servers.as_deref() == Some(servers)
+        }))
+}
+
+// remove_central_dns removes our IPs from the DNS servers configured for the network, leaving any
+// other servers in place.
+pub async fn remove_central_dns(
+    ips: Vec<String>,
+    client: central_api::Client,
+    network: String,
+) -> Result<(), errors::Error> {
+    let mut zt_network = client.get_network_by_id(&network).await.central_context()?;
+
+    if let Some(mut zt_network_config) = zt_network.config.to_owned() {
+        if let Some(mut dns) = zt_network_config.dns.clone() {
+            let existing = dns.servers.clone().unwrap_or_default();
+            let servers: Vec<String> = existing
+                .iter()
+                .filter(|s| !ips.contains(s))
+                .cloned()
+                .collect();
+
+            if servers.len() == existing.len() {
+                return Ok(());
+            }
+
+            dns.servers = Some(servers);
+            zt_network_config.dns = Some(dns);
+            zt_network.config = Some(zt_network_config);
+            client
+                .update_network(&network, &zt_network)
+                .await
+                .central_context()?;
+        }
     }
 
     Ok(())
 }
+
+// the most pages of members fetched in a single sync unless max_pages says otherwise; a guard
+// against Central handing out next links forever.
+pub const DEFAULT_MAX_MEMBER_PAGES: u32 = 100;
+
+// get_network_members fetches the members of a network. Central currently returns them all in
+// one response, but should it page the list, RFC 8288 `Link: <...>; rel="next"` headers are
+// followed until they run out, or max_pages have been fetched.
+pub async fn get_network_members(
+    client: &central_api::Client,
+    network_id: &str,
+    max_pages: u32,
+) -> Result<Vec<central_api::types::Member>, errors::Error> {
+    let mut url = reqwest::Url::parse(&format!(
+        "{}/network/{}/member",
+        client.baseurl(),
+        network_id
+    ))
+    .change_context(errors::Error::Config)?;
+
+    let mut members = Vec::new();
+    let mut seen = HashSet::new();
+
+    for _ in 0..max_pages {
+        let response = client
+            .client()
+            .get(url.clone())
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                let status = e.status().map(|s| s.as_u16());
+                Report::new(e).change_context(errors::Error::CentralApi { status })
+            })
+            .attach_printable_lazy(|| format!("Fetching members: {}", url))?;
+
+        let next = next_link(response.headers(), &url);
+        let body = response
+            .bytes()
+            .await
+            .change_context(errors::Error::CentralApi { status: None })?;
+        let mut page: Vec<central_api::types::Member> = serde_json::from_slice(&body)
+            .change_context(errors::Error::CentralApi { status: None })
+            .attach_printable_lazy(|| format!("Fetching members: {}", url))?;
+        members.append(&mut page);
+        seen.insert(url);
+
+        match next {
+            Some(next) if seen.contains(&next) => {
+                warn!("Central returned member page {} twice; stopping", next);
+                return Ok(members);
+            }
+            Some(next) => url = next,
+            None => return Ok(members),
+        }
+    }
+
+    warn!(
+        "Stopped fetching members after {} pages; some members may be missing",
+        max_pages
+    );
+    Ok(members)
+}
+
+// next_link finds the rel="next" target of a response's Link headers, resolved against the URL
+// that was requested.
+fn next_link(headers: &HeaderMap, base: &reqwest::Url) -> Option<reqwest::Url> {
+    headers
+        .get_all(reqwest::header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let mut parts = link.split(';');
+            let target = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+
+            let is_next = parts.any(|param| match param.trim().split_once('=') {
+                Some((key, value)) => {
+                    key.trim() == "rel" && value.trim().trim_matches('"') == "next"
+                }
+                None => false,
+            });
+
+            if is_next {
+                base.join(target).ok()
+            } else {
+                None
+            }
+        })
+}
+
+// TokenStatus is the outcome of checking a token against Central.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenStatus {
+    // the token works and can see this many networks
+    Valid(usize),
+    // Central did not accept the token
+    Invalid,
+    // the token was accepted but may not list networks
+    Forbidden,
+    // Central could not be reached, or answered with something unexpected
+    Unreachable(String),
+}
+
+impl TokenStatus {
+    // exit_code is the process exit code for the token-validate subcommand.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TokenStatus::Valid(_) => 0,
+            TokenStatus::Invalid | TokenStatus::Forbidden => 1,
+            TokenStatus::Unreachable(_) => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for TokenStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenStatus::Valid(count) => {
+                write!(f, "Token is valid; it can see {} network(s)", count)
+            }
+            TokenStatus::Invalid => write!(
+                f,
+                "Token is invalid (401): check that it was copied correctly and has not been revoked"
+            ),
+            TokenStatus::Forbidden => write!(
+                f,
+                "Token does not have permission to list networks (403): it must belong to an account that administers the network"
+            ),
+            TokenStatus::Unreachable(e) => write!(f, "Could not talk to Central: {}", e),
+        }
+    }
+}
+
+// validate_token checks the token a client was built with by listing the networks it can see.
+pub async fn validate_token(client: &central_api::Client) -> TokenStatus {
+    match client.get_network_list().await {
+        Ok(networks) => TokenStatus::Valid(networks.len()),
+        Err(e) => match e.status().map(|s| s.as_u16()) {
+            Some(401) => TokenStatus::Invalid,
+            Some(403) => TokenStatus::Forbidden,
+            _ => TokenStatus::Unreachable(e.to_string()),
+        },
+    }
+}
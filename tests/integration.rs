@@ -5,7 +5,12 @@ mod service;
 
 #[ctor::ctor]
 fn init() {
-    init_logger(Some(tracing::Level::ERROR));
+    init_logger(
+        zeronsd::log::env_filter(zeronsd::log::LevelFilter::Error, None).unwrap(),
+        zeronsd::log::LogFormat::Text,
+        zeronsd::log::LogTarget::Stdout,
+        None,
+    );
 }
 
 #[cfg(feature = "integration-tests")]
@@ -690,11 +695,13 @@ mod all {
             .unwrap();
 
         let listen_ips = get_listen_ips(
-            &authtoken_path(None),
+            &authtoken_path(None).unwrap(),
             &tn.network.clone().id.unwrap(),
             ZEROTIER_LOCAL_URL.into(),
+            DEFAULT_SERVICE_API_TIMEOUT,
         )
-        .await?;
+        .await
+        .unwrap();
 
         eprintln!("My listen IP is {}", listen_ips.first().unwrap());
         assert_ne!(*listen_ips.first().unwrap(), String::from(""));
@@ -710,13 +717,15 @@ mod all {
         ips.sort();
 
         let mut listen_ips: Vec<String> = get_listen_ips(
-            &authtoken_path(None),
+            &authtoken_path(None).unwrap(),
             &tn.network.clone().id.unwrap(),
             ZEROTIER_LOCAL_URL.into(),
+            DEFAULT_SERVICE_API_TIMEOUT,
         )
-        .await?
+        .await
+        .unwrap()
         .iter()
-        .map(|x| parse_ip_from_cidr(x.clone()).to_string())
+        .map(|x| parse_ip_from_cidr(x.clone()).unwrap().to_string())
         .collect();
         listen_ips.sort();
 
@@ -728,17 +737,19 @@ mod all {
             .unwrap();
 
         let mut listen_ips: Vec<String> = get_listen_ips(
-            &authtoken_path(None),
+            &authtoken_path(None).unwrap(),
             &tn.network.clone().id.unwrap(),
             ZEROTIER_LOCAL_URL.into(),
+            DEFAULT_SERVICE_API_TIMEOUT,
         )
-        .await?
+        .await
+        .unwrap()
         .iter()
-        .map(|x| parse_ip_from_cidr(x.clone()).to_string())
+        .map(|x| parse_ip_from_cidr(x.clone()).unwrap().to_string())
         .collect();
         listen_ips.sort();
 
-        let mut ips = vec![tn.member().clone().rfc4193()?.ip().to_string()];
+        let mut ips = vec![tn.member().rfc4193().unwrap().ip().to_string()];
         ips.sort();
 
         assert_eq!(listen_ips, ips);
@@ -751,17 +762,19 @@ mod all {
             .unwrap();
 
         let mut listen_ips: Vec<String> = get_listen_ips(
-            &authtoken_path(None),
+            &authtoken_path(None).unwrap(),
             &tn.network.clone().id.unwrap(),
             ZEROTIER_LOCAL_URL.into(),
+            DEFAULT_SERVICE_API_TIMEOUT,
         )
-        .await?
+        .await
+        .unwrap()
         .iter()
-        .map(|x| parse_ip_from_cidr(x.clone()).to_string())
+        .map(|x| parse_ip_from_cidr(x.clone()).unwrap().to_string())
         .collect();
         listen_ips.sort();
 
-        let mut ips = vec![tn.member().clone().sixplane()?.ip().to_string()];
+        let mut ips = vec![tn.member().sixplane().unwrap().ip().to_string()];
         ips.sort();
 
         assert_eq!(listen_ips, ips);
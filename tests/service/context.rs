@@ -1,4 +1,6 @@
-use zeronsd::utils::{central_client, local_client, ZEROTIER_LOCAL_URL};
+use zeronsd::utils::{
+    central_client, local_client, Secret, DEFAULT_SERVICE_API_TIMEOUT, ZEROTIER_LOCAL_URL,
+};
 
 use super::{
     member::MemberUtil,
@@ -28,13 +30,18 @@ impl TestContext {
 
     pub async fn default() -> Self {
         let authtoken = get_authtoken(None).expect("Could not read authtoken");
-        let zerotier = local_client(authtoken.clone(), ZEROTIER_LOCAL_URL.into()).unwrap();
+        let zerotier = local_client(
+            Secret::new(authtoken.clone()),
+            ZEROTIER_LOCAL_URL.into(),
+            DEFAULT_SERVICE_API_TIMEOUT,
+        )
+        .unwrap();
         let identity = get_identity(&zerotier)
             .await
             .expect("Could not retrieve identity from zerotier");
 
         let token = std::env::var("TOKEN").expect("Please provide TOKEN in the environment");
-        let central = central_client(token.clone()).unwrap();
+        let central = central_client(Secret::new(token.clone())).unwrap();
 
         Self {
             member_config: None,
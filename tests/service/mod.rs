@@ -25,7 +25,8 @@ use zeronsd::{
     server::Server,
     traits::{ToHostname, ToPointerSOA},
     utils::{
-        authtoken_path, domain_or_default, get_listen_ips, parse_ip_from_cidr, ZEROTIER_LOCAL_URL,
+        authtoken_path, domain_or_default, get_listen_ips, parse_ip_from_cidr,
+        DEFAULT_SERVICE_API_TIMEOUT, ZEROTIER_LOCAL_URL,
     },
 };
 
@@ -166,9 +167,10 @@ impl Service {
         wildcard_everything: bool,
     ) -> Vec<SocketAddr> {
         let listen_cidrs = get_listen_ips(
-            &authtoken_path(None),
+            &authtoken_path(None).unwrap(),
             &tn.network.clone().id.unwrap(),
             ZEROTIER_LOCAL_URL.into(),
+            DEFAULT_SERVICE_API_TIMEOUT,
         )
         .await
         .unwrap();
@@ -179,28 +181,14 @@ impl Service {
         let mut authority_map = HashMap::new();
 
         for cidr in listen_cidrs.clone() {
-            let listen_ip = parse_ip_from_cidr(cidr.clone());
+            let listen_ip = parse_ip_from_cidr(cidr.clone()).unwrap();
             let socket_addr = SocketAddr::new(listen_ip.clone(), 53);
             listen_ips.push(socket_addr);
             let cidr = IpNetwork::from_str(&cidr.clone()).unwrap();
             if !ipmap.contains_key(&listen_ip) {
                 ipmap.insert(listen_ip, cidr.network());
             }
-
-            if !authority_map.contains_key(&cidr) {
-                let ptr_authority = RecordAuthority::new(
-                    cidr.to_ptr_soa_name().unwrap(),
-                    cidr.to_ptr_soa_name().unwrap(),
-                )
-                .await
-                .unwrap();
-                authority_map.insert(cidr, ptr_authority.clone());
-            }
-        }
-
-        if let Some(v6assign) = tn.network.config.clone().unwrap().v6_assign_mode {
-            if v6assign.rfc4193.unwrap_or(false) {
-                let cidr = tn.network.clone().rfc4193().unwrap();
+            for cidr in cidr.to_ptr_zones() {
                 if !authority_map.contains_key(&cidr) {
                     let ptr_authority = RecordAuthority::new(
                         cidr.to_ptr_soa_name().unwrap(),
@@ -208,7 +196,23 @@ impl Service {
                     )
                     .await
                     .unwrap();
-                    authority_map.insert(cidr, ptr_authority);
+                    authority_map.insert(cidr, ptr_authority.clone());
+                }
+            }
+        }
+
+        if let Some(v6assign) = tn.network.config.clone().unwrap().v6_assign_mode {
+            if v6assign.rfc4193.unwrap_or(false) {
+                for cidr in tn.network.rfc4193().unwrap().to_ptr_zones() {
+                    if !authority_map.contains_key(&cidr) {
+                        let ptr_authority = RecordAuthority::new(
+                            cidr.to_ptr_soa_name().unwrap(),
+                            cidr.to_ptr_soa_name().unwrap(),
+                        )
+                        .await
+                        .unwrap();
+                        authority_map.insert(cidr, ptr_authority);
+                    }
                 }
             }
         }
@@ -224,23 +228,24 @@ impl Service {
         .unwrap();
 
         let ztauthority = ZTAuthority {
-            network_id: tn.network.clone().id.unwrap(),
-            client: tn.central(),
-            hosts_file: format_hosts_file(hosts),
-            reverse_authority_map: authority_map,
-            update_interval,
-            forward_authority: authority.clone(),
+            hosts_files: format_hosts_file(hosts).into_iter().collect(),
             wildcard: wildcard_everything,
-            hosts: None,
+            ..ZTAuthority::new(
+                tn.network.clone().id.unwrap(),
+                Some(Arc::new(tn.central())),
+                authority.clone(),
+                authority_map,
+                update_interval,
+            )
         };
 
-        tokio::spawn(find_members(ztauthority.clone()));
+        tokio::spawn(find_members(Arc::new(ztauthority.clone())));
         tokio::time::sleep(update_interval).await;
 
         for ip in listen_ips.clone() {
             let server = Server::new(ztauthority.to_owned());
             info!("Serving {}", ip.clone());
-            tokio::spawn(server.listen(ip.ip(), Duration::new(1, 0), None, None, None));
+            tokio::spawn(server.listen(ip.ip(), Duration::new(1, 0), None));
         }
 
         listen_ips
@@ -1,7 +1,9 @@
 use std::time::Duration;
 
 use tracing::warn;
-use zeronsd::utils::{authtoken_path, get_listen_ips, ZEROTIER_LOCAL_URL};
+use zeronsd::utils::{
+    authtoken_path, get_listen_ips, DEFAULT_SERVICE_API_TIMEOUT, ZEROTIER_LOCAL_URL,
+};
 
 use zerotier_api::{
     central_api::{
@@ -93,8 +95,13 @@ impl TestNetwork {
         let id = self.network.id.clone().unwrap();
         let mut count = 0;
 
-        while let Err(e) =
-            get_listen_ips(&authtoken_path(None), &id, ZEROTIER_LOCAL_URL.into()).await
+        while let Err(e) = get_listen_ips(
+            &authtoken_path(None).unwrap(),
+            &id,
+            ZEROTIER_LOCAL_URL.into(),
+            DEFAULT_SERVICE_API_TIMEOUT,
+        )
+        .await
         {
             tokio::time::sleep(Duration::new(1, 0)).await;
             count += 1;
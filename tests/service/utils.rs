@@ -57,9 +57,9 @@ pub async fn get_identity(client: &service_api::Client) -> Result<String, anyhow
 
 // unpack the authtoken based on what we're passed
 pub fn get_authtoken(or: Option<&str>) -> Result<String, anyhow::Error> {
-    Ok(std::fs::read_to_string(authtoken_path(
-        or.map(|c| Path::new(c)),
-    ))?)
+    Ok(std::fs::read_to_string(
+        authtoken_path(or.map(|c| Path::new(c))).unwrap(),
+    )?)
 }
 
 pub enum HostsType {